@@ -0,0 +1,128 @@
+// WebAuthn (passkey) ceremonies for the HTMX pages.
+//
+// The server sends the options as JSON with binary fields encoded in
+// base64url; they are converted to ArrayBuffers for navigator.credentials and
+// the authenticator response is converted back before being posted.
+
+function webauthnBase64urlToBuffer(value) {
+    const base64 = value.replace(/-/g, '+').replace(/_/g, '/');
+    const padded = base64 + '='.repeat((4 - (base64.length % 4)) % 4);
+    return Uint8Array.from(atob(padded), c => c.charCodeAt(0)).buffer;
+}
+
+function webauthnBufferToBase64url(buffer) {
+    const bytes = new Uint8Array(buffer);
+    let binary = '';
+    bytes.forEach(b => { binary += String.fromCharCode(b); });
+    return btoa(binary).replace(/\+/g, '-').replace(/\//g, '_').replace(/=+$/, '');
+}
+
+function webauthnShowError(selector, message) {
+    const container = document.querySelector(selector);
+    if (container) {
+        container.textContent = message;
+    }
+}
+
+async function webauthnPost(url, body) {
+    const response = await fetch(url, {
+        method: 'POST',
+        credentials: 'same-origin',
+        headers: { 'Content-Type': 'application/json' },
+        body: JSON.stringify(body || {}),
+    });
+    const data = await response.json().catch(() => ({}));
+    if (!response.ok) {
+        throw new Error(data.description || data.error || 'Request failed');
+    }
+    return data;
+}
+
+async function webauthnCreate(options) {
+    const publicKey = options.publicKey;
+    publicKey.challenge = webauthnBase64urlToBuffer(publicKey.challenge);
+    publicKey.user.id = webauthnBase64urlToBuffer(publicKey.user.id);
+    (publicKey.excludeCredentials || []).forEach(c => { c.id = webauthnBase64urlToBuffer(c.id); });
+
+    const credential = await navigator.credentials.create({ publicKey });
+    return {
+        id: credential.id,
+        rawId: webauthnBufferToBase64url(credential.rawId),
+        type: credential.type,
+        response: {
+            attestationObject: webauthnBufferToBase64url(credential.response.attestationObject),
+            clientDataJSON: webauthnBufferToBase64url(credential.response.clientDataJSON),
+        },
+        extensions: credential.getClientExtensionResults(),
+    };
+}
+
+async function webauthnGet(options) {
+    const publicKey = options.publicKey;
+    publicKey.challenge = webauthnBase64urlToBuffer(publicKey.challenge);
+    (publicKey.allowCredentials || []).forEach(c => { c.id = webauthnBase64urlToBuffer(c.id); });
+
+    const credential = await navigator.credentials.get({ publicKey });
+    const response = credential.response;
+    return {
+        id: credential.id,
+        rawId: webauthnBufferToBase64url(credential.rawId),
+        type: credential.type,
+        response: {
+            authenticatorData: webauthnBufferToBase64url(response.authenticatorData),
+            clientDataJSON: webauthnBufferToBase64url(response.clientDataJSON),
+            signature: webauthnBufferToBase64url(response.signature),
+            userHandle: response.userHandle ? webauthnBufferToBase64url(response.userHandle) : null,
+        },
+        extensions: credential.getClientExtensionResults(),
+    };
+}
+
+// Registers a new passkey for the signed-in user, then reloads the passkeys
+// section of the profile page
+async function webauthnRegister(name, errorSelector) {
+    try {
+        const start = await webauthnPost('/auth/webauthn/register/start');
+        const credential = await webauthnCreate(start.options);
+        await webauthnPost('/auth/webauthn/register/finish', {
+            challenge_id: start.challenge_id,
+            name: name,
+            credential: credential,
+        });
+        htmx.ajax('GET', '/users/profile/passkeys_fragment', { target: '#passkeys-section', swap: 'innerHTML' });
+    } catch (err) {
+        webauthnShowError(errorSelector, 'Could not register the passkey: ' + err.message);
+    }
+}
+
+// Runs an authentication ceremony against the given start/finish endpoints
+// and follows the redirect returned on success
+async function webauthnAuthenticate(startUrl, startBody, finishUrl, errorSelector) {
+    try {
+        const start = await webauthnPost(startUrl, startBody);
+        const credential = await webauthnGet(start.options);
+        const result = await webauthnPost(finishUrl, {
+            challenge_id: start.challenge_id,
+            credential: credential,
+        });
+        window.location.href = result.redirect || '/home';
+    } catch (err) {
+        webauthnShowError(errorSelector, 'Log in failed: ' + err.message);
+    }
+}
+
+// Passwordless login with one of the passkeys of the given account
+function webauthnLogin(email, errorSelector) {
+    if (!email) {
+        webauthnShowError(errorSelector, 'Enter your email address to sign in with a passkey.');
+        return;
+    }
+    webauthnAuthenticate('/auth/webauthn/login/start', { email: email },
+        '/auth/webauthn/login/finish', errorSelector);
+}
+
+// Second login step with a passkey
+function webauthnSecondFactor(errorSelector) {
+    webauthnAuthenticate('/auth/login/2fa/webauthn/start', {},
+        '/auth/login/2fa/webauthn/finish', errorSelector);
+}
@@ -9,6 +9,28 @@ mod m20250416_052930_ssh_keys;
 mod m20250416_173257_add_pgp_key_to_users;
 mod m20250419_061315_add_pgp_verification_to_users;
 mod m20250420_150931_add_unique_constraint_to_teams_name;
+mod m20250502_081512_sessions;
+mod m20250503_091204_totp;
+mod m20250504_102347_webauthn;
+mod m20250505_143018_login_protection;
+mod m20250506_090412_expiring_tokens;
+mod m20250507_083015_oidc_identities;
+mod m20250508_101522_account_status;
+mod m20250509_140233_personal_access_tokens;
+mod m20250510_093114_service_accounts;
+mod m20250511_154402_impersonation;
+mod m20250512_081936_email_failures;
+mod m20250513_094127_ownership_transfers;
+mod m20250514_101203_roles_and_permissions;
+mod m20250515_083412_organizations;
+mod m20250516_092315_email_invitations;
+mod m20250517_101846_invitation_senders;
+mod m20250518_083529_invitation_messages;
+mod m20250519_094412_team_join_links;
+mod m20250520_101135_join_requests;
+mod m20250521_084127_session_expiry;
+mod m20250522_090318_totp_last_step;
+mod m20250523_081204_team_names_outside_organizations;
 pub struct Migrator;
 
 #[async_trait::async_trait]
@@ -22,6 +44,28 @@ impl MigratorTrait for Migrator {
             Box::new(m20250416_173257_add_pgp_key_to_users::Migration),
             Box::new(m20250419_061315_add_pgp_verification_to_users::Migration),
             Box::new(m20250420_150931_add_unique_constraint_to_teams_name::Migration),
+            Box::new(m20250502_081512_sessions::Migration),
+            Box::new(m20250503_091204_totp::Migration),
+            Box::new(m20250504_102347_webauthn::Migration),
+            Box::new(m20250505_143018_login_protection::Migration),
+            Box::new(m20250506_090412_expiring_tokens::Migration),
+            Box::new(m20250507_083015_oidc_identities::Migration),
+            Box::new(m20250508_101522_account_status::Migration),
+            Box::new(m20250509_140233_personal_access_tokens::Migration),
+            Box::new(m20250510_093114_service_accounts::Migration),
+            Box::new(m20250511_154402_impersonation::Migration),
+            Box::new(m20250512_081936_email_failures::Migration),
+            Box::new(m20250513_094127_ownership_transfers::Migration),
+            Box::new(m20250514_101203_roles_and_permissions::Migration),
+            Box::new(m20250515_083412_organizations::Migration),
+            Box::new(m20250516_092315_email_invitations::Migration),
+            Box::new(m20250517_101846_invitation_senders::Migration),
+            Box::new(m20250518_083529_invitation_messages::Migration),
+            Box::new(m20250519_094412_team_join_links::Migration),
+            Box::new(m20250520_101135_join_requests::Migration),
+            Box::new(m20250521_084127_session_expiry::Migration),
+            Box::new(m20250522_090318_totp_last_step::Migration),
+            Box::new(m20250523_081204_team_names_outside_organizations::Migration),
             // inject-above (do not remove this comment)
         ]
     }
@@ -0,0 +1,29 @@
+use loco_rs::schema::*;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        create_table(
+            m,
+            "sessions",
+            &[
+                ("id", ColType::PkAuto),
+                ("jti", ColType::StringUniq),
+                ("last_seen", ColType::TimestampWithTimeZone),
+                ("user_agent", ColType::TextNull),
+                ("ip", ColType::StringNull),
+                ("revoked_at", ColType::TimestampWithTimeZoneNull),
+            ],
+            &[("user", "")],
+        )
+        .await
+    }
+
+    async fn down(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        drop_table(m, "sessions").await
+    }
+}
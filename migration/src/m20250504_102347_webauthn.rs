@@ -0,0 +1,44 @@
+use loco_rs::schema::*;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        create_table(
+            m,
+            "webauthn_credentials",
+            &[
+                ("id", ColType::PkAuto),
+                ("name", ColType::String),
+                ("credential_id", ColType::StringUniq),
+                ("passkey", ColType::Text),
+                ("last_used_at", ColType::TimestampWithTimeZoneNull),
+            ],
+            &[("user", "")],
+        )
+        .await?;
+        create_table(
+            m,
+            "webauthn_challenges",
+            &[
+                ("id", ColType::PkAuto),
+                ("token", ColType::StringUniq),
+                ("kind", ColType::String),
+                ("state", ColType::Text),
+                ("expires_at", ColType::TimestampWithTimeZone),
+            ],
+            &[("user", "")],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn down(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        drop_table(m, "webauthn_challenges").await?;
+        drop_table(m, "webauthn_credentials").await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+use loco_rs::schema::*;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        add_column(
+            m,
+            "users",
+            "failed_login_count",
+            ColType::IntegerWithDefault(0),
+        )
+        .await?;
+        add_column(
+            m,
+            "users",
+            "last_failed_login_at",
+            ColType::TimestampWithTimeZoneNull,
+        )
+        .await?;
+        add_column(
+            m,
+            "users",
+            "locked_until",
+            ColType::TimestampWithTimeZoneNull,
+        )
+        .await?;
+        create_table(
+            m,
+            "login_attempts",
+            &[
+                ("id", ColType::PkAuto),
+                ("ip", ColType::StringUniq),
+                ("failure_count", ColType::IntegerWithDefault(0)),
+                ("last_failure_at", ColType::TimestampWithTimeZone),
+                ("blocked_until", ColType::TimestampWithTimeZoneNull),
+            ],
+            &[],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn down(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        drop_table(m, "login_attempts").await?;
+        remove_column(m, "users", "locked_until").await?;
+        remove_column(m, "users", "last_failed_login_at").await?;
+        remove_column(m, "users", "failed_login_count").await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,64 @@
+use chrono::{Duration, Utc};
+use loco_rs::schema::*;
+use sea_orm_migration::prelude::*;
+
+/// Lifetime given to the invitations still pending when expiry is introduced
+const PENDING_INVITATION_LIFETIME_DAYS: i64 = 7;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        for column in [
+            "reset_expires_at",
+            "email_verification_expires_at",
+            "pgp_verification_expires_at",
+        ] {
+            add_column(m, "users", column, ColType::TimestampWithTimeZoneNull).await?;
+        }
+        add_column(
+            m,
+            "team_memberships",
+            "invitation_expires_at",
+            ColType::TimestampWithTimeZoneNull,
+        )
+        .await?;
+
+        // Tokens are now stored hashed: the plaintext ones issued before can
+        // no longer be matched, users have to request new links
+        m.exec_stmt(
+            Query::update()
+                .table(Alias::new("users"))
+                .value(Alias::new("reset_token"), Option::<String>::None)
+                .value(
+                    Alias::new("email_verification_token"),
+                    Option::<String>::None,
+                )
+                .value(Alias::new("pgp_verification_token"), Option::<String>::None)
+                .to_owned(),
+        )
+        .await?;
+        m.exec_stmt(
+            Query::update()
+                .table(Alias::new("team_memberships"))
+                .value(
+                    Alias::new("invitation_expires_at"),
+                    Utc::now() + Duration::days(PENDING_INVITATION_LIFETIME_DAYS),
+                )
+                .and_where(Expr::col(Alias::new("pending")).eq(true))
+                .to_owned(),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn down(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        remove_column(m, "team_memberships", "invitation_expires_at").await?;
+        remove_column(m, "users", "pgp_verification_expires_at").await?;
+        remove_column(m, "users", "email_verification_expires_at").await?;
+        remove_column(m, "users", "reset_expires_at").await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,40 @@
+use loco_rs::schema::*;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        create_table(
+            m,
+            "oidc_identities",
+            &[
+                ("id", ColType::PkAuto),
+                ("provider", ColType::String),
+                ("subject", ColType::String),
+                ("last_login_at", ColType::TimestampWithTimeZoneNull),
+            ],
+            &[("user", "")],
+        )
+        .await?;
+        // A subject is only unique within the identity provider that issued it
+        m.create_index(
+            Index::create()
+                .unique()
+                .name("idx-oidc_identities-provider-subject-unique")
+                .table(Alias::new("oidc_identities"))
+                .col(Alias::new("provider"))
+                .col(Alias::new("subject"))
+                .to_owned(),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn down(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        drop_table(m, "oidc_identities").await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,36 @@
+use loco_rs::schema::*;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        // users.api_key is no longer accepted for authentication. It is kept
+        // because SQLite cannot drop a column with a UNIQUE constraint.
+        create_table(
+            m,
+            "personal_access_tokens",
+            &[
+                ("id", ColType::PkAuto),
+                ("pid", ColType::Uuid),
+                ("name", ColType::String),
+                ("token_hash", ColType::StringUniq),
+                ("token_prefix", ColType::String),
+                ("scopes", ColType::Text),
+                ("expires_at", ColType::TimestampWithTimeZoneNull),
+                ("last_used_at", ColType::TimestampWithTimeZoneNull),
+                ("revoked_at", ColType::TimestampWithTimeZoneNull),
+            ],
+            &[("user", "")],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn down(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        drop_table(m, "personal_access_tokens").await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,30 @@
+use loco_rs::schema::*;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        // The service account itself is a `users` row that cannot log in; this
+        // table records the team owning it and the member who created it
+        create_table(
+            m,
+            "service_accounts",
+            &[
+                ("id", ColType::PkAuto),
+                ("pid", ColType::Uuid),
+                ("description", ColType::StringNull),
+            ],
+            &[("user", ""), ("team", ""), ("user?", "created_by_id")],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn down(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        drop_table(m, "service_accounts").await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,33 @@
+use loco_rs::schema::*;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        // Set on the sessions an administrator opened as another user
+        add_column(m, "sessions", "impersonator_id", ColType::IntegerNull).await?;
+        // Audit trail of impersonations, kept after either user is deleted
+        create_table(
+            m,
+            "impersonation_events",
+            &[
+                ("id", ColType::PkAuto),
+                ("event", ColType::String),
+                ("session_jti", ColType::String),
+                ("ip", ColType::StringNull),
+            ],
+            &[("user?", "admin_id"), ("user?", "target_id")],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn down(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        drop_table(m, "impersonation_events").await?;
+        remove_column(m, "sessions", "impersonator_id").await?;
+        Ok(())
+    }
+}
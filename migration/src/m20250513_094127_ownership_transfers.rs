@@ -0,0 +1,34 @@
+use loco_rs::schema::*;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        // An Owner offering the ownership of a team to another member, until
+        // that member accepts or declines
+        create_table(
+            m,
+            "ownership_transfers",
+            &[
+                ("id", ColType::PkAuto),
+                ("pid", ColType::Uuid),
+                ("expires_at", ColType::TimestampWithTimeZone),
+            ],
+            &[
+                ("team", ""),
+                ("user", "from_user_id"),
+                ("user", "to_user_id"),
+            ],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn down(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        drop_table(m, "ownership_transfers").await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,178 @@
+use loco_rs::schema::*;
+use sea_orm_migration::prelude::*;
+
+/// Permissions of the catalogue, with their description
+const PERMISSIONS: [(&str, &str); 9] = [
+    (
+        "team.view",
+        "View the team, its members and its service accounts",
+    ),
+    ("team.update", "Change the name and description of the team"),
+    ("team.delete", "Delete the team"),
+    (
+        "team.invite",
+        "Invite users to the team and cancel invitations",
+    ),
+    (
+        "members.remove",
+        "Remove members whose role grants fewer permissions",
+    ),
+    (
+        "members.manage",
+        "Change the role of members and remove any member",
+    ),
+    (
+        "service_accounts.manage",
+        "Create, change and delete service accounts and their tokens",
+    ),
+    (
+        "ssh_keys.deploy",
+        "Deploy SSH keys to the servers of the team",
+    ),
+    ("server.reboot", "Reboot the servers of the team"),
+];
+
+/// Roles that existed before roles were stored, with their description and
+/// permissions
+const ROLES: [(&str, &str, &[&str]); 4] = [
+    (
+        "Owner",
+        "Full control of the team, including its ownership",
+        &[
+            "team.view",
+            "team.update",
+            "team.delete",
+            "team.invite",
+            "members.remove",
+            "members.manage",
+            "service_accounts.manage",
+            "ssh_keys.deploy",
+            "server.reboot",
+        ],
+    ),
+    (
+        "Administrator",
+        "Manages the members and service accounts of the team",
+        &[
+            "team.view",
+            "team.invite",
+            "members.remove",
+            "service_accounts.manage",
+            "ssh_keys.deploy",
+            "server.reboot",
+        ],
+    ),
+    (
+        "Developer",
+        "Works on the servers of the team",
+        &["team.view", "ssh_keys.deploy", "server.reboot"],
+    ),
+    (
+        "Observer",
+        "Sees the team without changing it",
+        &["team.view"],
+    ),
+];
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        create_table(
+            m,
+            "permissions",
+            &[
+                ("id", ColType::PkAuto),
+                ("name", ColType::StringUniq),
+                ("description", ColType::String),
+            ],
+            &[],
+        )
+        .await?;
+        // Team memberships keep referencing roles by name
+        create_table(
+            m,
+            "roles",
+            &[
+                ("id", ColType::PkAuto),
+                ("pid", ColType::Uuid),
+                ("name", ColType::StringUniq),
+                ("description", ColType::StringNull),
+                ("builtin", ColType::BooleanWithDefault(false)),
+            ],
+            &[],
+        )
+        .await?;
+        create_join_table(
+            m,
+            "role_permissions",
+            &[],
+            &[("role", ""), ("permission", "")],
+        )
+        .await?;
+
+        for (name, description) in PERMISSIONS {
+            m.exec_stmt(
+                Query::insert()
+                    .into_table(Alias::new("permissions"))
+                    .columns([Alias::new("name"), Alias::new("description")])
+                    .values_panic([name.into(), description.into()])
+                    .to_owned(),
+            )
+            .await?;
+        }
+        for (name, description, permissions) in ROLES {
+            m.exec_stmt(
+                Query::insert()
+                    .into_table(Alias::new("roles"))
+                    .columns([
+                        Alias::new("pid"),
+                        Alias::new("name"),
+                        Alias::new("description"),
+                        Alias::new("builtin"),
+                    ])
+                    .values_panic([
+                        sea_orm_migration::sea_orm::prelude::Uuid::new_v4().into(),
+                        name.into(),
+                        description.into(),
+                        true.into(),
+                    ])
+                    .to_owned(),
+            )
+            .await?;
+            m.exec_stmt(
+                Query::insert()
+                    .into_table(Alias::new("role_permissions"))
+                    .columns([Alias::new("role_id"), Alias::new("permission_id")])
+                    .select_from(
+                        Query::select()
+                            .column((Alias::new("roles"), Alias::new("id")))
+                            .column((Alias::new("permissions"), Alias::new("id")))
+                            .from(Alias::new("roles"))
+                            .from(Alias::new("permissions"))
+                            .and_where(
+                                Expr::col((Alias::new("roles"), Alias::new("name"))).eq(name),
+                            )
+                            .and_where(
+                                Expr::col((Alias::new("permissions"), Alias::new("name")))
+                                    .is_in(permissions.iter().copied()),
+                            )
+                            .to_owned(),
+                    )
+                    .map_err(|err| DbErr::Migration(err.to_string()))?
+                    .to_owned(),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        drop_table(m, "role_permissions").await?;
+        drop_table(m, "roles").await?;
+        drop_table(m, "permissions").await?;
+        Ok(())
+    }
+}
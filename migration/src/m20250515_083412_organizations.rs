@@ -0,0 +1,88 @@
+use loco_rs::schema::*;
+use sea_orm_migration::prelude::*;
+
+use crate::m20240323_000001_teams::Teams;
+
+#[derive(DeriveIden)]
+enum TeamsOrganization {
+    OrganizationId,
+}
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        create_table(
+            m,
+            "organizations",
+            &[
+                ("id", ColType::PkAuto),
+                ("pid", ColType::Uuid),
+                ("name", ColType::StringUniq),
+                ("description", ColType::StringNull),
+            ],
+            &[],
+        )
+        .await?;
+        // Members of an organization have their role, one of the roles of
+        // team members, in every team of the organization
+        create_table(
+            m,
+            "organization_memberships",
+            &[
+                ("id", ColType::PkAuto),
+                ("pid", ColType::Uuid),
+                ("role", ColType::String),
+            ],
+            &[("organization", ""), ("user", "")],
+        )
+        .await?;
+        // Teams outside of any organization keep a null organization
+        add_column(m, "teams", "organization_id", ColType::IntegerNull).await?;
+
+        // Team names are unique within an organization only
+        m.drop_index(
+            Index::drop()
+                .name("idx-teams-name-unique")
+                .table(Teams::Table)
+                .to_owned(),
+        )
+        .await?;
+        m.create_index(
+            Index::create()
+                .unique()
+                .name("idx-teams-organization-name-unique")
+                .table(Teams::Table)
+                .col(TeamsOrganization::OrganizationId)
+                .col(Teams::Name)
+                .to_owned(),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn down(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        m.drop_index(
+            Index::drop()
+                .name("idx-teams-organization-name-unique")
+                .table(Teams::Table)
+                .to_owned(),
+        )
+        .await?;
+        m.create_index(
+            Index::create()
+                .unique()
+                .name("idx-teams-name-unique")
+                .table(Teams::Table)
+                .col(Teams::Name)
+                .to_owned(),
+        )
+        .await?;
+        remove_column(m, "teams", "organization_id").await?;
+        drop_table(m, "organization_memberships").await?;
+        drop_table(m, "organizations").await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,161 @@
+use loco_rs::schema::*;
+use sea_orm_migration::{prelude::*, sea_orm::DatabaseBackend};
+
+use crate::m20220101_000001_users::Users;
+use crate::m20240323_000001_teams::Teams;
+
+#[derive(Clone, Copy, DeriveIden)]
+enum TeamMemberships {
+    Table,
+    Id,
+    Pid,
+    TeamId,
+    UserId,
+    Role,
+    Pending,
+    InvitationToken,
+    InvitationSentAt,
+    InvitationExpiresAt,
+    CreatedAt,
+    UpdatedAt,
+}
+
+/// Name of the table built while `SQLite` rebuilds `team_memberships`
+const REBUILT_TABLE: &str = "team_memberships_rebuilt";
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Returns the definition of the `team_memberships` table under the given
+/// name, with a nullable `user_id` column when `user_id_null` is true
+fn memberships_table(name: DynIden, user_id_null: bool) -> TableCreateStatement {
+    let user_id = if user_id_null {
+        integer_null(TeamMemberships::UserId)
+    } else {
+        integer(TeamMemberships::UserId)
+    };
+    table_auto_tz(name.clone())
+        .col(pk_auto(TeamMemberships::Id))
+        .col(uuid(TeamMemberships::Pid))
+        .col(integer(TeamMemberships::TeamId).not_null())
+        .col(user_id)
+        .col(string(TeamMemberships::Role))
+        .col(boolean(TeamMemberships::Pending).default(true))
+        .col(string_null(TeamMemberships::InvitationToken))
+        .col(timestamp_with_time_zone_null(
+            TeamMemberships::InvitationSentAt,
+        ))
+        .col(timestamp_with_time_zone_null(
+            TeamMemberships::InvitationExpiresAt,
+        ))
+        .foreign_key(
+            ForeignKey::create()
+                .name("fk_team_memberships_team_id")
+                .from(name.clone(), TeamMemberships::TeamId)
+                .to(Teams::Table, Teams::Id)
+                .on_delete(ForeignKeyAction::Cascade),
+        )
+        .foreign_key(
+            ForeignKey::create()
+                .name("fk_team_memberships_user_id")
+                .from(name, TeamMemberships::UserId)
+                .to(Users::Table, Users::Id)
+                .on_delete(ForeignKeyAction::Cascade),
+        )
+        .to_owned()
+}
+
+/// Changes whether `team_memberships.user_id` accepts nulls. `SQLite` cannot
+/// alter a column, so the table is rebuilt and its rows copied over.
+async fn set_user_id_null(m: &SchemaManager<'_>, user_id_null: bool) -> Result<(), DbErr> {
+    if m.get_database_backend() != DatabaseBackend::Sqlite {
+        let user_id = if user_id_null {
+            integer_null(TeamMemberships::UserId)
+        } else {
+            integer(TeamMemberships::UserId)
+        };
+        return m
+            .alter_table(
+                Table::alter()
+                    .table(TeamMemberships::Table)
+                    .modify_column(user_id)
+                    .to_owned(),
+            )
+            .await;
+    }
+
+    let columns = [
+        TeamMemberships::Id,
+        TeamMemberships::Pid,
+        TeamMemberships::TeamId,
+        TeamMemberships::UserId,
+        TeamMemberships::Role,
+        TeamMemberships::Pending,
+        TeamMemberships::InvitationToken,
+        TeamMemberships::InvitationSentAt,
+        TeamMemberships::InvitationExpiresAt,
+        TeamMemberships::CreatedAt,
+        TeamMemberships::UpdatedAt,
+    ];
+    m.create_table(memberships_table(
+        Alias::new(REBUILT_TABLE).into_iden(),
+        user_id_null,
+    ))
+    .await?;
+    m.exec_stmt(
+        Query::insert()
+            .into_table(Alias::new(REBUILT_TABLE))
+            .columns(columns)
+            .select_from(
+                Query::select()
+                    .columns(columns)
+                    .from(TeamMemberships::Table)
+                    .to_owned(),
+            )
+            .map_err(|e| DbErr::Custom(e.to_string()))?
+            .to_owned(),
+    )
+    .await?;
+    m.drop_table(Table::drop().table(TeamMemberships::Table).to_owned())
+        .await?;
+    m.rename_table(
+        Table::rename()
+            .table(Alias::new(REBUILT_TABLE), TeamMemberships::Table)
+            .to_owned(),
+    )
+    .await?;
+    m.create_index(
+        Index::create()
+            .name("idx_unique_team_user")
+            .table(TeamMemberships::Table)
+            .col(TeamMemberships::TeamId)
+            .col(TeamMemberships::UserId)
+            .unique()
+            .to_owned(),
+    )
+    .await
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        // Invitations sent to an email address nobody registered yet are
+        // bound to a user only once the address is verified
+        set_user_id_null(m, true).await?;
+        add_column(m, "team_memberships", "invited_email", ColType::StringNull).await?;
+        Ok(())
+    }
+
+    async fn down(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        m.exec_stmt(
+            Query::delete()
+                .from_table(TeamMemberships::Table)
+                .and_where(Expr::col(TeamMemberships::UserId).is_null())
+                .to_owned(),
+        )
+        .await?;
+        remove_column(m, "team_memberships", "invited_email").await?;
+        set_user_id_null(m, false).await?;
+        Ok(())
+    }
+}
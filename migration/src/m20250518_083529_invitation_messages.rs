@@ -0,0 +1,26 @@
+use loco_rs::schema::*;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        // Message written by the member sending an invitation, sent again
+        // with the invitation when it is resent
+        add_column(
+            m,
+            "team_memberships",
+            "invitation_message",
+            ColType::TextNull,
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn down(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        remove_column(m, "team_memberships", "invitation_message").await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,35 @@
+use loco_rs::schema::*;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        // A link any logged-in user can open to join a team, until it expires,
+        // runs out of uses or is revoked. Only a hash of its token is stored.
+        create_table(
+            m,
+            "team_join_links",
+            &[
+                ("id", ColType::PkAuto),
+                ("pid", ColType::Uuid),
+                ("token_hash", ColType::StringUniq),
+                ("role", ColType::String),
+                ("max_uses", ColType::IntegerNull),
+                ("use_count", ColType::Integer),
+                ("expires_at", ColType::TimestampWithTimeZoneNull),
+                ("allowed_domain", ColType::StringNull),
+            ],
+            &[("team", ""), ("user?", "created_by_id")],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn down(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        drop_table(m, "team_join_links").await?;
+        Ok(())
+    }
+}
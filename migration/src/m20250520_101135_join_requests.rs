@@ -0,0 +1,29 @@
+use loco_rs::schema::*;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        // Teams listed in the team directory, where users request to join
+        add_column(m, "teams", "listed", ColType::BooleanWithDefault(false)).await?;
+        // A pending membership asked for by the user rather than an invitation,
+        // waiting for a team administrator to approve or deny it
+        add_column(
+            m,
+            "team_memberships",
+            "requested",
+            ColType::BooleanWithDefault(false),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn down(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        remove_column(m, "team_memberships", "requested").await?;
+        remove_column(m, "teams", "listed").await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,41 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20240323_000001_teams::Teams;
+
+#[derive(DeriveIden)]
+enum TeamsOrganization {
+    OrganizationId,
+}
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        // The unique index on (organization_id, name) does not apply to
+        // teams outside of any organization, whose organization_id is null
+        m.create_index(
+            Index::create()
+                .unique()
+                .name("idx-teams-name-unique-without-organization")
+                .table(Teams::Table)
+                .col(Teams::Name)
+                .and_where(Expr::col(TeamsOrganization::OrganizationId).is_null())
+                .to_owned(),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn down(&self, m: &SchemaManager) -> Result<(), DbErr> {
+        m.drop_index(
+            Index::drop()
+                .name("idx-teams-name-unique-without-organization")
+                .table(Teams::Table)
+                .to_owned(),
+        )
+        .await?;
+        Ok(())
+    }
+}
@@ -16,11 +16,15 @@ use std::path::Path;
 use uuid;
 
 use crate::{
-    controllers,
-    initializers,
-    models::_entities::{ssh_keys, team_memberships, teams, users},
-    //tasks,
-    workers::downloader::DownloadWorker,
+    controllers, initializers, models,
+    models::_entities::{
+        email_failures, impersonation_events, login_attempts, oidc_identities,
+        organization_memberships, organizations, ownership_transfers, personal_access_tokens,
+        recovery_codes, service_accounts, sessions, ssh_keys, team_join_links, team_memberships,
+        teams, users, webauthn_challenges, webauthn_credentials,
+    },
+    tasks,
+    workers::{downloader::DownloadWorker, email_delivery::EmailDeliveryWorker},
 };
 
 pub struct App;
@@ -54,6 +58,8 @@ impl Hooks for App {
             .add_route(controllers::auth_api::routes())
             .add_route(controllers::auth_pages::routes())
             .add_route(controllers::home_pages::routes())
+            .add_route(controllers::oidc_pages::routes())
+            .add_route(controllers::organizations_pages::routes())
             .add_route(controllers::pgp_pages::routes())
             .add_route(controllers::ssh_key_api::routes())
             .add_route(controllers::teams_api::routes())
@@ -62,25 +68,45 @@ impl Hooks for App {
     }
 
     async fn initializers(_ctx: &AppContext) -> Result<Vec<Box<dyn Initializer>>> {
-        Ok(vec![Box::new(
-            initializers::view_engine::ViewEngineInitializer,
-        )])
+        Ok(vec![
+            Box::new(initializers::view_engine::ViewEngineInitializer),
+            Box::new(initializers::oidc::OidcInitializer),
+        ])
     }
 
-    fn register_tasks(_tasks: &mut Tasks) {
+    fn register_tasks(tasks: &mut Tasks) {
+        tasks.register(tasks::purge_expired_invitations::PurgeExpiredInvitations);
+        tasks.register(tasks::purge_expired_sessions::PurgeExpiredSessions);
         // tasks-inject (do not remove)
     }
 
     async fn connect_workers(ctx: &AppContext, queue: &Queue) -> Result<()> {
         queue.register(DownloadWorker::build(ctx)).await?;
+        queue.register(EmailDeliveryWorker::build(ctx)).await?;
         Ok(())
     }
 
     async fn truncate(ctx: &AppContext) -> Result<()> {
+        truncate_table(&ctx.db, email_failures::Entity).await?;
+        truncate_table(&ctx.db, impersonation_events::Entity).await?;
+        truncate_table(&ctx.db, login_attempts::Entity).await?;
+        truncate_table(&ctx.db, oidc_identities::Entity).await?;
+        truncate_table(&ctx.db, ownership_transfers::Entity).await?;
+        truncate_table(&ctx.db, personal_access_tokens::Entity).await?;
+        truncate_table(&ctx.db, webauthn_challenges::Entity).await?;
+        truncate_table(&ctx.db, webauthn_credentials::Entity).await?;
+        truncate_table(&ctx.db, recovery_codes::Entity).await?;
+        truncate_table(&ctx.db, service_accounts::Entity).await?;
+        truncate_table(&ctx.db, sessions::Entity).await?;
+        truncate_table(&ctx.db, team_join_links::Entity).await?;
         truncate_table(&ctx.db, team_memberships::Entity).await?;
         truncate_table(&ctx.db, teams::Entity).await?;
+        truncate_table(&ctx.db, organization_memberships::Entity).await?;
+        truncate_table(&ctx.db, organizations::Entity).await?;
         truncate_table(&ctx.db, users::Entity).await?;
         truncate_table(&ctx.db, ssh_keys::Entity).await?;
+        // The permission catalogue and built-in roles come from migrations
+        models::roles::Model::delete_custom_roles(&ctx.db).await?;
         Ok(())
     }
 
@@ -104,6 +130,16 @@ impl Hooks for App {
             pgp_key: Default::default(),
             pgp_verification_token: ActiveValue::NotSet,
             pgp_verified_at: ActiveValue::NotSet,
+            totp_secret: ActiveValue::NotSet,
+            totp_enabled_at: ActiveValue::NotSet,
+            failed_login_count: ActiveValue::NotSet,
+            last_failed_login_at: ActiveValue::NotSet,
+            locked_until: ActiveValue::NotSet,
+            reset_expires_at: ActiveValue::NotSet,
+            email_verification_expires_at: ActiveValue::NotSet,
+            pgp_verification_expires_at: ActiveValue::NotSet,
+            status: ActiveValue::NotSet,
+            totp_last_step: ActiveValue::NotSet,
         };
         user.insert(&ctx.db).await?;
         Ok(())
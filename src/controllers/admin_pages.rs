@@ -1,21 +1,55 @@
 use crate::{
     mailers::auth::AuthMailer,
     middleware::auth_no_error::JWTWithUserOpt,
-    models::{_entities::users, users::UpdateDetailsParams},
+    models::{
+        _entities::{teams, users},
+        email_failures, impersonation_events, permissions,
+        roles::{self, OWNER_ROLE, RoleParams},
+        sessions, statistics,
+        team_memberships::{self, UpdateRoleParams},
+        teams::UpdateTeamParams,
+        tokens::TokenLifetimes,
+        user_imports::{self, DataFormat},
+        users::UpdateDetailsParams,
+    },
     views::{error_fragment, error_page, redirect, render_template},
 };
 use axum::{
     debug_handler,
     extract::{Form, Path, Query, State},
-    http::HeaderMap,
+    http::{HeaderMap, HeaderValue, header},
     response::{Html, IntoResponse, Response},
-    routing::{get, post},
+    routing::{delete, get, post},
 };
 use loco_rs::{app::AppContext, prelude::*};
 use sea_orm::{EntityTrait, PaginatorTrait, QueryOrder};
 use serde::Deserialize;
+use std::collections::HashMap;
 use tracing::error;
 
+/// Params for adding a user to a team from the admin pages
+#[derive(Deserialize, Debug)]
+pub struct AddTeamMemberParams {
+    pub user_name: String,
+    pub role: String,
+}
+
+/// Params for importing users from the admin pages
+#[derive(Deserialize, Debug)]
+pub struct ImportUsersParams {
+    pub format: DataFormat,
+    pub data: String,
+    /// Only check the records and show what the import would do
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Query params of the user export
+#[derive(Deserialize, Debug)]
+pub struct ExportUsersParams {
+    pub format: DataFormat,
+}
+
 /// Struct for pagination query parameters
 #[derive(Deserialize, Debug)]
 pub struct PaginationParams {
@@ -25,6 +59,13 @@ pub struct PaginationParams {
     page_size: u64,
 }
 
+/// Number of registrations and email failures listed on the dashboard
+const DASHBOARD_LIST_SIZE: u64 = 10;
+/// Period, in days, over which the email delivery failures are counted
+const EMAIL_FAILURES_DAYS: i64 = 7;
+/// Number of impersonation events listed on the user management page
+const IMPERSONATION_EVENTS_SHOWN: u64 = 20;
+
 fn default_page() -> u64 {
     1
 }
@@ -33,6 +74,97 @@ fn default_page_size() -> u64 {
     12
 }
 
+/// Handler for the admin dashboard page. Its panels are loaded and refreshed
+/// by HTMX.
+#[debug_handler]
+async fn dashboard_page(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+
+    if !layout_context.is_app_admin {
+        return error_page(&v, "Admin check failed.", None);
+    }
+
+    render_template(
+        &v,
+        "admin/dashboard.html",
+        data!({
+            "user": &user,
+            "invitation_count": &layout_context.invitation_count,
+            "pending_user_count": &layout_context.pending_user_count,
+            "is_app_admin": &layout_context.is_app_admin,
+            "active_page": "admin_dashboard",
+        }),
+    )
+}
+
+/// Handler for the HTMX fragments of the dashboard panels.
+#[debug_handler]
+async fn get_dashboard_panel(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Path(panel): Path<String>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+
+    if !layout_context.is_app_admin {
+        return redirect("/auth/login", headers);
+    }
+
+    match panel.as_str() {
+        "users" => render_template(
+            &v,
+            "admin/_dashboard_users.html",
+            data!({ "stats": statistics::user_statistics(&ctx.db).await? }),
+        ),
+        "teams" => render_template(
+            &v,
+            "admin/_dashboard_teams.html",
+            data!({ "stats": statistics::team_statistics(&ctx.db).await? }),
+        ),
+        "ssh_keys" => render_template(
+            &v,
+            "admin/_dashboard_ssh_keys.html",
+            data!({ "stats": statistics::ssh_key_statistics(&ctx.db).await? }),
+        ),
+        "registrations" => render_template(
+            &v,
+            "admin/_dashboard_registrations.html",
+            data!({
+                "users": statistics::recent_registrations(&ctx.db, DASHBOARD_LIST_SIZE).await?,
+            }),
+        ),
+        "email_failures" => render_template(
+            &v,
+            "admin/_dashboard_email_failures.html",
+            data!({
+                "count": email_failures::Model::count_since_days(&ctx.db, EMAIL_FAILURES_DAYS).await?,
+                "days": EMAIL_FAILURES_DAYS,
+                "failures": email_failures::Model::find_recent(&ctx.db, DASHBOARD_LIST_SIZE).await?,
+            }),
+        ),
+        _ => not_found(),
+    }
+}
+
 /// Handler for the main user management page.
 #[debug_handler]
 async fn manage_users_page(
@@ -121,6 +253,11 @@ async fn get_user_list_fragment(
 
     let num_pages = paginator.num_pages().await?;
     let users = paginator.fetch_page(page - 1).await?;
+    let locked_pids = users
+        .iter()
+        .filter(|user| user.is_locked())
+        .map(|user| user.pid.to_string())
+        .collect::<Vec<_>>();
 
     // Calculate pagination URLs
     let base_url = "/admin/users/fragment";
@@ -153,6 +290,7 @@ async fn get_user_list_fragment(
         "admin/_user_list.html",
         data!({
             "users": &users,
+            "locked_pids": &locked_pids,
             "current_page": page,
             "total_pages": num_pages,
             "page_size": page_size,
@@ -196,6 +334,7 @@ async fn get_user_edit_form(
                 "admin/_user_edit_form.html",
                 data!({
                     "user": &user,
+                    "is_locked": user.is_locked(),
                     "update_url": &update_url,
                     "cancel_url": &cancel_url
                 }),
@@ -242,6 +381,7 @@ async fn get_user_row_view(
                 "admin/_user_row_view.html",
                 data!({
                     "user": &user,
+                    "is_locked": user.is_locked(),
                     "edit_url": &edit_url,
                     "reset_password_url": &reset_password_url
                 }),
@@ -299,15 +439,27 @@ async fn update_user_details_admin(
             let mut final_user_state = updated_user.clone();
 
             if email_changed {
+                // The account now points to another mailbox: sign out every session
+                if let Err(e) =
+                    sessions::Model::revoke_all_for_user(&ctx.db, updated_user.id, None).await
+                {
+                    tracing::error!(user_pid = updated_user.pid.to_string(), error = ?e, "Admin Update: Failed to revoke user sessions");
+                }
                 let user_clone_for_token = updated_user.clone();
                 let user_with_token_result = users::ActiveModel::from(user_clone_for_token)
-                    .generate_email_verification_token(&ctx.db)
+                    .generate_email_verification_token(
+                        &ctx.db,
+                        &TokenLifetimes::from_context(&ctx)?,
+                    )
                     .await;
 
                 match user_with_token_result {
-                    Ok(user_with_token) => {
+                    Ok((user_with_token, verification_token)) => {
                         let user_with_token_clone = user_with_token.clone();
-                        if let Err(e) = AuthMailer::send_welcome(&ctx, &user_with_token).await {
+                        if let Err(e) =
+                            AuthMailer::send_welcome(&ctx, &user_with_token, &verification_token)
+                                .await
+                        {
                             tracing::error!(user_pid = user_with_token.pid.to_string(), error = ?e, "Admin Update: Failed to send verification email");
                         } else if let Err(e) = users::ActiveModel::from(user_with_token_clone)
                             .set_email_verification_sent(&ctx.db)
@@ -331,6 +483,7 @@ async fn update_user_details_admin(
                 "admin/_user_row_view.html",
                 data!({
                    "user": &final_user_state,
+                   "is_locked": final_user_state.is_locked(),
                    "edit_url": &edit_url,
                    "reset_password_url": &reset_password_url
                 }),
@@ -346,6 +499,7 @@ async fn update_user_details_admin(
                 "admin/_user_edit_form.html",
                 data!({
                     "user": &target_user,
+                    "is_locked": target_user.is_locked(),
                     "error_message": &error_message,
                     "update_url": &update_url,
                     "cancel_url": &cancel_url
@@ -384,8 +538,11 @@ async fn trigger_password_reset_admin(
         }
     };
 
-    let user_with_token = match target_user.initiate_password_reset(&ctx.db).await {
-        Ok(u) => u,
+    let (user_with_token, reset_token) = match target_user
+        .initiate_password_reset(&ctx.db, &TokenLifetimes::from_context(&ctx)?)
+        .await
+    {
+        Ok(result) => result,
         Err(e) => {
             error!(user_email = %target_user.email, error = ?e, "Admin Reset PW: Failed to initiate password reset (token generation/save)");
             return error_fragment(
@@ -396,7 +553,11 @@ async fn trigger_password_reset_admin(
         }
     };
 
-    if let Err(e) = AuthMailer::forgot_password(&ctx, &user_with_token).await {
+    if let Err(e) = sessions::Model::revoke_all_for_user(&ctx.db, target_user.id, None).await {
+        error!(user_email = %target_user.email, error = ?e, "Admin Reset PW: Failed to revoke user sessions");
+    }
+
+    if let Err(e) = AuthMailer::forgot_password(&ctx, &user_with_token, &reset_token).await {
         error!(user_email = %target_user.email, error = ?e, "Admin Reset PW: Failed to send forgot password email");
         error_fragment(
             &v,
@@ -423,17 +584,1645 @@ async fn trigger_password_reset_admin(
     }
 }
 
-/// Admin routes
-pub fn routes() -> Routes {
-    Routes::new()
-        .prefix("/admin")
-        .add("/users", get(manage_users_page))
-        .add("/users/fragment", get(get_user_list_fragment))
-        .add("/users/{user_pid}/edit", get(get_user_edit_form))
-        .add("/users/{user_pid}", post(update_user_details_admin))
-        .add("/users/{user_pid}", get(get_user_row_view))
-        .add(
-            "/users/{user_pid}/reset-password",
-            post(trigger_password_reset_admin),
+/// Handler to revoke every session of a user, signing them out on all devices.
+#[debug_handler]
+async fn revoke_user_sessions_admin(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Path(user_pid): Path<String>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+
+    if !layout_context.is_app_admin {
+        return redirect("/auth/login", headers);
+    }
+
+    let target_user = match users::Model::find_by_pid(&ctx.db, &user_pid).await {
+        Ok(user) => user,
+        Err(e) => {
+            tracing::error!(error = ?e, user_pid = user_pid, "Admin Sign Out: Failed to find user");
+            return error_fragment(&v, "User not found", "#admin-user-messages");
+        }
+    };
+
+    match sessions::Model::revoke_all_for_user(&ctx.db, target_user.id, None).await {
+        Ok(count) => {
+            tracing::info!(admin_user_pid=%user.pid, target_user_pid=%target_user.pid, revoked = count, "User sessions revoked by admin.");
+            format::render().view(
+                &v,
+                "fragments/success_message.html",
+                data!({
+                    "message": format!("{} signed out of {} session(s).", target_user.email, count)
+                }),
+            )
+        }
+        Err(e) => {
+            error!(user_email = %target_user.email, error = ?e, "Admin Sign Out: Failed to revoke user sessions");
+            error_fragment(
+                &v,
+                "Failed to revoke user sessions.",
+                "#admin-user-messages",
+            )
+        }
+    }
+}
+
+/// Handler to lift the lockout of an account locked after too many failed
+/// logins.
+#[debug_handler]
+async fn unlock_user_admin(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Path(user_pid): Path<String>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+
+    if !layout_context.is_app_admin {
+        return redirect("/auth/login", headers);
+    }
+
+    let target_user = match users::Model::find_by_pid(&ctx.db, &user_pid).await {
+        Ok(user) => user,
+        Err(e) => {
+            tracing::error!(error = ?e, user_pid = user_pid, "Admin Unlock: Failed to find user");
+            return error_fragment(&v, "User not found", "#admin-user-messages");
+        }
+    };
+
+    match target_user
+        .into_active_model()
+        .clear_failed_logins(&ctx.db)
+        .await
+    {
+        Ok(unlocked_user) => {
+            tracing::info!(admin_user_pid=%user.pid, target_user_pid=%unlocked_user.pid, "User account unlocked by admin.");
+            let edit_url = format!("/admin/users/{}/edit", unlocked_user.pid);
+            let reset_password_url = format!("/admin/users/{}/reset-password", unlocked_user.pid);
+            format::render().view(
+                &v,
+                "admin/_user_row_view.html",
+                data!({
+                    "user": &unlocked_user,
+                    "is_locked": false,
+                    "edit_url": &edit_url,
+                    "reset_password_url": &reset_password_url
+                }),
+            )
+        }
+        Err(e) => {
+            error!(user_pid = %user_pid, error = ?e, "Admin Unlock: Failed to unlock user");
+            error_fragment(&v, "Failed to unlock user.", "#admin-user-messages")
+        }
+    }
+}
+
+/// Renders the list of accounts waiting for an approval, with an optional
+/// message about the last action
+async fn render_pending_users(
+    v: &TeraView,
+    ctx: &AppContext,
+    message: Option<String>,
+) -> Result<Response> {
+    let pending_users = users::Model::find_pending_approval(&ctx.db).await?;
+    format::render().view(
+        v,
+        "admin/_pending_users.html",
+        data!({
+            "pending_users": &pending_users,
+            "message": &message,
+        }),
+    )
+}
+
+/// Handler for the HTMX fragment listing the accounts waiting for an approval.
+#[debug_handler]
+async fn get_pending_users_fragment(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+
+    if !layout_context.is_app_admin {
+        return redirect("/auth/login", headers);
+    }
+
+    render_pending_users(&v, &ctx, None).await
+}
+
+/// Handler to approve an account waiting for an approval, and tell its owner.
+#[debug_handler]
+async fn approve_user_admin(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Path(user_pid): Path<String>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+
+    if !layout_context.is_app_admin {
+        return redirect("/auth/login", headers);
+    }
+
+    let target_user = match users::Model::find_by_pid(&ctx.db, &user_pid).await {
+        Ok(user) => user,
+        Err(e) => {
+            tracing::error!(error = ?e, user_pid = user_pid, "Admin Approve: Failed to find user");
+            return error_fragment(&v, "User not found", "#admin-user-messages");
+        }
+    };
+
+    let approved_user = match target_user.approve(&ctx.db).await {
+        Ok(user) => user,
+        Err(e) => {
+            error!(user_pid = %user_pid, error = ?e, "Admin Approve: Failed to approve user");
+            return error_fragment(&v, "Failed to approve user.", "#admin-user-messages");
+        }
+    };
+    tracing::info!(admin_user_pid=%user.pid, target_user_pid=%approved_user.pid, "User account approved by admin.");
+
+    let message = match AuthMailer::account_approved(&ctx, &approved_user).await {
+        Ok(()) => format!("{} approved and notified by email.", approved_user.email),
+        Err(e) => {
+            error!(user_email = %approved_user.email, error = ?e, "Admin Approve: Failed to send account approved email");
+            format!(
+                "{} approved, but the notification email could not be sent.",
+                approved_user.email
+            )
+        }
+    };
+
+    let mut response = render_pending_users(&v, &ctx, Some(message)).await?;
+    response
+        .headers_mut()
+        .insert("HX-Trigger", HeaderValue::from_static("refreshUserList"));
+    Ok(response)
+}
+
+/// Handler to reject an account waiting for an approval: the account is
+/// deleted and its owner told by email.
+#[debug_handler]
+async fn reject_user_admin(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Path(user_pid): Path<String>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+
+    if !layout_context.is_app_admin {
+        return redirect("/auth/login", headers);
+    }
+
+    let target_user = match users::Model::find_by_pid(&ctx.db, &user_pid).await {
+        Ok(user) => user,
+        Err(e) => {
+            tracing::error!(error = ?e, user_pid = user_pid, "Admin Reject: Failed to find user");
+            return error_fragment(&v, "User not found", "#admin-user-messages");
+        }
+    };
+
+    if let Err(e) = target_user.clone().reject(&ctx.db).await {
+        error!(user_pid = %user_pid, error = ?e, "Admin Reject: Failed to reject user");
+        return error_fragment(&v, "Failed to reject user.", "#admin-user-messages");
+    }
+    tracing::info!(admin_user_pid=%user.pid, target_user_pid=%target_user.pid, "User account rejected by admin.");
+
+    let message = match AuthMailer::account_rejected(&ctx, &target_user).await {
+        Ok(()) => format!("{} rejected and notified by email.", target_user.email),
+        Err(e) => {
+            error!(user_email = %target_user.email, error = ?e, "Admin Reject: Failed to send account rejected email");
+            format!(
+                "{} rejected, but the notification email could not be sent.",
+                target_user.email
+            )
+        }
+    };
+
+    let mut response = render_pending_users(&v, &ctx, Some(message)).await?;
+    response
+        .headers_mut()
+        .insert("HX-Trigger", HeaderValue::from_static("refreshUserList"));
+    Ok(response)
+}
+
+/// Renders the read-only row of a user in the user list
+fn render_user_row(v: &TeraView, user: &users::Model) -> Result<Response> {
+    let edit_url = format!("/admin/users/{}/edit", user.pid);
+    let reset_password_url = format!("/admin/users/{}/reset-password", user.pid);
+    format::render().view(
+        v,
+        "admin/_user_row_view.html",
+        data!({
+            "user": user,
+            "is_locked": user.is_locked(),
+            "edit_url": &edit_url,
+            "reset_password_url": &reset_password_url
+        }),
+    )
+}
+
+/// Handler to suspend an account: it is signed out, can no longer log in nor
+/// use the API, and its owner is told by email.
+#[debug_handler]
+async fn suspend_user_admin(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Path(user_pid): Path<String>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+
+    if !layout_context.is_app_admin {
+        return redirect("/auth/login", headers);
+    }
+
+    let target_user = match users::Model::find_by_pid(&ctx.db, &user_pid).await {
+        Ok(user) => user,
+        Err(e) => {
+            tracing::error!(error = ?e, user_pid = user_pid, "Admin Suspend: Failed to find user");
+            return error_fragment(&v, "User not found", "#admin-user-messages");
+        }
+    };
+    if target_user.id == user.id {
+        return error_fragment(
+            &v,
+            "You cannot suspend your own account.",
+            "#admin-user-messages",
+        );
+    }
+
+    let suspended_user = match target_user.suspend(&ctx.db).await {
+        Ok(user) => user,
+        Err(ModelError::Message(msg)) => {
+            return error_fragment(&v, &msg, "#admin-user-messages");
+        }
+        Err(e) => {
+            error!(user_pid = %user_pid, error = ?e, "Admin Suspend: Failed to suspend user");
+            return error_fragment(&v, "Failed to suspend user.", "#admin-user-messages");
+        }
+    };
+    tracing::info!(admin_user_pid=%user.pid, target_user_pid=%suspended_user.pid, "User account suspended by admin.");
+
+    if let Err(e) = AuthMailer::account_suspended(&ctx, &suspended_user).await {
+        error!(user_email = %suspended_user.email, error = ?e, "Admin Suspend: Failed to send account suspended email");
+    }
+    render_user_row(&v, &suspended_user)
+}
+
+/// Handler to reactivate a suspended account, and tell its owner.
+#[debug_handler]
+async fn reactivate_user_admin(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Path(user_pid): Path<String>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+
+    if !layout_context.is_app_admin {
+        return redirect("/auth/login", headers);
+    }
+
+    let target_user = match users::Model::find_by_pid(&ctx.db, &user_pid).await {
+        Ok(user) => user,
+        Err(e) => {
+            tracing::error!(error = ?e, user_pid = user_pid, "Admin Reactivate: Failed to find user");
+            return error_fragment(&v, "User not found", "#admin-user-messages");
+        }
+    };
+
+    let reactivated_user = match target_user.reactivate(&ctx.db).await {
+        Ok(user) => user,
+        Err(ModelError::Message(msg)) => {
+            return error_fragment(&v, &msg, "#admin-user-messages");
+        }
+        Err(e) => {
+            error!(user_pid = %user_pid, error = ?e, "Admin Reactivate: Failed to reactivate user");
+            return error_fragment(&v, "Failed to reactivate user.", "#admin-user-messages");
+        }
+    };
+    tracing::info!(admin_user_pid=%user.pid, target_user_pid=%reactivated_user.pid, "User account reactivated by admin.");
+
+    if let Err(e) = AuthMailer::account_reactivated(&ctx, &reactivated_user).await {
+        error!(user_email = %reactivated_user.email, error = ?e, "Admin Reactivate: Failed to send account reactivated email");
+    }
+    render_user_row(&v, &reactivated_user)
+}
+
+/// Handler for the confirmation form of the deletion of a user, asking for a
+/// new Owner for each team the user is the only Owner of.
+#[debug_handler]
+async fn get_delete_user_form(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Path(user_pid): Path<String>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+
+    if !layout_context.is_app_admin {
+        return redirect("/auth/login", headers);
+    }
+
+    let target_user = match users::Model::find_by_pid(&ctx.db, &user_pid).await {
+        Ok(user) => user,
+        Err(e) => {
+            tracing::error!(error = ?e, user_pid = user_pid, "Admin Delete: Failed to find user");
+            return error_fragment(&v, "User not found", "#admin-user-messages");
+        }
+    };
+    if target_user.id == user.id {
+        return error_fragment(
+            &v,
+            "You cannot delete your own account.",
+            "#admin-user-messages",
+        );
+    }
+
+    let mut sole_owned_teams = Vec::new();
+    for team in target_user.find_sole_owned_teams(&ctx.db).await? {
+        let candidates = team
+            .get_members(&ctx.db)
+            .await?
+            .into_iter()
+            .filter(|(member, _)| member.id != target_user.id && !member.is_service_account())
+            .map(|(member, role)| {
+                serde_json::json!({
+                    "pid": member.pid.to_string(),
+                    "name": member.name,
+                    "role": role,
+                })
+            })
+            .collect::<Vec<_>>();
+        sole_owned_teams.push(serde_json::json!({
+            "pid": team.pid.to_string(),
+            "name": team.name,
+            "candidates": candidates,
+        }));
+    }
+
+    format::render().view(
+        &v,
+        "admin/_user_delete_form.html",
+        data!({
+            "target_user": &target_user,
+            "sole_owned_teams": &sole_owned_teams,
+        }),
+    )
+}
+
+/// Handler to delete a user. The form sends a `new_owner_<team pid>` field
+/// with the pid of the new Owner of each team the user is the only Owner of.
+#[debug_handler]
+async fn delete_user_admin(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Path(user_pid): Path<String>,
+    Form(fields): Form<Vec<(String, String)>>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+
+    if !layout_context.is_app_admin {
+        return redirect("/auth/login", headers);
+    }
+
+    let target_user = match users::Model::find_by_pid(&ctx.db, &user_pid).await {
+        Ok(user) => user,
+        Err(e) => {
+            tracing::error!(error = ?e, user_pid = user_pid, "Admin Delete: Failed to find user");
+            return error_fragment(&v, "User not found", "#user-delete-errors");
+        }
+    };
+    if target_user.id == user.id {
+        return error_fragment(
+            &v,
+            "You cannot delete your own account.",
+            "#user-delete-errors",
+        );
+    }
+
+    let mut new_owners = HashMap::new();
+    for (key, value) in fields {
+        let Some(team_pid) = key.strip_prefix("new_owner_") else {
+            continue;
+        };
+        if value.is_empty() {
+            continue;
+        }
+        let (Ok(team), Ok(new_owner)) = (
+            teams::Model::find_by_pid(&ctx.db, team_pid).await,
+            users::Model::find_by_pid(&ctx.db, &value).await,
+        ) else {
+            return error_fragment(&v, "Team or new owner not found.", "#user-delete-errors");
+        };
+        new_owners.insert(team.id, new_owner.id);
+    }
+
+    if let Err(e) = target_user
+        .clone()
+        .delete_account(&ctx.db, &new_owners)
+        .await
+    {
+        return match e {
+            ModelError::Message(msg) => error_fragment(&v, &msg, "#user-delete-errors"),
+            e => {
+                error!(user_pid = %user_pid, error = ?e, "Admin Delete: Failed to delete user");
+                error_fragment(&v, "Failed to delete user.", "#user-delete-errors")
+            }
+        };
+    }
+    tracing::info!(admin_user_pid=%user.pid, target_user_pid=%target_user.pid, "User account deleted by admin.");
+
+    let message = match AuthMailer::account_deleted(&ctx, &target_user).await {
+        Ok(()) => format!("{} deleted and notified by email.", target_user.email),
+        Err(e) => {
+            error!(user_email = %target_user.email, error = ?e, "Admin Delete: Failed to send account deleted email");
+            format!(
+                "{} deleted, but the notification email could not be sent.",
+                target_user.email
+            )
+        }
+    };
+
+    let mut response = format::render().view(
+        &v,
+        "fragments/success_message.html",
+        data!({ "message": message }),
+    )?;
+    response
+        .headers_mut()
+        .insert("HX-Trigger", HeaderValue::from_static("refreshUserList"));
+    Ok(response)
+}
+
+/// Handler to start impersonating a user: the administrator's session is
+/// replaced by a session of the user, marked as an impersonation, and the
+/// browser is sent to the home page of the user.
+#[debug_handler]
+async fn impersonate_user_admin(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    device: sessions::SessionDevice,
+    Path(user_pid): Path<String>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+
+    if !layout_context.is_app_admin {
+        return redirect("/auth/login", headers);
+    }
+    if auth
+        .claims
+        .as_ref()
+        .and_then(sessions::impersonator_from_claims)
+        .is_some()
+    {
+        return error_fragment(
+            &v,
+            "Stop the current impersonation first.",
+            "#admin-user-messages",
+        );
+    }
+
+    let target_user = match users::Model::find_by_pid(&ctx.db, &user_pid).await {
+        Ok(user) => user,
+        Err(e) => {
+            tracing::error!(error = ?e, user_pid = user_pid, "Admin Impersonate: Failed to find user");
+            return error_fragment(&v, "User not found", "#admin-user-messages");
+        }
+    };
+
+    let jwt_secret = ctx.config.get_jwt_config()?;
+    let (token, session) = match target_user
+        .generate_impersonation_jwt(&ctx.db, &user, &jwt_secret.secret, &device)
+        .await
+    {
+        Ok(result) => result,
+        Err(ModelError::Message(msg)) => {
+            return error_fragment(&v, &msg, "#admin-user-messages");
+        }
+        Err(e) => {
+            error!(user_pid = %user_pid, error = ?e, "Admin Impersonate: Failed to open the session");
+            return error_fragment(&v, "Failed to impersonate user.", "#admin-user-messages");
+        }
+    };
+    impersonation_events::Model::record(
+        &ctx.db,
+        impersonation_events::EVENT_START,
+        user.id,
+        target_user.id,
+        &session.jti,
+        device.ip.as_deref(),
+    )
+    .await?;
+    tracing::info!(admin_user_pid=%user.pid, target_user_pid=%target_user.pid, "Admin started impersonating user.");
+
+    // The administrator gets a new session when the impersonation stops
+    if let Some(jti) = auth.claims.as_ref().and_then(sessions::jti_from_claims)
+        && let Err(e) = sessions::Model::revoke_by_jti(&ctx.db, jti).await
+    {
+        error!(error = ?e, "Admin Impersonate: Failed to revoke the admin session");
+    }
+
+    let response = Response::builder()
+        .header("HX-Redirect", "/home")
+        .header("Set-Cookie", format!("auth_token={token}; Path=/"))
+        .body(axum::body::Body::empty())?;
+    Ok(response)
+}
+
+/// Handler for the banner shown on every page while impersonating a user,
+/// empty otherwise
+#[debug_handler]
+async fn get_impersonation_banner(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+) -> Result<Response> {
+    let (Some(user), Some(admin_pid)) = (
+        auth.user.as_ref(),
+        auth.claims
+            .as_ref()
+            .and_then(sessions::impersonator_from_claims),
+    ) else {
+        return Ok(Html(String::new()).into_response());
+    };
+    let admin = users::Model::find_by_pid(&ctx.db, admin_pid).await.ok();
+
+    format::render().view(
+        &v,
+        "admin/_impersonation_banner.html",
+        data!({
+            "user": user,
+            "admin": &admin,
+        }),
+    )
+}
+
+/// Handler to stop impersonating a user: the impersonation session is
+/// revoked and the administrator gets a session of their own again.
+#[debug_handler]
+async fn stop_impersonation(
+    auth: JWTWithUserOpt<users::Model>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    device: sessions::SessionDevice,
+) -> Result<Response> {
+    let Some(claims) = auth.claims.as_ref() else {
+        return redirect("/auth/login", headers);
+    };
+    let (Some(admin_pid), Some(jti)) = (
+        sessions::impersonator_from_claims(claims),
+        sessions::jti_from_claims(claims),
+    ) else {
+        return redirect("/home", headers);
+    };
+
+    let session = sessions::Model::find_by_jti(&ctx.db, jti).await?;
+    let admin = users::Model::find_by_pid(&ctx.db, admin_pid).await?;
+    if session.impersonator_id != Some(admin.id) {
+        return unauthorized("not an impersonation session");
+    }
+    let target_id = session.user_id;
+    let session_jti = session.jti.clone();
+    session.revoke(&ctx.db).await?;
+
+    impersonation_events::Model::record(
+        &ctx.db,
+        impersonation_events::EVENT_STOP,
+        admin.id,
+        target_id,
+        &session_jti,
+        device.ip.as_deref(),
+    )
+    .await?;
+    tracing::info!(admin_user_pid=%admin.pid, "Admin stopped impersonating user.");
+
+    // The administrator may have lost their rights in the meantime
+    if !admin.is_admin(&ctx.db, &ctx).await.unwrap_or(false) {
+        let response = Response::builder()
+            .header("HX-Redirect", "/auth/login")
+            .header(
+                "Set-Cookie",
+                "auth_token=; Path=/; Expires=Thu, 01 Jan 1970 00:00:00 GMT",
+            )
+            .body(axum::body::Body::empty())?;
+        return Ok(response);
+    }
+    let jwt_secret = ctx.config.get_jwt_config()?;
+    let token = admin
+        .generate_jwt(&ctx.db, &jwt_secret.secret, &jwt_secret.expiration, &device)
+        .await?;
+    let response = Response::builder()
+        .header("HX-Redirect", "/admin/users")
+        .header("Set-Cookie", format!("auth_token={token}; Path=/"))
+        .body(axum::body::Body::empty())?;
+    Ok(response)
+}
+
+/// Handler for the HTMX fragment listing the latest impersonation events.
+#[debug_handler]
+async fn get_impersonation_events_fragment(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+
+    if !layout_context.is_app_admin {
+        return redirect("/auth/login", headers);
+    }
+
+    let events = impersonation_events::Model::find_recent(&ctx.db, IMPERSONATION_EVENTS_SHOWN)
+        .await?
+        .into_iter()
+        .map(|details| {
+            serde_json::json!({
+                "event": details.event.event,
+                "created_at": details.event.created_at,
+                "ip": details.event.ip,
+                "admin": details.admin.map(|admin| admin.email),
+                "target": details.target.map(|target| target.email),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    format::render().view(
+        &v,
+        "admin/_impersonation_events.html",
+        data!({ "events": &events }),
+    )
+}
+
+/// Renders the list of every team with its size and owners
+async fn render_admin_team_list(
+    v: &TeraView,
+    ctx: &AppContext,
+    page: u64,
+    page_size: u64,
+) -> Result<Response> {
+    let paginator = teams::Entity::find()
+        .order_by_asc(teams::Column::Name)
+        .paginate(&ctx.db, page_size);
+    let num_pages = paginator.num_pages().await?;
+
+    let mut team_list = Vec::new();
+    for team in paginator.fetch_page(page - 1).await? {
+        let members = team.get_members(&ctx.db).await?;
+        let owners = members
+            .iter()
+            .filter(|(_, role)| role == OWNER_ROLE)
+            .map(|(member, _)| member.name.clone())
+            .collect::<Vec<_>>();
+        team_list.push(serde_json::json!({
+            "pid": team.pid.to_string(),
+            "name": team.name,
+            "description": team.description,
+            "member_count": members.len(),
+            "owners": owners,
+            "organization": team.organization(&ctx.db).await?.map(|organization| organization.name),
+            "is_system_admin_team": team.is_admin_team(ctx),
+        }));
+    }
+
+    let base_url = "/admin/teams/fragment";
+    let prev_page_url =
+        (page > 1).then(|| format!("{base_url}?page={}&page_size={page_size}", page - 1));
+    let next_page_url =
+        (page < num_pages).then(|| format!("{base_url}?page={}&page_size={page_size}", page + 1));
+
+    format::render().view(
+        v,
+        "admin/_team_list.html",
+        data!({
+            "teams": &team_list,
+            "current_page": page,
+            "total_pages": num_pages,
+            "prev_page_url": &prev_page_url,
+            "next_page_url": &next_page_url,
+            "page_url_base": format!("{base_url}?page="),
+            "page_size_suffix": format!("&page_size={page_size}"),
+        }),
+    )
+}
+
+/// Renders the management panel of a team: its details, members and the
+/// form to add a member, with an optional message about the last action
+async fn render_admin_team_detail(
+    v: &TeraView,
+    ctx: &AppContext,
+    team: &teams::Model,
+    message: Option<String>,
+) -> Result<Response> {
+    let members = team
+        .get_members(&ctx.db)
+        .await?
+        .into_iter()
+        .map(|(member, role)| {
+            serde_json::json!({
+                "pid": member.pid.to_string(),
+                "name": member.name,
+                "email": member.email,
+                "role": role,
+                "is_service_account": member.is_service_account(),
+            })
+        })
+        .collect::<Vec<_>>();
+    let has_owner = members.iter().any(|member| member["role"] == OWNER_ROLE);
+    let roles = roles::Model::names(&ctx.db).await?;
+
+    let mut response = format::render().view(
+        v,
+        "admin/_team_detail.html",
+        data!({
+            "team": team,
+            "members": &members,
+            "has_owner": has_owner,
+            "roles": &roles,
+            "message": &message,
+        }),
+    )?;
+    // Keep the member counts and owners of the team list up to date
+    response
+        .headers_mut()
+        .insert("HX-Trigger", HeaderValue::from_static("refreshTeamList"));
+    Ok(response)
+}
+
+/// Finds a member of the team, with their membership
+async fn find_team_member(
+    ctx: &AppContext,
+    team: &teams::Model,
+    user_pid: &str,
+) -> ModelResult<(users::Model, team_memberships::Model)> {
+    let member = users::Model::find_by_pid(&ctx.db, user_pid).await?;
+    let membership =
+        team_memberships::Model::find_by_team_and_user(&ctx.db, team.id, member.id).await?;
+    Ok((member, membership))
+}
+
+/// Handler for the team management page.
+#[debug_handler]
+async fn manage_teams_page(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Query(pagination): Query<PaginationParams>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+
+    if !layout_context.is_app_admin {
+        return redirect("/auth/login", headers);
+    }
+
+    let page = pagination.page.max(1);
+    let page_size = pagination.page_size.clamp(1, 100);
+
+    render_template(
+        &v,
+        "admin/manage_teams.html",
+        data!({
+            "user": &user,
+            "invitation_count": &layout_context.invitation_count,
+            "pending_user_count": &layout_context.pending_user_count,
+            "is_app_admin": &layout_context.is_app_admin,
+            "active_page": "admin_teams",
+            "team_list_fragment_url": format!("/admin/teams/fragment?page={page}&page_size={page_size}"),
+        }),
+    )
+}
+
+/// Handler for the HTMX team list fragment (table + pagination).
+#[debug_handler]
+async fn get_team_list_fragment(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Query(pagination): Query<PaginationParams>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+
+    if !layout_context.is_app_admin {
+        return redirect("/auth/login", headers);
+    }
+
+    let page = pagination.page.max(1);
+    let page_size = pagination.page_size.clamp(1, 100);
+    render_admin_team_list(&v, &ctx, page, page_size).await
+}
+
+/// Handler for the management panel of a team.
+#[debug_handler]
+async fn get_team_detail_admin(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Path(team_pid): Path<String>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+
+    if !layout_context.is_app_admin {
+        return redirect("/auth/login", headers);
+    }
+
+    let team = match teams::Model::find_by_pid(&ctx.db, &team_pid).await {
+        Ok(team) => team,
+        Err(e) => {
+            tracing::error!(error = ?e, team_pid = team_pid, "Admin Teams: Failed to find team");
+            return error_fragment(&v, "Team not found", "#admin-team-messages");
+        }
+    };
+
+    render_admin_team_detail(&v, &ctx, &team, None).await
+}
+
+/// Handler to rename a team or change its description.
+#[debug_handler]
+async fn update_team_admin(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Path(team_pid): Path<String>,
+    Form(params): Form<UpdateTeamParams>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+
+    if !layout_context.is_app_admin {
+        return redirect("/auth/login", headers);
+    }
+
+    let team = match teams::Model::find_by_pid(&ctx.db, &team_pid).await {
+        Ok(team) => team,
+        Err(e) => {
+            tracing::error!(error = ?e, team_pid = team_pid, "Admin Teams: Failed to find team");
+            return error_fragment(&v, "Team not found", "#admin-team-messages");
+        }
+    };
+
+    let params = UpdateTeamParams {
+        name: params.name.trim().to_string(),
+        description: params
+            .description
+            .map(|description| description.trim().to_string())
+            .filter(|description| !description.is_empty()),
+    };
+    let team = match team.update(&ctx.db, &params).await {
+        Ok(team) => team,
+        Err(ModelError::Message(msg)) => {
+            return error_fragment(&v, &msg, "#admin-team-detail-errors");
+        }
+        Err(ModelError::Validation(_)) => {
+            return error_fragment(
+                &v,
+                "Name must be at least 2 characters long.",
+                "#admin-team-detail-errors",
+            );
+        }
+        Err(e) => {
+            error!(team_pid = %team_pid, error = ?e, "Admin Teams: Failed to update team");
+            return error_fragment(&v, "Failed to update team.", "#admin-team-detail-errors");
+        }
+    };
+    tracing::info!(admin_user_pid=%user.pid, team_pid=%team.pid, "Team updated by admin.");
+    render_admin_team_detail(&v, &ctx, &team, Some("Team updated.".to_string())).await
+}
+
+/// Handler to delete a team, its memberships and service accounts.
+#[debug_handler]
+async fn delete_team_admin(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Path(team_pid): Path<String>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+
+    if !layout_context.is_app_admin {
+        return redirect("/auth/login", headers);
+    }
+
+    let team = match teams::Model::find_by_pid(&ctx.db, &team_pid).await {
+        Ok(team) => team,
+        Err(e) => {
+            tracing::error!(error = ?e, team_pid = team_pid, "Admin Teams: Failed to find team");
+            return error_fragment(&v, "Team not found", "#admin-team-messages");
+        }
+    };
+
+    if team.is_admin_team(&ctx) {
+        return error_fragment(
+            &v,
+            "The administrators team cannot be deleted.",
+            "#admin-team-messages",
+        );
+    }
+    if let Err(e) = teams::Model::delete(&team, &ctx.db).await {
+        error!(team_pid = %team_pid, error = ?e, "Admin Teams: Failed to delete team");
+        return error_fragment(&v, "Failed to delete team.", "#admin-team-messages");
+    }
+    tracing::info!(admin_user_pid=%user.pid, team_pid=%team.pid, "Team deleted by admin.");
+
+    let mut response = format::render().view(
+        &v,
+        "fragments/success_message.html",
+        data!({ "message": format!("Team {} deleted.", team.name) }),
+    )?;
+    response
+        .headers_mut()
+        .insert("HX-Trigger", HeaderValue::from_static("refreshTeamList"));
+    Ok(response)
+}
+
+/// Handler to add a user to a team with a role, without an invitation.
+#[debug_handler]
+async fn add_team_member_admin(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Path(team_pid): Path<String>,
+    Form(params): Form<AddTeamMemberParams>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+
+    if !layout_context.is_app_admin {
+        return redirect("/auth/login", headers);
+    }
+
+    let team = match teams::Model::find_by_pid(&ctx.db, &team_pid).await {
+        Ok(team) => team,
+        Err(e) => {
+            tracing::error!(error = ?e, team_pid = team_pid, "Admin Teams: Failed to find team");
+            return error_fragment(&v, "Team not found", "#admin-team-messages");
+        }
+    };
+
+    let Some(new_member) = users::Entity::find()
+        .filter(users::Column::Name.eq(params.user_name.trim()))
+        .one(&ctx.db)
+        .await?
+    else {
+        return error_fragment(&v, "User not found", "#admin-team-detail-errors");
+    };
+    match team_memberships::Model::add_member(&ctx.db, team.id, &new_member, &params.role).await {
+        Ok(_) => {}
+        Err(ModelError::Message(msg)) => {
+            return error_fragment(&v, &msg, "#admin-team-detail-errors");
+        }
+        Err(e) => {
+            error!(team_pid = %team_pid, error = ?e, "Admin Teams: Failed to add member");
+            return error_fragment(&v, "Failed to add member.", "#admin-team-detail-errors");
+        }
+    }
+    tracing::info!(admin_user_pid=%user.pid, team_pid=%team.pid, member_pid=%new_member.pid, role=%params.role, "Team member added by admin.");
+    render_admin_team_detail(
+        &v,
+        &ctx,
+        &team,
+        Some(format!("{} added as {}.", new_member.name, params.role)),
+    )
+    .await
+}
+
+/// Handler to change the role of a member, including making them an Owner
+/// of a team left without one.
+#[debug_handler]
+async fn update_team_member_role_admin(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Path((team_pid, user_pid)): Path<(String, String)>,
+    Form(params): Form<UpdateRoleParams>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+
+    if !layout_context.is_app_admin {
+        return redirect("/auth/login", headers);
+    }
+
+    let team = match teams::Model::find_by_pid(&ctx.db, &team_pid).await {
+        Ok(team) => team,
+        Err(e) => {
+            tracing::error!(error = ?e, team_pid = team_pid, "Admin Teams: Failed to find team");
+            return error_fragment(&v, "Team not found", "#admin-team-messages");
+        }
+    };
+    let (member, membership) = match find_team_member(&ctx, &team, &user_pid).await {
+        Ok(found) => found,
+        Err(_) => {
+            return error_fragment(&v, "Member not found", "#admin-team-detail-errors");
+        }
+    };
+    if member.is_service_account() {
+        return error_fragment(
+            &v,
+            "The role of a service account is managed from its team page.",
+            "#admin-team-detail-errors",
+        );
+    }
+    match membership.update_role(&ctx.db, &params.role).await {
+        Ok(_) => {}
+        Err(ModelError::Message(msg)) => {
+            return error_fragment(&v, &msg, "#admin-team-detail-errors");
+        }
+        Err(e) => {
+            error!(team_pid = %team_pid, error = ?e, "Admin Teams: Failed to update role");
+            return error_fragment(&v, "Failed to update role.", "#admin-team-detail-errors");
+        }
+    }
+    tracing::info!(admin_user_pid=%user.pid, team_pid=%team.pid, member_pid=%member.pid, role=%params.role, "Team member role changed by admin.");
+    render_admin_team_detail(
+        &v,
+        &ctx,
+        &team,
+        Some(format!("{} is now {}.", member.name, params.role)),
+    )
+    .await
+}
+
+/// Handler to remove a member from a team.
+#[debug_handler]
+async fn remove_team_member_admin(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Path((team_pid, user_pid)): Path<(String, String)>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+
+    if !layout_context.is_app_admin {
+        return redirect("/auth/login", headers);
+    }
+
+    let team = match teams::Model::find_by_pid(&ctx.db, &team_pid).await {
+        Ok(team) => team,
+        Err(e) => {
+            tracing::error!(error = ?e, team_pid = team_pid, "Admin Teams: Failed to find team");
+            return error_fragment(&v, "Team not found", "#admin-team-messages");
+        }
+    };
+    let (member, membership) = match find_team_member(&ctx, &team, &user_pid).await {
+        Ok(found) => found,
+        Err(_) => {
+            return error_fragment(&v, "Member not found", "#admin-team-detail-errors");
+        }
+    };
+    if member.is_service_account() {
+        return error_fragment(
+            &v,
+            "Service accounts are deleted from their team page.",
+            "#admin-team-detail-errors",
+        );
+    }
+    match membership.remove_from_team(&ctx.db).await {
+        Ok(()) => {}
+        Err(ModelError::Message(msg)) => {
+            return error_fragment(&v, &msg, "#admin-team-detail-errors");
+        }
+        Err(e) => {
+            error!(team_pid = %team_pid, error = ?e, "Admin Teams: Failed to remove member");
+            return error_fragment(&v, "Failed to remove member.", "#admin-team-detail-errors");
+        }
+    }
+    tracing::info!(admin_user_pid=%user.pid, team_pid=%team.pid, member_pid=%member.pid, "Team member removed by admin.");
+    render_admin_team_detail(
+        &v,
+        &ctx,
+        &team,
+        Some(format!("{} removed from the team.", member.name)),
+    )
+    .await
+}
+
+/// Data shared by the roles page and its fragment
+async fn admin_roles_data(ctx: &AppContext) -> ModelResult<serde_json::Value> {
+    Ok(serde_json::json!({
+        "roles": roles::Model::list(&ctx.db).await?,
+        "permissions": permissions::Model::list(&ctx.db).await?,
+        "owner_role": OWNER_ROLE,
+        "name_max_length": roles::ROLE_NAME_MAX_LENGTH,
+    }))
+}
+
+/// Renders the list of roles, with an optional message about the last action
+async fn render_admin_roles(
+    v: &TeraView,
+    ctx: &AppContext,
+    message: Option<String>,
+) -> Result<Response> {
+    let mut data = admin_roles_data(ctx).await?;
+    data["message"] = serde_json::json!(message);
+    format::render().view(v, "admin/_roles.html", data)
+}
+
+/// Handler for the page where admins define roles and their permissions.
+#[debug_handler]
+async fn manage_roles_page(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+
+    if !layout_context.is_app_admin {
+        return redirect("/auth/login", headers);
+    }
+
+    let mut data = admin_roles_data(&ctx).await?;
+    data["user"] = serde_json::json!(user);
+    data["invitation_count"] = serde_json::json!(layout_context.invitation_count);
+    data["pending_user_count"] = serde_json::json!(layout_context.pending_user_count);
+    data["is_app_admin"] = serde_json::json!(layout_context.is_app_admin);
+    data["active_page"] = serde_json::json!("admin_roles");
+    render_template(&v, "admin/manage_roles.html", data)
+}
+
+/// Handler for admins to define a custom role.
+#[debug_handler]
+async fn create_role_admin(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Form(fields): Form<Vec<(String, String)>>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+
+    if !layout_context.is_app_admin {
+        return redirect("/auth/login", headers);
+    }
+
+    let params = RoleParams::from_form_fields(fields);
+    let role = match roles::Model::create(&ctx.db, &params).await {
+        Ok(role) => role,
+        Err(ModelError::Message(msg)) => {
+            return error_fragment(&v, &msg, "#admin-roles-errors");
+        }
+        Err(e) => {
+            error!(error = ?e, "Admin Roles: Failed to create role");
+            return error_fragment(&v, "Failed to create the role.", "#admin-roles-errors");
+        }
+    };
+    tracing::info!(admin_user_pid=%user.pid, role = role.name, "Role created by admin.");
+    render_admin_roles(&v, &ctx, Some(format!("Role {} created.", role.name))).await
+}
+
+/// Handler for admins to change the description and permissions of a role.
+#[debug_handler]
+async fn update_role_admin(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Path(role_pid): Path<String>,
+    Form(fields): Form<Vec<(String, String)>>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+
+    if !layout_context.is_app_admin {
+        return redirect("/auth/login", headers);
+    }
+
+    let Ok(role) = roles::Model::find_by_pid(&ctx.db, &role_pid).await else {
+        return error_fragment(&v, "Role not found", "#admin-roles-errors");
+    };
+    let params = RoleParams::from_form_fields(fields);
+    let role = match role.update(&ctx.db, &params).await {
+        Ok(role) => role,
+        Err(ModelError::Message(msg)) => {
+            return error_fragment(&v, &msg, "#admin-roles-errors");
+        }
+        Err(e) => {
+            error!(role_pid = %role_pid, error = ?e, "Admin Roles: Failed to update role");
+            return error_fragment(&v, "Failed to update the role.", "#admin-roles-errors");
+        }
+    };
+    tracing::info!(admin_user_pid=%user.pid, role = role.name, "Role updated by admin.");
+    render_admin_roles(&v, &ctx, Some(format!("Role {} updated.", role.name))).await
+}
+
+/// Handler for admins to delete a custom role no member has.
+#[debug_handler]
+async fn delete_role_admin(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Path(role_pid): Path<String>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+
+    if !layout_context.is_app_admin {
+        return redirect("/auth/login", headers);
+    }
+
+    let Ok(role) = roles::Model::find_by_pid(&ctx.db, &role_pid).await else {
+        return error_fragment(&v, "Role not found", "#admin-roles-errors");
+    };
+    let name = role.name.clone();
+    match role.delete(&ctx.db).await {
+        Ok(()) => {}
+        Err(ModelError::Message(msg)) => {
+            return error_fragment(&v, &msg, "#admin-roles-errors");
+        }
+        Err(e) => {
+            error!(role_pid = %role_pid, error = ?e, "Admin Roles: Failed to delete role");
+            return error_fragment(&v, "Failed to delete the role.", "#admin-roles-errors");
+        }
+    }
+    tracing::info!(admin_user_pid=%user.pid, role = name, "Role deleted by admin.");
+    render_admin_roles(&v, &ctx, Some(format!("Role {name} deleted."))).await
+}
+
+/// Handler for the page to import users from a CSV or JSON file.
+#[debug_handler]
+async fn import_users_page(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+
+    if !layout_context.is_app_admin {
+        return redirect("/auth/login", headers);
+    }
+
+    render_template(
+        &v,
+        "admin/import_users.html",
+        data!({
+            "user": &user,
+            "invitation_count": &layout_context.invitation_count,
+            "pending_user_count": &layout_context.pending_user_count,
+            "is_app_admin": &layout_context.is_app_admin,
+            "active_page": "admin_users",
+            "max_records": user_imports::MAX_IMPORT_RECORDS,
+            "default_role": user_imports::DEFAULT_IMPORT_ROLE,
+        }),
+    )
+}
+
+/// Handler to preview an import (dry run) or import the users, then email
+/// each created user the link to choose their password.
+#[debug_handler]
+async fn import_users_admin(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Form(params): Form<ImportUsersParams>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+
+    if !layout_context.is_app_admin {
+        return redirect("/auth/login", headers);
+    }
+
+    let records = match user_imports::parse(params.format, &params.data) {
+        Ok(records) => records,
+        Err(ModelError::Message(msg)) => return error_fragment(&v, &msg, "#import-result"),
+        Err(e) => {
+            error!(error = ?e, "Admin Import: Failed to parse the file");
+            return error_fragment(&v, "Failed to read the file.", "#import-result");
+        }
+    };
+    let plan =
+        user_imports::plan(&ctx.db, &users::Model::get_admin_team_name(&ctx), records).await?;
+
+    if params.dry_run || !plan.is_valid() {
+        return format::render().view(&v, "admin/_import_preview.html", data!({ "plan": &plan }));
+    }
+
+    let created = match user_imports::apply(&ctx.db, &plan).await {
+        Ok(created) => created,
+        Err(e) => {
+            error!(error = ?e, "Admin Import: Failed to import users");
+            return error_fragment(
+                &v,
+                "The import failed, no users or teams were imported.",
+                "#import-result",
+            );
+        }
+    };
+    tracing::info!(admin_user_pid=%user.pid, user_count = created.len(), "Users imported by admin.");
+
+    let lifetimes = TokenLifetimes::from_context(&ctx)?;
+    let mut unsent = Vec::new();
+    for created_user in &created {
+        let sent = match created_user
+            .initiate_account_setup(&ctx.db, &lifetimes)
+            .await
+        {
+            Ok((created_user, token)) => {
+                AuthMailer::account_created(&ctx, &created_user, &token).await
+            }
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = sent {
+            error!(user_email = %created_user.email, error = ?e, "Admin Import: Failed to send the account setup email");
+            unsent.push(created_user.email.clone());
+        }
+    }
+
+    let mut message = format!(
+        "Imported {} users and created {} teams. Each user was emailed a link to choose their password.",
+        plan.user_count, plan.team_count
+    );
+    if !unsent.is_empty() {
+        message.push_str(&format!(
+            " The email could not be sent to {}; they can use the forgot password link instead.",
+            unsent.join(", ")
+        ));
+    }
+    let mut response = format::render().view(
+        &v,
+        "fragments/success_message.html",
+        data!({ "message": message }),
+    )?;
+    response
+        .headers_mut()
+        .insert("HX-Trigger", HeaderValue::from_static("refreshUserList"));
+    Ok(response)
+}
+
+/// Handler to download every user with their teams, roles and SSH keys, in
+/// the format of the import files.
+#[debug_handler]
+async fn export_users_admin(
+    auth: JWTWithUserOpt<users::Model>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Query(params): Query<ExportUsersParams>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+
+    if !layout_context.is_app_admin {
+        return redirect("/auth/login", headers);
+    }
+
+    let records = user_imports::export(&ctx.db).await?;
+    let data = user_imports::serialize(params.format, &records)?;
+    tracing::info!(admin_user_pid=%user.pid, record_count = records.len(), "Users exported by admin.");
+
+    let disposition = format!(
+        "attachment; filename=\"users-{}.{}\"",
+        chrono::Utc::now().format("%Y-%m-%d"),
+        params.format.extension()
+    );
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                params.format.content_type().to_string(),
+            ),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+/// Admin routes
+pub fn routes() -> Routes {
+    Routes::new()
+        .prefix("/admin")
+        .add("/", get(dashboard_page))
+        .add("/dashboard/{panel}", get(get_dashboard_panel))
+        .add("/users", get(manage_users_page))
+        .add("/users/fragment", get(get_user_list_fragment))
+        .add("/users/pending", get(get_pending_users_fragment))
+        .add(
+            "/users/import",
+            get(import_users_page).post(import_users_admin),
+        )
+        .add("/users/export", get(export_users_admin))
+        .add("/users/{user_pid}/edit", get(get_user_edit_form))
+        .add("/users/{user_pid}", post(update_user_details_admin))
+        .add("/users/{user_pid}", get(get_user_row_view))
+        .add(
+            "/users/{user_pid}/reset-password",
+            post(trigger_password_reset_admin),
+        )
+        .add(
+            "/users/{user_pid}/sign-out",
+            post(revoke_user_sessions_admin),
+        )
+        .add("/users/{user_pid}/unlock", post(unlock_user_admin))
+        .add("/users/{user_pid}/approve", post(approve_user_admin))
+        .add("/users/{user_pid}/reject", post(reject_user_admin))
+        .add("/users/{user_pid}/suspend", post(suspend_user_admin))
+        .add("/users/{user_pid}/reactivate", post(reactivate_user_admin))
+        .add(
+            "/users/{user_pid}/delete",
+            get(get_delete_user_form).post(delete_user_admin),
+        )
+        .add(
+            "/users/{user_pid}/impersonate",
+            post(impersonate_user_admin),
+        )
+        .add("/impersonation/banner", get(get_impersonation_banner))
+        .add("/impersonation/stop", post(stop_impersonation))
+        .add("/impersonations", get(get_impersonation_events_fragment))
+        .add("/teams", get(manage_teams_page))
+        .add("/teams/fragment", get(get_team_list_fragment))
+        .add(
+            "/teams/{team_pid}",
+            get(get_team_detail_admin)
+                .post(update_team_admin)
+                .delete(delete_team_admin),
+        )
+        .add("/teams/{team_pid}/members", post(add_team_member_admin))
+        .add(
+            "/teams/{team_pid}/members/{user_pid}",
+            delete(remove_team_member_admin),
+        )
+        .add(
+            "/teams/{team_pid}/members/{user_pid}/role",
+            post(update_team_member_role_admin),
+        )
+        .add("/roles", get(manage_roles_page).post(create_role_admin))
+        .add(
+            "/roles/{role_pid}",
+            post(update_role_admin).delete(delete_role_admin),
         )
 }
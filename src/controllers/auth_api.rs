@@ -1,24 +1,23 @@
 use crate::{
     mailers::auth::AuthMailer,
+    middleware::{auth_no_error::JWTOpt, auth_session::JWTWithSession},
     models::{
         _entities::users,
-        users::{LoginParams, RegisterParams},
+        login_attempts,
+        password_policy::PasswordPolicy,
+        registration::RegistrationPolicy,
+        sessions,
+        tokens::{self, TokenLifetimes},
+        users::{LoginOutcome, LoginParams, RegisterParams, is_already_registered},
+        webauthn_credentials::{self, LoginFinishParams, LoginStartParams, RegisterFinishParams},
+    },
+    views::auth::{
+        CurrentResponse, LoginResponse, MfaChallengeResponse, WebauthnChallengeResponse,
     },
-    views::auth::{CurrentResponse, LoginResponse},
 };
-use axum::debug_handler;
+use axum::{debug_handler, http::StatusCode};
 use loco_rs::prelude::*;
-use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::sync::OnceLock;
-
-pub static EMAIL_DOMAIN_RE: OnceLock<Regex> = OnceLock::new();
-
-fn get_allow_email_domain_re() -> &'static Regex {
-    EMAIL_DOMAIN_RE.get_or_init(|| {
-        Regex::new(r"@example\.com$|@gmail\.com$").expect("Failed to compile regex")
-    })
-}
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ForgotParams {
@@ -36,6 +35,24 @@ pub struct MagicLinkParams {
     pub email: String,
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SecondFactorParams {
+    pub mfa_token: String,
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct WebauthnSecondFactorStartParams {
+    pub mfa_token: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct WebauthnSecondFactorFinishParams {
+    pub mfa_token: String,
+    #[serde(flatten)]
+    pub assertion: LoginFinishParams,
+}
+
 /// Register function creates a new user with the given parameters and sends a
 /// welcome email to the user
 #[debug_handler]
@@ -43,10 +60,23 @@ async fn register(
     State(ctx): State<AppContext>,
     Json(params): Json<RegisterParams>,
 ) -> Result<Response> {
-    let res = users::Model::create_with_password(&ctx.db, &params).await;
+    // The registration mode is public, so refusing an email address for it
+    // does not reveal anything about existing accounts either
+    let registration = RegistrationPolicy::from_context(&ctx)?;
+    if let Err(err) = registration.check(&ctx.db, &params.email).await {
+        return bad_request(err.to_string());
+    }
+
+    let policy = PasswordPolicy::from_context(&ctx)?;
+    let res = users::Model::create_with_password(&ctx.db, &params, &policy).await;
 
     let user = match res {
         Ok(user) => user,
+        // Unlike an email or name already in use, a rejected password does
+        // not reveal anything about existing accounts, so the reason is returned
+        Err(err @ ModelError::Message(_)) if !is_already_registered(&err) => {
+            return bad_request(err.to_string());
+        }
         Err(err) => {
             tracing::info!(
                 message = err.to_string(),
@@ -57,9 +87,11 @@ async fn register(
         }
     };
 
-    let user = user
+    let user = registration.admit(&ctx.db, user).await?;
+
+    let (user, verification_token) = user
         .into_active_model()
-        .generate_email_verification_token(&ctx.db)
+        .generate_email_verification_token(&ctx.db, &TokenLifetimes::from_context(&ctx)?)
         .await?;
 
     let user = user
@@ -67,7 +99,7 @@ async fn register(
         .set_email_verification_sent(&ctx.db)
         .await?;
 
-    AuthMailer::send_welcome(&ctx, &user).await?;
+    AuthMailer::send_welcome(&ctx, &user, &verification_token).await?;
 
     format::json(())
 }
@@ -76,7 +108,13 @@ async fn register(
 /// the system.
 #[debug_handler]
 async fn verify(State(ctx): State<AppContext>, Path(token): Path<String>) -> Result<Response> {
-    let user = users::Model::find_by_verification_token(&ctx.db, &token).await?;
+    let user = match users::Model::find_by_verification_token(&ctx.db, &token).await {
+        Ok(user) => user,
+        Err(err) if tokens::is_expired(&err) => {
+            return bad_request("verification token expired");
+        }
+        Err(err) => return Err(err.into()),
+    };
 
     if user.email_verified_at.is_some() {
         tracing::info!(pid = user.pid.to_string(), "user already verified");
@@ -104,14 +142,16 @@ async fn forgot(
         return format::json(());
     };
 
-    let user_with_token = user.initiate_password_reset(&ctx.db).await?;
+    let (user_with_token, reset_token) = user
+        .initiate_password_reset(&ctx.db, &TokenLifetimes::from_context(&ctx)?)
+        .await?;
 
     let user_initiated = user_with_token
         .into_active_model()
         .set_forgot_password_sent(&ctx.db)
         .await?;
 
-    AuthMailer::forgot_password(&ctx, &user_initiated).await?;
+    AuthMailer::forgot_password(&ctx, &user_initiated, &reset_token).await?;
 
     format::json(())
 }
@@ -119,42 +159,301 @@ async fn forgot(
 /// reset user password by the given parameters
 #[debug_handler]
 async fn reset(State(ctx): State<AppContext>, Json(params): Json<ResetParams>) -> Result<Response> {
-    let Ok(user) = users::Model::find_by_reset_token(&ctx.db, &params.token).await else {
-        // we don't want to expose our users email. if the email is invalid we still
-        // returning success to the caller
-        tracing::info!("reset token not found");
+    let user = match users::Model::find_by_reset_token(&ctx.db, &params.token).await {
+        Ok(user) => user,
+        Err(err) if tokens::is_expired(&err) => return bad_request("reset token expired"),
+        Err(_) => {
+            // we don't want to expose our users email. if the email is invalid we still
+            // returning success to the caller
+            tracing::info!("reset token not found");
 
-        return format::json(());
+            return format::json(());
+        }
     };
-    user.into_active_model()
-        .reset_password(&ctx.db, &params.password)
-        .await?;
+    let policy = PasswordPolicy::from_context(&ctx)?;
+    let user = match user
+        .into_active_model()
+        .reset_password(&ctx.db, &params.password, &policy)
+        .await
+    {
+        Ok(user) => user,
+        Err(ModelError::Message(msg)) => return bad_request(msg),
+        Err(err) => return Err(err.into()),
+    };
+    sessions::Model::revoke_all_for_user(&ctx.db, user.id, None).await?;
 
     format::json(())
 }
 
-/// Creates a user login and returns a token
-#[debug_handler]
-async fn login(State(ctx): State<AppContext>, Json(params): Json<LoginParams>) -> Result<Response> {
-    let user = users::Model::find_by_email(&ctx.db, &params.email).await?;
+/// Rejects a login attempt made while the account or IP address is throttled
+fn too_many_attempts(retry_after_sec: i64) -> Result<Response> {
+    format::render()
+        .status(StatusCode::TOO_MANY_REQUESTS)
+        .header("Retry-After", retry_after_sec.to_string())
+        .json(serde_json::json!({
+            "error": "too_many_attempts",
+            "description": format!(
+                "Too many failed login attempts. Try again in {}.",
+                login_attempts::describe_retry_after(retry_after_sec)
+            ),
+        }))
+}
+
+/// Emails the owner of an account that was just locked by a failed login
+async fn notify_account_locked(ctx: &AppContext, locked: Option<users::Model>) {
+    if let Some(user) = locked
+        && let Err(err) = AuthMailer::account_locked(ctx, &user).await
+    {
+        tracing::error!(
+            message = err.to_string(),
+            user_email = &user.email,
+            "could not send account locked email",
+        );
+    }
+}
 
-    let valid = user.verify_password(&params.password);
+/// Completes a login: returns the session token, or a two-factor challenge
+/// when the user has enrolled TOTP or registered a passkey
+async fn issue_login_response(
+    ctx: &AppContext,
+    user: &users::Model,
+    device: &sessions::SessionDevice,
+) -> Result<Response> {
+    let jwt_secret = ctx.config.get_jwt_config()?;
 
-    if !valid {
-        return unauthorized("unauthorized!");
+    let methods = user.second_factor_methods(&ctx.db).await?;
+    if !methods.is_empty() {
+        let mfa_token = user
+            .generate_mfa_challenge(&jwt_secret.secret)
+            .or_else(|_| unauthorized("unauthorized!"))?;
+        return format::json(MfaChallengeResponse::new(&mfa_token, &methods));
     }
 
+    issue_session_token(ctx, user, device).await
+}
+
+/// Opens a new session for a user who passed every login step and returns
+/// its token
+async fn issue_session_token(
+    ctx: &AppContext,
+    user: &users::Model,
+    device: &sessions::SessionDevice,
+) -> Result<Response> {
     let jwt_secret = ctx.config.get_jwt_config()?;
 
     let token = user
-        .generate_jwt(&jwt_secret.secret, &jwt_secret.expiration)
+        .generate_jwt(&ctx.db, &jwt_secret.secret, &jwt_secret.expiration, device)
+        .await
         .or_else(|_| unauthorized("unauthorized!"))?;
 
-    format::json(LoginResponse::new(&user, &token))
+    format::json(LoginResponse::new(user, &token))
+}
+
+/// Creates a user login and returns a token, or a two-factor challenge when
+/// the user has enrolled TOTP or registered a passkey
+#[debug_handler]
+async fn login(
+    State(ctx): State<AppContext>,
+    device: sessions::SessionDevice,
+    Json(params): Json<LoginParams>,
+) -> Result<Response> {
+    let outcome = users::Model::check_password_login(
+        &ctx.db,
+        &params.email,
+        &params.password,
+        device.ip.as_deref(),
+    )
+    .await?;
+
+    match outcome {
+        LoginOutcome::Success(user) => issue_login_response(&ctx, &user, &device).await,
+        LoginOutcome::Failed { locked } => {
+            notify_account_locked(&ctx, locked).await;
+            unauthorized("unauthorized!")
+        }
+        LoginOutcome::Throttled { retry_after_sec } => too_many_attempts(retry_after_sec),
+        LoginOutcome::Inactive(_) => unauthorized("account is not active"),
+    }
+}
+
+/// Second login step: exchanges a two-factor challenge and a TOTP (or
+/// recovery) code for a session token
+#[debug_handler]
+async fn login_second_factor(
+    State(ctx): State<AppContext>,
+    device: sessions::SessionDevice,
+    Json(params): Json<SecondFactorParams>,
+) -> Result<Response> {
+    let jwt_secret = ctx.config.get_jwt_config()?;
+
+    let Ok(user) =
+        users::Model::find_by_mfa_challenge(&ctx.db, &jwt_secret.secret, &params.mfa_token).await
+    else {
+        return unauthorized("unauthorized!");
+    };
+
+    let outcome = user
+        .check_second_factor_login(&ctx.db, &params.code, device.ip.as_deref())
+        .await?;
+
+    match outcome {
+        LoginOutcome::Success(user) => issue_session_token(&ctx, &user, &device).await,
+        LoginOutcome::Failed { locked } => {
+            notify_account_locked(&ctx, locked).await;
+            unauthorized("unauthorized!")
+        }
+        LoginOutcome::Throttled { retry_after_sec } => too_many_attempts(retry_after_sec),
+        LoginOutcome::Inactive(_) => unauthorized("account is not active"),
+    }
 }
 
+/// Starts a WebAuthn assertion to use a passkey as the second factor of a
+/// login
 #[debug_handler]
-async fn current(auth: auth::JWT, State(ctx): State<AppContext>) -> Result<Response> {
+async fn login_second_factor_webauthn_start(
+    State(ctx): State<AppContext>,
+    Json(params): Json<WebauthnSecondFactorStartParams>,
+) -> Result<Response> {
+    let jwt_secret = ctx.config.get_jwt_config()?;
+
+    let Ok(user) =
+        users::Model::find_by_mfa_challenge(&ctx.db, &jwt_secret.secret, &params.mfa_token).await
+    else {
+        return unauthorized("unauthorized!");
+    };
+
+    let Ok((challenge_id, options)) =
+        webauthn_credentials::Model::start_authentication(&ctx, &user).await
+    else {
+        return bad_request("no passkey registered");
+    };
+
+    format::json(WebauthnChallengeResponse::new(challenge_id, options))
+}
+
+/// Completes a login with a passkey as the second factor and returns the
+/// session token
+#[debug_handler]
+async fn login_second_factor_webauthn_finish(
+    State(ctx): State<AppContext>,
+    device: sessions::SessionDevice,
+    Json(params): Json<WebauthnSecondFactorFinishParams>,
+) -> Result<Response> {
+    let jwt_secret = ctx.config.get_jwt_config()?;
+
+    let Ok(user) =
+        users::Model::find_by_mfa_challenge(&ctx.db, &jwt_secret.secret, &params.mfa_token).await
+    else {
+        return unauthorized("unauthorized!");
+    };
+
+    match webauthn_credentials::Model::finish_authentication(&ctx, &params.assertion).await {
+        Ok(authenticated) if authenticated.id == user.id => {}
+        Ok(_) => return unauthorized("unauthorized!"),
+        Err(err) => {
+            tracing::info!(
+                message = err.to_string(),
+                user_email = &user.email,
+                "passkey second factor failed",
+            );
+            return unauthorized("unauthorized!");
+        }
+    }
+
+    issue_session_token(&ctx, &user, &device).await
+}
+
+/// Starts a passwordless login: returns the WebAuthn assertion options for
+/// the passkeys of the account
+#[debug_handler]
+async fn webauthn_login_start(
+    State(ctx): State<AppContext>,
+    Json(params): Json<LoginStartParams>,
+) -> Result<Response> {
+    // Unknown accounts and accounts without a passkey get the same answer,
+    // so that the endpoint cannot tell which emails are registered
+    let Ok(user) = users::Model::find_by_email(&ctx.db, &params.email).await else {
+        tracing::info!(
+            message = "Unknown user passkey login attempt,",
+            user_email = &params.email,
+        );
+        return unauthorized("unauthorized!");
+    };
+
+    match webauthn_credentials::Model::start_authentication(&ctx, &user).await {
+        Ok((challenge_id, options)) => {
+            format::json(WebauthnChallengeResponse::new(challenge_id, options))
+        }
+        Err(err) => {
+            tracing::info!(
+                message = "Could not start passkey login,",
+                user_email = &params.email,
+                error = err.to_string(),
+            );
+            unauthorized("unauthorized!")
+        }
+    }
+}
+
+/// Completes a passwordless login. Passkeys require user verification
+/// (PIN or biometrics), so no other factor is asked for.
+#[debug_handler]
+async fn webauthn_login_finish(
+    State(ctx): State<AppContext>,
+    device: sessions::SessionDevice,
+    Json(params): Json<LoginFinishParams>,
+) -> Result<Response> {
+    let user = match webauthn_credentials::Model::finish_authentication(&ctx, &params).await {
+        Ok(user) => user,
+        Err(err) => {
+            tracing::info!(message = err.to_string(), "passkey login failed");
+            return unauthorized("unauthorized!");
+        }
+    };
+
+    issue_session_token(&ctx, &user, &device).await
+}
+
+/// Starts the registration of a passkey for the authenticated user
+#[debug_handler]
+async fn webauthn_register_start(
+    auth: JWTWithSession,
+    State(ctx): State<AppContext>,
+) -> Result<Response> {
+    let user = users::Model::find_by_pid(&ctx.db, &auth.claims.pid).await?;
+    let (challenge_id, options) =
+        webauthn_credentials::Model::start_registration(&ctx, &user).await?;
+    format::json(WebauthnChallengeResponse::new(challenge_id, options))
+}
+
+/// Completes the registration of a passkey for the authenticated user
+#[debug_handler]
+async fn webauthn_register_finish(
+    auth: JWTWithSession,
+    State(ctx): State<AppContext>,
+    Json(params): Json<RegisterFinishParams>,
+) -> Result<Response> {
+    let user = users::Model::find_by_pid(&ctx.db, &auth.claims.pid).await?;
+    let credential =
+        match webauthn_credentials::Model::finish_registration(&ctx, &user, &params).await {
+            Ok(credential) => credential,
+            Err(err) => {
+                tracing::info!(
+                    message = err.to_string(),
+                    user_email = &user.email,
+                    "could not register passkey",
+                );
+                return bad_request("could not register passkey");
+            }
+        };
+    format::json(serde_json::json!({
+        "id": credential.id,
+        "name": credential.name,
+    }))
+}
+
+#[debug_handler]
+async fn current(auth: JWTWithSession, State(ctx): State<AppContext>) -> Result<Response> {
     let user = users::Model::find_by_pid(&ctx.db, &auth.claims.pid).await?;
     format::json(CurrentResponse::new(&user))
 }
@@ -169,7 +468,8 @@ async fn current(auth: auth::JWT, State(ctx): State<AppContext>) -> Result<Respo
 ///
 /// 2. **Click the Magic Link**:  
 ///    The user clicks the link (/magic-link/{token}), which validates the token and its expiration.  
-///    If valid, the server generates a JWT and responds with a [`LoginResponse`].  
+///    If valid, the server generates a JWT and responds with a [`LoginResponse`]
+///    (or a [`MfaChallengeResponse`] when the user has two-factor authentication enabled).  
 ///    If invalid or expired, an unauthorized response is returned.
 ///
 /// This flow enhances security by avoiding traditional passwords and providing a seamless login experience.
@@ -177,8 +477,7 @@ async fn magic_link(
     State(ctx): State<AppContext>,
     Json(params): Json<MagicLinkParams>,
 ) -> Result<Response> {
-    let email_regex = get_allow_email_domain_re();
-    if !email_regex.is_match(&params.email) {
+    if !RegistrationPolicy::from_context(&ctx)?.allows_domain(&params.email) {
         tracing::debug!(
             email = params.email,
             "The provided email is invalid or does not match the allowed domains"
@@ -192,6 +491,10 @@ async fn magic_link(
         tracing::debug!(email = params.email, "user not found by email");
         return format::empty_json();
     };
+    if !user.is_active() {
+        tracing::debug!(email = params.email, "user is not active");
+        return format::empty_json();
+    }
 
     let user = user.into_active_model().create_magic_link(&ctx.db).await?;
     AuthMailer::send_magic_link(&ctx, &user).await?;
@@ -203,6 +506,7 @@ async fn magic_link(
 async fn magic_link_verify(
     Path(token): Path<String>,
     State(ctx): State<AppContext>,
+    device: sessions::SessionDevice,
 ) -> Result<Response> {
     let Ok(user) = users::Model::find_by_magic_token(&ctx.db, &token).await else {
         // we don't want to expose our users email. if the email is invalid we still
@@ -212,20 +516,16 @@ async fn magic_link_verify(
 
     let user = user.into_active_model().clear_magic_link(&ctx.db).await?;
 
-    let jwt_secret = ctx.config.get_jwt_config()?;
-
-    let token = user
-        .generate_jwt(&jwt_secret.secret, &jwt_secret.expiration)
-        .or_else(|_| unauthorized("unauthorized!"))?;
-
-    format::json(LoginResponse::new(&user, &token))
+    issue_login_response(&ctx, &user, &device).await
 }
 
-/// Handles user logout by clearing the auth token cookie
+/// Handles user logout by revoking the session bound to the bearer token and
+/// clearing the auth token cookie
 #[debug_handler]
-async fn logout() -> Result<Response> {
-    // TODO: This is not the correct way to implement this. APIs do not use Session cookies.
-    // - Instead of clearing the session cookie, the bearer token must be removed from the DB
+async fn logout(auth: JWTOpt, State(ctx): State<AppContext>) -> Result<Response> {
+    if let Some(jti) = auth.claims.as_ref().and_then(sessions::jti_from_claims) {
+        sessions::Model::revoke_by_jti(&ctx.db, jti).await?;
+    }
     let response = Response::builder()
         .header(
             "Set-Cookie",
@@ -241,6 +541,19 @@ pub fn routes() -> Routes {
         .add("/register", post(register))
         .add("/verify/{token}", get(verify))
         .add("/login", post(login))
+        .add("/login/2fa", post(login_second_factor))
+        .add(
+            "/login/2fa/webauthn/start",
+            post(login_second_factor_webauthn_start),
+        )
+        .add(
+            "/login/2fa/webauthn/finish",
+            post(login_second_factor_webauthn_finish),
+        )
+        .add("/webauthn/login/start", post(webauthn_login_start))
+        .add("/webauthn/login/finish", post(webauthn_login_finish))
+        .add("/webauthn/register/start", post(webauthn_register_start))
+        .add("/webauthn/register/finish", post(webauthn_register_finish))
         .add("/forgot", post(forgot))
         .add("/reset", post(reset))
         .add("/current", get(current))
@@ -1,10 +1,22 @@
 use crate::{
     mailers::auth::AuthMailer,
-    middleware::auth_no_error::JWTWithUserOpt,
+    middleware::auth_no_error::{JWTOpt, JWTWithUserOpt},
     models::{
+        _entities::teams,
+        impersonation_events, login_attempts,
+        oidc::OidcConfig,
+        password_policy::{PasswordPolicy, estimate_strength},
+        registration::RegistrationPolicy,
+        sessions, team_memberships,
+        tokens::{self, TokenLifetimes},
         users,
-        users::{ForgotPasswordParams, LoginParams, RegisterParams, ResetPasswordParams},
+        users::{
+            ForgotPasswordParams, LoginOutcome, LoginParams, MFA_CHALLENGE_EXPIRATION_SEC,
+            RegisterParams, ResetPasswordParams,
+        },
+        webauthn_credentials::{self, LoginFinishParams, LoginStartParams, RegisterFinishParams},
     },
+    views::auth::WebauthnChallengeResponse,
     views::render_template,
     views::*,
 };
@@ -16,6 +28,7 @@ use axum::{
     extract::{Form, Path, Query, State},
 };
 use loco_rs::prelude::*;
+use serde::Deserialize;
 use std::collections::HashMap;
 
 use rand::Rng;
@@ -37,13 +50,95 @@ fn is_oidc_auth(ctx: &AppContext) -> bool {
         })
 }
 
+/// Emails the owner of an account that was just locked by a failed login
+async fn notify_account_locked(ctx: &AppContext, locked: Option<users::Model>) {
+    if let Some(user) = locked
+        && let Err(err) = AuthMailer::account_locked(ctx, &user).await
+    {
+        tracing::error!(
+            message = "Failed to send account locked email,",
+            user_email = &user.email,
+            error = err.to_string(),
+        );
+    }
+}
+
+/// Renders the error shown when a login attempt is rejected because the
+/// account or IP address is throttled
+fn too_many_attempts_fragment(v: &TeraView, retry_after_sec: i64) -> Result<Response> {
+    error_fragment(
+        v,
+        &format!(
+            "Log in failed: Too many failed attempts. Please try again in {}.",
+            login_attempts::describe_retry_after(retry_after_sec)
+        ),
+        "#error-container",
+    )
+}
+
+/// Renders the error shown when valid credentials are given for an account
+/// that may not log in: waiting for an approval or suspended
+fn inactive_account_fragment(v: &TeraView, user: &users::Model) -> Result<Response> {
+    let message = if user.is_pending_approval() {
+        "Log in failed: Your account is waiting for the approval of an administrator."
+    } else {
+        "Log in failed: Your account has been suspended. Please contact an administrator."
+    };
+    error_fragment(v, message, "#error-container")
+}
+
+/// Kinds of emailed links that can expire, as used in `/auth/link-expired/{kind}`
+pub const EXPIRED_LINK_RESET: &str = "reset";
+pub const EXPIRED_LINK_VERIFICATION: &str = "verification";
+pub const EXPIRED_LINK_PGP: &str = "pgp";
+pub const EXPIRED_LINK_INVITATION: &str = "invitation";
+const EXPIRED_LINK_KINDS: [&str; 4] = [
+    EXPIRED_LINK_RESET,
+    EXPIRED_LINK_VERIFICATION,
+    EXPIRED_LINK_PGP,
+    EXPIRED_LINK_INVITATION,
+];
+
+/// Returns the URL of the page telling the user that a link of the given
+/// kind has expired
+#[must_use]
+pub fn expired_link_url(kind: &str) -> String {
+    format!("/auth/link-expired/{kind}")
+}
+
+/// Name of the cookie holding the challenge of a login waiting for its second factor
+const MFA_CHALLENGE_COOKIE: &str = "mfa_challenge";
+
+#[derive(Debug, Deserialize)]
+pub struct SecondFactorForm {
+    pub code: String,
+}
+
+/// Password typed in a registration, reset or change form, with the name and
+/// email entered in the same form when there are some
+#[derive(Debug, Deserialize)]
+pub struct PasswordStrengthForm {
+    pub password: String,
+    pub name: Option<String>,
+    pub email: Option<String>,
+}
+
+/// Helper function to read a cookie value from the request headers
+fn get_cookie(headers: &HeaderMap, name: &str) -> Option<String> {
+    let jar = axum_extra::extract::cookie::CookieJar::from_headers(headers);
+    jar.get(name)
+        .map(|cookie| cookie.value().to_string())
+        .filter(|value| !value.is_empty())
+}
+
 /// Renders the registration page
 #[debug_handler]
 async fn register(
     auth: JWTWithUserOpt<users::Model>,
     ViewEngine(v): ViewEngine<TeraView>,
-    State(_ctx): State<AppContext>,
+    State(ctx): State<AppContext>,
     headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
 ) -> Result<Response> {
     match auth.user {
         Some(_user) => {
@@ -51,7 +146,35 @@ async fn register(
             tracing::info!("User is already authenticated, redirecting to home");
             redirect("/home", headers)
         }
-        None => render_template(&v, "auth/register.html", data!({})),
+        None => {
+            let registration = RegistrationPolicy::from_context(&ctx)?;
+            // Invitation sent to an email address nobody registered with yet
+            let invitation = match params.get("invitation") {
+                Some(token) => {
+                    match team_memberships::Model::find_by_invitation_token(&ctx.db, token).await {
+                        Ok(invitation) => invitation.invited_email.clone().zip(
+                            teams::Entity::find_by_id(invitation.team_id)
+                                .one(&ctx.db)
+                                .await?,
+                        ),
+                        Err(err) if tokens::is_expired(&err) => {
+                            return redirect(&expired_link_url(EXPIRED_LINK_INVITATION), headers);
+                        }
+                        Err(_) => None,
+                    }
+                }
+                None => None,
+            };
+            render_template(
+                &v,
+                "auth/register.html",
+                data!({
+                    "registration": registration,
+                    "email": invitation.as_ref().map(|(email, _)| email),
+                    "invitation_team": invitation.as_ref().map(|(_, team)| &team.name),
+                }),
+            )
+        }
     }
 }
 
@@ -80,8 +203,27 @@ async fn handle_register(
         password_confirmation: form.password_confirmation,
     };
 
+    // Refuse email addresses the registration mode does not accept
+    let registration = RegistrationPolicy::from_context(&ctx)?;
+    if let Err(err) = registration.check(&ctx.db, &params.email).await {
+        tracing::info!(
+            error = err.to_string(),
+            user_email = &params.email,
+            "Registration refused",
+        );
+        return match err {
+            ModelError::Message(msg) => error_fragment(&v, &msg, "#error-container"),
+            _ => error_fragment(
+                &v,
+                "Could not register account due to an unexpected error.",
+                "#error-container",
+            ),
+        };
+    }
+
     // Create user account
-    let res = users::Model::create_with_password(&ctx.db, &params).await;
+    let policy = PasswordPolicy::from_context(&ctx)?;
+    let res = users::Model::create_with_password(&ctx.db, &params, &policy).await;
 
     match res {
         Ok(user) => {
@@ -95,16 +237,39 @@ async fn handle_register(
                 return error_page(&v, "Failed to create administrators team.", Some(e.into()));
             }
 
+            // Leave the account pending when registrations need an approval
+            let user = match registration.admit(&ctx.db, user).await {
+                Ok(user) => user,
+                Err(err) => {
+                    tracing::error!(
+                        message = "Failed to apply the registration mode",
+                        error = err.to_string(),
+                    );
+                    return error_page(
+                        &v,
+                        "Account registered, but failed to finalize setup. Please contact support.",
+                        Some(loco_rs::Error::Model(err)),
+                    );
+                }
+            };
+            let login_url = if user.is_active() {
+                "/auth/login?registered=true"
+            } else {
+                "/auth/login?registered=true&pending_approval=true"
+            };
+
             // Generate email verification token
             match user
                 .clone()
                 .into_active_model()
-                .generate_email_verification_token(&ctx.db)
+                .generate_email_verification_token(&ctx.db, &TokenLifetimes::from_context(&ctx)?)
                 .await
             {
-                Ok(user_with_token) => {
+                Ok((user_with_token, verification_token)) => {
                     // Send verification email first
-                    match AuthMailer::send_welcome(&ctx, &user_with_token).await {
+                    match AuthMailer::send_welcome(&ctx, &user_with_token, &verification_token)
+                        .await
+                    {
                         Ok(_) => {
                             // Email sent successfully, now update verification status
                             match user_with_token
@@ -115,7 +280,7 @@ async fn handle_register(
                             {
                                 Ok(_) => {
                                     // All good, redirect to login page with success message
-                                    redirect("/auth/login?registered=true", headers)
+                                    redirect(login_url, headers)
                                 }
                                 Err(err) => {
                                     tracing::error!(
@@ -202,6 +367,7 @@ async fn login(
     State(ctx): State<AppContext>,
     Query(params): Query<HashMap<String, String>>,
     headers: HeaderMap,
+    device: sessions::SessionDevice,
 ) -> Result<Response> {
     if let Some(oidc_header) = headers.get("X-Oidc-Roles").and_then(|h| h.to_str().ok()) {
         tracing::info!("X-Oidc-Roles = {}", oidc_header);
@@ -244,7 +410,13 @@ async fn login(
                             };
 
                             let token = match user
-                                .generate_jwt(&jwt_secret.secret, &jwt_secret.expiration)
+                                .generate_jwt(
+                                    &ctx.db,
+                                    &jwt_secret.secret,
+                                    &jwt_secret.expiration,
+                                    &device,
+                                )
+                                .await
                             {
                                 Ok(token) => token,
                                 Err(err) => {
@@ -300,9 +472,14 @@ async fn login(
                                     password_confirmation: random_password.clone(),
                                 };
 
-                                // Create user account
-                                let res =
-                                    users::Model::create_with_password(&ctx.db, &user_params).await;
+                                // Create user account. The random password is never shown
+                                // to the user, so the configured policy does not apply to it
+                                let res = users::Model::create_with_password(
+                                    &ctx.db,
+                                    &user_params,
+                                    &PasswordPolicy::default(),
+                                )
+                                .await;
 
                                 match res {
                                     Ok(user) => {
@@ -326,14 +503,18 @@ async fn login(
                                         match user
                                             .clone()
                                             .into_active_model()
-                                            .generate_email_verification_token(&ctx.db)
+                                            .generate_email_verification_token(
+                                                &ctx.db,
+                                                &TokenLifetimes::from_context(&ctx)?,
+                                            )
                                             .await
                                         {
-                                            Ok(user_with_token) => {
+                                            Ok((user_with_token, verification_token)) => {
                                                 // Send verification email first
                                                 match AuthMailer::send_welcome(
                                                     &ctx,
                                                     &user_with_token,
+                                                    &verification_token,
                                                 )
                                                 .await
                                                 {
@@ -455,6 +636,7 @@ async fn login(
             }
 
             let registered = params.get("registered") == Some(&"true".to_string());
+            let pending_approval = params.get("pending_approval") == Some(&"true".to_string());
             let mut email = "";
             // Parse cookies from headers
             if let Some(cookie_header) = headers.get("cookie").and_then(|h| h.to_str().ok()) {
@@ -467,12 +649,35 @@ async fn login(
                 }
             }
 
+            // Single sign-on providers offered next to the login form
+            let oidc_providers = match OidcConfig::from_context(&ctx) {
+                Ok(config) => config
+                    .authorization_code
+                    .iter()
+                    .map(|provider| {
+                        serde_json::json!({
+                            "identifier": provider.client_identifier,
+                            "name": provider.name(),
+                        })
+                    })
+                    .collect::<Vec<_>>(),
+                Err(err) => {
+                    tracing::error!(
+                        message = "Invalid single sign-on configuration,",
+                        error = err.to_string(),
+                    );
+                    Vec::new()
+                }
+            };
+
             format::render().view(
                 &v,
                 "auth/login.html",
                 data!({
                 "registered": registered, 
-                "email": email}),
+                "pending_approval": pending_approval,
+                "email": email,
+                "oidc_providers": oidc_providers}),
             )
         }
     }
@@ -484,6 +689,7 @@ async fn handle_login(
     ViewEngine(v): ViewEngine<TeraView>,
     State(ctx): State<AppContext>,
     headers: HeaderMap,
+    device: sessions::SessionDevice,
     Form(form): Form<LoginParams>,
 ) -> Result<Response> {
     // Convert form data to login params
@@ -493,25 +699,18 @@ async fn handle_login(
         remember_me: form.remember_me,
     };
 
-    // Try to login
-    let user_result = users::Model::find_by_email(&ctx.db, &params.email).await;
-
-    match user_result {
-        Ok(user) => {
-            let valid = user.verify_password(&params.password);
-
-            if !valid {
-                tracing::info!(
-                    message = "Invalid password in login attempt,",
-                    user_email = &params.email,
-                );
-                return error_fragment(
-                    &v,
-                    "Log in failed: Invalid email or password",
-                    "#error-container",
-                );
-            };
+    // Try to login, unless this account or IP address is throttled after
+    // too many failed attempts
+    let outcome = users::Model::check_password_login(
+        &ctx.db,
+        &params.email,
+        &params.password,
+        device.ip.as_deref(),
+    )
+    .await;
 
+    match outcome {
+        Ok(LoginOutcome::Success(user)) => {
             // Get JWT secret, handling potential error
             let jwt_secret = match ctx.config.get_jwt_config() {
                 Ok(config) => config,
@@ -529,29 +728,70 @@ async fn handle_login(
                 }
             };
 
-            let token = match user.generate_jwt(&jwt_secret.secret, &jwt_secret.expiration) {
-                Ok(token) => token,
+            let second_factor_methods = match user.second_factor_methods(&ctx.db).await {
+                Ok(methods) => methods,
                 Err(err) => {
                     tracing::error!(
-                        message = "Failed to generate JWT token,",
+                        message = "Failed to read the second factors of the user,",
                         user_email = &params.email,
                         error = err.to_string(),
                     );
-                    return error_fragment(
-                        &v,
-                        "Log in failed: Failed to generate JWT token",
-                        "#error-container",
-                    );
+                    return error_fragment(&v, "Log in failed: Server error.", "#error-container");
                 }
             };
 
-            // Redirect to home with cookies
-            let mut response = redirect("/home", headers)?;
+            // Users enrolled in two-factor authentication only get a short-lived
+            // challenge here; the auth token is issued by the second login step
+            let (redirect_url, auth_cookie) = if !second_factor_methods.is_empty() {
+                match user.generate_mfa_challenge(&jwt_secret.secret) {
+                    Ok(challenge) => (
+                        "/auth/login/2fa",
+                        format!(
+                            "{MFA_CHALLENGE_COOKIE}={challenge}; Path=/auth; HttpOnly; SameSite=Strict; Max-Age={MFA_CHALLENGE_EXPIRATION_SEC}"
+                        ),
+                    ),
+                    Err(err) => {
+                        tracing::error!(
+                            message = "Failed to generate two-factor challenge,",
+                            user_email = &params.email,
+                            error = err.to_string(),
+                        );
+                        return error_fragment(
+                            &v,
+                            "Log in failed: Failed to generate JWT token",
+                            "#error-container",
+                        );
+                    }
+                }
+            } else {
+                let token = match user
+                    .generate_jwt(&ctx.db, &jwt_secret.secret, &jwt_secret.expiration, &device)
+                    .await
+                {
+                    Ok(token) => token,
+                    Err(err) => {
+                        tracing::error!(
+                            message = "Failed to generate JWT token,",
+                            user_email = &params.email,
+                            error = err.to_string(),
+                        );
+                        return error_fragment(
+                            &v,
+                            "Log in failed: Failed to generate JWT token",
+                            "#error-container",
+                        );
+                    }
+                };
+                ("/home", format!("auth_token={}; Path=/", token))
+            };
+
+            // Redirect to home (or the second login step) with cookies
+            let mut response = redirect(redirect_url, headers)?;
 
             // Add Set-Cookie header for the auth token
             response.headers_mut().append(
                 axum::http::header::SET_COOKIE,
-                HeaderValue::from_str(&format!("auth_token={}; Path=/", token))?,
+                HeaderValue::from_str(&auth_cookie)?,
             );
 
             // If remember me is checked, set a persistent cookie with email
@@ -583,17 +823,380 @@ async fn handle_login(
             );
             Ok(response)
         }
-        Err(_) => {
+        Ok(LoginOutcome::Failed { locked }) => {
             tracing::info!(
-                message = "Unknown user login attempt,",
+                message = "Invalid email or password in login attempt,",
                 user_email = &params.email,
             );
+            notify_account_locked(&ctx, locked).await;
             error_fragment(
                 &v,
                 "Log in failed: Invalid email or password",
                 "#error-container",
             )
         }
+        Ok(LoginOutcome::Throttled { retry_after_sec }) => {
+            tracing::info!(
+                message = "Throttled login attempt,",
+                user_email = &params.email,
+                retry_after_sec,
+            );
+            too_many_attempts_fragment(&v, retry_after_sec)
+        }
+        Ok(LoginOutcome::Inactive(user)) => {
+            tracing::info!(
+                message = "Login attempt on an inactive account,",
+                user_email = &params.email,
+                status = &user.status,
+            );
+            inactive_account_fragment(&v, &user)
+        }
+        Err(err) => {
+            tracing::error!(
+                message = "Failed to check login attempt,",
+                user_email = &params.email,
+                error = err.to_string(),
+            );
+            error_fragment(&v, "Log in failed: Server error.", "#error-container")
+        }
+    }
+}
+
+/// Finds the user of the login waiting for its second factor, identified by
+/// the challenge cookie
+async fn find_mfa_challenge_user(ctx: &AppContext, headers: &HeaderMap) -> Option<users::Model> {
+    let challenge = get_cookie(headers, MFA_CHALLENGE_COOKIE)?;
+    let jwt_secret = ctx.config.get_jwt_config().ok()?;
+    users::Model::find_by_mfa_challenge(&ctx.db, &jwt_secret.secret, &challenge)
+        .await
+        .ok()
+}
+
+/// Renders the second login step, asking for a TOTP or recovery code and/or
+/// a passkey, depending on what the user has set up
+#[debug_handler]
+async fn login_second_factor(
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let Some(user) = find_mfa_challenge_user(&ctx, &headers).await else {
+        return redirect("/auth/login", headers);
+    };
+    let methods = user.second_factor_methods(&ctx.db).await?;
+    format::render().view(
+        &v,
+        "auth/login_2fa.html",
+        data!({
+            "totp": methods.contains(&"totp"),
+            "webauthn": methods.contains(&"webauthn"),
+        }),
+    )
+}
+
+/// Handles the second login step: verifies the TOTP or recovery code of the
+/// user identified by the challenge cookie and issues the auth token
+#[debug_handler]
+async fn handle_login_second_factor(
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    device: sessions::SessionDevice,
+    Form(form): Form<SecondFactorForm>,
+) -> Result<Response> {
+    let Some(challenge) = get_cookie(&headers, MFA_CHALLENGE_COOKIE) else {
+        return redirect("/auth/login", headers);
+    };
+
+    let jwt_secret = match ctx.config.get_jwt_config() {
+        Ok(config) => config,
+        Err(err) => {
+            tracing::error!(
+                message = "Failed to get JWT configuration,",
+                error = err.to_string(),
+            );
+            return error_fragment(
+                &v,
+                "Log in failed: Server configuration error.",
+                "#error-container",
+            );
+        }
+    };
+
+    let user =
+        match users::Model::find_by_mfa_challenge(&ctx.db, &jwt_secret.secret, &challenge).await {
+            Ok(user) => user,
+            Err(err) => {
+                tracing::info!(
+                    message = "Invalid two-factor challenge,",
+                    error = err.to_string(),
+                );
+                return error_fragment(
+                    &v,
+                    "Your sign-in attempt has expired. Please log in again.",
+                    "#error-container",
+                );
+            }
+        };
+
+    let user_email = user.email.clone();
+    let user = match user
+        .check_second_factor_login(&ctx.db, &form.code, device.ip.as_deref())
+        .await
+    {
+        Ok(LoginOutcome::Success(user)) => user,
+        Ok(LoginOutcome::Failed { locked }) => {
+            tracing::info!(
+                message = "Invalid two-factor code in login attempt,",
+                user_email = &user_email,
+            );
+            notify_account_locked(&ctx, locked).await;
+            return error_fragment(
+                &v,
+                "Log in failed: Invalid authentication code",
+                "#error-container",
+            );
+        }
+        Ok(LoginOutcome::Throttled { retry_after_sec }) => {
+            return too_many_attempts_fragment(&v, retry_after_sec);
+        }
+        Ok(LoginOutcome::Inactive(user)) => {
+            return inactive_account_fragment(&v, &user);
+        }
+        Err(err) => {
+            tracing::error!(
+                message = "Failed to verify two-factor code,",
+                user_email = &user_email,
+                error = err.to_string(),
+            );
+            return error_fragment(
+                &v,
+                "Log in failed: Could not verify the authentication code",
+                "#error-container",
+            );
+        }
+    };
+
+    let token = match user
+        .generate_jwt(&ctx.db, &jwt_secret.secret, &jwt_secret.expiration, &device)
+        .await
+    {
+        Ok(token) => token,
+        Err(err) => {
+            tracing::error!(
+                message = "Failed to generate JWT token,",
+                user_email = &user.email,
+                error = err.to_string(),
+            );
+            return error_fragment(
+                &v,
+                "Log in failed: Failed to generate JWT token",
+                "#error-container",
+            );
+        }
+    };
+
+    let mut response = redirect("/home", headers)?;
+    response.headers_mut().append(
+        axum::http::header::SET_COOKIE,
+        HeaderValue::from_str(&format!("auth_token={}; Path=/", token))?,
+    );
+    response.headers_mut().append(
+        axum::http::header::SET_COOKIE,
+        HeaderValue::from_str(&format!(
+            "{MFA_CHALLENGE_COOKIE}=; Path=/auth; Expires=Thu, 01 Jan 1970 00:00:00 GMT"
+        ))?,
+    );
+
+    tracing::info!(
+        message = "User login with second factor successful,",
+        user_email = &user.email,
+    );
+    Ok(response)
+}
+
+/// Builds the JSON response ending a login completed in JavaScript (WebAuthn
+/// ceremonies): opens the session, sets the auth token cookie, clears the
+/// two-factor challenge cookie and tells the page where to go next
+async fn webauthn_login_response(
+    ctx: &AppContext,
+    user: &users::Model,
+    device: &sessions::SessionDevice,
+) -> Result<Response> {
+    if !user.is_active() {
+        return unauthorized("account is not active");
+    }
+    let jwt_secret = ctx.config.get_jwt_config()?;
+    let token = user
+        .generate_jwt(&ctx.db, &jwt_secret.secret, &jwt_secret.expiration, device)
+        .await?;
+
+    let mut response = format::json(serde_json::json!({ "redirect": "/home" }))?;
+    response.headers_mut().append(
+        axum::http::header::SET_COOKIE,
+        HeaderValue::from_str(&format!("auth_token={}; Path=/", token))?,
+    );
+    response.headers_mut().append(
+        axum::http::header::SET_COOKIE,
+        HeaderValue::from_str(&format!(
+            "{MFA_CHALLENGE_COOKIE}=; Path=/auth; Expires=Thu, 01 Jan 1970 00:00:00 GMT"
+        ))?,
+    );
+    Ok(response)
+}
+
+/// Starts a passwordless login with a passkey (called from the login page
+/// script)
+#[debug_handler]
+async fn webauthn_login_start(
+    State(ctx): State<AppContext>,
+    Json(params): Json<LoginStartParams>,
+) -> Result<Response> {
+    let Ok(user) = users::Model::find_by_email(&ctx.db, &params.email).await else {
+        tracing::info!(
+            message = "Unknown user passkey login attempt,",
+            user_email = &params.email,
+        );
+        return bad_request("No passkey is registered for this account");
+    };
+    match webauthn_credentials::Model::start_authentication(&ctx, &user).await {
+        Ok((challenge_id, options)) => {
+            format::json(WebauthnChallengeResponse::new(challenge_id, options))
+        }
+        Err(err) => {
+            tracing::info!(
+                message = "Could not start passkey login,",
+                user_email = &params.email,
+                error = err.to_string(),
+            );
+            bad_request("No passkey is registered for this account")
+        }
+    }
+}
+
+/// Completes a passwordless login with a passkey and sets the auth token
+/// cookie
+#[debug_handler]
+async fn webauthn_login_finish(
+    State(ctx): State<AppContext>,
+    device: sessions::SessionDevice,
+    Json(params): Json<LoginFinishParams>,
+) -> Result<Response> {
+    let user = match webauthn_credentials::Model::finish_authentication(&ctx, &params).await {
+        Ok(user) => user,
+        Err(err) => {
+            tracing::info!(message = "Passkey login failed,", error = err.to_string(),);
+            return unauthorized("Log in failed: the passkey could not be verified");
+        }
+    };
+
+    tracing::info!(
+        message = "User passkey login successful,",
+        user_email = &user.email,
+    );
+    webauthn_login_response(&ctx, &user, &device).await
+}
+
+/// Starts the verification of a passkey as the second login step
+#[debug_handler]
+async fn login_second_factor_webauthn_start(
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let Some(user) = find_mfa_challenge_user(&ctx, &headers).await else {
+        return unauthorized("Your sign-in attempt has expired. Please log in again.");
+    };
+    match webauthn_credentials::Model::start_authentication(&ctx, &user).await {
+        Ok((challenge_id, options)) => {
+            format::json(WebauthnChallengeResponse::new(challenge_id, options))
+        }
+        Err(err) => {
+            tracing::info!(
+                message = "Could not start passkey second factor,",
+                user_email = &user.email,
+                error = err.to_string(),
+            );
+            bad_request("No passkey is registered for this account")
+        }
+    }
+}
+
+/// Completes the second login step with a passkey and sets the auth token
+/// cookie
+#[debug_handler]
+async fn login_second_factor_webauthn_finish(
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    device: sessions::SessionDevice,
+    Json(params): Json<LoginFinishParams>,
+) -> Result<Response> {
+    let Some(user) = find_mfa_challenge_user(&ctx, &headers).await else {
+        return unauthorized("Your sign-in attempt has expired. Please log in again.");
+    };
+    match webauthn_credentials::Model::finish_authentication(&ctx, &params).await {
+        Ok(authenticated) if authenticated.id == user.id => {}
+        Ok(_) | Err(_) => {
+            tracing::info!(
+                message = "Invalid passkey in second factor login attempt,",
+                user_email = &user.email,
+            );
+            return unauthorized("Log in failed: the passkey could not be verified");
+        }
+    }
+
+    tracing::info!(
+        message = "User login with passkey second factor successful,",
+        user_email = &user.email,
+    );
+    webauthn_login_response(&ctx, &user, &device).await
+}
+
+/// Starts the registration of a passkey for the signed-in user (called from
+/// the profile page script)
+#[debug_handler]
+async fn webauthn_register_start(
+    auth: JWTWithUserOpt<users::Model>,
+    State(ctx): State<AppContext>,
+) -> Result<Response> {
+    let Some(user) = auth.user else {
+        return unauthorized("You must be signed in to register a passkey");
+    };
+    let (challenge_id, options) =
+        webauthn_credentials::Model::start_registration(&ctx, &user).await?;
+    format::json(WebauthnChallengeResponse::new(challenge_id, options))
+}
+
+/// Completes the registration of a passkey for the signed-in user
+#[debug_handler]
+async fn webauthn_register_finish(
+    auth: JWTWithUserOpt<users::Model>,
+    State(ctx): State<AppContext>,
+    Json(params): Json<RegisterFinishParams>,
+) -> Result<Response> {
+    let Some(user) = auth.user else {
+        return unauthorized("You must be signed in to register a passkey");
+    };
+    match webauthn_credentials::Model::finish_registration(&ctx, &user, &params).await {
+        Ok(credential) => {
+            tracing::info!(
+                message = "Passkey registered,",
+                user_email = &user.email,
+                credential_name = &credential.name,
+            );
+            format::json(serde_json::json!({
+                "id": credential.id,
+                "name": credential.name,
+            }))
+        }
+        Err(ModelError::Message(msg)) => bad_request(msg),
+        Err(err) => {
+            tracing::info!(
+                message = "Could not register passkey,",
+                user_email = &user.email,
+                error = err.to_string(),
+            );
+            bad_request("The passkey could not be registered")
+        }
     }
 }
 
@@ -624,8 +1227,11 @@ async fn handle_forgot_password(
 ) -> Result<Response> {
     match users::Model::find_by_email(&ctx.db, &form.email).await {
         Ok(user) => {
-            let user_with_token = match user.initiate_password_reset(&ctx.db).await {
-                Ok(u) => u,
+            let (user_with_token, reset_token) = match user
+                .initiate_password_reset(&ctx.db, &TokenLifetimes::from_context(&ctx)?)
+                .await
+            {
+                Ok(result) => result,
                 Err(e) => {
                     error!(user_email = %form.email, error = ?e, "Failed to initiate password reset (token generation/save)");
                     return redirect("/auth/reset-email-sent", headers);
@@ -633,7 +1239,8 @@ async fn handle_forgot_password(
             };
 
             // Correct mailer function name: forgot_password
-            if let Err(e) = AuthMailer::forgot_password(&ctx, &user_with_token).await {
+            if let Err(e) = AuthMailer::forgot_password(&ctx, &user_with_token, &reset_token).await
+            {
                 error!(user_email = %form.email, error = ?e, "Failed to send forgot password email");
             } else if let Err(e) = user_with_token
                 .clone()
@@ -661,34 +1268,21 @@ async fn reset_password(
     ViewEngine(v): ViewEngine<TeraView>,
     State(ctx): State<AppContext>,
     Path(token): Path<String>,
+    headers: HeaderMap,
 ) -> Result<Response> {
-    // Attempt to find the user by the reset token
+    // Attempt to find the user by the reset token; the finder checks that the
+    // token has not expired
     match users::Model::find_by_reset_token(&ctx.db, &token).await {
-        Ok(user) => {
-            // Check if the token is still valid (e.g., within an expiration timeframe)
-            // Assuming the find_by_reset_token implicitly checks validity or that validity check happens on POST.
-            // For now, just render the form if the token leads to a user.
-            if user.reset_token.is_some() && user.reset_sent_at.is_some() {
-                // Optional: Add explicit time validation if needed
-                // let expiry_duration = Duration::hours(1); // Example: 1 hour validity
-                // if user.reset_sent_at.unwrap() + expiry_duration < chrono::Utc::now().naive_utc() { ... handle expired ... }
-
-                format::render().view(
-                    &v,
-                    "auth/reset-password.html",
-                    data!({ "token": token }), // Pass only token when valid
-                )
-            } else {
-                // Token exists but seems invalid (e.g., already used)
-                format::render().view(
-                    &v,
-                    "auth/reset-password.html",
-                    data!({ "error": "Invalid or expired reset link." }), // Pass only error when invalid
-                )
-            }
+        Ok(_) => format::render().view(
+            &v,
+            "auth/reset-password.html",
+            data!({ "token": token }), // Pass only token when valid
+        ),
+        Err(err) if tokens::is_expired(&err) => {
+            redirect(&expired_link_url(EXPIRED_LINK_RESET), headers)
         }
         Err(_) => {
-            // Token not found or other DB error
+            // Token not found (already used) or other DB error
             format::render().view(
                 &v,
                 "auth/reset-password.html",
@@ -704,6 +1298,7 @@ async fn verify_email(
     ViewEngine(v): ViewEngine<TeraView>,
     State(ctx): State<AppContext>,
     Path(token): Path<String>,
+    headers: HeaderMap,
 ) -> Result<Response> {
     let user_result = users::Model::find_by_verification_token(&ctx.db, &token).await;
 
@@ -724,6 +1319,17 @@ async fn verify_email(
             let user_active_model = user.clone().into_active_model();
             match user_active_model.verified(&ctx.db).await {
                 Ok(verified_user_model) => {
+                    // The verified address may have been invited to teams
+                    let invited_teams = team_memberships::Model::attach_email_invitations(
+                        &ctx.db,
+                        &verified_user_model,
+                    )
+                    .await
+                    .unwrap_or_else(|e| {
+                        tracing::error!(user_email = %verified_user_model.email, error = ?e, "Failed to attach the team invitations of the verified email.");
+                        Vec::new()
+                    });
+
                     // Now try to fetch and update the PGP key using the new model method
                     let fetch_result = verified_user_model
                         .clone()
@@ -746,6 +1352,19 @@ async fn verify_email(
                         }
                     };
 
+                    let message = if invited_teams.is_empty() {
+                        message
+                    } else {
+                        let team_names: Vec<&str> = invited_teams
+                            .iter()
+                            .map(|team| team.name.as_str())
+                            .collect();
+                        format!(
+                            "{message} You have been invited to join these teams: {}. Accept or decline the invitations from your invitations page.",
+                            team_names.join(", ")
+                        )
+                    };
+
                     render_template(
                         &v,
                         "auth/verify.html",
@@ -769,6 +1388,9 @@ async fn verify_email(
                 }
             }
         }
+        Err(err) if tokens::is_expired(&err) => {
+            redirect(&expired_link_url(EXPIRED_LINK_VERIFICATION), headers)
+        }
         Err(err) => {
             tracing::error!(
                 message = "Invalid or expired email verification token",
@@ -787,13 +1409,38 @@ async fn verify_email(
     }
 }
 
-/// Handles user logout by clearing the auth token cookie
+/// Handles user logout by revoking the current session and clearing the auth
+/// token cookie
 #[debug_handler]
-async fn handle_logout(State(ctx): State<AppContext>) -> Result<Response> {
-    // TODO: Implement server-side JWT invalidation (e.g., token blacklist)
-    // This implementation only clears the client-side cookie. The JWT itself
-    // remains valid until it expires. For a fully secure logout, the token
-    // should be invalidated on the server-side as well.
+async fn handle_logout(auth: JWTOpt, State(ctx): State<AppContext>) -> Result<Response> {
+    // Logging out ends an impersonation too
+    if let Some(jti) = auth.claims.as_ref().and_then(sessions::jti_from_claims)
+        && let Ok(session) = sessions::Model::find_by_jti(&ctx.db, jti).await
+        && session.revoked_at.is_none()
+        && let Some(admin_id) = session.impersonator_id
+        && let Err(err) = impersonation_events::Model::record(
+            &ctx.db,
+            impersonation_events::EVENT_STOP,
+            admin_id,
+            session.user_id,
+            jti,
+            session.ip.as_deref(),
+        )
+        .await
+    {
+        tracing::error!(
+            message = "Failed to record the end of an impersonation on logout,",
+            error = err.to_string(),
+        );
+    }
+    if let Some(jti) = auth.claims.as_ref().and_then(sessions::jti_from_claims)
+        && let Err(err) = sessions::Model::revoke_by_jti(&ctx.db, jti).await
+    {
+        tracing::error!(
+            message = "Failed to revoke session on logout,",
+            error = err.to_string(),
+        );
+    }
     let redirect_url = match is_oidc_auth(&ctx) {
         true => "/oidc/logout",
         false => "/auth/login",
@@ -825,43 +1472,46 @@ async fn handle_reset_password(
         );
     }
 
-    // Find user by reset token
+    // Find user by reset token; the finder checks that the token has not expired
     match users::Model::find_by_reset_token(&ctx.db, &form.token).await {
         Ok(user) => {
-            // Check if token is actually associated with this user and potentially check expiry
-            if user.reset_token.as_deref() == Some(&form.token) && user.reset_sent_at.is_some() {
-                // Use the reset_password method on ActiveModel
-                match user
-                    .into_active_model()
-                    .reset_password(&ctx.db, &form.password)
-                    .await
-                {
-                    Ok(_) => {
-                        // Redirect to login page with success message
-                        redirect("/auth/login?reset=success", headers)
-                    }
-                    Err(e) => {
+            let user_pid = user.pid;
+            // Use the reset_password method on ActiveModel
+            let policy = PasswordPolicy::from_context(&ctx)?;
+            match user
+                .into_active_model()
+                .reset_password(&ctx.db, &form.password, &policy)
+                .await
+            {
+                Ok(user) => {
+                    // Sign out every device still holding a token issued with the old password
+                    if let Err(e) =
+                        sessions::Model::revoke_all_for_user(&ctx.db, user.id, None).await
+                    {
                         tracing::error!(
-                            "Failed to update password for token {}: {}",
-                            &form.token,
+                            "Failed to revoke sessions after password reset for user {}: {}",
+                            user.pid,
                             e
                         );
-                        error_fragment(
-                            &v,
-                            "Failed to reset password. Please try again.",
-                            "#error-container",
-                        )
                     }
+                    // Redirect to login page with success message
+                    redirect("/auth/login?reset=success", headers)
+                }
+                // The new password does not comply with the password policy
+                Err(ModelError::Message(msg)) => error_fragment(&v, &msg, "#error-container"),
+                Err(e) => {
+                    tracing::error!("Failed to update password for user {}: {}", user_pid, e);
+                    error_fragment(
+                        &v,
+                        "Failed to reset password. Please try again.",
+                        "#error-container",
+                    )
                 }
-            } else {
-                // Token mismatch or already cleared - treat as invalid
-                error_fragment(
-                    &v,
-                    "Invalid or expired password reset link.",
-                    "#error-container",
-                )
             }
         }
+        Err(err) if tokens::is_expired(&err) => {
+            redirect(&expired_link_url(EXPIRED_LINK_RESET), headers)
+        }
         Err(_) => {
             // User not found for the token
             error_fragment(
@@ -873,6 +1523,105 @@ async fn handle_reset_password(
     }
 }
 
+/// Renders the page shown when an emailed link has expired, offering to send
+/// a new one
+#[debug_handler]
+async fn link_expired(
+    ViewEngine(v): ViewEngine<TeraView>,
+    Path(kind): Path<String>,
+) -> Result<Response> {
+    if !EXPIRED_LINK_KINDS.contains(&kind.as_str()) {
+        return not_found();
+    }
+    render_template(&v, "auth/link-expired.html", data!({ "kind": kind }))
+}
+
+/// Sends a new email verification link to an account that is not verified
+/// yet. The same response is returned whether the account exists or not.
+#[debug_handler]
+async fn handle_resend_verification(
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    Form(form): Form<ForgotPasswordParams>,
+) -> Result<Response> {
+    match users::Model::find_by_email(&ctx.db, &form.email).await {
+        Ok(user) if user.email_verified_at.is_none() => {
+            match user
+                .into_active_model()
+                .generate_email_verification_token(&ctx.db, &TokenLifetimes::from_context(&ctx)?)
+                .await
+            {
+                Ok((user_with_token, verification_token)) => {
+                    if let Err(e) =
+                        AuthMailer::send_welcome(&ctx, &user_with_token, &verification_token).await
+                    {
+                        error!(user_email = %form.email, error = ?e, "Failed to resend verification email");
+                    } else if let Err(e) = user_with_token
+                        .into_active_model()
+                        .set_email_verification_sent(&ctx.db)
+                        .await
+                    {
+                        error!(user_email = %form.email, error = ?e, "Failed to set email verification sent timestamp");
+                    }
+                }
+                Err(e) => {
+                    error!(user_email = %form.email, error = ?e, "Failed to generate email verification token");
+                }
+            }
+        }
+        Ok(_) => {
+            info!(user_email = %form.email, "Verification link requested for an already verified user");
+        }
+        Err(ModelError::EntityNotFound) => {
+            info!(user_email = %form.email, "Verification link requested for non-existent user");
+        }
+        Err(e) => {
+            error!(user_email = %form.email, error = ?e, "Database error finding user for verification link");
+        }
+    }
+
+    render_template(
+        &v,
+        "fragments/success_message.html",
+        data!({
+            "message": "If an account with that email address is waiting for verification, we have sent it a new verification link.",
+        }),
+    )
+}
+
+/// Renders the strength indicator of a password being typed, along with the
+/// rules of the password policy it does not comply with yet
+#[debug_handler]
+async fn password_strength(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    Form(form): Form<PasswordStrengthForm>,
+) -> Result<Response> {
+    if form.password.is_empty() {
+        return format::html("");
+    }
+
+    let (name, email) = match auth.user {
+        Some(user) => (user.name, user.email),
+        None => (
+            form.name.unwrap_or_default(),
+            form.email.unwrap_or_default(),
+        ),
+    };
+    let user_inputs = [name.as_str(), email.as_str()];
+    let policy = PasswordPolicy::from_context(&ctx)?;
+
+    render_template(
+        &v,
+        "auth/_password_strength.html",
+        data!({
+            "strength": estimate_strength(&form.password, &user_inputs),
+            "violations": policy.violations(&form.password, &user_inputs),
+        }),
+    )
+}
+
 /// Authentication page routes
 pub fn routes() -> Routes {
     Routes::new()
@@ -886,6 +1635,25 @@ pub fn routes() -> Routes {
         .add("/reset-email-sent", get(render_reset_email_sent_page))
         .add("/reset-password/{token}", get(reset_password))
         .add("/reset-password", post(handle_reset_password))
+        .add("/password-strength", post(password_strength))
+        .add("/link-expired/{kind}", get(link_expired))
+        .add("/resend-verification", post(handle_resend_verification))
         .add("/verify/{token}", get(verify_email))
         .add("/logout", post(handle_logout))
+        .add(
+            "/login/2fa",
+            get(login_second_factor).post(handle_login_second_factor),
+        )
+        .add(
+            "/login/2fa/webauthn/start",
+            post(login_second_factor_webauthn_start),
+        )
+        .add(
+            "/login/2fa/webauthn/finish",
+            post(login_second_factor_webauthn_finish),
+        )
+        .add("/webauthn/login/start", post(webauthn_login_start))
+        .add("/webauthn/login/finish", post(webauthn_login_finish))
+        .add("/webauthn/register/start", post(webauthn_register_start))
+        .add("/webauthn/register/finish", post(webauthn_register_finish))
 }
@@ -5,6 +5,8 @@ pub mod admin_pages;
 pub mod auth_api;
 pub mod auth_pages;
 pub mod home_pages;
+pub mod oidc_pages;
+pub mod organizations_pages;
 pub mod pgp_pages;
 pub mod ssh_key_api;
 pub mod teams_api;
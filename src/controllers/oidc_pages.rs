@@ -0,0 +1,205 @@
+use crate::{
+    middleware::auth_no_error::JWTWithUserOpt,
+    models::{
+        oidc::{OIDC_LOGIN_EXPIRATION_SEC, OidcConfig},
+        sessions, users,
+    },
+    views::{error_page, redirect},
+};
+use axum::{
+    debug_handler,
+    extract::{Path, Query, State},
+    http::{HeaderMap, HeaderValue},
+    response::Response,
+};
+use loco_rs::prelude::*;
+use serde::Deserialize;
+
+/// Name of the cookie holding the state of a login started at an identity provider
+const OIDC_LOGIN_COOKIE: &str = "oidc_login";
+
+/// Query parameters of the callback of an identity provider
+#[derive(Debug, Deserialize)]
+pub struct CallbackParams {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+/// Helper function to read a cookie value from the request headers
+fn get_cookie(headers: &HeaderMap, name: &str) -> Option<String> {
+    let jar = axum_extra::extract::cookie::CookieJar::from_headers(headers);
+    jar.get(name)
+        .map(|cookie| cookie.value().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Redirects the user to an identity provider to log in
+#[debug_handler]
+async fn start_login(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    Path(provider): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    if auth.user.is_some() {
+        return redirect("/home", headers);
+    }
+
+    let config = OidcConfig::from_context(&ctx)?;
+    let Ok(provider) = config.provider(&provider) else {
+        return error_page(&v, "Unknown identity provider.", None);
+    };
+
+    match provider.start_login(&ctx).await {
+        Ok((authorize_url, state_token)) => {
+            let mut response = redirect(&authorize_url, headers)?;
+            // The provider sends the user back with a cross-site redirect,
+            // so the cookie cannot be SameSite=Strict
+            response.headers_mut().append(
+                axum::http::header::SET_COOKIE,
+                HeaderValue::from_str(&format!(
+                    "{OIDC_LOGIN_COOKIE}={state_token}; Path=/auth/oidc; HttpOnly; SameSite=Lax; Max-Age={OIDC_LOGIN_EXPIRATION_SEC}"
+                ))?,
+            );
+            Ok(response)
+        }
+        Err(err) => {
+            tracing::error!(
+                message = "Failed to start single sign-on login,",
+                provider = provider.client_identifier,
+                error = err.to_string(),
+            );
+            error_page(
+                &v,
+                &format!(
+                    "Could not reach {}. Please try again later.",
+                    provider.name()
+                ),
+                None,
+            )
+        }
+    }
+}
+
+/// Completes a login when the identity provider sends the user back
+#[debug_handler]
+async fn callback(
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    Path(provider): Path<String>,
+    Query(params): Query<CallbackParams>,
+    headers: HeaderMap,
+    device: sessions::SessionDevice,
+) -> Result<Response> {
+    let config = OidcConfig::from_context(&ctx)?;
+    let Ok(provider) = config.provider(&provider) else {
+        return error_page(&v, "Unknown identity provider.", None);
+    };
+
+    if let Some(error) = params.error {
+        tracing::info!(
+            message = "Single sign-on login refused by the provider,",
+            provider = provider.client_identifier,
+            error = error,
+            error_description = params.error_description,
+        );
+        return error_page(
+            &v,
+            &format!("{} did not let you log in.", provider.name()),
+            None,
+        );
+    }
+    let (Some(code), Some(state), Some(state_token)) = (
+        params.code,
+        params.state,
+        get_cookie(&headers, OIDC_LOGIN_COOKIE),
+    ) else {
+        return error_page(
+            &v,
+            "This single sign-on login is invalid or has expired. Please try again.",
+            None,
+        );
+    };
+
+    let identity = match provider
+        .finish_login(&ctx, &state_token, &state, &code)
+        .await
+    {
+        Ok(identity) => identity,
+        Err(err) => {
+            tracing::warn!(
+                message = "Failed to complete single sign-on login,",
+                provider = provider.client_identifier,
+                error = err.to_string(),
+            );
+            return error_page(
+                &v,
+                "This single sign-on login is invalid or has expired. Please try again.",
+                None,
+            );
+        }
+    };
+
+    let user = match provider.sign_in(&ctx, &identity).await {
+        Ok(user) => user,
+        Err(ModelError::Message(msg)) => return error_page(&v, &msg, None),
+        Err(err) => {
+            tracing::error!(
+                message = "Failed to sign in single sign-on user,",
+                provider = provider.client_identifier,
+                subject = identity.subject,
+                error = err.to_string(),
+            );
+            return error_page(&v, "Log in failed: Server error.", Some(err.into()));
+        }
+    };
+
+    if user.is_pending_approval() {
+        return error_page(
+            &v,
+            "Your account is waiting for the approval of an administrator.",
+            None,
+        );
+    }
+    if !user.is_active() {
+        return error_page(
+            &v,
+            "Your account has been suspended. Please contact an administrator.",
+            None,
+        );
+    }
+
+    let jwt_secret = ctx.config.get_jwt_config()?;
+    let token = user
+        .generate_jwt(&ctx.db, &jwt_secret.secret, &jwt_secret.expiration, &device)
+        .await?;
+
+    tracing::info!(
+        message = "User login from single sign-on successful,",
+        user_email = &user.email,
+        provider = provider.client_identifier,
+    );
+
+    let mut response = redirect("/home", headers)?;
+    response.headers_mut().append(
+        axum::http::header::SET_COOKIE,
+        HeaderValue::from_str(&format!("auth_token={token}; Path=/"))?,
+    );
+    response.headers_mut().append(
+        axum::http::header::SET_COOKIE,
+        HeaderValue::from_str(&format!(
+            "{OIDC_LOGIN_COOKIE}=; Path=/auth/oidc; Expires=Thu, 01 Jan 1970 00:00:00 GMT"
+        ))?,
+    );
+    Ok(response)
+}
+
+pub fn routes() -> Routes {
+    Routes::new()
+        .prefix("/auth/oidc")
+        .add("/{provider}", get(start_login))
+        .add("/{provider}/callback", get(callback))
+}
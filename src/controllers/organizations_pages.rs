@@ -0,0 +1,634 @@
+use crate::{
+    middleware::auth_no_error::JWTWithUserOpt,
+    models::{
+        _entities::users,
+        organization_memberships::{self, AddOrganizationMemberParams},
+        organizations::{self, OrganizationParams},
+        roles::{self, OWNER_ROLE},
+        team_memberships::UpdateRoleParams,
+    },
+    views::{error_fragment, error_page, redirect, render_template},
+};
+use axum::{
+    debug_handler,
+    extract::{Form, Path, State},
+    http::header::HeaderMap,
+    response::{Html, IntoResponse},
+};
+use loco_rs::{app::AppContext, prelude::*};
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use serde::Deserialize;
+use serde_json::json;
+
+/// Name of the cookie holding the pid of the organization chosen with the
+/// switcher of the layout, which narrows the list of teams
+const CURRENT_ORGANIZATION_COOKIE: &str = "current_organization";
+
+/// Reads the pid of the organization chosen with the switcher, if any
+#[must_use]
+pub fn current_organization_pid(headers: &HeaderMap) -> Option<String> {
+    let jar = axum_extra::extract::cookie::CookieJar::from_headers(headers);
+    jar.get(CURRENT_ORGANIZATION_COOKIE)
+        .map(|cookie| cookie.value().to_string())
+        .filter(|pid| !pid.is_empty())
+}
+
+/// Response refreshing the organization page after an action
+fn refresh_page() -> Result<Response> {
+    Ok(Response::builder()
+        .header("HX-Refresh", "true")
+        .body(axum::body::Body::empty())?)
+}
+
+/// Finds an organization and checks that the user is one of its Owners
+async fn find_owned_organization(
+    ctx: &AppContext,
+    organization_pid: &str,
+    user: &users::Model,
+) -> std::result::Result<organizations::Model, String> {
+    let organization = organizations::Model::find_by_pid(&ctx.db, organization_pid)
+        .await
+        .map_err(|_| "Organization not found".to_string())?;
+    match organization.is_owner(&ctx.db, user.id).await {
+        Ok(true) => Ok(organization),
+        Ok(false) => Err("Only the owners of the organization can manage it".to_string()),
+        Err(e) => {
+            tracing::error!(
+                "Failed to check the role of user {} in organization {}: {}",
+                user.id,
+                organization.id,
+                e
+            );
+            Err("Could not verify your permissions. Please try again later.".to_string())
+        }
+    }
+}
+
+/// List organizations page
+#[debug_handler]
+async fn list_organizations(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let Some(user) = auth.user else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+    let organizations = match organizations::Model::list_for_user(&ctx.db, user.id).await {
+        Ok(organizations) => organizations
+            .into_iter()
+            .map(|(organization, role)| {
+                json!({
+                    "pid": organization.pid.to_string(),
+                    "name": organization.name,
+                    "description": organization.description,
+                    "role": role,
+                })
+            })
+            .collect::<Vec<_>>(),
+        Err(e) => {
+            tracing::error!("Failed to load organizations for user {}: {}", user.id, e);
+            return error_page(
+                &v,
+                "Could not load your organizations. Please try again later.",
+                Some(e.into()),
+            );
+        }
+    };
+
+    render_template(
+        &v,
+        "organizations/list.html",
+        data!({
+            "user": &user,
+            "organizations": &organizations,
+            "active_page": "organizations",
+            "invitation_count": &layout_context.invitation_count,
+            "pending_user_count": &layout_context.pending_user_count,
+            "is_app_admin": &layout_context.is_app_admin,
+        }),
+    )
+}
+
+/// Create organization page
+#[debug_handler]
+async fn create_organization_page(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let Some(user) = auth.user else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+    render_template(
+        &v,
+        "organizations/new.html",
+        data!({
+            "user": &user,
+            "active_page": "organizations",
+            "invitation_count": &layout_context.invitation_count,
+            "pending_user_count": &layout_context.pending_user_count,
+            "is_app_admin": &layout_context.is_app_admin,
+        }),
+    )
+}
+
+/// Create organization handler, the creator becoming its Owner
+#[debug_handler]
+async fn create_organization_handler(
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    auth: JWTWithUserOpt<users::Model>,
+    headers: HeaderMap,
+    Form(params): Form<OrganizationParams>,
+) -> Result<impl IntoResponse> {
+    let Some(user) = auth.user else {
+        return redirect("/auth/login", headers);
+    };
+
+    let organization = match organizations::Model::create(&ctx.db, user.id, &params).await {
+        Ok(organization) => organization,
+        Err(ModelError::Message(msg)) => {
+            return error_fragment(&v, &msg, "#error-container");
+        }
+        Err(ModelError::Validation(_)) => {
+            return error_fragment(
+                &v,
+                "Name must be at least 2 characters long.",
+                "#error-container",
+            );
+        }
+        Err(e) => {
+            tracing::error!("Failed to create organization: {}", e);
+            return error_fragment(
+                &v,
+                "Failed to create organization due to an unexpected error.",
+                "#error-container",
+            );
+        }
+    };
+
+    redirect(&format!("/organizations/{}", organization.pid), headers)
+}
+
+/// Organization details page, with its members and teams
+#[debug_handler]
+async fn organization_details(
+    ViewEngine(v): ViewEngine<TeraView>,
+    auth: JWTWithUserOpt<users::Model>,
+    State(ctx): State<AppContext>,
+    Path(organization_pid): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let Some(user) = auth.user else {
+        return redirect("/auth/login", headers);
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+    let Ok(organization) = organizations::Model::find_by_pid(&ctx.db, &organization_pid).await
+    else {
+        return error_page(&v, "Organization not found", None);
+    };
+    let membership = match organization.membership(&ctx.db, user.id).await {
+        Ok(Some(membership)) => membership,
+        Ok(None) => {
+            return error_page(
+                &v,
+                "You are not authorized to view this organization because you are not one of its members",
+                None,
+            );
+        }
+        Err(e) => {
+            tracing::error!(
+                "Failed to load membership for user {} in organization {}: {}",
+                user.id,
+                organization.id,
+                e
+            );
+            return error_page(
+                &v,
+                "Could not check your organization membership. Please try again later.",
+                Some(e.into()),
+            );
+        }
+    };
+
+    let members = match organization.get_members(&ctx.db).await {
+        Ok(members) => members
+            .into_iter()
+            .map(|(member, membership)| {
+                json!({
+                    "user_pid": member.pid.to_string(),
+                    "name": member.name,
+                    "email": member.email,
+                    "role": membership.role,
+                })
+            })
+            .collect::<Vec<_>>(),
+        Err(e) => {
+            tracing::error!(
+                "Failed to load members of organization {}: {}",
+                organization.id,
+                e
+            );
+            return error_page(
+                &v,
+                "Could not load the organization members. Please try again later.",
+                Some(e.into()),
+            );
+        }
+    };
+    let teams = match organization.teams(&ctx.db).await {
+        Ok(teams) => teams,
+        Err(e) => {
+            tracing::error!(
+                "Failed to load teams of organization {}: {}",
+                organization.id,
+                e
+            );
+            return error_page(
+                &v,
+                "Could not load the organization teams. Please try again later.",
+                Some(e.into()),
+            );
+        }
+    };
+    let roles = match roles::Model::names(&ctx.db).await {
+        Ok(roles) => roles,
+        Err(e) => {
+            tracing::error!("Failed to load roles: {}", e);
+            return error_page(
+                &v,
+                "Could not load the roles. Please try again later.",
+                Some(e.into()),
+            );
+        }
+    };
+
+    render_template(
+        &v,
+        "organizations/show.html",
+        data!({
+            "user": &user,
+            "organization": {
+                "pid": organization.pid.to_string(),
+                "name": organization.name,
+                "description": organization.description,
+            },
+            "is_owner": membership.role == OWNER_ROLE,
+            "role": membership.role,
+            "members": &members,
+            "teams": &teams,
+            "roles": &roles,
+            "active_page": "organizations",
+            "invitation_count": &layout_context.invitation_count,
+            "pending_user_count": &layout_context.pending_user_count,
+            "is_app_admin": &layout_context.is_app_admin,
+        }),
+    )
+}
+
+/// Update organization handler
+#[debug_handler]
+async fn update_organization_handler(
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    auth: JWTWithUserOpt<users::Model>,
+    Path(organization_pid): Path<String>,
+    headers: HeaderMap,
+    Form(params): Form<OrganizationParams>,
+) -> Result<impl IntoResponse> {
+    let Some(user) = auth.user else {
+        return redirect("/auth/login", headers);
+    };
+    let organization = match find_owned_organization(&ctx, &organization_pid, &user).await {
+        Ok(organization) => organization,
+        Err(msg) => return error_fragment(&v, &msg, "#organization-errors"),
+    };
+
+    match organization.update(&ctx.db, &params).await {
+        Ok(_) => refresh_page(),
+        Err(ModelError::Message(msg)) => error_fragment(&v, &msg, "#organization-errors"),
+        Err(ModelError::Validation(_)) => error_fragment(
+            &v,
+            "Name must be at least 2 characters long.",
+            "#organization-errors",
+        ),
+        Err(e) => {
+            tracing::error!("Failed to update organization {}: {}", organization.id, e);
+            error_fragment(
+                &v,
+                "Could not update the organization. Please try again later.",
+                "#organization-errors",
+            )
+        }
+    }
+}
+
+/// Delete organization handler, refused while the organization has teams
+#[debug_handler]
+async fn delete_organization(
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    auth: JWTWithUserOpt<users::Model>,
+    Path(organization_pid): Path<String>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse> {
+    let Some(user) = auth.user else {
+        return redirect("/auth/login", headers);
+    };
+    let organization = match find_owned_organization(&ctx, &organization_pid, &user).await {
+        Ok(organization) => organization,
+        Err(msg) => return error_fragment(&v, &msg, "#organization-errors"),
+    };
+
+    match organization.delete(&ctx.db).await {
+        Ok(()) => redirect("/organizations", headers),
+        Err(ModelError::Message(msg)) => error_fragment(&v, &msg, "#organization-errors"),
+        Err(e) => {
+            tracing::error!("Failed to delete organization {}: {}", organization_pid, e);
+            error_fragment(
+                &v,
+                "Could not delete the organization. Please try again later.",
+                "#organization-errors",
+            )
+        }
+    }
+}
+
+/// Add member handler: an Owner adds a user by name with a role
+#[debug_handler]
+async fn add_member_handler(
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    auth: JWTWithUserOpt<users::Model>,
+    Path(organization_pid): Path<String>,
+    headers: HeaderMap,
+    Form(params): Form<AddOrganizationMemberParams>,
+) -> Result<impl IntoResponse> {
+    let Some(user) = auth.user else {
+        return redirect("/auth/login", headers);
+    };
+    let organization = match find_owned_organization(&ctx, &organization_pid, &user).await {
+        Ok(organization) => organization,
+        Err(msg) => return error_fragment(&v, &msg, "#member-errors"),
+    };
+
+    let member = match users::Entity::find()
+        .filter(users::Column::Name.eq(params.user_name.trim()))
+        .one(&ctx.db)
+        .await
+    {
+        Ok(Some(member)) => member,
+        Ok(None) => return error_fragment(&v, "User not found", "#member-errors"),
+        Err(e) => {
+            tracing::error!("Failed to find user {}: {}", params.user_name, e);
+            return error_fragment(
+                &v,
+                "Could not find the user. Please try again later.",
+                "#member-errors",
+            );
+        }
+    };
+
+    match organization_memberships::Model::add_member(
+        &ctx.db,
+        organization.id,
+        &member,
+        &params.role,
+    )
+    .await
+    {
+        Ok(_) => {
+            tracing::info!(
+                organization = organization.name,
+                member_pid = %member.pid,
+                role = params.role,
+                added_by = %user.pid,
+                "Organization member added"
+            );
+            refresh_page()
+        }
+        Err(ModelError::Message(msg)) => error_fragment(&v, &msg, "#member-errors"),
+        Err(e) => {
+            tracing::error!(
+                "Failed to add user {} to organization {}: {}",
+                member.id,
+                organization.id,
+                e
+            );
+            error_fragment(
+                &v,
+                "Could not add the member. Please try again later.",
+                "#member-errors",
+            )
+        }
+    }
+}
+
+/// Update member role handler
+#[debug_handler]
+async fn update_member_role(
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    auth: JWTWithUserOpt<users::Model>,
+    Path((organization_pid, user_pid)): Path<(String, String)>,
+    headers: HeaderMap,
+    Form(params): Form<UpdateRoleParams>,
+) -> Result<impl IntoResponse> {
+    let Some(user) = auth.user else {
+        return redirect("/auth/login", headers);
+    };
+    let organization = match find_owned_organization(&ctx, &organization_pid, &user).await {
+        Ok(organization) => organization,
+        Err(msg) => return error_fragment(&v, &msg, "#member-errors"),
+    };
+    let Ok(member) = users::Model::find_by_pid(&ctx.db, &user_pid).await else {
+        return error_fragment(&v, "Target user not found.", "#member-errors");
+    };
+    let Ok(membership) = organization_memberships::Model::find_by_organization_and_user(
+        &ctx.db,
+        organization.id,
+        member.id,
+    )
+    .await
+    else {
+        return error_fragment(
+            &v,
+            "User is not a member of this organization",
+            "#member-errors",
+        );
+    };
+
+    match membership.update_role(&ctx.db, &params.role).await {
+        Ok(_) => refresh_page(),
+        Err(ModelError::Message(msg)) => error_fragment(&v, &msg, "#member-errors"),
+        Err(e) => {
+            tracing::error!(
+                "Failed to update role for user {} in organization {}: {}",
+                member.id,
+                organization.id,
+                e
+            );
+            error_fragment(
+                &v,
+                "Could not update member role. Please try again later.",
+                "#member-errors",
+            )
+        }
+    }
+}
+
+/// Remove member handler: Owners remove members, and members leave
+#[debug_handler]
+async fn remove_member(
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    auth: JWTWithUserOpt<users::Model>,
+    Path((organization_pid, user_pid)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse> {
+    let Some(user) = auth.user else {
+        return redirect("/auth/login", headers);
+    };
+    let Ok(member) = users::Model::find_by_pid(&ctx.db, &user_pid).await else {
+        return error_fragment(&v, "Target user not found.", "#member-errors");
+    };
+    let organization = if member.id == user.id {
+        match organizations::Model::find_by_pid(&ctx.db, &organization_pid).await {
+            Ok(organization) => organization,
+            Err(_) => return error_fragment(&v, "Organization not found", "#member-errors"),
+        }
+    } else {
+        match find_owned_organization(&ctx, &organization_pid, &user).await {
+            Ok(organization) => organization,
+            Err(msg) => return error_fragment(&v, &msg, "#member-errors"),
+        }
+    };
+    let Ok(membership) = organization_memberships::Model::find_by_organization_and_user(
+        &ctx.db,
+        organization.id,
+        member.id,
+    )
+    .await
+    else {
+        return error_fragment(
+            &v,
+            "User is not a member of this organization",
+            "#member-errors",
+        );
+    };
+
+    match membership.remove(&ctx.db).await {
+        Ok(()) if member.id == user.id => redirect("/organizations", headers),
+        Ok(()) => refresh_page(),
+        Err(ModelError::Message(msg)) => error_fragment(&v, &msg, "#member-errors"),
+        Err(e) => {
+            tracing::error!(
+                "Failed to remove user {} from organization {}: {}",
+                member.id,
+                organization.id,
+                e
+            );
+            error_fragment(
+                &v,
+                "Could not remove the member. Please try again later.",
+                "#member-errors",
+            )
+        }
+    }
+}
+
+/// Organization switcher of the layout, loaded by every page
+#[debug_handler]
+async fn switcher(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let Some(user) = auth.user else {
+        return Ok(Html(String::new()).into_response());
+    };
+    let organizations = organizations::Model::list_for_user(&ctx.db, user.id)
+        .await?
+        .into_iter()
+        .map(|(organization, _)| organization)
+        .collect::<Vec<_>>();
+    render_template(
+        &v,
+        "organizations/_switcher.html",
+        data!({
+            "organizations": &organizations,
+            "current_organization": current_organization_pid(&headers),
+        }),
+    )
+}
+
+/// Form of the organization switcher
+#[derive(Debug, Deserialize)]
+struct SwitchOrganizationForm {
+    /// Pid of the chosen organization, empty for every team
+    #[serde(default)]
+    organization: String,
+}
+
+/// Chooses the organization whose teams are listed, remembered in a cookie
+#[debug_handler]
+async fn switch_organization(
+    auth: JWTWithUserOpt<users::Model>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Form(form): Form<SwitchOrganizationForm>,
+) -> Result<Response> {
+    let Some(user) = auth.user else {
+        return redirect("/auth/login", headers);
+    };
+    let pid = if form.organization.is_empty() {
+        String::new()
+    } else {
+        let organization = organizations::Model::find_by_pid(&ctx.db, &form.organization).await?;
+        if organization.membership(&ctx.db, user.id).await?.is_none() {
+            return unauthorized("You are not a member of this organization");
+        }
+        organization.pid.to_string()
+    };
+    Ok(Response::builder()
+        .header("HX-Redirect", "/teams")
+        .header(
+            "Set-Cookie",
+            format!("{CURRENT_ORGANIZATION_COOKIE}={pid}; Path=/; SameSite=Lax"),
+        )
+        .body(axum::body::Body::empty())?)
+}
+
+pub fn routes() -> Routes {
+    Routes::new()
+        .prefix("/organizations")
+        .add("/", get(list_organizations))
+        .add("/new", get(create_organization_page))
+        .add("/new", post(create_organization_handler))
+        .add("/switcher", get(switcher))
+        .add("/switch", post(switch_organization))
+        .add("/{organization_pid}", get(organization_details))
+        .add("/{organization_pid}", delete(delete_organization))
+        .add(
+            "/{organization_pid}/update",
+            post(update_organization_handler),
+        )
+        .add("/{organization_pid}/members", post(add_member_handler))
+        .add(
+            "/{organization_pid}/members/{user_pid}/role",
+            post(update_member_role),
+        )
+        .add(
+            "/{organization_pid}/members/{user_pid}",
+            delete(remove_member),
+        )
+}
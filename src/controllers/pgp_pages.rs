@@ -1,8 +1,8 @@
 // src/controllers/pgp_pages.rs
 use crate::{
+    controllers::auth_pages::{EXPIRED_LINK_PGP, expired_link_url},
     middleware::auth_no_error::JWTWithUserOpt, // Re-use existing auth middleware
-    models::_entities::users::Column,
-    models::users,
+    models::{tokens, users},
     views::{error_page, redirect}, // Use existing view helpers
 };
 use axum::http::HeaderMap;
@@ -15,14 +15,6 @@ use axum::{
 use loco_rs::prelude::*;
 use loco_rs::prelude::{AppContext, TeraView, ViewEngine};
 
-async fn find_user_by_pgp_token(db: &DatabaseConnection, token: &str) -> ModelResult<users::Model> {
-    let user = users::Entity::find()
-        .filter(Column::PgpVerificationToken.eq(token))
-        .one(db)
-        .await?;
-    user.ok_or_else(|| ModelError::EntityNotFound)
-}
-
 #[debug_handler]
 async fn verify_pgp_token(
     Path(token): Path<String>,
@@ -31,7 +23,7 @@ async fn verify_pgp_token(
     auth: JWTWithUserOpt<users::Model>,  // Get current user for context/potential checks
     headers: HeaderMap,
 ) -> Result<Response> {
-    match find_user_by_pgp_token(&ctx.db, &token).await {
+    match users::Model::find_by_pgp_verification_token(&ctx.db, &token).await {
         Ok(user) => {
             // Enforce that the token belongs to the currently logged-in user
             if let Some(current_user) = auth.user {
@@ -76,6 +68,9 @@ async fn verify_pgp_token(
                 }
             }
         }
+        Err(err) if tokens::is_expired(&err) => {
+            redirect(&expired_link_url(EXPIRED_LINK_PGP), headers)
+        }
         Err(ModelError::EntityNotFound) => {
             tracing::warn!(
                 "Invalid or expired PGP verification token received: {}",
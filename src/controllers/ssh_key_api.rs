@@ -1,9 +1,11 @@
 use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64_STANDARD};
-use loco_rs::controller::extractor::auth;
 use loco_rs::prelude::*;
 use serde::{Deserialize, Serialize};
 
-use crate::models::_entities::{ssh_keys, users};
+use crate::{
+    middleware::auth_token::ApiAuth,
+    models::{_entities::ssh_keys, personal_access_tokens},
+};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SshKeyPayload {
@@ -31,8 +33,9 @@ pub fn is_valid_ssh_public_key(key: &str) -> bool {
     BASE64_STANDARD.decode(parts[1]).is_ok()
 }
 
-async fn list_keys(auth: auth::JWT, State(ctx): State<AppContext>) -> Result<Response> {
-    let user = users::Model::find_by_pid(&ctx.db, &auth.claims.pid).await?;
+async fn list_keys(auth: ApiAuth, State(ctx): State<AppContext>) -> Result<Response> {
+    auth.require_scope(personal_access_tokens::SCOPE_SSH_KEYS_READ)?;
+    let user = auth.user;
 
     let keys = ssh_keys::Entity::find()
         .filter(ssh_keys::Column::UserId.eq(user.id))
@@ -43,11 +46,12 @@ async fn list_keys(auth: auth::JWT, State(ctx): State<AppContext>) -> Result<Res
 }
 
 async fn add_key(
-    auth: auth::JWT,
+    auth: ApiAuth,
     State(ctx): State<AppContext>,
     Form(params): Form<SshKeyPayload>,
 ) -> Result<Response> {
-    let user = users::Model::find_by_pid(&ctx.db, &auth.claims.pid).await?;
+    auth.require_scope(personal_access_tokens::SCOPE_SSH_KEYS_WRITE)?;
+    let user = auth.user;
 
     // Validate the input
     if params.public_key.is_empty() {
@@ -94,11 +98,12 @@ async fn add_key(
 }
 
 async fn delete_key(
-    auth: auth::JWT,
+    auth: ApiAuth,
     State(ctx): State<AppContext>,
     Path(key_id): Path<i32>,
 ) -> Result<Response> {
-    let user = users::Model::find_by_pid(&ctx.db, &auth.claims.pid).await?;
+    auth.require_scope(personal_access_tokens::SCOPE_SSH_KEYS_WRITE)?;
+    let user = auth.user;
 
     let key = ssh_keys::Entity::find_by_id(key_id)
         .filter(ssh_keys::Column::UserId.eq(user.id)) // Ensure the key belongs to the user
@@ -1,6 +1,6 @@
 use axum::debug_handler;
 use loco_rs::prelude::*;
-use sea_orm::{ColumnTrait, EntityTrait, PaginatorTrait};
+use sea_orm::{ColumnTrait, EntityTrait};
 
 use crate::{
     mailers::team::TeamMailer,
@@ -10,25 +10,28 @@ use crate::{
                 self, Column as TeamMembershipColumn, Entity as TeamMembershipEntity,
                 Model as TeamMembershipModel,
             },
-            teams::{Entity as TeamEntity, Model as TeamModel},
+            teams::Model as TeamModel,
             users::Entity as UserEntity,
         },
-        team_memberships::{InviteMemberParams, UpdateRoleParams, VALID_ROLES},
+        permissions, personal_access_tokens, roles,
+        team_memberships::{InvitationParams, InviteMemberParams, UpdateRoleParams},
         teams::{CreateTeamParams, UpdateTeamParams},
+        tokens::TokenLifetimes,
         users,
     },
     views::teams::{MemberResponse, TeamResponse},
 };
 
-use loco_rs::controller::extractor::auth::JWT;
+use crate::middleware::auth_token::ApiAuth;
 
 #[debug_handler]
 async fn create_team(
-    auth: JWT,
+    auth: ApiAuth,
     State(ctx): State<AppContext>,
     Json(params): Json<CreateTeamParams>,
 ) -> Result<Response> {
-    let user = users::Model::find_by_pid(&ctx.db, &auth.claims.pid).await?;
+    auth.require_scope(personal_access_tokens::SCOPE_TEAMS_WRITE)?;
+    let user = auth.user;
 
     // Create a new team
     let team = TeamModel::create_team(&ctx.db, user.id, &params).await?;
@@ -37,25 +40,16 @@ async fn create_team(
 }
 
 #[debug_handler]
-async fn list_teams(auth: JWT, State(ctx): State<AppContext>) -> Result<Response> {
-    let user = users::Model::find_by_pid(&ctx.db, &auth.claims.pid).await?;
+async fn list_teams(auth: ApiAuth, State(ctx): State<AppContext>) -> Result<Response> {
+    auth.require_scope(personal_access_tokens::SCOPE_TEAMS_READ)?;
+    let user = auth.user;
 
-    // Get all teams where the user is a member
-    let teams = TeamEntity::find()
-        .find_with_related(TeamMembershipEntity)
-        .all(&ctx.db)
+    // Get all teams where the user is a member, directly or through their
+    // organization
+    let teams = TeamModel::list_visible_to(&ctx.db, user.id)
         .await?
-        .into_iter()
-        .filter_map(|(team, memberships)| {
-            let is_member = memberships
-                .iter()
-                .any(|m| m.user_id == user.id && !m.pending);
-            if is_member {
-                Some(TeamResponse::from(&team))
-            } else {
-                None
-            }
-        })
+        .iter()
+        .map(TeamResponse::from)
         .collect::<Vec<_>>();
 
     format::json(teams)
@@ -63,15 +57,18 @@ async fn list_teams(auth: JWT, State(ctx): State<AppContext>) -> Result<Response
 
 #[debug_handler]
 async fn get_team(
-    auth: JWT,
+    auth: ApiAuth,
     State(ctx): State<AppContext>,
     Path(team_pid): Path<String>,
 ) -> Result<Response> {
-    let user = users::Model::find_by_pid(&ctx.db, &auth.claims.pid).await?;
+    auth.require_scope(personal_access_tokens::SCOPE_TEAMS_READ)?;
+    let user = auth.user;
     let team = TeamModel::find_by_pid(&ctx.db, &team_pid).await?;
 
-    // Check if user is a member of this team
-    let has_access = team.has_role(&ctx.db, user.id, "Observer").await?;
+    // Check if user may view this team
+    let has_access = team
+        .has_permission(&ctx.db, user.id, permissions::TEAM_VIEW)
+        .await?;
     if !has_access {
         return unauthorized("You are not a member of this team");
     }
@@ -81,18 +78,21 @@ async fn get_team(
 
 #[debug_handler]
 async fn update_team(
-    auth: JWT,
+    auth: ApiAuth,
     State(ctx): State<AppContext>,
     Path(team_pid): Path<String>,
     Json(params): Json<UpdateTeamParams>,
 ) -> Result<Response> {
-    let user = users::Model::find_by_pid(&ctx.db, &auth.claims.pid).await?;
+    auth.require_scope(personal_access_tokens::SCOPE_TEAMS_WRITE)?;
+    let user = auth.user;
     let team = TeamModel::find_by_pid(&ctx.db, &team_pid).await?;
 
-    // Check if user is an owner of this team
-    let is_owner = team.has_role(&ctx.db, user.id, "Owner").await?;
-    if !is_owner {
-        return unauthorized("Only team owners can update team details");
+    // Check if user may update this team
+    let may_update = team
+        .has_permission(&ctx.db, user.id, permissions::TEAM_UPDATE)
+        .await?;
+    if !may_update {
+        return unauthorized("You are not allowed to update the details of this team");
     }
 
     let updated_team = team.update(&ctx.db, &params).await?;
@@ -102,17 +102,20 @@ async fn update_team(
 
 #[debug_handler]
 async fn delete_team(
-    auth: JWT,
+    auth: ApiAuth,
     State(ctx): State<AppContext>,
     Path(team_pid): Path<String>,
 ) -> Result<Response> {
-    let user = users::Model::find_by_pid(&ctx.db, &auth.claims.pid).await?;
+    auth.require_scope(personal_access_tokens::SCOPE_TEAMS_WRITE)?;
+    let user = auth.user;
     let team = TeamModel::find_by_pid(&ctx.db, &team_pid).await?;
 
-    // Check if user is an owner of this team
-    let is_owner = team.has_role(&ctx.db, user.id, "Owner").await?;
-    if !is_owner {
-        return unauthorized("Only team owners can delete a team");
+    // Check if user may delete this team
+    let may_delete = team
+        .has_permission(&ctx.db, user.id, permissions::TEAM_DELETE)
+        .await?;
+    if !may_delete {
+        return unauthorized("You are not allowed to delete this team");
     }
 
     // Delete the team
@@ -129,15 +132,18 @@ async fn delete_team(
 
 #[debug_handler]
 async fn list_members(
-    auth: JWT,
+    auth: ApiAuth,
     State(ctx): State<AppContext>,
     Path(team_pid): Path<String>,
 ) -> Result<Response> {
-    let user = users::Model::find_by_pid(&ctx.db, &auth.claims.pid).await?;
+    auth.require_scope(personal_access_tokens::SCOPE_TEAMS_READ)?;
+    let user = auth.user;
     let team = TeamModel::find_by_pid(&ctx.db, &team_pid).await?;
 
-    // Check if user is a member of this team
-    let has_access = team.has_role(&ctx.db, user.id, "Observer").await?;
+    // Check if user may view this team
+    let has_access = team
+        .has_permission(&ctx.db, user.id, permissions::TEAM_VIEW)
+        .await?;
     if !has_access {
         return unauthorized("You are not a member of this team");
     }
@@ -152,9 +158,10 @@ async fn list_members(
     // Get user details for each membership
     let mut responses = Vec::new();
     for membership in memberships {
-        let user = UserEntity::find_by_id(membership.user_id)
-            .one(&ctx.db)
-            .await?;
+        let Some(user_id) = membership.user_id else {
+            continue;
+        };
+        let user = UserEntity::find_by_id(user_id).one(&ctx.db).await?;
         if let Some(user) = user {
             responses.push(MemberResponse {
                 user_pid: user.pid.to_string(),
@@ -170,23 +177,40 @@ async fn list_members(
 
 #[debug_handler]
 async fn invite_member(
-    auth: JWT,
+    auth: ApiAuth,
     State(ctx): State<AppContext>,
     Path(team_pid): Path<String>,
     Json(params): Json<InviteMemberParams>,
 ) -> Result<Response> {
-    let user = users::Model::find_by_pid(&ctx.db, &auth.claims.pid).await?;
+    auth.require_scope(personal_access_tokens::SCOPE_TEAMS_WRITE)?;
+    let user = auth.user;
     let team = TeamModel::find_by_pid(&ctx.db, &team_pid).await?;
 
-    // Check if user is an admin of this team
-    let is_admin = team.has_role(&ctx.db, user.id, "Administrator").await?;
-    if !is_admin {
-        return unauthorized("Only team administrators can invite members");
+    // Check if user may invite members into this team
+    let may_invite = team
+        .has_permission(&ctx.db, user.id, permissions::TEAM_INVITE)
+        .await?;
+    if !may_invite {
+        return unauthorized("You are not allowed to invite members into this team");
     }
+    let invitation_params = match InvitationParams::new(user.id, &params) {
+        Ok(invitation_params) => invitation_params,
+        Err(err) => return bad_request(err.to_string()),
+    };
 
-    // Find the target user by name
+    // Find the target user by name, or by email address when one was given
     let target_user = match users::Model::find_by_name(&ctx.db, &params.user_name).await {
         Ok(user) => user,
+        Err(ModelError::EntityNotFound) if params.user_name.contains('@') => {
+            match users::Model::find_by_email_ignoring_case(&ctx.db, &params.user_name).await {
+                Ok(user) => user,
+                Err(ModelError::EntityNotFound) => {
+                    return invite_email_address(&ctx, &user, &team, &params, &invitation_params)
+                        .await;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
         Err(_) => {
             return bad_request(format!("No user found with name {}", &params.user_name));
         }
@@ -201,7 +225,12 @@ async fn invite_member(
 
     if let Some(membership) = existing_membership {
         // User already has a relationship with this team
-        let error_message = if membership.pending {
+        let error_message = if membership.requested {
+            format!(
+                "User {} has requested to join this team. Approve their request instead",
+                params.user_name
+            )
+        } else if membership.pending {
             format!(
                 "User {} already has a pending invitation to this team",
                 params.user_name
@@ -216,9 +245,18 @@ async fn invite_member(
     // so, proceed with inviting to the team
 
     // Create invitation entity
-    /*let invitation =*/
-    match team_memberships::Model::create_invitation(&ctx.db, team.id, &params.user_name).await {
+    let lifetimes = TokenLifetimes::from_context(&ctx)?;
+    let (invitation, invitation_token) = match team_memberships::Model::create_invitation(
+        &ctx.db,
+        team.id,
+        &params.user_name,
+        &invitation_params,
+        &lifetimes,
+    )
+    .await
+    {
         Ok(invit) => invit,
+        Err(ModelError::Message(msg)) => return bad_request(msg),
         Err(e) => {
             // Something terribly wrong happened, abort with an error message
 
@@ -237,63 +275,117 @@ async fn invite_member(
     };
 
     // Send notification e_mail to target user
-    TeamMailer::send_invitation(&ctx, &user, &target_user, &team).await?;
+    TeamMailer::send_invitation(
+        &ctx,
+        &user,
+        &target_user,
+        &team,
+        &invitation,
+        &invitation_token,
+    )
+    .await?;
+
+    format::empty_json()
+}
+
+/// Invites an email address no account is registered with: the invitation
+/// waits for an account to be registered and verified with this address
+async fn invite_email_address(
+    ctx: &AppContext,
+    user: &users::Model,
+    team: &TeamModel,
+    params: &InviteMemberParams,
+    invitation_params: &InvitationParams,
+) -> Result<Response> {
+    let lifetimes = TokenLifetimes::from_context(ctx)?;
+    let (invitation, invitation_token) = match team_memberships::Model::create_email_invitation(
+        &ctx.db,
+        team.id,
+        &params.user_name,
+        invitation_params,
+        &lifetimes,
+    )
+    .await
+    {
+        Ok(invitation) => invitation,
+        Err(ModelError::Message(msg)) => return bad_request(msg),
+        Err(e) => {
+            tracing::error!(
+                error = e.to_string(),
+                team_id = team.id,
+                "Failed to create an email invitation"
+            );
+            return Err(Error::InternalServerError);
+        }
+    };
+
+    TeamMailer::send_signup_invitation(
+        ctx,
+        user,
+        params.user_name.trim(),
+        team,
+        &invitation,
+        &invitation_token,
+    )
+    .await?;
 
     format::empty_json()
 }
 
 #[debug_handler]
 async fn update_member_role(
-    auth: JWT,
+    auth: ApiAuth,
     State(ctx): State<AppContext>,
     Path((team_pid, user_pid)): Path<(String, String)>,
     Json(params): Json<UpdateRoleParams>,
 ) -> Result<Response> {
-    let current_user = users::Model::find_by_pid(&ctx.db, &auth.claims.pid).await?;
+    auth.require_scope(personal_access_tokens::SCOPE_TEAMS_WRITE)?;
+    let current_user = auth.user;
     let team = TeamModel::find_by_pid(&ctx.db, &team_pid).await?;
     let target_user = users::Model::find_by_pid(&ctx.db, &user_pid).await?;
 
     // Validate role
-    if !VALID_ROLES.contains(&params.role.as_str()) {
-        return bad_request(format!("Invalid role. Valid roles are: {:?}", VALID_ROLES));
-    }
-
-    // Check if current user is an owner of this team
-    let is_owner = team.has_role(&ctx.db, current_user.id, "Owner").await?;
-    if !is_owner {
-        return unauthorized("Only team owners can update member roles");
-    }
+    let role = match roles::Model::find_by_name(&ctx.db, &params.role).await {
+        Ok(role) => role,
+        Err(ModelError::EntityNotFound) => {
+            let role_names = roles::Model::names(&ctx.db).await?;
+            return bad_request(format!("Invalid role. Valid roles are: {role_names:?}"));
+        }
+        Err(e) => return Err(e.into()),
+    };
 
     // Get membership
     let membership =
         TeamMembershipModel::find_by_team_and_user(&ctx.db, team.id, target_user.id).await?;
 
-    // Cannot change owner's role if there's only one owner
-    if membership.role == "Owner" && params.role != "Owner" {
-        let owners_count = TeamMembershipEntity::find()
-            .filter(TeamMembershipColumn::TeamId.eq(team.id))
-            .filter(TeamMembershipColumn::Role.eq("Owner"))
-            .count(&ctx.db)
-            .await?;
-
-        if owners_count <= 1 {
-            return bad_request("Cannot change the role of the last owner");
-        }
+    // Check if current user may give this role to this member
+    match membership
+        .check_role_change_allowed(&ctx.db, &team, current_user.id, &role)
+        .await
+    {
+        Ok(()) => {}
+        Err(ModelError::Message(msg)) => return unauthorized(msg),
+        Err(e) => return Err(e.into()),
     }
 
-    // Update role
-    membership.update_role(&ctx.db, &params.role).await?;
+    // Update role, the model refusing to demote the last owner
+    match membership.update_role(&ctx.db, &params.role).await {
+        Ok(_) => {}
+        Err(ModelError::Message(msg)) => return bad_request(msg),
+        Err(e) => return Err(e.into()),
+    }
 
     format::empty_json()
 }
 
 #[debug_handler]
 async fn remove_member(
-    auth: JWT,
+    auth: ApiAuth,
     State(ctx): State<AppContext>,
     Path((team_pid, user_pid)): Path<(String, String)>,
 ) -> Result<Response> {
-    let current_user = users::Model::find_by_pid(&ctx.db, &auth.claims.pid).await?;
+    auth.require_scope(personal_access_tokens::SCOPE_TEAMS_WRITE)?;
+    let current_user = auth.user;
     let team = TeamModel::find_by_pid(&ctx.db, &team_pid).await?;
     let target_user = users::Model::find_by_pid(&ctx.db, &user_pid).await?;
 
@@ -311,55 +403,34 @@ async fn remove_member(
         .await?
         .ok_or_else(|| ModelError::msg("User is not a member of this team"))?;
 
-    // Check permissions
-    let current_user_is_owner = team.has_role(&ctx.db, current_user.id, "Owner").await?;
-    let current_user_is_admin = team
-        .has_role(&ctx.db, current_user.id, "Administrator")
-        .await?;
-
-    // Cannot remove an owner unless you're an owner
-    if target_membership.role == "Owner" && !current_user_is_owner {
-        return unauthorized("Only team owners can remove another owner");
-    }
-
-    // Cannot remove an admin unless you're an owner
-    if target_membership.role == "Administrator" && !current_user_is_owner {
-        return unauthorized("Only team owners can remove an administrator");
-    }
-
-    // Cannot remove a developer/observer unless you're an admin or owner
-    if !current_user_is_admin {
-        return unauthorized("Only team administrators and owners can remove members");
+    // Check if current user may remove this member
+    match target_membership
+        .check_removal_allowed(&ctx.db, &team, current_user.id)
+        .await
+    {
+        Ok(()) => {}
+        Err(ModelError::Message(msg)) => return unauthorized(msg),
+        Err(e) => return Err(e.into()),
     }
 
-    // Special case: Prevent removing the last owner
-    if target_membership.role == "Owner" {
-        // Count owners
-        let owner_count = TeamMembershipEntity::find()
-            .filter(TeamMembershipColumn::TeamId.eq(team.id))
-            .filter(TeamMembershipColumn::Role.eq("Owner"))
-            .filter(TeamMembershipColumn::Pending.eq(false))
-            .count(&ctx.db)
-            .await?;
-
-        if owner_count <= 1 {
-            return bad_request("Cannot remove the last owner");
-        }
+    // Remove the member, the model refusing to remove the last owner
+    match target_membership.remove_from_team(&ctx.db).await {
+        Ok(()) => {}
+        Err(ModelError::Message(msg)) => return bad_request(msg),
+        Err(e) => return Err(e.into()),
     }
 
-    // Remove the member
-    target_membership.remove_from_team(&ctx.db).await?;
-
     format::empty_json()
 }
 
 #[debug_handler]
 async fn leave_team(
-    auth: JWT,
+    auth: ApiAuth,
     State(ctx): State<AppContext>,
     Path(team_pid): Path<String>,
 ) -> Result<Response> {
-    let user = users::Model::find_by_pid(&ctx.db, &auth.claims.pid).await?;
+    auth.require_scope(personal_access_tokens::SCOPE_TEAMS_WRITE)?;
+    let user = auth.user;
     let team = TeamModel::find_by_pid(&ctx.db, &team_pid).await?;
 
     // Get the user's membership
@@ -371,32 +442,20 @@ async fn leave_team(
         .await?
         .ok_or_else(|| ModelError::msg("You are not a member of this team"))?;
 
-    // If user is an owner, check if they're the last owner
-    if membership.role == "Owner" {
-        // Count owners
-        let owner_count = TeamMembershipEntity::find()
-            .filter(TeamMembershipColumn::TeamId.eq(team.id))
-            .filter(TeamMembershipColumn::Role.eq("Owner"))
-            .filter(TeamMembershipColumn::Pending.eq(false))
-            .count(&ctx.db)
-            .await?;
-
-        if owner_count <= 1 {
-            return bad_request(
-                "As the last owner, you cannot leave the team. Either delete the team or transfer ownership first.",
-            );
-        }
+    // Remove the membership, the model refusing to let the last owner leave
+    match membership.remove_from_team(&ctx.db).await {
+        Ok(()) => {}
+        Err(ModelError::Message(msg)) => return bad_request(msg),
+        Err(e) => return Err(e.into()),
     }
 
-    // Remove the membership
-    membership.remove_from_team(&ctx.db).await?;
-
     format::empty_json()
 }
 
 #[debug_handler]
-async fn list_invitations(auth: JWT, State(ctx): State<AppContext>) -> Result<Response> {
-    let user = users::Model::find_by_pid(&ctx.db, &auth.claims.pid).await?;
+async fn list_invitations(auth: ApiAuth, State(ctx): State<AppContext>) -> Result<Response> {
+    auth.require_scope(personal_access_tokens::SCOPE_TEAMS_READ)?;
+    let user = auth.user;
 
     let invitations = team_memberships::Model::get_user_invitations(&ctx.db, user.id).await?;
 
@@ -404,13 +463,14 @@ async fn list_invitations(auth: JWT, State(ctx): State<AppContext>) -> Result<Re
         .into_iter()
         .map(|(membership, team)| {
             serde_json::json!({
-                "token": membership.invitation_token,
+                "pid": membership.pid.to_string(),
                 "team": {
                     "pid": team.pid.to_string(),
                     "name": team.name,
                     "description": team.description
                 },
-                "sent_at": membership.invitation_sent_at
+                "sent_at": membership.invitation_sent_at,
+                "expires_at": membership.invitation_expires_at
             })
         })
         .collect::<Vec<_>>();
@@ -1,9 +1,22 @@
 use crate::{
+    controllers::{
+        auth_pages::{EXPIRED_LINK_INVITATION, expired_link_url},
+        organizations_pages::current_organization_pid,
+    },
+    mailers::team::TeamMailer,
     middleware::auth_no_error::JWTWithUserOpt,
     models::{
         _entities::{team_memberships, teams, users},
-        team_memberships::{InviteMemberParams, UpdateRoleParams},
+        organizations, ownership_transfers, permissions,
+        personal_access_tokens::{self, CreateTokenParams},
+        roles::{self, OWNER_ROLE},
+        service_accounts::{self, CreateServiceAccountParams},
+        team_join_links::{self, CreateJoinLinkParams},
+        team_memberships::{
+            DEFAULT_INVITATION_ROLE, InvitationParams, InviteMemberParams, UpdateRoleParams,
+        },
         teams::{CreateTeamParams, UpdateTeamParams},
+        tokens::{self, TokenLifetimes},
     },
     views::render_template,
     views::{error_fragment, error_page, redirect},
@@ -16,9 +29,7 @@ use axum::{
     response::{Html, IntoResponse},
 };
 use loco_rs::{app::AppContext, prelude::*};
-use sea_orm::{
-    ActiveModelTrait, ColumnTrait, Condition, EntityTrait, PaginatorTrait, QueryFilter, QuerySelect,
-}; // Added ActiveModelTrait
+use sea_orm::{ActiveModelTrait, ColumnTrait, Condition, EntityTrait, QueryFilter, QuerySelect}; // Added ActiveModelTrait
 use serde::Deserialize;
 use serde_json::json;
 use tracing;
@@ -38,12 +49,30 @@ async fn create_team_page(
     };
 
     let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+    // Teams are created in the organizations the user owns
+    let organizations = match organizations::Model::list_for_user(&ctx.db, user.id).await {
+        Ok(organizations) => organizations
+            .into_iter()
+            .filter(|(_, role)| role == OWNER_ROLE)
+            .map(|(organization, _)| organization)
+            .collect::<Vec<_>>(),
+        Err(e) => {
+            tracing::error!("Failed to load organizations for user {}: {}", user.id, e);
+            return error_page(
+                &v,
+                "Could not load your organizations. Please try again later.",
+                Some(e.into()),
+            );
+        }
+    };
 
     render_template(
         &v,
         "teams/new.html",
         data!({
             "user": &user,
+            "organizations": &organizations,
+            "current_organization": current_organization_pid(&headers),
             "active_page": "teams",
             "invitation_count": &layout_context.invitation_count,
             "pending_user_count": &layout_context.pending_user_count,
@@ -67,14 +96,10 @@ async fn list_teams(
     };
 
     let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
-    // Get all teams where the user is a member
-    let teams_data_result = teams::Entity::find()
-        .find_with_related(team_memberships::Entity)
-        .all(&ctx.db)
-        .await;
-
-    let teams_data = match teams_data_result {
-        Ok(data) => data,
+    // Get all teams where the user is a member, directly or through their
+    // organization
+    let teams = match teams::Model::list_visible_to(&ctx.db, user.id).await {
+        Ok(teams) => teams,
         Err(e) => {
             tracing::error!("Failed to load teams for user {}: {}", user.id, e);
             return error_page(
@@ -84,32 +109,52 @@ async fn list_teams(
             );
         }
     };
-
-    let teams_result = teams_data
-        .into_iter()
-        .filter_map(|(team, memberships)| {
-            let is_member = memberships
-                .iter()
-                .any(|m| m.user_id == user.id && !m.pending);
-            if is_member {
-                // Find user's role in this team
-                let role = memberships
-                    .iter()
-                    .find(|m| m.user_id == user.id && !m.pending)
-                    .map(|m| m.role.clone())
-                    .unwrap_or_else(|| "Unknown".to_string());
-
-                Some(json!({
-                    "pid": team.pid.to_string(),
-                    "name": team.name,
-                    "description": team.description,
-                    "role": role
-                }))
-            } else {
-                None
-            }
-        })
-        .collect::<Vec<_>>();
+    let organizations = match organizations::Model::list_for_user(&ctx.db, user.id).await {
+        Ok(organizations) => organizations,
+        Err(e) => {
+            tracing::error!("Failed to load organizations for user {}: {}", user.id, e);
+            return error_page(
+                &v,
+                "Could not load your team information. Please try again later.",
+                Some(e.into()),
+            );
+        }
+    };
+    // Organization chosen with the switcher of the layout, if the user still
+    // belongs to it
+    let current_organization = current_organization_pid(&headers).and_then(|pid| {
+        organizations
+            .iter()
+            .find(|(organization, _)| organization.pid.to_string() == pid)
+    });
+
+    let mut teams_result = Vec::new();
+    for team in teams {
+        if let Some((organization, _)) = current_organization
+            && team.organization_id != Some(organization.id)
+        {
+            continue;
+        }
+        let membership =
+            team_memberships::Model::find_by_team_and_user(&ctx.db, team.id, user.id).await;
+        let organization = organizations
+            .iter()
+            .find(|(organization, _)| Some(organization.id) == team.organization_id);
+        // Members of the organization without a role of their own in the
+        // team have the role of their organization
+        let role = match (membership, organization) {
+            (Ok(membership), _) => membership.role,
+            (Err(_), Some((_, role))) => format!("{role} (organization)"),
+            (Err(_), None) => "Unknown".to_string(),
+        };
+        teams_result.push(json!({
+            "pid": team.pid.to_string(),
+            "name": team.name,
+            "description": team.description,
+            "organization": organization.map(|(organization, _)| &organization.name),
+            "role": role
+        }));
+    }
 
     render_template(
         &v,
@@ -117,6 +162,7 @@ async fn list_teams(
         data!({
             "user": &user,
             "teams": &teams_result,
+            "current_organization": current_organization.map(|(organization, _)| organization),
             "active_page": "teams",
             "invitation_count": &layout_context.invitation_count,
             "pending_user_count": &layout_context.pending_user_count,
@@ -153,7 +199,8 @@ async fn team_details(
         }
     };
 
-    // Check if user is a member of this team
+    // Membership of the user in the team, if any: members of the
+    // organization of the team may have none
     let membership_result = team_memberships::Entity::find()
         .filter(team_memberships::Column::TeamId.eq(team.id))
         .filter(team_memberships::Column::UserId.eq(user.id))
@@ -178,7 +225,25 @@ async fn team_details(
         }
     };
 
-    if membership.is_none() {
+    // Permissions granted by the roles of the user in the team and in its
+    // organization, which decide what the page offers
+    let member_permissions = match team.member_permissions(&ctx.db, user.id).await {
+        Ok(member_permissions) => member_permissions,
+        Err(e) => {
+            tracing::error!(
+                "Failed to load the permissions of user {} in team {}: {}",
+                user.id,
+                team.id,
+                e
+            );
+            return error_page(
+                &v,
+                "Could not verify your permissions. Please try again later.",
+                Some(e.into()),
+            );
+        }
+    };
+    if member_permissions.is_empty() {
         tracing::error!(
             "Access to team {} by unauthorized user: {:?}",
             team.name,
@@ -190,18 +255,34 @@ async fn team_details(
             None,
         );
     }
-
-    // Check if user is an admin or owner
-    let is_admin = if let Some(membership) = &membership {
-        membership.role == "Owner" || membership.role == "Administrator"
-    } else {
-        false
+    if !member_permissions.contains(permissions::TEAM_VIEW) {
+        return error_page(&v, "You are not allowed to view this team", None);
+    }
+    let grantable_roles = match team.grantable_roles(&ctx.db, user.id).await {
+        Ok(grantable_roles) => grantable_roles,
+        Err(e) => {
+            tracing::error!("Failed to load the roles of team {}: {}", team.id, e);
+            return error_page(
+                &v,
+                "Could not load the roles. Please try again later.",
+                Some(e.into()),
+            );
+        }
     };
 
-    // Retrieve the configured administrator team name
-    let admin_team_name = users::Model::get_admin_team_name(&ctx);
     // Check if the current team is the administrators team
-    let is_system_admin_team = team.name == admin_team_name;
+    let is_system_admin_team = team.is_admin_team(&ctx);
+    let organization = match team.organization(&ctx.db).await {
+        Ok(organization) => organization,
+        Err(e) => {
+            tracing::error!("Failed to load the organization of team {}: {}", team.id, e);
+            return error_page(
+                &v,
+                "Could not load the organization of the team. Please try again later.",
+                Some(e.into()),
+            );
+        }
+    };
 
     // Get team members (non-pending)
     let memberships_result = team_memberships::Entity::find()
@@ -224,14 +305,15 @@ async fn team_details(
 
     let mut members = Vec::new();
     for membership in memberships {
-        let member_result = users::Entity::find_by_id(membership.user_id)
-            .one(&ctx.db)
-            .await;
+        let Some(user_id) = membership.user_id else {
+            continue;
+        };
+        let member_result = users::Entity::find_by_id(user_id).one(&ctx.db).await;
 
         let member = match member_result {
             Ok(member) => member,
             Err(e) => {
-                tracing::error!("Failed to find user with id {}: {}", membership.user_id, e);
+                tracing::error!("Failed to find user with id {}: {}", user_id, e);
                 return error_page(
                     &v,
                     "Could not load team member details. Please try again later.",
@@ -240,7 +322,8 @@ async fn team_details(
             }
         };
 
-        if let Some(member) = member {
+        // Service accounts are listed in their own section
+        if let Some(member) = member.filter(|member| !member.is_service_account()) {
             members.push(json!({
                 "id": member.id,
                 "user_pid": member.pid.to_string(),
@@ -253,10 +336,11 @@ async fn team_details(
     }
 
     // Get pending invitations for this team
-    if is_admin {
+    if member_permissions.contains(permissions::TEAM_INVITE) {
         let pending_memberships_result = team_memberships::Entity::find()
             .filter(team_memberships::Column::TeamId.eq(team.id))
             .filter(team_memberships::Column::Pending.eq(true))
+            .filter(team_memberships::Column::Requested.eq(false))
             .all(&ctx.db)
             .await;
 
@@ -273,14 +357,27 @@ async fn team_details(
         };
 
         for membership in pending_memberships {
-            let member_result = users::Entity::find_by_id(membership.user_id)
-                .one(&ctx.db)
-                .await;
+            // Invitations sent to an email address nobody registered with yet
+            let Some(user_id) = membership.user_id else {
+                let email = membership.invited_email.clone().unwrap_or_default();
+                members.push(json!({
+                    "name": email,
+                    "email": email,
+                    "user_pid": "",
+                    "role": "Invited",
+                    "pending": true,
+                    "not_registered": true,
+                    "invitation_pid": membership.pid.to_string(),
+                    "invitation_expired": membership.is_invitation_expired()
+                }));
+                continue;
+            };
+            let member_result = users::Entity::find_by_id(user_id).one(&ctx.db).await;
 
             let member = match member_result {
                 Ok(member) => member,
                 Err(e) => {
-                    tracing::error!("Failed to find user with id {}: {}", membership.user_id, e);
+                    tracing::error!("Failed to find user with id {}: {}", user_id, e);
                     return error_page(
                         &v,
                         "Could not load invited user details. Please try again later.",
@@ -297,24 +394,96 @@ async fn team_details(
                     "email": member.email,
                     "role": "Invited",
                     "pending": true,
-                    "invitation_token": membership.invitation_token
+                    "invitation_pid": membership.pid.to_string(),
+                    "invitation_expired": membership.is_invitation_expired()
                 }));
             }
         }
     }
 
+    // Requests to join sent from the team directory
+    let join_requests = if member_permissions.contains(permissions::TEAM_INVITE) {
+        match team_memberships::Model::get_team_requests(&ctx.db, team.id).await {
+            Ok(requests) => requests
+                .into_iter()
+                .map(|(request, requesting_user)| {
+                    json!({
+                        "pid": request.pid.to_string(),
+                        "name": requesting_user.name,
+                        "email": requesting_user.email,
+                        "requested_at": request.invitation_sent_at,
+                    })
+                })
+                .collect::<Vec<_>>(),
+            Err(e) => {
+                tracing::error!("Failed to load requests to join team {}: {}", team.id, e);
+                return error_page(
+                    &v,
+                    "Could not load the requests to join the team. Please try again later.",
+                    Some(e.into()),
+                );
+            }
+        }
+    } else {
+        Vec::new()
+    };
+
+    let is_owner = membership
+        .as_ref()
+        .is_some_and(|membership| membership.role == OWNER_ROLE);
+
+    // Ownership transfer waiting for the answer of the receiving member
+    let transfer_result = ownership_transfers::Model::find_pending_for_team(&ctx.db, team.id).await;
+    let ownership_transfer = match transfer_result {
+        Ok(Some(transfer)) => {
+            let from_user = users::Model::find_by_id(&ctx.db, transfer.from_user_id).await;
+            let to_user = users::Model::find_by_id(&ctx.db, transfer.to_user_id).await;
+            match (from_user, to_user) {
+                (Ok(from_user), Ok(to_user)) => Some(json!({
+                    "from_name": from_user.name,
+                    "to_name": to_user.name,
+                    "expires_at": transfer.expires_at,
+                    "is_recipient": to_user.id == user.id,
+                })),
+                _ => None,
+            }
+        }
+        Ok(None) => None,
+        Err(e) => {
+            tracing::error!(
+                "Failed to load the ownership transfer of team {}: {}",
+                team.id,
+                e
+            );
+            None
+        }
+    };
+
     render_template(
         &v,
         "teams/show.html",
         data!({
+            "is_owner": &is_owner,
+            "ownership_transfer": &ownership_transfer,
             "user": &user,
             "team": {
                 "pid": team.pid.to_string(),
                 "name": team.name,
                 "description": team.description
             },
+            "organization": organization.as_ref().map(|organization| json!({
+                "pid": organization.pid.to_string(),
+                "name": organization.name,
+            })),
             "members": &members,
-            "is_admin": &is_admin,
+            "join_requests": &join_requests,
+            "default_role": DEFAULT_INVITATION_ROLE,
+            "permissions": &member_permissions,
+            // Ownership changes through ownership transfers
+            "roles": grantable_roles
+                .iter()
+                .filter(|role| *role != OWNER_ROLE)
+                .collect::<Vec<_>>(),
             "active_page": "teams",
             "invitation_count": &layout_context.invitation_count,
             "pending_user_count": &layout_context.pending_user_count,
@@ -349,19 +518,15 @@ async fn invite_member_page(
         }
     };
 
-    // Check if user is an admin or owner of this team
-    let membership_result = team_memberships::Entity::find()
-        .filter(team_memberships::Column::TeamId.eq(team.id))
-        .filter(team_memberships::Column::UserId.eq(user.id))
-        .filter(team_memberships::Column::Pending.eq(false))
-        .one(&ctx.db)
-        .await;
-
-    let membership = match membership_result {
-        Ok(membership) => membership,
+    // Check if user may invite members into this team
+    let may_invite = match team
+        .has_permission(&ctx.db, user.id, permissions::TEAM_INVITE)
+        .await
+    {
+        Ok(may_invite) => may_invite,
         Err(e) => {
             tracing::error!(
-                "Failed to load membership for user {} in team {}: {}",
+                "Failed to check the permissions of user {} in team {}: {}",
                 user.id,
                 team.id,
                 e
@@ -373,24 +538,29 @@ async fn invite_member_page(
             );
         }
     };
-
-    if let Some(membership) = membership {
-        if membership.role != "Owner" && membership.role != "Administrator" {
-            tracing::error!(
-                "Unauthorized user: {:?} tried to invite into team {}",
-                user,
-                team.name,
-            );
-            return error_page(&v, "Only team administrators can invite members", None);
-        }
-    } else {
+    if !may_invite {
         tracing::error!(
-            "Access to team {} by unauthorized user: {:?}",
+            "Unauthorized user: {:?} tried to invite into team {}",
+            user,
             team.name,
-            user
         );
-        return error_page(&v, "You are not a member of this team", None);
+        return error_page(
+            &v,
+            "You are not allowed to invite members into this team",
+            None,
+        );
     }
+    let grantable_roles = match team.grantable_roles(&ctx.db, user.id).await {
+        Ok(grantable_roles) => grantable_roles,
+        Err(e) => {
+            tracing::error!("Failed to load the roles of team {}: {}", team.id, e);
+            return error_page(
+                &v,
+                "Could not load the roles. Please try again later.",
+                Some(e.into()),
+            );
+        }
+    };
 
     render_template(
         &v,
@@ -402,6 +572,8 @@ async fn invite_member_page(
                 "name": team.name,
                 "description": team.description
             },
+            "roles": grantable_roles,
+            "default_role": DEFAULT_INVITATION_ROLE,
             "active_page": "teams",
             "invitation_count": &layout_context.invitation_count,
             "pending_user_count": &layout_context.pending_user_count,
@@ -436,19 +608,15 @@ async fn edit_team_page(
         }
     };
 
-    // Check if user is an admin or owner of this team
-    let membership_result = team_memberships::Entity::find()
-        .filter(team_memberships::Column::TeamId.eq(team.id))
-        .filter(team_memberships::Column::UserId.eq(user.id))
-        .filter(team_memberships::Column::Pending.eq(false))
-        .one(&ctx.db)
-        .await;
-
-    let membership = match membership_result {
-        Ok(membership) => membership,
+    // Check if user may edit this team
+    let may_update = match team
+        .has_permission(&ctx.db, user.id, permissions::TEAM_UPDATE)
+        .await
+    {
+        Ok(may_update) => may_update,
         Err(e) => {
             tracing::error!(
-                "Failed to load membership for user {} in team {}: {}",
+                "Failed to check the permissions of user {} in team {}: {}",
                 user.id,
                 team.id,
                 e
@@ -460,35 +628,72 @@ async fn edit_team_page(
             );
         }
     };
-
-    if let Some(membership) = membership {
-        if membership.role != "Owner" && membership.role != "Administrator" {
-            tracing::error!(
-                "Unauthorized user: {:?} tried to edit team {}",
-                user,
-                team.name,
-            );
-            return error_page(&v, "Only team administrators can team details", None);
-        }
-    } else {
+    if !may_update {
         tracing::error!(
-            "Access to team {} by unauthorized user: {:?}",
+            "Unauthorized user: {:?} tried to edit team {}",
+            user,
             team.name,
-            user
         );
-        return error_page(&v, "You are not a member of this team", None);
+        return error_page(
+            &v,
+            "You are not allowed to edit the details of this team",
+            None,
+        );
     }
 
+    // Organizations the team may move to, when the user may move it
+    let organizations = if team.is_admin_team(&ctx)
+        || team
+            .check_move_allowed(&ctx.db, user.id, None)
+            .await
+            .is_err()
+    {
+        None
+    } else {
+        match organizations::Model::list_for_user(&ctx.db, user.id).await {
+            Ok(organizations) => Some(
+                organizations
+                    .into_iter()
+                    .filter(|(_, role)| role == OWNER_ROLE)
+                    .map(|(organization, _)| organization)
+                    .collect::<Vec<_>>(),
+            ),
+            Err(e) => {
+                tracing::error!("Failed to load organizations for user {}: {}", user.id, e);
+                return error_page(
+                    &v,
+                    "Could not load your organizations. Please try again later.",
+                    Some(e.into()),
+                );
+            }
+        }
+    };
+    let organization_pid = match team.organization(&ctx.db).await {
+        Ok(organization) => organization.map(|organization| organization.pid.to_string()),
+        Err(e) => {
+            tracing::error!("Failed to load the organization of team {}: {}", team.id, e);
+            return error_page(
+                &v,
+                "Could not load the organization of the team. Please try again later.",
+                Some(e.into()),
+            );
+        }
+    };
+
     render_template(
         &v,
         "teams/edit.html",
         data!({
             "user": &user,
+            "organizations": &organizations,
+            "organization_pid": &organization_pid,
             "team": {
                 "pid": team.pid.to_string(),
                 "name": team.name,
-                "description": team.description
+                "description": team.description,
+                "listed": team.listed,
             },
+            "is_system_admin_team": team.is_admin_team(&ctx),
             "active_page": "teams",
             "invitation_count": &layout_context.invitation_count,
             "pending_user_count": &layout_context.pending_user_count,
@@ -497,6 +702,16 @@ async fn edit_team_page(
     )
 }
 
+/// Form creating a team, optionally in an organization
+#[derive(Debug, Deserialize)]
+struct CreateTeamForm {
+    name: String,
+    description: Option<String>,
+    /// Pid of the organization of the team, empty for none
+    #[serde(default)]
+    organization: String,
+}
+
 /// Create team handler
 #[debug_handler]
 async fn create_team_handler(
@@ -504,7 +719,7 @@ async fn create_team_handler(
     State(ctx): State<AppContext>,
     auth: JWTWithUserOpt<users::Model>,
     headers: HeaderMap,
-    Form(mut params): Form<CreateTeamParams>,
+    Form(form): Form<CreateTeamForm>,
 ) -> Result<impl IntoResponse> {
     let user = if let Some(user) = auth.user {
         user
@@ -513,10 +728,51 @@ async fn create_team_handler(
     };
 
     // Trim whitespace from team name
-    params.name = params.name.trim().to_string();
+    let params = CreateTeamParams {
+        name: form.name.trim().to_string(),
+        description: form.description,
+    };
+
+    // Only the Owners of an organization create teams in it
+    let organization_id = if form.organization.is_empty() {
+        None
+    } else {
+        let organization =
+            match organizations::Model::find_by_pid(&ctx.db, &form.organization).await {
+                Ok(organization) => organization,
+                Err(e) => {
+                    tracing::error!("Failed to find organization {}: {}", form.organization, e);
+                    return error_fragment(&v, "Organization not found", "#error-container");
+                }
+            };
+        match organization.is_owner(&ctx.db, user.id).await {
+            Ok(true) => Some(organization.id),
+            Ok(false) => {
+                return error_fragment(
+                    &v,
+                    "Only the owners of an organization can create teams in it",
+                    "#error-container",
+                );
+            }
+            Err(e) => {
+                tracing::error!(
+                    "Failed to check the role of user {} in organization {}: {}",
+                    user.id,
+                    organization.id,
+                    e
+                );
+                return error_fragment(
+                    &v,
+                    "Could not verify your permissions. Please try again later.",
+                    "#error-container",
+                );
+            }
+        }
+    };
 
     // Create the team
-    let team = match teams::Model::create_team(&ctx.db, user.id, &params).await {
+    let team = match teams::Model::create_team_in(&ctx.db, user.id, &params, organization_id).await
+    {
         Ok(team) => {
             tracing::info!(
                 "Team created successfully with id: {}, pid: {}",
@@ -580,19 +836,15 @@ async fn update_team_handler(
         }
     };
 
-    // Check if user is an owner of this team
-    let membership_result = team_memberships::Entity::find()
-        .filter(team_memberships::Column::TeamId.eq(team.id))
-        .filter(team_memberships::Column::UserId.eq(user.id))
-        .filter(team_memberships::Column::Pending.eq(false))
-        .one(&ctx.db)
-        .await;
-
-    let membership = match membership_result {
-        Ok(membership) => membership,
+    // Check if user may edit this team
+    let may_update = match team
+        .has_permission(&ctx.db, user.id, permissions::TEAM_UPDATE)
+        .await
+    {
+        Ok(may_update) => may_update,
         Err(e) => {
             tracing::error!(
-                "Failed to load membership for user {} in team {}: {}",
+                "Failed to check the permissions of user {} in team {}: {}",
                 user.id,
                 team.id,
                 e
@@ -604,24 +856,17 @@ async fn update_team_handler(
             );
         }
     };
-
-    if let Some(membership) = membership {
-        if membership.role != "Owner" {
-            return error_fragment(
-                &v,
-                "Only team owners can edit team details",
-                "#error-container",
-            );
-        }
-    } else {
-        return error_fragment(&v, "You are not a member of this team", "#error-container");
+    if !may_update {
+        return error_fragment(
+            &v,
+            "You are not allowed to edit the details of this team",
+            "#error-container",
+        );
     }
 
     // Prevent renaming the admin team
-    let admin_team_name = users::Model::get_admin_team_name(&ctx);
-
     let incoming_name = params.name.trim(); // Trim incoming name for comparison
-    if team.name == admin_team_name && incoming_name != team.name {
+    if team.is_admin_team(&ctx) && incoming_name != team.name {
         // If it's the admin team AND the name is being changed, return an error fragment
         return error_fragment(
             &v,
@@ -652,95 +897,298 @@ async fn update_team_handler(
     redirect(&redirect_url, headers)
 }
 
-/// Accept invitation handler
+/// Form moving a team to another organization
+#[derive(Debug, Deserialize)]
+struct MoveTeamForm {
+    /// Pid of the destination organization, empty to move the team out of
+    /// its organization
+    #[serde(default)]
+    organization: String,
+}
+
+/// Move team handler: the team joins another organization, or none
 #[debug_handler]
-async fn accept_invitation(
+async fn move_team_handler(
     ViewEngine(v): ViewEngine<TeraView>,
     State(ctx): State<AppContext>,
     auth: JWTWithUserOpt<users::Model>,
-    Path(token): Path<String>,
+    Path(team_pid): Path<String>,
     headers: HeaderMap,
+    Form(form): Form<MoveTeamForm>,
 ) -> Result<impl IntoResponse> {
-    let user = if let Some(user) = auth.user {
-        user
-    } else {
+    let Some(user) = auth.user else {
         return redirect("/auth/login", headers);
     };
 
-    // Find invitation by token
-    let invitation = match team_memberships::Entity::find()
-        .filter(team_memberships::Column::InvitationToken.eq(token.clone()))
-        .one(&ctx.db)
-        .await
-    {
-        Ok(value) => match value {
-            Some(invitation) => invitation,
-            None => return error_page(&v, "Invitation not found", None),
-        },
-        Err(err) => {
-            tracing::error!("Failed to find invitation: {:?}", err);
-            return error_page(&v, "Database error while searching for invitation", None);
+    let team = match teams::Model::find_by_pid(&ctx.db, &team_pid).await {
+        Ok(team) => team,
+        Err(e) => {
+            tracing::error!("Failed to find team with pid {}: {:?}", team_pid, e);
+            return error_fragment(&v, "Team not found", "#move-errors");
         }
     };
-
-    if invitation.user_id != user.id {
-        return error_page(&v, "This invitation is not for you", None);
-    }
-
-    // Accept invitation
-    let mut invitation_model: team_memberships::ActiveModel = invitation.into();
-    invitation_model.pending = sea_orm::ActiveValue::set(false);
-    let update_result = invitation_model.update(&ctx.db).await; // Requires ActiveModelTrait
-    if let Err(e) = update_result {
-        tracing::error!("Failed to accept invitation : {}", e);
-        return error_page(
+    if team.is_admin_team(&ctx) {
+        return error_fragment(
             &v,
-            "Could not accept the invitation. Please try again later.",
-            Some(e.into()),
+            "The administrators team cannot belong to an organization.",
+            "#move-errors",
         );
     }
+    let destination = if form.organization.is_empty() {
+        None
+    } else {
+        match organizations::Model::find_by_pid(&ctx.db, &form.organization).await {
+            Ok(organization) => Some(organization),
+            Err(e) => {
+                tracing::error!("Failed to find organization {}: {}", form.organization, e);
+                return error_fragment(&v, "Organization not found", "#move-errors");
+            }
+        }
+    };
 
-    // Remove the invitation row from the UI
-    let response = Response::builder()
-        .status(StatusCode::OK)
-        .header("HX-Trigger", "updateInvitationCount")
-        .body(axum::body::Body::empty())?;
-
-    Ok(response)
-}
-
-/// Decline invitation handler
-#[debug_handler]
-async fn decline_invitation(
-    ViewEngine(v): ViewEngine<TeraView>,
+    match team
+        .check_move_allowed(&ctx.db, user.id, destination.as_ref())
+        .await
+    {
+        Ok(()) => {}
+        Err(ModelError::Message(msg)) => return error_fragment(&v, &msg, "#move-errors"),
+        Err(e) => {
+            tracing::error!(
+                "Failed to check the permissions of user {} in team {}: {}",
+                user.id,
+                team.id,
+                e
+            );
+            return error_fragment(
+                &v,
+                "Could not verify your permissions. Please try again later.",
+                "#move-errors",
+            );
+        }
+    }
+    let team = match team
+        .move_to_organization(
+            &ctx.db,
+            destination.as_ref().map(|organization| organization.id),
+        )
+        .await
+    {
+        Ok(team) => team,
+        Err(ModelError::Message(msg)) => return error_fragment(&v, &msg, "#move-errors"),
+        Err(e) => {
+            tracing::error!("Failed to move team {}: {}", team.id, e);
+            return error_fragment(
+                &v,
+                "Could not move the team. Please try again later.",
+                "#move-errors",
+            );
+        }
+    };
+    tracing::info!(
+        team_pid = %team.pid,
+        organization = ?destination.map(|organization| organization.name),
+        moved_by = %user.pid,
+        "Team moved"
+    );
+
+    redirect(&format!("/teams/{}", team.pid), headers)
+}
+
+/// Form listing a team in the team directory, or removing it from there
+#[derive(Debug, Deserialize)]
+struct TeamListingForm {
+    /// Sent by the checkbox only when it is checked
+    listed: Option<String>,
+}
+
+/// Team listing handler: the team is listed in the team directory, where
+/// users can request to join it, or removed from there
+#[debug_handler]
+async fn update_team_listing(
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
     auth: JWTWithUserOpt<users::Model>,
+    Path(team_pid): Path<String>,
+    headers: HeaderMap,
+    Form(form): Form<TeamListingForm>,
+) -> Result<impl IntoResponse> {
+    let Some(user) = auth.user else {
+        return redirect("/auth/login", headers);
+    };
+
+    let team = match teams::Model::find_by_pid(&ctx.db, &team_pid).await {
+        Ok(team) => team,
+        Err(e) => {
+            tracing::error!("Failed to find team with pid {}: {:?}", team_pid, e);
+            return error_fragment(&v, "Team not found", "#listing-errors");
+        }
+    };
+    match team
+        .has_permission(&ctx.db, user.id, permissions::TEAM_UPDATE)
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => {
+            return error_fragment(
+                &v,
+                "You are not allowed to edit the details of this team",
+                "#listing-errors",
+            );
+        }
+        Err(e) => {
+            tracing::error!(
+                "Failed to check the permissions of user {} in team {}: {}",
+                user.id,
+                team.id,
+                e
+            );
+            return error_fragment(
+                &v,
+                "Could not verify your permissions. Please try again later.",
+                "#listing-errors",
+            );
+        }
+    }
+    let listed = form.listed.is_some();
+    if listed && team.is_admin_team(&ctx) {
+        return error_fragment(
+            &v,
+            "The administrators team cannot be listed in the team directory.",
+            "#listing-errors",
+        );
+    }
+    let team = match team.set_listed(&ctx.db, listed).await {
+        Ok(team) => team,
+        Err(e) => {
+            tracing::error!("Failed to change the listing of team {}: {}", team.id, e);
+            return error_fragment(
+                &v,
+                "Could not update the team. Please try again later.",
+                "#listing-errors",
+            );
+        }
+    };
+    tracing::info!(
+        team_pid = %team.pid,
+        listed,
+        updated_by = %user.pid,
+        "Team directory listing changed"
+    );
+
+    redirect(&format!("/teams/{}", team.pid), headers)
+}
+
+/// Opens the link sent in an invitation email: the token is checked, then the
+/// user is sent to their invitations to accept or decline it
+#[debug_handler]
+async fn open_invitation(
+    ViewEngine(v): ViewEngine<TeraView>,
     State(ctx): State<AppContext>,
     Path(token): Path<String>,
     headers: HeaderMap,
 ) -> Result<Response> {
+    match team_memberships::Model::find_by_invitation_token(&ctx.db, &token).await {
+        Ok(_) => redirect("/users/invitations", headers),
+        Err(err) if tokens::is_expired(&err) => {
+            redirect(&expired_link_url(EXPIRED_LINK_INVITATION), headers)
+        }
+        Err(ModelError::EntityNotFound) => error_page(
+            &v,
+            "This invitation is no longer valid. It may have been accepted, declined or cancelled.",
+            None,
+        ),
+        Err(err) => {
+            tracing::error!("Failed to find invitation: {:?}", err);
+            error_page(&v, "Database error while searching for invitation", None)
+        }
+    }
+}
+
+/// Accept invitation handler
+#[debug_handler]
+async fn accept_invitation(
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    auth: JWTWithUserOpt<users::Model>,
+    Path(invitation_pid): Path<String>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse> {
     let user = if let Some(user) = auth.user {
         user
     } else {
         return redirect("/auth/login", headers);
     };
 
-    // Find invitation by token
-    let invitation = match team_memberships::Entity::find()
-        .filter(team_memberships::Column::InvitationToken.eq(token.clone()))
-        .one(&ctx.db)
-        .await
-    {
-        Ok(value) => match value {
-            Some(invitation) => invitation,
-            None => return error_page(&v, "Invitation not found", None),
-        },
-        Err(err) => {
-            tracing::error!("Failed to find invitation: {:?}", err);
-            return error_page(&v, "Database error while searching for invitation", None);
-        }
+    // Find the pending invitation
+    let invitation =
+        match team_memberships::Model::find_pending_by_pid(&ctx.db, &invitation_pid).await {
+            Ok(invitation) => invitation,
+            Err(ModelError::EntityNotFound) => {
+                return error_page(&v, "Invitation not found", None);
+            }
+            Err(err) => {
+                tracing::error!("Failed to find invitation: {:?}", err);
+                return error_page(&v, "Database error while searching for invitation", None);
+            }
+        };
+
+    if invitation.user_id != Some(user.id) {
+        return error_page(&v, "This invitation is not for you", None);
+    }
+
+    if invitation.is_invitation_expired() {
+        return redirect(&expired_link_url(EXPIRED_LINK_INVITATION), headers);
+    }
+
+    // Accept invitation
+    let update_result = invitation.accept_invitation(&ctx.db).await;
+    if let Err(e) = update_result {
+        tracing::error!("Failed to accept invitation : {}", e);
+        return error_page(
+            &v,
+            "Could not accept the invitation. Please try again later.",
+            Some(e.into()),
+        );
+    }
+
+    // Remove the invitation row from the UI
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header("HX-Trigger", "updateInvitationCount")
+        .body(axum::body::Body::empty())?;
+
+    Ok(response)
+}
+
+/// Decline invitation handler
+#[debug_handler]
+async fn decline_invitation(
+    ViewEngine(v): ViewEngine<TeraView>,
+    auth: JWTWithUserOpt<users::Model>,
+    State(ctx): State<AppContext>,
+    Path(invitation_pid): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
     };
 
-    if invitation.user_id != user.id {
+    // Find the pending invitation; expired invitations can still be declined
+    let invitation =
+        match team_memberships::Model::find_pending_by_pid(&ctx.db, &invitation_pid).await {
+            Ok(invitation) => invitation,
+            Err(ModelError::EntityNotFound) => {
+                return error_page(&v, "Invitation not found", None);
+            }
+            Err(err) => {
+                tracing::error!("Failed to find invitation: {:?}", err);
+                return error_page(&v, "Database error while searching for invitation", None);
+            }
+        };
+
+    if invitation.user_id != Some(user.id) {
         return error_page(&v, "This invitation is not for you", None);
     }
 
@@ -771,7 +1219,7 @@ async fn cancel_invitation(
     ViewEngine(v): ViewEngine<TeraView>,
     auth: JWTWithUserOpt<users::Model>,
     State(ctx): State<AppContext>,
-    Path((team_pid, token)): Path<(String, String)>,
+    Path((team_pid, invitation_pid)): Path<(String, String)>,
     headers: HeaderMap,
 ) -> Result<Response> {
     let user = if let Some(user) = auth.user {
@@ -789,60 +1237,58 @@ async fn cancel_invitation(
         }
     };
 
-    // Check if user is an admin or owner of this team
-    let is_admin_result = team.has_role(&ctx.db, user.id, "Administrator").await;
-    let is_admin = match is_admin_result {
-        Ok(is_admin) => is_admin,
+    // Check if user may cancel invitations of this team
+    let may_invite_result = team
+        .has_permission(&ctx.db, user.id, permissions::TEAM_INVITE)
+        .await;
+    let may_invite = match may_invite_result {
+        Ok(may_invite) => may_invite,
         Err(e) => {
             tracing::error!(
-                "Failed to check admin role for user {} in team {}: {}",
+                "Failed to check the permissions of user {} in team {}: {}",
                 user.id,
                 team.id,
                 e
             );
             return error_page(
                 &v,
-                "Failed to check if you are administrator of this team",
+                "Could not verify your permissions. Please try again later.",
                 None,
             );
         }
     };
 
-    if !is_admin {
-        tracing::error!("Access to team {} by non-admin user: {:?}", team.name, user);
+    if !may_invite {
+        tracing::error!(
+            "Unauthorized user: {:?} tried to cancel an invitation of team {}",
+            user,
+            team.name
+        );
         return error_page(
             &v,
-            "You are not authorized to cancel invitations for this team because you are not one of its administrators",
+            "You are not allowed to cancel the invitations of this team",
             None,
         );
     }
 
-    // Find invitation by token and team ID
-    let invitation = match team_memberships::Entity::find()
-        .filter(team_memberships::Column::TeamId.eq(team.id))
-        .filter(team_memberships::Column::InvitationToken.eq(token.clone()))
-        .filter(team_memberships::Column::Pending.eq(true))
-        .one(&ctx.db)
-        .await
-    {
-        Ok(value) => match value {
-            Some(invitation) => invitation,
-            None => return error_fragment(&v, "Invitation not found", "#error-container"),
-        },
-        Err(err) => {
-            tracing::error!("Failed to find invitation: {:?}", err);
-            return error_fragment(
-                &v,
-                "Database error while searching for invitation",
-                "#error-container",
-            );
-        }
-    };
+    // Find the pending invitation, making sure it is for this team
+    let invitation =
+        match team_memberships::Model::find_pending_by_pid(&ctx.db, &invitation_pid).await {
+            Ok(invitation) if invitation.team_id == team.id => invitation,
+            Ok(_) | Err(ModelError::EntityNotFound) => {
+                return error_fragment(&v, "Invitation not found", "#error-container");
+            }
+            Err(err) => {
+                tracing::error!("Failed to find invitation: {:?}", err);
+                return error_fragment(
+                    &v,
+                    "Database error while searching for invitation",
+                    "#error-container",
+                );
+            }
+        };
 
-    tracing::info!(
-        "Found invitation for user_id: {}, preparing to delete",
-        invitation.user_id
-    );
+    tracing::info!("Found invitation {}, preparing to delete", invitation.pid);
 
     // Cancel invitation - delete the membership
     let invitation_model: team_memberships::ActiveModel = invitation.into();
@@ -869,88 +1315,222 @@ async fn cancel_invitation(
     Ok(response)
 }
 
-/// Update member role handler
+/// Outstanding invitations page of a team, with who sent each invitation
 #[debug_handler]
-async fn update_member_role(
+async fn team_invitations_page(
     ViewEngine(v): ViewEngine<TeraView>,
-    State(ctx): State<AppContext>,
     auth: JWTWithUserOpt<users::Model>,
-    Path((team_pid, user_pid)): Path<(String, String)>,
+    State(ctx): State<AppContext>,
+    Path(team_pid): Path<String>,
     headers: HeaderMap,
-    Form(params): Form<UpdateRoleParams>,
-) -> Result<impl IntoResponse> {
-    let current_user = if let Some(user) = auth.user {
-        user
-    } else {
+) -> Result<Response> {
+    let Some(user) = auth.user else {
         return redirect("/auth/login", headers);
     };
-
-    let team_result = teams::Model::find_by_pid(&ctx.db, &team_pid).await;
-    let team = match team_result {
+    let team = match find_team_inviting_with(&ctx, &team_pid, &user).await {
         Ok(team) => team,
-        Err(e) => {
-            tracing::error!("Failed to find team {}: {}", team_pid, e);
-            return error_fragment(&v, "Team not found.", "#error-container");
-        }
-    };
-    let target_user_result = users::Model::find_by_pid(&ctx.db, &user_pid).await;
-    let target_user = match target_user_result {
-        Ok(user) => user,
-        Err(e) => {
-            tracing::error!("Failed to find user {}: {}", user_pid, e);
-            return error_fragment(&v, "Target user not found.", "#error-container");
-        }
+        Err(msg) => return error_page(&v, msg, None),
     };
 
-    // Validate role
-    if !crate::models::team_memberships::VALID_ROLES.contains(&params.role.as_str()) {
-        return error_fragment(
-            &v,
-            &format!(
-                "Invalid role. Valid roles are: {:?}",
-                crate::models::team_memberships::VALID_ROLES
-            ),
-            "#error-container",
-        );
-    }
-
-    // Check if current user is an owner of this team
-    let is_owner_result = team_memberships::Entity::find()
-        .filter(team_memberships::Column::TeamId.eq(team.id))
-        .filter(team_memberships::Column::UserId.eq(current_user.id))
-        .filter(team_memberships::Column::Role.eq("Owner"))
-        .filter(team_memberships::Column::Pending.eq(false))
-        .one(&ctx.db)
-        .await;
-
-    let is_owner = match is_owner_result {
-        Ok(membership_opt) => membership_opt.is_some(),
+    let invitations = match team_memberships::Model::get_team_invitations(&ctx.db, team.id).await {
+        Ok(invitations) => invitations,
         Err(e) => {
-            tracing::error!(
-                "Failed to check ownership for user {} in team {}: {}",
-                current_user.id,
-                team.id,
-                e
-            );
-            return error_fragment(
+            tracing::error!("Failed to load invitations of team {}: {}", team.id, e);
+            return error_page(
                 &v,
-                "Could not verify your permissions. Please try again later.",
-                "#error-container",
+                "Could not load the invitations of this team. Please try again later.",
+                Some(e.into()),
             );
         }
     };
+    let invitations: Vec<_> = invitations
+        .into_iter()
+        .map(|team_invitation| {
+            let invitation = &team_invitation.invitation;
+            let (name, email) = match &team_invitation.invited_user {
+                Some(invited_user) => (invited_user.name.clone(), invited_user.email.clone()),
+                None => {
+                    let email = invitation.invited_email.clone().unwrap_or_default();
+                    (email.clone(), email)
+                }
+            };
+            json!({
+                "pid": invitation.pid.to_string(),
+                "name": name,
+                "email": email,
+                "not_registered": team_invitation.invited_user.is_none(),
+                "role": invitation.role,
+                "invited_by": team_invitation.invited_by.as_ref().map(|member| &member.name),
+                "sent_at": invitation.invitation_sent_at,
+                "expires_at": invitation.invitation_expires_at,
+                "expired": invitation.is_invitation_expired(),
+            })
+        })
+        .collect();
 
-    if !is_owner {
-        return error_fragment(
-            &v,
-            "Only team owners can update member roles",
-            "#error-container",
-        );
-    }
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+    render_template(
+        &v,
+        "teams/invitations.html",
+        data!({
+            "user": &user,
+            "team": {
+                "pid": team.pid.to_string(),
+                "name": team.name,
+            },
+            "invitations": invitations,
+            "active_page": "teams",
+            "invitation_count": &layout_context.invitation_count,
+            "pending_user_count": &layout_context.pending_user_count,
+            "is_app_admin": &layout_context.is_app_admin,
+        }),
+    )
+}
 
-    // Get membership
-    let membership = match team_memberships::Entity::find()
-        .filter(team_memberships::Column::TeamId.eq(team.id))
+/// Sends a pending invitation again, with a new link and a new expiry
+#[debug_handler]
+async fn resend_invitation(
+    ViewEngine(v): ViewEngine<TeraView>,
+    auth: JWTWithUserOpt<users::Model>,
+    State(ctx): State<AppContext>,
+    Path((team_pid, invitation_pid)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let Some(user) = auth.user else {
+        return redirect("/auth/login", headers);
+    };
+    let team = match find_team_inviting_with(&ctx, &team_pid, &user).await {
+        Ok(team) => team,
+        Err(msg) => return error_fragment(&v, msg, "#error-container"),
+    };
+
+    let invitation =
+        match team_memberships::Model::find_pending_by_pid(&ctx.db, &invitation_pid).await {
+            Ok(invitation) if invitation.team_id == team.id => invitation,
+            Ok(_) | Err(ModelError::EntityNotFound) => {
+                return error_fragment(&v, "Invitation not found", "#error-container");
+            }
+            Err(err) => {
+                tracing::error!("Failed to find invitation: {:?}", err);
+                return error_fragment(
+                    &v,
+                    "Database error while searching for invitation",
+                    "#error-container",
+                );
+            }
+        };
+
+    let lifetimes = TokenLifetimes::from_context(&ctx)?;
+    let (invitation, invitation_token) =
+        match invitation.resend_invitation(&ctx.db, &lifetimes).await {
+            Ok(resent) => resent,
+            Err(ModelError::Message(msg)) => return error_fragment(&v, &msg, "#error-container"),
+            Err(e) => {
+                tracing::error!("Failed to renew invitation {}: {}", invitation.pid, e);
+                return error_fragment(
+                    &v,
+                    "Could not resend the invitation. Please try again later.",
+                    "#error-container",
+                );
+            }
+        };
+
+    // The email still comes from the member who wrote the invitation message
+    let inviting_user = match invitation.invited_by_id {
+        Some(invited_by_id) => users::Model::find_by_id(&ctx.db, invited_by_id)
+            .await
+            .unwrap_or(user),
+        None => user,
+    };
+    let mailer_result = match (invitation.user_id, &invitation.invited_email) {
+        (Some(invited_user_id), _) => {
+            match users::Model::find_by_id(&ctx.db, invited_user_id).await {
+                Ok(invited_user) => {
+                    TeamMailer::send_invitation(
+                        &ctx,
+                        &inviting_user,
+                        &invited_user,
+                        &team,
+                        &invitation,
+                        &invitation_token,
+                    )
+                    .await
+                }
+                Err(e) => Err(e.into()),
+            }
+        }
+        (None, Some(email)) => {
+            TeamMailer::send_signup_invitation(
+                &ctx,
+                &inviting_user,
+                email,
+                &team,
+                &invitation,
+                &invitation_token,
+            )
+            .await
+        }
+        (None, None) => Ok(()),
+    };
+    if let Err(e) = mailer_result {
+        tracing::error!("Failed to resend invitation email: {}", e);
+        return error_fragment(
+            &v,
+            "The invitation was renewed, but the email could not be sent.",
+            "#error-container",
+        );
+    }
+
+    Ok(Response::builder()
+        .header("HX-Refresh", "true")
+        .body(axum::body::Body::empty())?)
+}
+
+/// Update member role handler
+#[debug_handler]
+async fn update_member_role(
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    auth: JWTWithUserOpt<users::Model>,
+    Path((team_pid, user_pid)): Path<(String, String)>,
+    headers: HeaderMap,
+    Form(params): Form<UpdateRoleParams>,
+) -> Result<impl IntoResponse> {
+    let current_user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let team_result = teams::Model::find_by_pid(&ctx.db, &team_pid).await;
+    let team = match team_result {
+        Ok(team) => team,
+        Err(e) => {
+            tracing::error!("Failed to find team {}: {}", team_pid, e);
+            return error_fragment(&v, "Team not found.", "#error-container");
+        }
+    };
+    let target_user_result = users::Model::find_by_pid(&ctx.db, &user_pid).await;
+    let target_user = match target_user_result {
+        Ok(user) => user,
+        Err(e) => {
+            tracing::error!("Failed to find user {}: {}", user_pid, e);
+            return error_fragment(&v, "Target user not found.", "#error-container");
+        }
+    };
+
+    // Validate role
+    let role = match roles::Model::find_by_name(&ctx.db, &params.role).await {
+        Ok(role) => role,
+        Err(e) => {
+            tracing::error!("Failed to find role {}: {}", params.role, e);
+            return error_fragment(&v, "Invalid role.", "#error-container");
+        }
+    };
+
+    // Get membership
+    let membership = match team_memberships::Entity::find()
+        .filter(team_memberships::Column::TeamId.eq(team.id))
         .filter(team_memberships::Column::UserId.eq(target_user.id))
         .filter(team_memberships::Column::Pending.eq(false))
         .one(&ctx.db)
@@ -972,53 +1552,50 @@ async fn update_member_role(
         }
     };
 
-    // Cannot change owner's role if there's only one owner
-    if membership.role == "Owner" && params.role != "Owner" {
-        let owners_count_result = team_memberships::Entity::find()
-            .filter(team_memberships::Column::TeamId.eq(team.id))
-            .filter(team_memberships::Column::Role.eq("Owner"))
-            .filter(team_memberships::Column::Pending.eq(false))
-            .count(&ctx.db)
-            .await;
-
-        let owners_count = match owners_count_result {
-            Ok(count) => count,
-            Err(e) => {
-                tracing::error!("Failed to count owners for team {}: {}", team.id, e);
-                return error_fragment(
-                    &v,
-                    "Could not verify team ownership status. Please try again later.",
-                    "#error-container",
-                );
-            }
-        };
-
-        if owners_count <= 1 {
+    // Check if current user may give this role to this member
+    match membership
+        .check_role_change_allowed(&ctx.db, &team, current_user.id, &role)
+        .await
+    {
+        Ok(()) => {}
+        Err(ModelError::Message(msg)) => {
+            return error_fragment(&v, &msg, "#error-container");
+        }
+        Err(e) => {
+            tracing::error!(
+                "Failed to check the permissions of user {} in team {}: {}",
+                current_user.id,
+                team.id,
+                e
+            );
             return error_fragment(
                 &v,
-                "Cannot change the role of the last owner",
+                "Could not verify your permissions. Please try again later.",
                 "#error-container",
             );
         }
     }
 
-    // Update role
-    let mut membership_model: team_memberships::ActiveModel = membership.into();
-    membership_model.role = sea_orm::ActiveValue::set(params.role);
-    let update_result = membership_model.update(&ctx.db).await; // Requires ActiveModelTrait
-    if let Err(e) = update_result {
-        tracing::error!(
-            "Failed to update role for user {} in team {}: {}",
-            target_user.id,
-            team.id,
-            e
-        );
-        return error_fragment(
-            &v,
-            "Could not update member role. Please try again later.",
-            "#error-container",
-        );
-    };
+    // Update role, the model refusing to demote the last owner
+    match membership.update_role(&ctx.db, &params.role).await {
+        Ok(_) => {}
+        Err(ModelError::Message(msg)) => {
+            return error_fragment(&v, &msg, "#error-container");
+        }
+        Err(e) => {
+            tracing::error!(
+                "Failed to update role for user {} in team {}: {}",
+                target_user.id,
+                team.id,
+                e
+            );
+            return error_fragment(
+                &v,
+                "Could not update member role. Please try again later.",
+                "#error-container",
+            );
+        }
+    }
 
     // Return a response that refreshes the page
     let response = Response::builder()
@@ -1087,91 +1664,47 @@ async fn remove_member(
         }
     };
 
-    // Check permissions
-    let current_user_membership = match team_memberships::Entity::find()
-        .filter(team_memberships::Column::TeamId.eq(team.id))
-        .filter(team_memberships::Column::UserId.eq(current_user.id))
-        .filter(team_memberships::Column::Pending.eq(false))
-        .one(&ctx.db)
+    // Check if current user may remove this member
+    match target_membership
+        .check_removal_allowed(&ctx.db, &team, current_user.id)
         .await
     {
-        Ok(value) => match value {
-            Some(membership) => membership,
-            None => return error_page(&v, "You are not a member of this team", None),
-        },
-        Err(err) => {
-            tracing::error!("Failed to find membership: {:?}", err);
-            return error_page(&v, "Database error while searching for membership", None);
+        Ok(()) => {}
+        Err(ModelError::Message(msg)) => return error_page(&v, &msg, None),
+        Err(e) => {
+            tracing::error!(
+                "Failed to check the permissions of user {} in team {}: {}",
+                current_user.id,
+                team.id,
+                e
+            );
+            return error_page(
+                &v,
+                "Could not verify your permissions. Please try again later.",
+                Some(e.into()),
+            );
         }
-    };
-
-    let current_user_is_owner = current_user_membership.role == "Owner";
-    let current_user_is_admin =
-        current_user_membership.role == "Administrator" || current_user_is_owner;
-
-    // Cannot remove an owner unless you're an owner
-    if target_membership.role == "Owner" && !current_user_is_owner {
-        return error_page(&v, "Only team owners can remove another owner", None);
-    }
-
-    // Cannot remove an admin unless you're an owner
-    if target_membership.role == "Administrator" && !current_user_is_owner {
-        return error_page(&v, "Only team owners can remove an administrator", None);
-    }
-
-    // Cannot remove a developer/observer unless you're an admin or owner
-    if !current_user_is_admin {
-        return error_page(
-            &v,
-            "Only team administrators and owners can remove members",
-            None,
-        );
     }
 
-    // Special case: Prevent removing the last owner
-    if target_membership.role == "Owner" {
-        // Count owners
-        let owner_count_result = team_memberships::Entity::find()
-            .filter(team_memberships::Column::TeamId.eq(team.id))
-            .filter(team_memberships::Column::Role.eq("Owner"))
-            .filter(team_memberships::Column::Pending.eq(false))
-            .count(&ctx.db)
-            .await;
-
-        let owner_count = match owner_count_result {
-            Ok(count) => count,
-            Err(e) => {
-                tracing::error!("Failed to count owners for team {}: {}", team.id, e);
-                return error_page(
-                    &v,
-                    "Could not verify team ownership status. Please try again later.",
-                    Some(e.into()),
-                );
-            }
-        };
-
-        if owner_count <= 1 {
-            return error_page(&v, "Cannot remove the last owner", None);
+    // Remove the member, the model refusing to remove the last owner
+    match target_membership.remove_from_team(&ctx.db).await {
+        Ok(()) => {}
+        Err(ModelError::Message(msg)) => return error_page(&v, &msg, None),
+        Err(e) => {
+            tracing::error!(
+                "Failed to remove user {} from team {}: {}",
+                target_user.id,
+                team.id,
+                e
+            );
+            return error_page(
+                &v,
+                "Could not remove member. Please try again later.",
+                Some(e.into()),
+            );
         }
     }
 
-    // Remove the member
-    let target_membership_model: team_memberships::ActiveModel = target_membership.into();
-    let delete_result = target_membership_model.delete(&ctx.db).await; // Requires ActiveModelTrait
-    if let Err(e) = delete_result {
-        tracing::error!(
-            "Failed to remove user {} from team {}: {}",
-            target_user.id,
-            team.id,
-            e
-        );
-        return error_page(
-            &v,
-            "Could not remove member. Please try again later.",
-            Some(e.into()),
-        );
-    };
-
     // Return a response that refreshes the page
     let response = Response::builder()
         .header("HX-Refresh", "true")
@@ -1204,13 +1737,15 @@ async fn delete_team(
         }
     };
 
-    // Check if user is an owner of this team
-    let is_owner_result = team.has_role(&ctx.db, user.id, "Owner").await;
-    let is_owner = match is_owner_result {
-        Ok(is_owner) => is_owner,
+    // Check if user may delete this team
+    let may_delete_result = team
+        .has_permission(&ctx.db, user.id, permissions::TEAM_DELETE)
+        .await;
+    let may_delete = match may_delete_result {
+        Ok(may_delete) => may_delete,
         Err(e) => {
             tracing::error!(
-                "Failed to check ownership for user {} in team {}: {}",
+                "Failed to check the permissions of user {} in team {}: {}",
                 user.id,
                 team.id,
                 e
@@ -1222,15 +1757,12 @@ async fn delete_team(
             );
         }
     };
-    if !is_owner {
-        return error_page(&v, "Only team owners can delete a team", None);
+    if !may_delete {
+        return error_page(&v, "You are not allowed to delete this team", None);
     }
 
-    // Read admin team name from configuration using helper
-    let admin_team_name = users::Model::get_admin_team_name(&ctx);
-
     // Check if this is the admin team
-    if team.name == admin_team_name {
+    if team.is_admin_team(&ctx) {
         // If they match, return an error page
         return error_page(&v, "The administrators team cannot be deleted.", None);
     }
@@ -1275,13 +1807,15 @@ async fn invite_member_handler(
         }
     };
 
-    // Check if user is an admin of this team
-    let is_admin_result = team.has_role(&ctx.db, user.id, "Administrator").await;
-    let is_admin = match is_admin_result {
-        Ok(is_admin) => is_admin,
+    // Check if user may invite members into this team
+    let may_invite_result = team
+        .has_permission(&ctx.db, user.id, permissions::TEAM_INVITE)
+        .await;
+    let may_invite = match may_invite_result {
+        Ok(may_invite) => may_invite,
         Err(e) => {
             tracing::error!(
-                "Failed to check admin role for user {} in team {}: {}",
+                "Failed to check the permissions of user {} in team {}: {}",
                 user.id,
                 team.id,
                 e
@@ -1294,18 +1828,48 @@ async fn invite_member_handler(
         }
     };
 
-    if !is_admin {
+    if !may_invite {
         // Return error message with HTMX
         return error_fragment(
             &v,
-            "Only team administrators can invite members",
+            "You are not allowed to invite members into this team",
             "#error-container",
         );
     }
 
-    // Find the target user by name
+    let invitation_params = match InvitationParams::new(user.id, &params) {
+        Ok(invitation_params) => invitation_params,
+        Err(err) => return error_fragment(&v, &err.to_string(), "#error-container"),
+    };
+
+    // Find the target user by name, or by email address when one was entered
     let target_user = match users::Model::find_by_name(&ctx.db, &params.user_name).await {
         Ok(user) => user,
+        Err(ModelError::EntityNotFound) if params.user_name.contains('@') => {
+            match users::Model::find_by_email_ignoring_case(&ctx.db, &params.user_name).await {
+                Ok(user) => user,
+                Err(ModelError::EntityNotFound) => {
+                    return invite_email_address(
+                        &v,
+                        &ctx,
+                        &user,
+                        &team,
+                        &params.user_name,
+                        &invitation_params,
+                        headers,
+                    )
+                    .await;
+                }
+                Err(e) => {
+                    tracing::error!("Failed to find user by email: {}", e);
+                    return error_fragment(
+                        &v,
+                        &format!("Error searching for user with email {}", &params.user_name),
+                        "#error-container",
+                    );
+                }
+            }
+        }
         Err(ModelError::EntityNotFound) => {
             return error_fragment(
                 &v,
@@ -1349,7 +1913,12 @@ async fn invite_member_handler(
 
     if let Some(membership) = existing_membership {
         // User already has a relationship with this team
-        let error_message = if membership.pending {
+        let error_message = if membership.requested {
+            format!(
+                "User {} has requested to join this team. Approve their request instead",
+                params.user_name
+            )
+        } else if membership.pending {
             format!(
                 "User {} already has a pending invitation to this team",
                 params.user_name
@@ -1364,9 +1933,18 @@ async fn invite_member_handler(
     // so, proceed with inviting to the team
 
     // Create invitation entity
-    /*let invitation =*/
-    match team_memberships::Model::create_invitation(&ctx.db, team.id, &params.user_name).await {
+    let lifetimes = TokenLifetimes::from_context(&ctx)?;
+    let (invitation, invitation_token) = match team_memberships::Model::create_invitation(
+        &ctx.db,
+        team.id,
+        &target_user.name,
+        &invitation_params,
+        &lifetimes,
+    )
+    .await
+    {
         Ok(invit) => invit,
+        Err(ModelError::Message(msg)) => return error_fragment(&v, &msg, "#error-container"),
         Err(e) => {
             // Something terribly wrong happened, abort with an error message
 
@@ -1389,8 +1967,15 @@ async fn invite_member_handler(
     };
 
     // Send notification e_mail to target user
-    let mailer_result =
-        crate::mailers::team::TeamMailer::send_invitation(&ctx, &user, &target_user, &team).await;
+    let mailer_result = crate::mailers::team::TeamMailer::send_invitation(
+        &ctx,
+        &user,
+        &target_user,
+        &team,
+        &invitation,
+        &invitation_token,
+    )
+    .await;
     if let Err(e) = mailer_result {
         // Log the error but proceed with the redirect, as the invitation was created.
         // TODO: The error message in the UI might be confusing if the redirect happens anyway.
@@ -1408,6 +1993,60 @@ async fn invite_member_handler(
     redirect(&redirect_url, headers)
 }
 
+/// Invites an email address no account is registered with: the invitation
+/// waits for an account to be registered and verified with this address
+async fn invite_email_address(
+    v: &TeraView,
+    ctx: &AppContext,
+    user: &users::Model,
+    team: &teams::Model,
+    email: &str,
+    invitation_params: &InvitationParams,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let lifetimes = TokenLifetimes::from_context(ctx)?;
+    let (invitation, invitation_token) = match team_memberships::Model::create_email_invitation(
+        &ctx.db,
+        team.id,
+        email,
+        invitation_params,
+        &lifetimes,
+    )
+    .await
+    {
+        Ok(invitation) => invitation,
+        Err(ModelError::Message(msg)) => return error_fragment(v, &msg, "#error-container"),
+        Err(e) => {
+            tracing::error!(
+                error = e.to_string(),
+                team_id = team.id,
+                "Failed to create an email invitation"
+            );
+            return error_fragment(
+                v,
+                "Could not create the invitation. Please try again later.",
+                "#error-container",
+            );
+        }
+    };
+
+    if let Err(e) = TeamMailer::send_signup_invitation(
+        ctx,
+        user,
+        email.trim(),
+        team,
+        &invitation,
+        &invitation_token,
+    )
+    .await
+    {
+        // The invitation stays listed on the team page, it can be cancelled
+        tracing::error!("Failed to send signup invitation email: {}", e);
+    }
+
+    redirect(&format!("/teams/{}", team.pid), headers)
+}
+
 /// Parameters for user search query
 #[derive(Deserialize, Debug)]
 pub struct SearchQuery {
@@ -1451,12 +2090,12 @@ async fn search_users(
         }
     };
 
-    // Security check: Ensure current user is an admin/owner of the team
-    let is_admin = match team
-        .has_role(&ctx.db, current_user.id, "Administrator")
+    // Security check: Ensure current user may invite members into the team
+    let may_invite = match team
+        .has_permission(&ctx.db, current_user.id, permissions::TEAM_INVITE)
         .await
     {
-        Ok(admin) => admin,
+        Ok(may_invite) => may_invite,
         Err(e) => {
             tracing::error!(
                 "Failed to check role for user {} in team {}: {:?}",
@@ -1468,7 +2107,7 @@ async fn search_users(
             return Ok(Html("".to_string()).into_response());
         }
     };
-    if !is_admin {
+    if !may_invite {
         tracing::warn!(
             "Unauthorized user {} attempted to search users for team {}",
             current_user.pid,
@@ -1495,6 +2134,7 @@ async fn search_users(
                 .add(users::Column::Email.contains(&search_term)),
         )
         .filter(users::Column::Id.is_not_in(existing_user_ids))
+        .filter(users::Column::Status.ne(crate::models::users::STATUS_SERVICE_ACCOUNT))
         .limit(10) // Limit results
         .all(&ctx.db)
         .await?;
@@ -1510,29 +2150,1232 @@ async fn search_users(
     format::render().view(&v, "teams/_user_search_results.html", context_data)
 }
 
-/// Team routes
-pub fn routes() -> Routes {
-    Routes::new()
-        .prefix("/teams")
-        .add("/", get(list_teams))
-        .add("/new", get(create_team_page))
-        .add("/new", post(create_team_handler))
-        .add("/{team_pid}", get(team_details))
-        .add("/{team_pid}", delete(delete_team))
-        .add("/{team_pid}/edit", get(edit_team_page))
-        .add("/{team_pid}/update", post(update_team_handler))
-        .add("/{team_pid}/invite", get(invite_member_page))
-        .add("/{team_pid}/invite", post(invite_member_handler))
-        .add("/{team_pid}/search-users", get(search_users))
-        .add("/invitations/{token}/accept", post(accept_invitation))
-        .add("/invitations/{token}/decline", post(decline_invitation))
-        .add(
-            "/{team_pid}/invitations/{token}/cancel",
-            post(cancel_invitation),
-        )
-        .add(
-            "/{team_pid}/members/{user_pid}/role",
-            put(update_member_role),
-        )
-        .add("/{team_pid}/members/{user_pid}", delete(remove_member))
+/// Form sent by an Owner to offer the ownership of a team to a member
+#[derive(Deserialize, Debug)]
+pub struct OwnershipTransferParams {
+    user_pid: String,
+}
+
+/// Response refreshing the team page after an action on its ownership
+fn refresh_page() -> Result<Response> {
+    Ok(Response::builder()
+        .header("HX-Refresh", "true")
+        .body(axum::body::Body::empty())?)
+}
+
+/// Offer ownership transfer handler: the receiving member is emailed and
+/// must accept before the roles swap
+#[debug_handler]
+async fn offer_ownership_transfer(
+    ViewEngine(v): ViewEngine<TeraView>,
+    auth: JWTWithUserOpt<users::Model>,
+    State(ctx): State<AppContext>,
+    Path(team_pid): Path<String>,
+    headers: HeaderMap,
+    Form(params): Form<OwnershipTransferParams>,
+) -> Result<Response> {
+    let Some(user) = auth.user else {
+        return redirect("/auth/login", headers);
+    };
+    let Ok(team) = teams::Model::find_by_pid(&ctx.db, &team_pid).await else {
+        return error_fragment(&v, "Team not found.", "#ownership-transfer-errors");
+    };
+    let Ok(to_user) = users::Model::find_by_pid(&ctx.db, &params.user_pid).await else {
+        return error_fragment(&v, "Member not found.", "#ownership-transfer-errors");
+    };
+
+    let lifetimes = TokenLifetimes::from_context(&ctx)?;
+    match ownership_transfers::Model::create(&ctx.db, &team, &user, &to_user, &lifetimes).await {
+        Ok(_) => {}
+        Err(ModelError::Message(msg)) => {
+            return error_fragment(&v, &msg, "#ownership-transfer-errors");
+        }
+        Err(e) => {
+            tracing::error!("Failed to offer the ownership of team {}: {}", team.id, e);
+            return error_fragment(
+                &v,
+                "Could not offer the ownership. Please try again later.",
+                "#ownership-transfer-errors",
+            );
+        }
+    }
+
+    // The offer stays visible on the team page if the email is lost
+    if let Err(e) = TeamMailer::send_ownership_transfer(
+        &ctx,
+        &user,
+        &to_user,
+        &team,
+        lifetimes.team_invitation_min / (24 * 60),
+    )
+    .await
+    {
+        tracing::error!(
+            "Failed to send the ownership transfer email to {}: {}",
+            to_user.email,
+            e
+        );
+    }
+
+    refresh_page()
+}
+
+/// Finds the ownership transfer waiting for an answer in a team
+async fn find_team_transfer(
+    ctx: &AppContext,
+    team_pid: &str,
+) -> std::result::Result<ownership_transfers::Model, &'static str> {
+    let team = teams::Model::find_by_pid(&ctx.db, team_pid)
+        .await
+        .map_err(|_| "Team not found.")?;
+    match ownership_transfers::Model::find_pending_for_team(&ctx.db, team.id).await {
+        Ok(Some(transfer)) => Ok(transfer),
+        Ok(None) => Err("This ownership transfer no longer exists or has expired."),
+        Err(e) => {
+            tracing::error!(
+                "Failed to find the ownership transfer of team {}: {}",
+                team.id,
+                e
+            );
+            Err("Could not load the ownership transfer. Please try again later.")
+        }
+    }
+}
+
+/// Accept ownership transfer handler
+#[debug_handler]
+async fn accept_ownership_transfer(
+    ViewEngine(v): ViewEngine<TeraView>,
+    auth: JWTWithUserOpt<users::Model>,
+    State(ctx): State<AppContext>,
+    Path(team_pid): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let Some(user) = auth.user else {
+        return redirect("/auth/login", headers);
+    };
+    let transfer = match find_team_transfer(&ctx, &team_pid).await {
+        Ok(transfer) => transfer,
+        Err(msg) => return error_fragment(&v, msg, "#ownership-transfer-errors"),
+    };
+    match transfer.accept(&ctx.db, &user).await {
+        Ok(()) => refresh_page(),
+        Err(ModelError::Message(msg)) => error_fragment(&v, &msg, "#ownership-transfer-errors"),
+        Err(e) => {
+            tracing::error!("Failed to accept an ownership transfer: {}", e);
+            error_fragment(
+                &v,
+                "Could not accept the ownership. Please try again later.",
+                "#ownership-transfer-errors",
+            )
+        }
+    }
+}
+
+/// Decline ownership transfer handler, also used by the Owners to cancel it
+#[debug_handler]
+async fn decline_ownership_transfer(
+    ViewEngine(v): ViewEngine<TeraView>,
+    auth: JWTWithUserOpt<users::Model>,
+    State(ctx): State<AppContext>,
+    Path(team_pid): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let Some(user) = auth.user else {
+        return redirect("/auth/login", headers);
+    };
+    let transfer = match find_team_transfer(&ctx, &team_pid).await {
+        Ok(transfer) => transfer,
+        Err(msg) => return error_fragment(&v, msg, "#ownership-transfer-errors"),
+    };
+    match transfer.decline(&ctx.db, &user).await {
+        Ok(()) => refresh_page(),
+        Err(ModelError::Message(msg)) => error_fragment(&v, &msg, "#ownership-transfer-errors"),
+        Err(e) => {
+            tracing::error!("Failed to drop an ownership transfer: {}", e);
+            error_fragment(
+                &v,
+                "Could not drop the ownership transfer. Please try again later.",
+                "#ownership-transfer-errors",
+            )
+        }
+    }
+}
+
+/// Finds a team whose invitations the user manages, i.e. where the role of
+/// the user grants the `team.invite` permission
+async fn find_team_inviting_with(
+    ctx: &AppContext,
+    team_pid: &str,
+    user: &users::Model,
+) -> std::result::Result<teams::Model, &'static str> {
+    let team = teams::Model::find_by_pid(&ctx.db, team_pid)
+        .await
+        .map_err(|_| "Team not found.")?;
+    match team
+        .has_permission(&ctx.db, user.id, permissions::TEAM_INVITE)
+        .await
+    {
+        Ok(true) => Ok(team),
+        Ok(false) => Err("You are not allowed to manage the invitations of this team."),
+        Err(e) => {
+            tracing::error!(
+                "Failed to check role for user {} in team {}: {}",
+                user.id,
+                team.id,
+                e
+            );
+            Err("Could not verify your permissions. Please try again later.")
+        }
+    }
+}
+
+/// Finds a team whose service accounts the user manages, i.e. where the role
+/// of the user grants the `service_accounts.manage` permission
+async fn find_team_managed_by(
+    ctx: &AppContext,
+    team_pid: &str,
+    user: &users::Model,
+) -> std::result::Result<teams::Model, &'static str> {
+    let team = teams::Model::find_by_pid(&ctx.db, team_pid)
+        .await
+        .map_err(|_| "Team not found.")?;
+    match team
+        .has_permission(&ctx.db, user.id, permissions::SERVICE_ACCOUNTS_MANAGE)
+        .await
+    {
+        Ok(true) => Ok(team),
+        Ok(false) => Err("You are not allowed to manage the service accounts of this team."),
+        Err(e) => {
+            tracing::error!(
+                "Failed to check role for user {} in team {}: {}",
+                user.id,
+                team.id,
+                e
+            );
+            Err("Could not verify your permissions. Please try again later.")
+        }
+    }
+}
+
+/// Renders the service accounts section of the team details page. The
+/// plaintext of a token that was just created is passed in `new_token` with
+/// the pid of its service account, as it can only be shown once.
+async fn render_service_accounts(
+    v: &TeraView,
+    ctx: &AppContext,
+    team: &teams::Model,
+    user: &users::Model,
+    new_token: Option<(&str, &str)>,
+) -> Result<Response> {
+    let accounts = match service_accounts::Model::find_for_team(&ctx.db, team.id).await {
+        Ok(accounts) => accounts,
+        Err(e) => {
+            tracing::error!("Failed to load service accounts of team {}: {}", team.id, e);
+            return error_fragment(
+                v,
+                "Could not load service accounts.",
+                "#service-accounts-error-container",
+            );
+        }
+    };
+    let roles = team.grantable_roles(&ctx.db, user.id).await?;
+
+    let mut service_accounts = Vec::with_capacity(accounts.len());
+    for account in accounts {
+        let tokens = personal_access_tokens::Model::find_for_user(&ctx.db, account.user.id).await?;
+        let service_account_pid = account.service_account.pid.to_string();
+        service_accounts.push(json!({
+            "pid": service_account_pid,
+            "name": account.user.name,
+            "description": account.service_account.description,
+            "role": account.role,
+            "created_at": account.service_account.created_at,
+            "created_by": account.created_by.map(|creator| creator.name),
+            "new_token": new_token
+                .filter(|(pid, _)| *pid == service_account_pid)
+                .map(|(_, token)| token),
+            "tokens": tokens
+                .iter()
+                .map(|token| {
+                    json!({
+                        "pid": token.pid.to_string(),
+                        "name": token.name,
+                        "token_prefix": token.token_prefix,
+                        "scopes": token.scope_list(),
+                        "last_used_at": token.last_used_at,
+                        "expires_at": token.expires_at,
+                        "usable": token.is_usable(),
+                    })
+                })
+                .collect::<Vec<_>>(),
+        }));
+    }
+    let scopes = personal_access_tokens::SCOPES
+        .iter()
+        .map(|(scope, description)| json!({"name": scope, "description": description}))
+        .collect::<Vec<_>>();
+
+    render_template(
+        v,
+        "teams/_service_accounts.html",
+        data!({
+            "team_pid": team.pid.to_string(),
+            "service_accounts": &service_accounts,
+            "roles": &roles,
+            "scopes": &scopes,
+        }),
+    )
+}
+
+/// Service accounts section of the team details page
+#[debug_handler]
+async fn service_accounts_fragment(
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    auth: JWTWithUserOpt<users::Model>,
+    Path(team_pid): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let team = match find_team_managed_by(&ctx, &team_pid, &user).await {
+        Ok(team) => team,
+        Err(msg) => return error_fragment(&v, msg, "#service-accounts-error-container"),
+    };
+    render_service_accounts(&v, &ctx, &team, &user, None).await
+}
+
+/// Create service account handler
+#[debug_handler]
+async fn create_service_account(
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    auth: JWTWithUserOpt<users::Model>,
+    Path(team_pid): Path<String>,
+    headers: HeaderMap,
+    Form(params): Form<CreateServiceAccountParams>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let team = match find_team_managed_by(&ctx, &team_pid, &user).await {
+        Ok(team) => team,
+        Err(msg) => return error_fragment(&v, msg, "#service-accounts-error-container"),
+    };
+    match service_accounts::Model::create(&ctx.db, &team, &user, &params).await {
+        Ok(_) => render_service_accounts(&v, &ctx, &team, &user, None).await,
+        Err(ModelError::Message(msg)) => {
+            error_fragment(&v, &msg, "#service-accounts-error-container")
+        }
+        Err(e) => {
+            tracing::error!(
+                "Failed to create service account in team {}: {}",
+                team.id,
+                e
+            );
+            error_fragment(
+                &v,
+                "Could not create the service account. Please try again.",
+                "#service-accounts-error-container",
+            )
+        }
+    }
+}
+
+/// Update service account role handler
+#[debug_handler]
+async fn update_service_account_role(
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    auth: JWTWithUserOpt<users::Model>,
+    Path((team_pid, service_account_pid)): Path<(String, String)>,
+    headers: HeaderMap,
+    Form(params): Form<UpdateRoleParams>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let team = match find_team_managed_by(&ctx, &team_pid, &user).await {
+        Ok(team) => team,
+        Err(msg) => return error_fragment(&v, msg, "#service-accounts-error-container"),
+    };
+    let Ok(service_account) =
+        service_accounts::Model::find_by_pid_for_team(&ctx.db, &service_account_pid, team.id).await
+    else {
+        return error_fragment(
+            &v,
+            "Service account not found.",
+            "#service-accounts-error-container",
+        );
+    };
+    match service_account
+        .update_role(&ctx.db, &team, &user, &params.role)
+        .await
+    {
+        Ok(()) => render_service_accounts(&v, &ctx, &team, &user, None).await,
+        Err(ModelError::Message(msg)) => {
+            error_fragment(&v, &msg, "#service-accounts-error-container")
+        }
+        Err(e) => {
+            tracing::error!(
+                "Failed to change the role of service account {}: {}",
+                service_account_pid,
+                e
+            );
+            error_fragment(
+                &v,
+                "Could not change the role. Please try again.",
+                "#service-accounts-error-container",
+            )
+        }
+    }
+}
+
+/// Delete service account handler
+#[debug_handler]
+async fn delete_service_account(
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    auth: JWTWithUserOpt<users::Model>,
+    Path((team_pid, service_account_pid)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let team = match find_team_managed_by(&ctx, &team_pid, &user).await {
+        Ok(team) => team,
+        Err(msg) => return error_fragment(&v, msg, "#service-accounts-error-container"),
+    };
+    let Ok(service_account) =
+        service_accounts::Model::find_by_pid_for_team(&ctx.db, &service_account_pid, team.id).await
+    else {
+        return error_fragment(
+            &v,
+            "Service account not found.",
+            "#service-accounts-error-container",
+        );
+    };
+    if let Err(e) = service_account.delete(&ctx.db, &team, &user).await {
+        tracing::error!(
+            "Failed to delete service account {}: {}",
+            service_account_pid,
+            e
+        );
+        return error_fragment(
+            &v,
+            "Could not delete the service account. Please try again.",
+            "#service-accounts-error-container",
+        );
+    }
+    render_service_accounts(&v, &ctx, &team, &user, None).await
+}
+
+/// Create service account token handler
+#[debug_handler]
+async fn create_service_account_token(
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    auth: JWTWithUserOpt<users::Model>,
+    Path((team_pid, service_account_pid)): Path<(String, String)>,
+    headers: HeaderMap,
+    Form(fields): Form<Vec<(String, String)>>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let team = match find_team_managed_by(&ctx, &team_pid, &user).await {
+        Ok(team) => team,
+        Err(msg) => return error_fragment(&v, msg, "#service-accounts-error-container"),
+    };
+    let Ok(service_account) =
+        service_accounts::Model::find_by_pid_for_team(&ctx.db, &service_account_pid, team.id).await
+    else {
+        return error_fragment(
+            &v,
+            "Service account not found.",
+            "#service-accounts-error-container",
+        );
+    };
+    let params = match CreateTokenParams::from_form_fields(fields) {
+        Ok(params) => params,
+        Err(e) => {
+            return error_fragment(&v, &e.to_string(), "#service-accounts-error-container");
+        }
+    };
+    match service_account
+        .create_token(&ctx.db, &team, &user, &params)
+        .await
+    {
+        Ok((_, plaintext)) => {
+            render_service_accounts(
+                &v,
+                &ctx,
+                &team,
+                &user,
+                Some((&service_account_pid, &plaintext)),
+            )
+            .await
+        }
+        Err(ModelError::Message(msg)) => {
+            error_fragment(&v, &msg, "#service-accounts-error-container")
+        }
+        Err(e) => {
+            tracing::error!(
+                "Failed to create a token for service account {}: {}",
+                service_account_pid,
+                e
+            );
+            error_fragment(
+                &v,
+                "Could not create the token. Please try again.",
+                "#service-accounts-error-container",
+            )
+        }
+    }
+}
+
+/// Revoke service account token handler
+#[debug_handler]
+async fn revoke_service_account_token(
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    auth: JWTWithUserOpt<users::Model>,
+    Path((team_pid, service_account_pid, token_pid)): Path<(String, String, String)>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let team = match find_team_managed_by(&ctx, &team_pid, &user).await {
+        Ok(team) => team,
+        Err(msg) => return error_fragment(&v, msg, "#service-accounts-error-container"),
+    };
+    let token =
+        match service_accounts::Model::find_by_pid_for_team(&ctx.db, &service_account_pid, team.id)
+            .await
+        {
+            Ok(service_account) => {
+                personal_access_tokens::Model::find_by_pid_for_user(
+                    &ctx.db,
+                    &token_pid,
+                    service_account.user_id,
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        };
+    let Ok(token) = token else {
+        return error_fragment(&v, "Token not found.", "#service-accounts-error-container");
+    };
+    if let Err(e) = token.revoke(&ctx.db).await {
+        tracing::error!(
+            "Failed to revoke token {} of service account {}: {}",
+            token_pid,
+            service_account_pid,
+            e
+        );
+        return error_fragment(
+            &v,
+            "Could not revoke the token. Please try again.",
+            "#service-accounts-error-container",
+        );
+    }
+    tracing::info!(
+        service_account_pid,
+        token_pid,
+        revoked_by = %user.pid,
+        "Service account token revoked"
+    );
+    render_service_accounts(&v, &ctx, &team, &user, None).await
+}
+
+/// Team directory page: the teams that accept requests to join, with the
+/// state of the requests of the user
+#[debug_handler]
+async fn team_directory(
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    auth: JWTWithUserOpt<users::Model>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let listed_teams = match teams::Model::list_directory(&ctx.db).await {
+        Ok(teams) => teams,
+        Err(e) => {
+            tracing::error!("Failed to load the team directory: {}", e);
+            return error_page(
+                &v,
+                "Could not load the team directory. Please try again later.",
+                Some(e.into()),
+            );
+        }
+    };
+    let memberships = team_memberships::Entity::find()
+        .filter(team_memberships::Column::UserId.eq(user.id))
+        .all(&ctx.db)
+        .await?;
+    let directory = listed_teams
+        .iter()
+        .map(|team| {
+            let membership = memberships
+                .iter()
+                .find(|membership| membership.team_id == team.id);
+            let status = match membership {
+                None => "none",
+                Some(membership) if !membership.pending => "member",
+                Some(membership) if membership.requested => "requested",
+                Some(_) => "invited",
+            };
+            json!({
+                "pid": team.pid.to_string(),
+                "name": team.name,
+                "description": team.description,
+                "status": status,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+    render_template(
+        &v,
+        "teams/directory.html",
+        data!({
+            "user": &user,
+            "teams": &directory,
+            "active_page": "teams",
+            "invitation_count": &layout_context.invitation_count,
+            "pending_user_count": &layout_context.pending_user_count,
+            "is_app_admin": &layout_context.is_app_admin,
+        }),
+    )
+}
+
+/// Request to join handler: the team administrators are emailed and must
+/// approve the request
+#[debug_handler]
+async fn request_to_join(
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    auth: JWTWithUserOpt<users::Model>,
+    Path(team_pid): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let Ok(team) = teams::Model::find_by_pid(&ctx.db, &team_pid).await else {
+        return error_fragment(&v, "Team not found", "#error-container");
+    };
+    match team_memberships::Model::create_request(&ctx.db, &team, &user).await {
+        Ok(_) => {}
+        Err(ModelError::Message(msg)) => return error_fragment(&v, &msg, "#error-container"),
+        Err(e) => {
+            tracing::error!("Failed to request to join team {}: {}", team.id, e);
+            return error_fragment(
+                &v,
+                "Could not send your request. Please try again later.",
+                "#error-container",
+            );
+        }
+    }
+    tracing::info!(
+        team_pid = %team.pid,
+        user_pid = %user.pid,
+        "Request to join team sent"
+    );
+
+    // The request stays visible on the team page if an email is lost
+    match team
+        .members_with_permission(&ctx.db, permissions::TEAM_INVITE)
+        .await
+    {
+        Ok(admins) => {
+            for admin in admins {
+                if let Err(e) = TeamMailer::send_join_request(&ctx, &admin, &user, &team).await {
+                    tracing::error!(
+                        "Failed to send the request to join email to {}: {}",
+                        admin.email,
+                        e
+                    );
+                }
+            }
+        }
+        Err(e) => {
+            tracing::error!(
+                "Failed to load the administrators of team {}: {}",
+                team.id,
+                e
+            );
+        }
+    }
+
+    redirect("/teams/directory", headers)
+}
+
+/// Finds a request to join a team whose requests the user answers: those
+/// who may invite to the team do
+async fn find_team_request(
+    ctx: &AppContext,
+    team_pid: &str,
+    request_pid: &str,
+    user: &users::Model,
+) -> std::result::Result<(teams::Model, team_memberships::Model, users::Model), &'static str> {
+    let team = teams::Model::find_by_pid(&ctx.db, team_pid)
+        .await
+        .map_err(|_| "Team not found.")?;
+    match team
+        .has_permission(&ctx.db, user.id, permissions::TEAM_INVITE)
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => return Err("You are not allowed to answer requests to join this team."),
+        Err(e) => {
+            tracing::error!(
+                "Failed to check role for user {} in team {}: {}",
+                user.id,
+                team.id,
+                e
+            );
+            return Err("Could not verify your permissions. Please try again later.");
+        }
+    }
+    let request = team_memberships::Model::find_request_by_pid(&ctx.db, request_pid, team.id)
+        .await
+        .map_err(|_| "Request not found. It may have been answered already.")?;
+    let requesting_user = match request.user_id {
+        Some(user_id) => users::Model::find_by_id(&ctx.db, user_id)
+            .await
+            .map_err(|_| "The user who sent this request no longer exists.")?,
+        None => return Err("The user who sent this request no longer exists."),
+    };
+    Ok((team, request, requesting_user))
+}
+
+/// Approve request to join handler
+#[debug_handler]
+async fn approve_join_request(
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    auth: JWTWithUserOpt<users::Model>,
+    Path((team_pid, request_pid)): Path<(String, String)>,
+    headers: HeaderMap,
+    Form(params): Form<UpdateRoleParams>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let (team, request, requesting_user) =
+        match find_team_request(&ctx, &team_pid, &request_pid, &user).await {
+            Ok(found) => found,
+            Err(msg) => return error_fragment(&v, msg, "#join-requests-errors"),
+        };
+    let membership = match request
+        .approve_request(&ctx.db, user.id, &params.role)
+        .await
+    {
+        Ok(membership) => membership,
+        Err(ModelError::Message(msg)) => {
+            return error_fragment(&v, &msg, "#join-requests-errors");
+        }
+        Err(e) => {
+            tracing::error!("Failed to approve request {}: {}", request_pid, e);
+            return error_fragment(
+                &v,
+                "Could not approve the request. Please try again later.",
+                "#join-requests-errors",
+            );
+        }
+    };
+    tracing::info!(
+        team_pid = %team.pid,
+        user_pid = %requesting_user.pid,
+        approved_by = %user.pid,
+        "Request to join team approved"
+    );
+
+    if let Err(e) = TeamMailer::send_join_request_answer(
+        &ctx,
+        &user,
+        &requesting_user,
+        &team,
+        Some(&membership),
+    )
+    .await
+    {
+        tracing::error!(
+            "Failed to send the request approval email to {}: {}",
+            requesting_user.email,
+            e
+        );
+    }
+
+    refresh_page()
+}
+
+/// Deny request to join handler
+#[debug_handler]
+async fn deny_join_request(
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    auth: JWTWithUserOpt<users::Model>,
+    Path((team_pid, request_pid)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let (team, request, requesting_user) =
+        match find_team_request(&ctx, &team_pid, &request_pid, &user).await {
+            Ok(found) => found,
+            Err(msg) => return error_fragment(&v, msg, "#join-requests-errors"),
+        };
+    if let Err(e) = request.deny_request(&ctx.db).await {
+        tracing::error!("Failed to deny request {}: {}", request_pid, e);
+        return error_fragment(
+            &v,
+            "Could not deny the request. Please try again later.",
+            "#join-requests-errors",
+        );
+    }
+    tracing::info!(
+        team_pid = %team.pid,
+        user_pid = %requesting_user.pid,
+        denied_by = %user.pid,
+        "Request to join team denied"
+    );
+
+    if let Err(e) =
+        TeamMailer::send_join_request_answer(&ctx, &user, &requesting_user, &team, None).await
+    {
+        tracing::error!(
+            "Failed to send the request denial email to {}: {}",
+            requesting_user.email,
+            e
+        );
+    }
+
+    refresh_page()
+}
+
+/// Finds a team whose join links the user manages, i.e. where the role of the
+/// user grants the `team.invite` permission
+async fn find_team_links_managed_by(
+    ctx: &AppContext,
+    team_pid: &str,
+    user: &users::Model,
+) -> std::result::Result<teams::Model, &'static str> {
+    let team = teams::Model::find_by_pid(&ctx.db, team_pid)
+        .await
+        .map_err(|_| "Team not found.")?;
+    match team
+        .has_permission(&ctx.db, user.id, permissions::TEAM_INVITE)
+        .await
+    {
+        Ok(true) => Ok(team),
+        Ok(false) => Err("You are not allowed to manage the join links of this team."),
+        Err(e) => {
+            tracing::error!(
+                "Failed to check role for user {} in team {}: {}",
+                user.id,
+                team.id,
+                e
+            );
+            Err("Could not verify your permissions. Please try again later.")
+        }
+    }
+}
+
+/// Renders the join links section of the team details page. The URL of a
+/// link that was just created is passed in `new_link` with its pid, as its
+/// token can only be shown once.
+async fn render_join_links(
+    v: &TeraView,
+    ctx: &AppContext,
+    team: &teams::Model,
+    user: &users::Model,
+    new_link: Option<(&str, &str)>,
+) -> Result<Response> {
+    let links = match team_join_links::Model::find_for_team(&ctx.db, team.id).await {
+        Ok(links) => links,
+        Err(e) => {
+            tracing::error!("Failed to load join links of team {}: {}", team.id, e);
+            return error_fragment(
+                v,
+                "Could not load join links.",
+                "#join-links-error-container",
+            );
+        }
+    };
+    let links = links
+        .iter()
+        .map(|link| {
+            let pid = link.pid.to_string();
+            json!({
+                "url": new_link
+                    .filter(|(new_pid, _)| *new_pid == pid)
+                    .map(|(_, url)| url),
+                "pid": pid,
+                "role": link.role,
+                "use_count": link.use_count,
+                "max_uses": link.max_uses,
+                "expires_at": link.expires_at,
+                "allowed_domain": link.allowed_domain,
+                "created_at": link.created_at,
+                "expired": link.is_expired(),
+                "used_up": link.is_used_up(),
+            })
+        })
+        .collect::<Vec<_>>();
+    let roles = team
+        .grantable_roles(&ctx.db, user.id)
+        .await?
+        .into_iter()
+        .filter(|role| role != OWNER_ROLE)
+        .collect::<Vec<_>>();
+
+    render_template(
+        v,
+        "teams/_join_links.html",
+        data!({
+            "team_pid": team.pid.to_string(),
+            "join_links": &links,
+            "roles": &roles,
+            "default_role": DEFAULT_INVITATION_ROLE,
+        }),
+    )
+}
+
+/// Join links section of the team details page
+#[debug_handler]
+async fn join_links_fragment(
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    auth: JWTWithUserOpt<users::Model>,
+    Path(team_pid): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let team = match find_team_links_managed_by(&ctx, &team_pid, &user).await {
+        Ok(team) => team,
+        Err(msg) => return error_fragment(&v, msg, "#join-links-error-container"),
+    };
+    render_join_links(&v, &ctx, &team, &user, None).await
+}
+
+/// Create join link handler
+#[debug_handler]
+async fn create_join_link(
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    auth: JWTWithUserOpt<users::Model>,
+    Path(team_pid): Path<String>,
+    headers: HeaderMap,
+    Form(fields): Form<Vec<(String, String)>>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let team = match find_team_links_managed_by(&ctx, &team_pid, &user).await {
+        Ok(team) => team,
+        Err(msg) => return error_fragment(&v, msg, "#join-links-error-container"),
+    };
+    let params = match CreateJoinLinkParams::from_form_fields(fields) {
+        Ok(params) => params,
+        Err(e) => return error_fragment(&v, &e.to_string(), "#join-links-error-container"),
+    };
+    match team_join_links::Model::create(&ctx.db, &team, &user, &params).await {
+        Ok((link, token)) => {
+            let url = format!("{}/teams/join/{}", ctx.config.server.host, token);
+            render_join_links(&v, &ctx, &team, &user, Some((&link.pid.to_string(), &url))).await
+        }
+        Err(ModelError::Message(msg)) => error_fragment(&v, &msg, "#join-links-error-container"),
+        Err(e) => {
+            tracing::error!("Failed to create a join link for team {}: {}", team.id, e);
+            error_fragment(
+                &v,
+                "Could not create the join link. Please try again.",
+                "#join-links-error-container",
+            )
+        }
+    }
+}
+
+/// Revoke join link handler
+#[debug_handler]
+async fn revoke_join_link(
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    auth: JWTWithUserOpt<users::Model>,
+    Path((team_pid, link_pid)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let team = match find_team_links_managed_by(&ctx, &team_pid, &user).await {
+        Ok(team) => team,
+        Err(msg) => return error_fragment(&v, msg, "#join-links-error-container"),
+    };
+    let Ok(link) = team_join_links::Model::find_by_pid_for_team(&ctx.db, &link_pid, team.id).await
+    else {
+        return error_fragment(&v, "Join link not found.", "#join-links-error-container");
+    };
+    if let Err(e) = link.revoke(&ctx.db, &team, &user).await {
+        tracing::error!("Failed to revoke join link {}: {}", link_pid, e);
+        return error_fragment(
+            &v,
+            "Could not revoke the join link. Please try again.",
+            "#join-links-error-container",
+        );
+    }
+    render_join_links(&v, &ctx, &team, &user, None).await
+}
+
+/// Finds the join link of a token, with its team
+async fn find_join_link(
+    ctx: &AppContext,
+    token: &str,
+) -> std::result::Result<(team_join_links::Model, teams::Model), &'static str> {
+    let link = match team_join_links::Model::find_by_token(&ctx.db, token).await {
+        Ok(link) => link,
+        Err(ModelError::EntityNotFound) => {
+            return Err("This join link is no longer valid. It may have been revoked.");
+        }
+        Err(err) => {
+            tracing::error!("Failed to find join link: {:?}", err);
+            return Err("Database error while searching for join link");
+        }
+    };
+    match teams::Entity::find_by_id(link.team_id).one(&ctx.db).await {
+        Ok(Some(team)) => Ok((link, team)),
+        Ok(None) => Err("Team not found"),
+        Err(err) => {
+            tracing::error!("Failed to find team of join link: {:?}", err);
+            Err("Database error while searching for team")
+        }
+    }
+}
+
+/// Opens a join link: the user is shown the team and the role they would
+/// get, and asked to confirm
+#[debug_handler]
+async fn open_join_link(
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    auth: JWTWithUserOpt<users::Model>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let (link, team) = match find_join_link(&ctx, &token).await {
+        Ok(found) => found,
+        Err(msg) => return error_page(&v, msg, None),
+    };
+    if team_memberships::Model::find_by_team_and_user(&ctx.db, team.id, user.id)
+        .await
+        .is_ok()
+    {
+        return redirect(&format!("/teams/{}", team.pid), headers);
+    }
+    let error = match link.check_usable_by(&user) {
+        Ok(()) => None,
+        Err(ModelError::Message(msg)) => Some(msg),
+        Err(e) => return Err(e.into()),
+    };
+
+    let layout_context = user.get_base_layout_context(&ctx.db, &ctx).await;
+    render_template(
+        &v,
+        "teams/join.html",
+        data!({
+            "user": &user,
+            "team": {
+                "name": team.name,
+                "description": team.description,
+            },
+            "token": token,
+            "role": link.role,
+            "error": error,
+            "active_page": "teams",
+            "invitation_count": &layout_context.invitation_count,
+            "pending_user_count": &layout_context.pending_user_count,
+            "is_app_admin": &layout_context.is_app_admin,
+        }),
+    )
+}
+
+/// Join team with a join link handler
+#[debug_handler]
+async fn join_with_link(
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    auth: JWTWithUserOpt<users::Model>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let (link, team) = match find_join_link(&ctx, &token).await {
+        Ok(found) => found,
+        Err(msg) => return error_page(&v, msg, None),
+    };
+    match link.join(&ctx.db, &user).await {
+        Ok(_) => redirect(&format!("/teams/{}", team.pid), headers),
+        Err(ModelError::Message(msg)) => error_fragment(&v, &msg, "#error-container"),
+        Err(e) => {
+            tracing::error!("Failed to join team {} with a join link: {}", team.id, e);
+            error_fragment(
+                &v,
+                "Could not join the team. Please try again.",
+                "#error-container",
+            )
+        }
+    }
+}
+
+/// Team routes
+pub fn routes() -> Routes {
+    Routes::new()
+        .prefix("/teams")
+        .add("/", get(list_teams))
+        .add("/new", get(create_team_page))
+        .add("/new", post(create_team_handler))
+        .add("/directory", get(team_directory))
+        .add("/{team_pid}", get(team_details))
+        .add("/{team_pid}", delete(delete_team))
+        .add("/{team_pid}/edit", get(edit_team_page))
+        .add("/{team_pid}/update", post(update_team_handler))
+        .add("/{team_pid}/organization", post(move_team_handler))
+        .add("/{team_pid}/directory", post(update_team_listing))
+        .add("/{team_pid}/invite", get(invite_member_page))
+        .add("/{team_pid}/invite", post(invite_member_handler))
+        .add("/{team_pid}/search-users", get(search_users))
+        .add("/invitations/{token}", get(open_invitation))
+        .add("/join/{token}", get(open_join_link).post(join_with_link))
+        .add(
+            "/invitations/{invitation_pid}/accept",
+            post(accept_invitation),
+        )
+        .add(
+            "/invitations/{invitation_pid}/decline",
+            post(decline_invitation),
+        )
+        .add("/{team_pid}/invitations", get(team_invitations_page))
+        .add(
+            "/{team_pid}/invitations/{invitation_pid}/resend",
+            post(resend_invitation),
+        )
+        .add(
+            "/{team_pid}/invitations/{invitation_pid}/cancel",
+            post(cancel_invitation),
+        )
+        .add(
+            "/{team_pid}/members/{user_pid}/role",
+            put(update_member_role),
+        )
+        .add("/{team_pid}/members/{user_pid}", delete(remove_member))
+        .add(
+            "/{team_pid}/ownership-transfer",
+            post(offer_ownership_transfer),
+        )
+        .add(
+            "/{team_pid}/ownership-transfer/accept",
+            post(accept_ownership_transfer),
+        )
+        .add(
+            "/{team_pid}/ownership-transfer/decline",
+            post(decline_ownership_transfer),
+        )
+        .add(
+            "/{team_pid}/service_accounts",
+            get(service_accounts_fragment).post(create_service_account),
+        )
+        .add(
+            "/{team_pid}/service_accounts/{service_account_pid}",
+            delete(delete_service_account),
+        )
+        .add(
+            "/{team_pid}/service_accounts/{service_account_pid}/role",
+            post(update_service_account_role),
+        )
+        .add(
+            "/{team_pid}/service_accounts/{service_account_pid}/tokens",
+            post(create_service_account_token),
+        )
+        .add(
+            "/{team_pid}/service_accounts/{service_account_pid}/tokens/{token_pid}",
+            delete(revoke_service_account_token),
+        )
+        .add(
+            "/{team_pid}/join_links",
+            get(join_links_fragment).post(create_join_link),
+        )
+        .add(
+            "/{team_pid}/join_links/{link_pid}",
+            delete(revoke_join_link),
+        )
+        .add("/{team_pid}/requests", post(request_to_join))
+        .add(
+            "/{team_pid}/requests/{request_pid}/approve",
+            post(approve_join_request),
+        )
+        .add(
+            "/{team_pid}/requests/{request_pid}/deny",
+            post(deny_join_request),
+        )
 }
@@ -6,8 +6,14 @@ use crate::{
         _entities::ssh_keys,
         _entities::team_memberships,
         _entities::teams,
+        password_policy::PasswordPolicy,
+        personal_access_tokens::{self, CreateTokenParams},
+        recovery_codes,
+        sessions,
+        tokens::TokenLifetimes,
         users,
         users::users::Column as UsersColumn, // Import Column specifically for users
+        webauthn_credentials,
     },
     views::{error_fragment, error_page, redirect, render_template},
 };
@@ -16,7 +22,7 @@ use axum::http::{StatusCode, header};
 use axum::response::Response;
 use axum::{
     debug_handler,
-    extract::{Form, Query, State},
+    extract::{Form, Path, Query, State},
     routing::{delete, get, post},
 };
 use loco_rs::prelude::Result;
@@ -88,12 +94,12 @@ async fn profile(
         .filter_map(|(team, memberships)| {
             let is_member = memberships
                 .iter()
-                .any(|m| m.user_id == user.id && !m.pending);
+                .any(|m| m.user_id == Some(user.id) && !m.pending);
             if is_member {
                 // Find user's role in this team
                 let role = memberships
                     .iter()
-                    .find(|m| m.user_id == user.id && !m.pending)
+                    .find(|m| m.user_id == Some(user.id) && !m.pending)
                     .map(|m| m.role.clone())
                     .unwrap_or_else(|| "Unknown".to_string());
 
@@ -191,15 +197,17 @@ async fn invitations(
     let invitations = invitations_data
         .into_iter()
         .filter_map(|(membership, teams)| {
-            if membership.user_id == user.id && membership.pending {
+            if membership.user_id == Some(user.id) && membership.pending && !membership.requested {
                 // Handle potential None from teams.first() safely
                 teams.first().map(|team| {
                     json!({
                         "team_name": team.name,
                         "team_description": team.description.clone(),
-                        "token": membership.invitation_token,
+                        "pid": membership.pid.to_string(),
                         "role": membership.role,
-                        "sent_at": membership.created_at.format("%Y-%m-%d").to_string()
+                        "message": membership.invitation_message,
+                        "sent_at": membership.created_at.format("%Y-%m-%d").to_string(),
+                        "expired": membership.is_invitation_expired(),
                     })
                 })
             } else {
@@ -208,8 +216,12 @@ async fn invitations(
         })
         .collect::<Vec<_>>();
 
-    // Get pending invitations count (now derived from the collected invitations)
-    let invitation_count = invitations.len();
+    // Get pending invitations count (now derived from the collected invitations,
+    // expired ones can no longer be accepted)
+    let invitation_count = invitations
+        .iter()
+        .filter(|invitation| invitation["expired"] != json!(true))
+        .count();
 
     render_template(
         &v,
@@ -243,6 +255,7 @@ async fn get_invitation_count(
     let invitation_count = team_memberships::Entity::find()
         .filter(team_memberships::Column::UserId.eq(user.id))
         .filter(team_memberships::Column::Pending.eq(true))
+        .filter(team_memberships::Column::Requested.eq(false))
         .count(&ctx.db)
         .await?;
 
@@ -278,6 +291,18 @@ async fn update_profile(
     let trimmed_email = params.email.trim().to_string(); // Trim email
 
     if user.email != trimmed_email {
+        if auth
+            .claims
+            .as_ref()
+            .and_then(sessions::impersonator_from_claims)
+            .is_some()
+        {
+            return error_fragment(
+                &v,
+                "The email address cannot be changed while impersonating a user",
+                "#notification-container",
+            );
+        }
         email_changed = true; // Mark email as changed
         // Check if the *new* email exists.
         match users::Model::find_by_email(&ctx.db, &trimmed_email).await {
@@ -348,11 +373,16 @@ async fn update_profile(
                 match updated_user
                     .clone() // Clone again for the subsequent operations
                     .into_active_model()
-                    .generate_email_verification_token(&ctx.db)
+                    .generate_email_verification_token(
+                        &ctx.db,
+                        &TokenLifetimes::from_context(&ctx)?,
+                    )
                     .await
                 {
-                    Ok(user_with_token) => {
-                        match AuthMailer::send_welcome(&ctx, &user_with_token).await {
+                    Ok((user_with_token, verification_token)) => {
+                        match AuthMailer::send_welcome(&ctx, &user_with_token, &verification_token)
+                            .await
+                        {
                             Ok(_) => {
                                 match user_with_token
                                     .into_active_model()
@@ -460,6 +490,19 @@ async fn update_password(
         return redirect("/auth/login", headers);
     };
 
+    if auth
+        .claims
+        .as_ref()
+        .and_then(sessions::impersonator_from_claims)
+        .is_some()
+    {
+        return error_fragment(
+            &v,
+            "The password cannot be changed while impersonating a user",
+            "#password-error-container",
+        );
+    }
+
     // Verify passwords match
     if params.password != params.password_confirmation {
         return error_fragment(&v, "Passwords do not match", "#password-error-container");
@@ -475,18 +518,28 @@ async fn update_password(
     }
 
     // Update password - handle result with match
+    let policy = PasswordPolicy::from_context(&ctx)?;
     match user
         .into_active_model()
-        .reset_password(&ctx.db, &params.password)
+        .reset_password(&ctx.db, &params.password, &policy)
         .await
     {
-        Ok(_updated_user) => {
+        Ok(updated_user) => {
+            // Sign out every other device, keeping the session used for this change
+            let current_jti = auth.claims.as_ref().and_then(sessions::jti_from_claims);
+            if let Err(e) =
+                sessions::Model::revoke_all_for_user(&ctx.db, updated_user.id, current_jti).await
+            {
+                tracing::error!("Failed to revoke sessions after password change: {}", e);
+            }
             // Return a response that refreshes the page on success
             let response = Response::builder()
                 .header("HX-Refresh", "true")
                 .body(axum::body::Body::empty())?;
             Ok(response)
         }
+        // The new password does not comply with the password policy
+        Err(ModelError::Message(msg)) => error_fragment(&v, &msg, "#password-error-container"),
         Err(e) => {
             // Use error_fragment on failure, target password errors
             tracing::error!("Failed to update password: {}", e);
@@ -666,12 +719,12 @@ async fn resend_verification_email(
     match user
         .clone()
         .into_active_model()
-        .generate_email_verification_token(&ctx.db)
+        .generate_email_verification_token(&ctx.db, &TokenLifetimes::from_context(&ctx)?)
         .await
     {
-        Ok(user_with_token) => {
+        Ok((user_with_token, verification_token)) => {
             // Send verification email first
-            match AuthMailer::send_welcome(&ctx, &user_with_token).await {
+            match AuthMailer::send_welcome(&ctx, &user_with_token, &verification_token).await {
                 Ok(_) => {
                     // Email sent successfully, now update verification status
                     match user_with_token
@@ -825,10 +878,12 @@ async fn verify_pgp_sending(
     // 1. Generate PGP verification token
     // Clone user before converting to ActiveModel to avoid move
     let active_user: users::ActiveModel = user.clone().into();
-    let updated_user_res = active_user.generate_pgp_verification_token(&ctx.db).await;
+    let updated_user_res = active_user
+        .generate_pgp_verification_token(&ctx.db, &TokenLifetimes::from_context(&ctx)?)
+        .await;
 
-    let updated_user = match updated_user_res {
-        Ok(u) => u,
+    let (updated_user, token) = match updated_user_res {
+        Ok(result) => result,
         Err(e) => {
             // Access user.id before it's potentially moved/borrow error occurs
             let user_id = user.id;
@@ -843,47 +898,32 @@ async fn verify_pgp_sending(
     };
 
     // 2. Send PGP encrypted verification email
-    // Ensure the token exists before sending
-    if let Some(token) = &updated_user.pgp_verification_token {
-        match AuthMailer::send_pgp_verification(&ctx, &updated_user, token).await {
-            Ok(_) => {
-                tracing::info!("PGP verification email sent to: {}", updated_user.email);
-                // Return a success message fragment for the notification area
-                render_template(
-                    &v,
-                    "fragments/success_message.html",
-                    data!({
-                        "message": "PGP verification email sent successfully. Please check your inbox.",
-                        "target": "#notification-container"
-                    }),
-                )
-            }
-            Err(e) => {
-                tracing::error!(
-                    "Failed to send PGP verification email to {}: {}",
-                    updated_user.email,
-                    e
-                );
-                // Pass target selector, rely on logging for error details
-                error_fragment(
-                    &v,
-                    "Failed to send PGP verification email.",
-                    "#notification-container",
-                )
-            }
+    match AuthMailer::send_pgp_verification(&ctx, &updated_user, &token).await {
+        Ok(_) => {
+            tracing::info!("PGP verification email sent to: {}", updated_user.email);
+            // Return a success message fragment for the notification area
+            render_template(
+                &v,
+                "fragments/success_message.html",
+                data!({
+                    "message": "PGP verification email sent successfully. Please check your inbox.",
+                    "target": "#notification-container"
+                }),
+            )
+        }
+        Err(e) => {
+            tracing::error!(
+                "Failed to send PGP verification email to {}: {}",
+                updated_user.email,
+                e
+            );
+            // Pass target selector, rely on logging for error details
+            error_fragment(
+                &v,
+                "Failed to send PGP verification email.",
+                "#notification-container",
+            )
         }
-    } else {
-        // This should not happen if token generation succeeded
-        tracing::error!(
-            "PGP token missing after generation for user {}",
-            updated_user.id
-        );
-        // Pass a default target selector instead of None
-        error_fragment(
-            &v,
-            "Internal error: PGP token generation failed.",
-            "#notification-container",
-        )
     }
 }
 
@@ -953,6 +993,623 @@ async fn delete_ssh_key(
     }
 }
 
+/// Renders the active sessions list fragment for the given user, flagging
+/// the session the request was made with
+async fn render_sessions_list(
+    v: &TeraView,
+    ctx: &AppContext,
+    user: &users::Model,
+    current_jti: Option<&str>,
+) -> Result<Response> {
+    let sessions = match sessions::Model::find_active_for_user(&ctx.db, user.id).await {
+        Ok(sessions) => sessions,
+        Err(e) => {
+            tracing::error!("Failed to load sessions for user {}: {}", user.id, e);
+            return error_fragment(
+                v,
+                "Could not load active sessions.",
+                "#sessions-error-container",
+            );
+        }
+    };
+
+    let sessions = sessions
+        .iter()
+        .map(|session| {
+            json!({
+                "id": session.id,
+                "user_agent": session.user_agent,
+                "ip": session.ip,
+                "created_at": session.created_at,
+                "last_seen": session.last_seen,
+                "is_current": Some(session.jti.as_str()) == current_jti,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    render_template(
+        v,
+        "users/_sessions_list.html",
+        data!({
+            "sessions": &sessions,
+        }),
+    )
+}
+
+/// Renders the active sessions fragment for the profile page
+#[debug_handler]
+async fn sessions_fragment(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+    let current_jti = auth.claims.as_ref().and_then(sessions::jti_from_claims);
+
+    render_sessions_list(&v, &ctx, &user, current_jti).await
+}
+
+/// Revokes one of the user's sessions, signing out the corresponding device
+#[debug_handler]
+async fn revoke_session(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Path(session_id): Path<i32>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+    let current_jti = auth.claims.as_ref().and_then(sessions::jti_from_claims);
+
+    let session = match sessions::Model::find_by_id_for_user(&ctx.db, session_id, user.id).await {
+        Ok(session) => session,
+        Err(_) => {
+            return error_fragment(&v, "Session not found.", "#sessions-error-container");
+        }
+    };
+
+    if Some(session.jti.as_str()) == current_jti {
+        return error_fragment(
+            &v,
+            "Use the logout button to end the current session.",
+            "#sessions-error-container",
+        );
+    }
+
+    if let Err(e) = session.revoke(&ctx.db).await {
+        tracing::error!(
+            "Failed to revoke session {} of user {}: {}",
+            session_id,
+            user.id,
+            e
+        );
+        return error_fragment(
+            &v,
+            "Could not revoke the session. Please try again.",
+            "#sessions-error-container",
+        );
+    }
+
+    render_sessions_list(&v, &ctx, &user, current_jti).await
+}
+
+/// Revokes every session of the user except the current one
+#[debug_handler]
+async fn revoke_other_sessions(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+    let Some(current_jti) = auth.claims.as_ref().and_then(sessions::jti_from_claims) else {
+        return redirect("/auth/login", headers);
+    };
+
+    if let Err(e) = sessions::Model::revoke_all_for_user(&ctx.db, user.id, Some(current_jti)).await
+    {
+        tracing::error!("Failed to revoke other sessions of user {}: {}", user.id, e);
+        return error_fragment(
+            &v,
+            "Could not sign out the other sessions. Please try again.",
+            "#sessions-error-container",
+        );
+    }
+
+    render_sessions_list(&v, &ctx, &user, Some(current_jti)).await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TotpCodeParams {
+    pub code: String,
+}
+
+/// Renders the two-factor authentication section of the profile page.
+/// `recovery_codes` is only set right after they were generated, as they
+/// cannot be shown again.
+async fn render_totp_section(
+    v: &TeraView,
+    ctx: &AppContext,
+    user: &users::Model,
+    recovery_codes: Option<Vec<String>>,
+) -> Result<Response> {
+    let pending_setup = !user.totp_enabled() && user.totp_secret.is_some();
+    let (qr_svg, provisioning_uri) = if pending_setup {
+        match (user.totp_qr_svg(), user.totp_provisioning_uri()) {
+            (Ok(svg), Ok(uri)) => (Some(svg), Some(uri)),
+            (Err(e), _) | (_, Err(e)) => {
+                tracing::error!(
+                    "Failed to build TOTP provisioning data for user {}: {}",
+                    user.id,
+                    e
+                );
+                return error_fragment(
+                    v,
+                    "Could not prepare two-factor authentication setup.",
+                    "#totp-error-container",
+                );
+            }
+        }
+    } else {
+        (None, None)
+    };
+
+    let remaining_codes = if user.totp_enabled() {
+        recovery_codes::Model::count_unused(&ctx.db, user.id)
+            .await
+            .unwrap_or_default()
+    } else {
+        0
+    };
+
+    render_template(
+        v,
+        "users/_totp_section.html",
+        data!({
+            "totp_enabled": user.totp_enabled(),
+            "totp_enabled_at": &user.totp_enabled_at,
+            "pending_setup": pending_setup,
+            "qr_svg": &qr_svg,
+            "provisioning_uri": &provisioning_uri,
+            "secret": if pending_setup { user.totp_secret.clone() } else { None },
+            "remaining_codes": remaining_codes,
+            "recovery_codes": &recovery_codes,
+        }),
+    )
+}
+
+/// Renders the two-factor authentication fragment for the profile page
+#[debug_handler]
+async fn totp_fragment(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    render_totp_section(&v, &ctx, &user, None).await
+}
+
+/// Starts the TOTP enrolment by generating a new secret
+#[debug_handler]
+async fn setup_totp(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    if user.totp_enabled() {
+        return error_fragment(
+            &v,
+            "Two-factor authentication is already enabled.",
+            "#totp-error-container",
+        );
+    }
+
+    match user.into_active_model().begin_totp_enrolment(&ctx.db).await {
+        Ok(user) => render_totp_section(&v, &ctx, &user, None).await,
+        Err(e) => {
+            tracing::error!("Failed to start TOTP enrolment: {}", e);
+            error_fragment(
+                &v,
+                "Could not start two-factor authentication setup. Please try again.",
+                "#totp-error-container",
+            )
+        }
+    }
+}
+
+/// Completes the TOTP enrolment once the user proved their authenticator
+/// app produces valid codes
+#[debug_handler]
+async fn enable_totp(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Form(params): Form<TotpCodeParams>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    if user.totp_enabled() || user.totp_secret.is_none() {
+        return error_fragment(
+            &v,
+            "Start the two-factor authentication setup first.",
+            "#totp-error-container",
+        );
+    }
+
+    if !user.verify_totp(&ctx.db, &params.code).await? {
+        return error_fragment(
+            &v,
+            "Invalid authentication code. Check the time on your device and try again.",
+            "#totp-error-container",
+        );
+    }
+
+    match user.into_active_model().enable_totp(&ctx.db).await {
+        Ok((user, codes)) => {
+            tracing::info!(user_pid = %user.pid, "Two-factor authentication enabled");
+            render_totp_section(&v, &ctx, &user, Some(codes)).await
+        }
+        Err(e) => {
+            tracing::error!("Failed to enable TOTP: {}", e);
+            error_fragment(
+                &v,
+                "Could not enable two-factor authentication. Please try again.",
+                "#totp-error-container",
+            )
+        }
+    }
+}
+
+/// Replaces the recovery codes of the user, after checking a second factor
+#[debug_handler]
+async fn regenerate_recovery_codes(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Form(params): Form<TotpCodeParams>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    if !user.verify_second_factor(&ctx.db, &params.code).await? {
+        return error_fragment(&v, "Invalid authentication code.", "#totp-error-container");
+    }
+
+    match recovery_codes::Model::regenerate_for_user(&ctx.db, user.id).await {
+        Ok(codes) => render_totp_section(&v, &ctx, &user, Some(codes)).await,
+        Err(e) => {
+            tracing::error!("Failed to regenerate recovery codes: {}", e);
+            error_fragment(
+                &v,
+                "Could not generate new recovery codes. Please try again.",
+                "#totp-error-container",
+            )
+        }
+    }
+}
+
+/// Disables two-factor authentication, after checking a second factor
+#[debug_handler]
+async fn disable_totp(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Form(params): Form<TotpCodeParams>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    if !user.verify_second_factor(&ctx.db, &params.code).await? {
+        return error_fragment(&v, "Invalid authentication code.", "#totp-error-container");
+    }
+
+    match user.into_active_model().disable_totp(&ctx.db).await {
+        Ok(user) => {
+            tracing::info!(user_pid = %user.pid, "Two-factor authentication disabled");
+            render_totp_section(&v, &ctx, &user, None).await
+        }
+        Err(e) => {
+            tracing::error!("Failed to disable TOTP: {}", e);
+            error_fragment(
+                &v,
+                "Could not disable two-factor authentication. Please try again.",
+                "#totp-error-container",
+            )
+        }
+    }
+}
+
+/// Renders the passkeys fragment listing the user's WebAuthn credentials
+async fn render_passkeys_list(
+    v: &TeraView,
+    ctx: &AppContext,
+    user: &users::Model,
+) -> Result<Response> {
+    let credentials = match webauthn_credentials::Model::find_for_user(&ctx.db, user.id).await {
+        Ok(credentials) => credentials,
+        Err(e) => {
+            tracing::error!("Failed to load passkeys for user {}: {}", user.id, e);
+            return error_fragment(v, "Could not load passkeys.", "#passkeys-error-container");
+        }
+    };
+
+    let credentials = credentials
+        .iter()
+        .map(|credential| {
+            json!({
+                "id": credential.id,
+                "name": credential.name,
+                "created_at": credential.created_at,
+                "last_used_at": credential.last_used_at,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    render_template(
+        v,
+        "users/_passkeys_list.html",
+        data!({
+            "credentials": &credentials,
+        }),
+    )
+}
+
+/// Renders the passkeys fragment for the profile page
+#[debug_handler]
+async fn passkeys_fragment(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    render_passkeys_list(&v, &ctx, &user).await
+}
+
+/// Removes one of the user's passkeys
+#[debug_handler]
+async fn delete_passkey(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Path(credential_id): Path<i32>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let credential =
+        match webauthn_credentials::Model::find_by_id_for_user(&ctx.db, credential_id, user.id)
+            .await
+        {
+            Ok(credential) => credential,
+            Err(_) => {
+                return error_fragment(&v, "Passkey not found.", "#passkeys-error-container");
+            }
+        };
+
+    if let Err(e) = credential.delete(&ctx.db).await {
+        tracing::error!(
+            "Failed to delete passkey {} of user {}: {}",
+            credential_id,
+            user.id,
+            e
+        );
+        return error_fragment(
+            &v,
+            "Could not remove the passkey. Please try again.",
+            "#passkeys-error-container",
+        );
+    }
+    tracing::info!(user_pid = %user.pid, credential_id, "Passkey removed");
+
+    render_passkeys_list(&v, &ctx, &user).await
+}
+
+/// Renders the personal access tokens fragment. The plaintext of a token that
+/// was just created is passed in `new_token`, as it can only be shown once.
+async fn render_tokens_list(
+    v: &TeraView,
+    ctx: &AppContext,
+    user: &users::Model,
+    new_token: Option<&str>,
+) -> Result<Response> {
+    let tokens = match personal_access_tokens::Model::find_for_user(&ctx.db, user.id).await {
+        Ok(tokens) => tokens,
+        Err(e) => {
+            tracing::error!(
+                "Failed to load personal access tokens for user {}: {}",
+                user.id,
+                e
+            );
+            return error_fragment(
+                v,
+                "Could not load personal access tokens.",
+                "#tokens-error-container",
+            );
+        }
+    };
+
+    let tokens = tokens
+        .iter()
+        .map(|token| {
+            json!({
+                "pid": token.pid.to_string(),
+                "name": token.name,
+                "token_prefix": token.token_prefix,
+                "scopes": token.scope_list(),
+                "created_at": token.created_at,
+                "last_used_at": token.last_used_at,
+                "expires_at": token.expires_at,
+                "revoked": token.revoked_at.is_some(),
+                "expired": token.is_expired(),
+            })
+        })
+        .collect::<Vec<_>>();
+    let scopes = personal_access_tokens::SCOPES
+        .iter()
+        .map(|(scope, description)| json!({"name": scope, "description": description}))
+        .collect::<Vec<_>>();
+
+    render_template(
+        v,
+        "users/_tokens_list.html",
+        data!({
+            "tokens": &tokens,
+            "scopes": &scopes,
+            "new_token": new_token,
+        }),
+    )
+}
+
+/// Renders the personal access tokens fragment for the profile page
+#[debug_handler]
+async fn tokens_fragment(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    render_tokens_list(&v, &ctx, &user, None).await
+}
+
+/// Creates a personal access token
+#[debug_handler]
+async fn create_token(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Form(fields): Form<Vec<(String, String)>>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let params = match CreateTokenParams::from_form_fields(fields) {
+        Ok(params) => params,
+        Err(e) => return error_fragment(&v, &e.to_string(), "#tokens-error-container"),
+    };
+
+    match personal_access_tokens::Model::create_for_user(&ctx.db, user.id, &params).await {
+        Ok((token, plaintext)) => {
+            tracing::info!(user_pid = %user.pid, token_pid = %token.pid, "Personal access token created");
+            render_tokens_list(&v, &ctx, &user, Some(&plaintext)).await
+        }
+        Err(ModelError::Message(msg)) => error_fragment(&v, &msg, "#tokens-error-container"),
+        Err(e) => {
+            tracing::error!(
+                "Failed to create personal access token for user {}: {}",
+                user.id,
+                e
+            );
+            error_fragment(
+                &v,
+                "Could not create the token. Please try again.",
+                "#tokens-error-container",
+            )
+        }
+    }
+}
+
+/// Revokes one of the user's personal access tokens
+#[debug_handler]
+async fn revoke_token(
+    auth: JWTWithUserOpt<users::Model>,
+    ViewEngine(v): ViewEngine<TeraView>,
+    State(ctx): State<AppContext>,
+    headers: HeaderMap,
+    Path(token_pid): Path<String>,
+) -> Result<Response> {
+    let user = if let Some(user) = auth.user {
+        user
+    } else {
+        return redirect("/auth/login", headers);
+    };
+
+    let token =
+        match personal_access_tokens::Model::find_by_pid_for_user(&ctx.db, &token_pid, user.id)
+            .await
+        {
+            Ok(token) => token,
+            Err(_) => {
+                return error_fragment(&v, "Token not found.", "#tokens-error-container");
+            }
+        };
+
+    if let Err(e) = token.revoke(&ctx.db).await {
+        tracing::error!(
+            "Failed to revoke personal access token {} of user {}: {}",
+            token_pid,
+            user.id,
+            e
+        );
+        return error_fragment(
+            &v,
+            "Could not revoke the token. Please try again.",
+            "#tokens-error-container",
+        );
+    }
+    tracing::info!(user_pid = %user.pid, token_pid, "Personal access token revoked");
+
+    render_tokens_list(&v, &ctx, &user, None).await
+}
+
 /// User routes
 pub fn routes() -> Routes {
     Routes::new()
@@ -970,4 +1627,23 @@ pub fn routes() -> Routes {
         .add("/profile/refresh-pgp", post(refresh_pgp))
         .add("/profile/verify-pgp", post(verify_pgp_sending))
         .add("/profile/ssh_keys", delete(delete_ssh_key))
+        .add("/profile/sessions_fragment", get(sessions_fragment))
+        .add("/profile/sessions/{session_id}", delete(revoke_session))
+        .add(
+            "/profile/sessions/revoke-others",
+            post(revoke_other_sessions),
+        )
+        .add("/profile/totp_fragment", get(totp_fragment))
+        .add("/profile/totp/setup", post(setup_totp))
+        .add("/profile/totp/enable", post(enable_totp))
+        .add("/profile/totp/disable", post(disable_totp))
+        .add(
+            "/profile/totp/recovery-codes",
+            post(regenerate_recovery_codes),
+        )
+        .add("/profile/passkeys_fragment", get(passkeys_fragment))
+        .add("/profile/passkeys/{credential_id}", delete(delete_passkey))
+        .add("/profile/tokens_fragment", get(tokens_fragment))
+        .add("/profile/tokens", post(create_token))
+        .add("/profile/tokens/{token_pid}", delete(revoke_token))
 }
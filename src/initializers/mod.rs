@@ -1 +1,2 @@
+pub mod oidc;
 pub mod view_engine;
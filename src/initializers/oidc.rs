@@ -0,0 +1,33 @@
+use async_trait::async_trait;
+use loco_rs::{
+    Error, Result,
+    app::{AppContext, Initializer},
+};
+use tracing::info;
+
+use crate::models::oidc::OidcConfig;
+
+/// Checks the single sign-on providers configured under
+/// `initializers.oauth2` when the server starts, so that configuration
+/// mistakes are reported before the first login
+#[allow(clippy::module_name_repetitions)]
+pub struct OidcInitializer;
+
+#[async_trait]
+impl Initializer for OidcInitializer {
+    fn name(&self) -> String {
+        "oidc".to_string()
+    }
+
+    async fn before_run(&self, ctx: &AppContext) -> Result<()> {
+        let config = OidcConfig::from_context(ctx).map_err(Error::Model)?;
+        for provider in &config.authorization_code {
+            info!(
+                provider = provider.client_identifier,
+                issuer_url = provider.issuer_url,
+                "single sign-on provider configured"
+            );
+        }
+        Ok(())
+    }
+}
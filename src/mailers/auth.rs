@@ -24,24 +24,41 @@ use std::io::Write;
 static welcome: Dir<'_> = include_dir!("src/mailers/auth/welcome");
 static forgot: Dir<'_> = include_dir!("src/mailers/auth/forgot");
 static magic_link: Dir<'_> = include_dir!("src/mailers/auth/magic_link");
+static account_locked: Dir<'_> = include_dir!("src/mailers/auth/account_locked");
+static account_approved: Dir<'_> = include_dir!("src/mailers/auth/account_approved");
+static account_rejected: Dir<'_> = include_dir!("src/mailers/auth/account_rejected");
+static account_suspended: Dir<'_> = include_dir!("src/mailers/auth/account_suspended");
+static account_reactivated: Dir<'_> = include_dir!("src/mailers/auth/account_reactivated");
+static account_deleted: Dir<'_> = include_dir!("src/mailers/auth/account_deleted");
+static account_created: Dir<'_> = include_dir!("src/mailers/auth/account_created");
 // #[derive(Mailer)] // -- disabled for faster build speed. it works. but lets
 // move on for now.
 
 #[allow(clippy::module_name_repetitions)]
 pub struct AuthMailer {}
-impl Mailer for AuthMailer {}
+#[async_trait]
+impl Mailer for AuthMailer {
+    async fn mail(ctx: &AppContext, email: &mailer::Email) -> Result<()> {
+        super::deliver(ctx, email).await
+    }
+}
 impl AuthMailer {
-    /// Sending welcome email the the given user
+    /// Sending welcome email the the given user, with the link to verify
+    /// their email address
     ///
     /// # Errors
     ///
     /// When email sending is failed
-    pub async fn send_welcome(ctx: &AppContext, user: &users::Model) -> Result<()> {
+    pub async fn send_welcome(
+        ctx: &AppContext,
+        user: &users::Model,
+        verification_token: &str,
+    ) -> Result<()> {
         let mut args = mailer::Args {
             to: user.email.to_string(),
             locals: json!({
               "name": user.name,
-              "verifyToken": user.email_verification_token,
+              "verifyToken": verification_token,
               "domain": &ctx.config.server.host,
             }),
             from: Some("test@example.com".to_string()),
@@ -72,10 +89,14 @@ impl AuthMailer {
     ///
     /// Returns an error only if the fallback unencrypted email sending fails.
     /// PGP-related errors are logged but do not prevent the fallback.
-    pub async fn forgot_password(ctx: &AppContext, user: &users::Model) -> Result<()> {
+    pub async fn forgot_password(
+        ctx: &AppContext,
+        user: &users::Model,
+        reset_token: &str,
+    ) -> Result<()> {
         // Check if user has a PGP key and it's verified
         if user.pgp_key.is_some() && user.pgp_verified_at.is_some() {
-            match Self::_try_send_forgot_password_pgp(ctx, user).await {
+            match Self::_try_send_forgot_password_pgp(ctx, user, reset_token).await {
                 Ok(_) => {
                     tracing::info!(
                         "Successfully sent PGP-encrypted password reset email to {}",
@@ -105,7 +126,7 @@ impl AuthMailer {
             to: user.email.to_string(),
             locals: json!({
               "name": user.name,
-              "resetToken": user.reset_token,
+              "resetToken": reset_token,
               "domain": &ctx.config.server.host,
             }),
             from: Some("test@example.com".to_string()),
@@ -124,7 +145,11 @@ impl AuthMailer {
     }
 
     /// Attempts to send the forgot password email PGP-encrypted.
-    async fn _try_send_forgot_password_pgp(ctx: &AppContext, user: &users::Model) -> Result<()> {
+    async fn _try_send_forgot_password_pgp(
+        ctx: &AppContext,
+        user: &users::Model,
+        reset_token: &str,
+    ) -> Result<()> {
         // 1. Get and parse PGP key
         let pgp_key_str = user
             .pgp_key
@@ -143,8 +168,7 @@ impl AuthMailer {
         // 2. Construct plain text body
         let reset_url = format!(
             "{}/auth/reset-password/{}",
-            &ctx.config.server.host,
-            user.reset_token.as_ref().unwrap_or(&String::new()) // Should always exist if called correctly
+            &ctx.config.server.host, reset_token
         );
         let email_body = format!(
             "Hello {},
@@ -180,6 +204,221 @@ Your Hosting Farm Server",
         .await
     }
 
+    /// Notifies the owner that their account was locked after too many failed
+    /// logins, PGP-encrypted if possible.
+    ///
+    /// Like [`AuthMailer::forgot_password`], the email is encrypted when the
+    /// user has a verified PGP key, and sent unencrypted otherwise or if
+    /// encryption fails.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error only if the fallback unencrypted email sending fails.
+    pub async fn account_locked(ctx: &AppContext, user: &users::Model) -> Result<()> {
+        let locked_until = user
+            .locked_until
+            .map(|until| until.format("%Y-%m-%d %H:%M %Z").to_string())
+            .unwrap_or_default();
+
+        if user.pgp_key.is_some() && user.pgp_verified_at.is_some() {
+            match Self::_try_send_account_locked_pgp(ctx, user, &locked_until).await {
+                Ok(_) => {
+                    tracing::info!(
+                        "Successfully sent PGP-encrypted account locked email to {}",
+                        user.email
+                    );
+                    return Ok(());
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "Failed to send PGP-encrypted account locked email to {}: {}. Falling back to unencrypted.",
+                        user.email,
+                        e
+                    );
+                }
+            }
+        }
+
+        let mut args = mailer::Args {
+            to: user.email.to_string(),
+            locals: json!({
+              "name": user.name,
+              "lockedUntil": locked_until,
+              "domain": &ctx.config.server.host,
+            }),
+            from: Some("test@example.com".to_string()),
+            ..Default::default()
+        };
+
+        if let Some(mailer_config) = &ctx.config.mailer
+            && let Some(smtp_config) = &mailer_config.smtp
+            && let Some(auth) = &smtp_config.auth
+        {
+            args.from = Some(auth.user.clone());
+        }
+
+        Self::mail_template(ctx, &account_locked, args).await?;
+        Ok(())
+    }
+
+    /// Attempts to send the account locked email PGP-encrypted.
+    async fn _try_send_account_locked_pgp(
+        ctx: &AppContext,
+        user: &users::Model,
+        locked_until: &str,
+    ) -> Result<()> {
+        let pgp_key_str = user
+            .pgp_key
+            .as_ref()
+            .ok_or_else(|| Error::string("User PGP key is None, shouldn't happen here"))?;
+
+        let recipient_cert = CertParser::from_bytes(pgp_key_str.as_bytes())
+            .map_err(|e| Error::string(&format!("Failed to parse PGP key: {}", e)))
+            .and_then(|mut certs| {
+                certs
+                    .next()
+                    .ok_or_else(|| Error::string("No valid PGP certificate found in key data."))
+            })?
+            .map_err(|e| Error::string(&format!("Failed to parse PGP certificate: {}", e)))?;
+
+        let email_body = format!(
+            "Hello {},
+
+There were too many failed attempts to sign in to your account, so it has been locked until {}.
+
+If it was you, you can try again after that time, or reset your password:
+{}/auth/forgot-password
+
+If it was not you, someone may be trying to guess your password. Consider changing it and enabling two-factor authentication from your profile page.
+
+Thanks,
+Your Hosting Farm Server",
+            user.name, locked_until, &ctx.config.server.host
+        );
+
+        let encrypted_body = Self::_encrypt_message_pgp(&recipient_cert, &email_body)?;
+
+        let from_mailbox: Mailbox = Self::_get_from_mailbox(ctx)?;
+        let to_mailbox: Mailbox = user
+            .email
+            .parse()
+            .map_err(|e| Error::string(&format!("Invalid recipient email: {}", e)))?;
+
+        Self::_send_raw_email(
+            ctx,
+            to_mailbox,
+            from_mailbox,
+            "Your account has been temporarily locked",
+            encrypted_body,
+        )
+        .await
+    }
+
+    /// Tells a user that an administrator approved their account
+    ///
+    /// # Errors
+    ///
+    /// When email sending is failed
+    pub async fn account_approved(ctx: &AppContext, user: &users::Model) -> Result<()> {
+        Self::send_account_notice(ctx, user, &account_approved).await
+    }
+
+    /// Tells a user that an administrator rejected their registration
+    ///
+    /// # Errors
+    ///
+    /// When email sending is failed
+    pub async fn account_rejected(ctx: &AppContext, user: &users::Model) -> Result<()> {
+        Self::send_account_notice(ctx, user, &account_rejected).await
+    }
+
+    /// Tells a user that an administrator suspended their account
+    ///
+    /// # Errors
+    ///
+    /// When email sending is failed
+    pub async fn account_suspended(ctx: &AppContext, user: &users::Model) -> Result<()> {
+        Self::send_account_notice(ctx, user, &account_suspended).await
+    }
+
+    /// Tells a user that an administrator reactivated their account
+    ///
+    /// # Errors
+    ///
+    /// When email sending is failed
+    pub async fn account_reactivated(ctx: &AppContext, user: &users::Model) -> Result<()> {
+        Self::send_account_notice(ctx, user, &account_reactivated).await
+    }
+
+    /// Tells a user that an administrator deleted their account
+    ///
+    /// # Errors
+    ///
+    /// When email sending is failed
+    pub async fn account_deleted(ctx: &AppContext, user: &users::Model) -> Result<()> {
+        Self::send_account_notice(ctx, user, &account_deleted).await
+    }
+
+    /// Tells a user that an administrator created their account, with the
+    /// link to choose their password
+    ///
+    /// # Errors
+    ///
+    /// When email sending is failed
+    pub async fn account_created(
+        ctx: &AppContext,
+        user: &users::Model,
+        reset_token: &str,
+    ) -> Result<()> {
+        let mut args = mailer::Args {
+            to: user.email.to_string(),
+            locals: json!({
+              "name": user.name,
+              "resetToken": reset_token,
+              "domain": &ctx.config.server.host,
+            }),
+            from: Some("test@example.com".to_string()),
+            ..Default::default()
+        };
+
+        if let Some(mailer_config) = &ctx.config.mailer
+            && let Some(smtp_config) = &mailer_config.smtp
+            && let Some(auth) = &smtp_config.auth
+        {
+            args.from = Some(auth.user.clone());
+        }
+
+        Self::mail_template(ctx, &account_created, args).await?;
+        Ok(())
+    }
+
+    /// Sends a notice about a decision an administrator made on the account
+    async fn send_account_notice(
+        ctx: &AppContext,
+        user: &users::Model,
+        template: &Dir<'_>,
+    ) -> Result<()> {
+        let mut args = mailer::Args {
+            to: user.email.to_string(),
+            locals: json!({
+              "name": user.name,
+              "domain": &ctx.config.server.host,
+            }),
+            from: Some("test@example.com".to_string()),
+            ..Default::default()
+        };
+
+        if let Some(mailer_config) = &ctx.config.mailer
+            && let Some(smtp_config) = &mailer_config.smtp
+            && let Some(auth) = &smtp_config.auth
+        {
+            args.from = Some(auth.user.clone());
+        }
+
+        Self::mail_template(ctx, template, args).await?;
+        Ok(())
+    }
+
     /// Sends a magic link authentication email to the user.
     ///
     /// # Errors
@@ -0,0 +1,10 @@
+<html>
+<head>
+  <title>Your account has been approved</title>
+</head>
+<body>
+  <p>Hey {{name}},</p>
+  <p>An administrator approved your account. You can now <a href="{{domain}}/auth/login">log in</a>.</p>
+  <p>Best regards,<br/>Your Hosting Farm server</p>
+</body>
+</html>
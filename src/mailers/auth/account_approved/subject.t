@@ -0,0 +1 @@
+Your account has been approved
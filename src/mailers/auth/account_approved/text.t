@@ -0,0 +1,8 @@
+Hey {{name}},
+
+An administrator approved your account. You can now log in using the link below:
+
+{{domain}}/auth/login
+
+Best regards,
+Your Hosting Farm server
@@ -0,0 +1,11 @@
+<html>
+<head>
+  <title>Your account is ready</title>
+</head>
+<body>
+  <p>Hey {{name}},</p>
+  <p>An administrator created an account for you. Choose your password by clicking the link below, then log in with your email address.</p>
+  <p><a href="{{domain}}/auth/reset-password/{{resetToken}}">Choose Your Password</a></p>
+  <p>Best regards,<br/>Your Hosting Farm server</p>
+</body>
+</html>
@@ -0,0 +1 @@
+Your Hosting Farm account is ready
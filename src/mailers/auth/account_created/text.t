@@ -0,0 +1,8 @@
+Hey {{name}},
+
+An administrator created an account for you. Choose your password using the link below, then log in with your email address:
+
+{{domain}}/auth/reset-password/{{resetToken}}
+
+Best regards,
+Your Hosting Farm server
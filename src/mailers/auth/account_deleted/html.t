@@ -0,0 +1,11 @@
+<html>
+<head>
+  <title>Your account has been deleted</title>
+</head>
+<body>
+  <p>Hey {{name}},</p>
+  <p>An administrator deleted your account on {{domain}}, along with your SSH keys and team memberships.</p>
+  <p>If you think this is a mistake, please contact the administrators of this server.</p>
+  <p>Best regards,<br/>Your Hosting Farm server</p>
+</body>
+</html>
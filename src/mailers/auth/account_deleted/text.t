@@ -0,0 +1,9 @@
+Your account has been deleted
+Hey {{name}},
+
+An administrator deleted your account on {{domain}}, along with your SSH keys and team memberships.
+
+If you think this is a mistake, please contact the administrators of this server.
+
+Best regards,
+Your Hosting Farm server
@@ -0,0 +1,12 @@
+<html>
+<head>
+  <title>Your account has been temporarily locked</title>
+</head>
+<body>
+  <p>Hey {{name}},</p>
+  <p>There were too many failed attempts to sign in to your account, so it has been locked until {{lockedUntil}}.</p>
+  <p>If it was you, you can try again after that time, or <a href="{{domain}}/auth/forgot-password">reset your password</a>.</p>
+  <p>If it was not you, someone may be trying to guess your password. Consider changing it and enabling two-factor authentication from your profile page.</p>
+  <p>Best regards,<br/>Your Hosting Farm server</p>
+</body>
+</html>
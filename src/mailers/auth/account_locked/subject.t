@@ -0,0 +1 @@
+Your account has been temporarily locked
@@ -0,0 +1,12 @@
+Hey {{name}},
+
+There were too many failed attempts to sign in to your account, so it has been locked until {{lockedUntil}}.
+
+If it was you, you can try again after that time, or reset your password using the link below:
+
+{{domain}}/auth/forgot-password
+
+If it was not you, someone may be trying to guess your password. Consider changing it and enabling two-factor authentication from your profile page.
+
+Best regards,
+Your Hosting Farm server
@@ -0,0 +1,11 @@
+<html>
+<head>
+  <title>Your account has been reactivated</title>
+</head>
+<body>
+  <p>Hey {{name}},</p>
+  <p>An administrator reactivated your account on {{domain}}. You can log in again.</p>
+  <p>If you did not expect this, please contact the administrators of this server.</p>
+  <p>Best regards,<br/>Your Hosting Farm server</p>
+</body>
+</html>
@@ -0,0 +1,9 @@
+Your account has been reactivated
+Hey {{name}},
+
+An administrator reactivated your account on {{domain}}. You can log in again.
+
+If you did not expect this, please contact the administrators of this server.
+
+Best regards,
+Your Hosting Farm server
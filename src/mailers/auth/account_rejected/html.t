@@ -0,0 +1,11 @@
+<html>
+<head>
+  <title>Your registration has been declined</title>
+</head>
+<body>
+  <p>Hey {{name}},</p>
+  <p>An administrator declined the account you registered with this email address, and it has been deleted.</p>
+  <p>If you think this is a mistake, please contact the administrators of this server.</p>
+  <p>Best regards,<br/>Your Hosting Farm server</p>
+</body>
+</html>
@@ -0,0 +1 @@
+Your registration has been declined
@@ -0,0 +1,8 @@
+Hey {{name}},
+
+An administrator declined the account you registered with this email address, and it has been deleted.
+
+If you think this is a mistake, please contact the administrators of this server.
+
+Best regards,
+Your Hosting Farm server
@@ -0,0 +1,11 @@
+<html>
+<head>
+  <title>Your account has been suspended</title>
+</head>
+<body>
+  <p>Hey {{name}},</p>
+  <p>An administrator suspended your account on {{domain}}. You have been signed out and can no longer log in or use the API.</p>
+  <p>If you think this is a mistake, please contact the administrators of this server.</p>
+  <p>Best regards,<br/>Your Hosting Farm server</p>
+</body>
+</html>
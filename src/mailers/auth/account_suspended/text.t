@@ -0,0 +1,9 @@
+Your account has been suspended
+Hey {{name}},
+
+An administrator suspended your account on {{domain}}. You have been signed out and can no longer log in or use the API.
+
+If you think this is a mistake, please contact the administrators of this server.
+
+Best regards,
+Your Hosting Farm server
@@ -1,2 +1,26 @@
 pub mod auth;
 pub mod team;
+
+use loco_rs::{
+    mailer::{DEFAULT_FROM_SENDER, Email},
+    prelude::*,
+};
+
+use crate::workers::email_delivery::EmailDeliveryWorker;
+
+/// Hands an email over to the delivery worker, which records it when it
+/// cannot be delivered. The application mailers use it instead of the
+/// `loco_rs` mailer worker.
+///
+/// # Errors
+///
+/// When the email could not be queued, or delivered in foreground mode
+pub(crate) async fn deliver(ctx: &AppContext, email: &Email) -> Result<()> {
+    let mut email = email.clone();
+    email.from = Some(
+        email
+            .from
+            .unwrap_or_else(|| DEFAULT_FROM_SENDER.to_string()),
+    );
+    EmailDeliveryWorker::perform_later(ctx, email).await
+}
@@ -1,21 +1,36 @@
 use loco_rs::{environment::Environment, prelude::*};
 use serde_json::json;
 
-use crate::models::_entities::{teams::Model as TeamModel, users::Model as UserModel};
+use crate::models::_entities::{
+    team_memberships::Model as TeamMembershipModel, teams::Model as TeamModel,
+    users::Model as UserModel,
+};
 
 // Define the static template directory
 static INVITATION: Dir<'_> = include_dir!("src/mailers/team/invitation");
+static JOIN_REQUEST: Dir<'_> = include_dir!("src/mailers/team/join_request");
+static JOIN_REQUEST_ANSWER: Dir<'_> = include_dir!("src/mailers/team/join_request_answer");
+static OWNERSHIP_TRANSFER: Dir<'_> = include_dir!("src/mailers/team/ownership_transfer");
+static SIGNUP_INVITATION: Dir<'_> = include_dir!("src/mailers/team/signup_invitation");
 
 pub struct TeamMailer {}
-impl Mailer for TeamMailer {}
+#[async_trait]
+impl Mailer for TeamMailer {
+    async fn mail(ctx: &AppContext, email: &mailer::Email) -> Result<()> {
+        super::deliver(ctx, email).await
+    }
+}
 
 impl TeamMailer {
-    /// Send a team invitation email
+    /// Send a team invitation email, with a link holding the invitation token
+    /// and the message of the inviting user if any
     pub async fn send_invitation(
         ctx: &AppContext,
         inviting_user: &UserModel,
         invited_user: &UserModel,
         team: &TeamModel,
+        invitation: &TeamMembershipModel,
+        invitation_token: &str,
     ) -> Result<()> {
         let invited_email = invited_user.email.clone();
 
@@ -27,7 +42,9 @@ impl TeamMailer {
             "name": invited_user.name,
             "other_user": inviting_user.name,
             "team_name": team.name,
-            "invitation_url": format!("{}/users/invitations", frontend_url)
+            "role": invitation.role,
+            "message": invitation.invitation_message,
+            "invitation_url": format!("{}/teams/invitations/{}", frontend_url, invitation_token)
         });
 
         // Check if mailer is configured
@@ -72,4 +89,137 @@ impl TeamMailer {
             }
         }
     }
+
+    /// Send a team invitation to an email address no account is registered
+    /// with, with a link to the registration page holding the invitation token
+    pub async fn send_signup_invitation(
+        ctx: &AppContext,
+        inviting_user: &UserModel,
+        invited_email: &str,
+        team: &TeamModel,
+        invitation: &TeamMembershipModel,
+        invitation_token: &str,
+    ) -> Result<()> {
+        let mut args = mailer::Args {
+            to: invited_email.to_string(),
+            locals: json!({
+                "other_user": inviting_user.name,
+                "team_name": team.name,
+                "role": invitation.role,
+                "message": invitation.invitation_message,
+                "signup_url": format!(
+                    "{}/auth/register?invitation={}",
+                    ctx.config.server.host, invitation_token
+                ),
+            }),
+            from: Some("test@example.com".to_string()),
+            ..Default::default()
+        };
+
+        if let Some(mailer_config) = &ctx.config.mailer
+            && let Some(smtp_config) = &mailer_config.smtp
+            && let Some(auth) = &smtp_config.auth
+        {
+            args.from = Some(auth.user.clone());
+        }
+
+        Self::mail_template(ctx, &SIGNUP_INVITATION, args).await
+    }
+
+    /// Send the offer of the ownership of a team to the receiving member,
+    /// with a link to the team page where they accept or decline it
+    pub async fn send_ownership_transfer(
+        ctx: &AppContext,
+        from_user: &UserModel,
+        to_user: &UserModel,
+        team: &TeamModel,
+        expires_in_days: i64,
+    ) -> Result<()> {
+        let mut args = mailer::Args {
+            to: to_user.email.clone(),
+            locals: json!({
+                "name": to_user.name,
+                "other_user": from_user.name,
+                "team_name": team.name,
+                "team_url": format!("{}/teams/{}", ctx.config.server.host, team.pid),
+                "expires_in_days": expires_in_days,
+            }),
+            from: Some("test@example.com".to_string()),
+            ..Default::default()
+        };
+
+        if let Some(mailer_config) = &ctx.config.mailer
+            && let Some(smtp_config) = &mailer_config.smtp
+            && let Some(auth) = &smtp_config.auth
+        {
+            args.from = Some(auth.user.clone());
+        }
+
+        Self::mail_template(ctx, &OWNERSHIP_TRANSFER, args).await
+    }
+
+    /// Send a request to join a team to a member who may approve it, with a
+    /// link to the team page where they approve or deny it
+    pub async fn send_join_request(
+        ctx: &AppContext,
+        admin: &UserModel,
+        requesting_user: &UserModel,
+        team: &TeamModel,
+    ) -> Result<()> {
+        let mut args = mailer::Args {
+            to: admin.email.clone(),
+            locals: json!({
+                "name": admin.name,
+                "other_user": requesting_user.name,
+                "other_email": requesting_user.email,
+                "team_name": team.name,
+                "team_url": format!("{}/teams/{}", ctx.config.server.host, team.pid),
+            }),
+            from: Some("test@example.com".to_string()),
+            ..Default::default()
+        };
+
+        if let Some(mailer_config) = &ctx.config.mailer
+            && let Some(smtp_config) = &mailer_config.smtp
+            && let Some(auth) = &smtp_config.auth
+        {
+            args.from = Some(auth.user.clone());
+        }
+
+        Self::mail_template(ctx, &JOIN_REQUEST, args).await
+    }
+
+    /// Tell the user who requested to join a team whether a team
+    /// administrator approved their request, in which case `membership` is
+    /// their new membership, or denied it
+    pub async fn send_join_request_answer(
+        ctx: &AppContext,
+        answering_user: &UserModel,
+        requesting_user: &UserModel,
+        team: &TeamModel,
+        membership: Option<&TeamMembershipModel>,
+    ) -> Result<()> {
+        let mut args = mailer::Args {
+            to: requesting_user.email.clone(),
+            locals: json!({
+                "name": requesting_user.name,
+                "other_user": answering_user.name,
+                "team_name": team.name,
+                "approved": membership.is_some(),
+                "role": membership.map(|membership| &membership.role),
+                "team_url": format!("{}/teams/{}", ctx.config.server.host, team.pid),
+            }),
+            from: Some("test@example.com".to_string()),
+            ..Default::default()
+        };
+
+        if let Some(mailer_config) = &ctx.config.mailer
+            && let Some(smtp_config) = &mailer_config.smtp
+            && let Some(auth) = &smtp_config.auth
+        {
+            args.from = Some(auth.user.clone());
+        }
+
+        Self::mail_template(ctx, &JOIN_REQUEST_ANSWER, args).await
+    }
 }
@@ -11,7 +11,10 @@
     
     <p>Hello {{ name }},</p>
     
-    <p>You have been invited by {{ other_user }} to join the team <strong>{{ team_name }}</strong>.</p>
+    <p>You have been invited by {{ other_user }} to join the team <strong>{{ team_name }}</strong> as {{ role }}.</p>
+    {% if message %}
+    <blockquote style="border-left: 4px solid #dee2e6; margin: 20px 0; padding: 10px 20px; color: #555; white-space: pre-line;">{{ message | escape }}</blockquote>
+    {% endif %}
     
     <p>To review this invitation, please click the button below</p>
     If you do not have one already, you will need to create an account on the Hosting Farm, using the same e-mail address this message was sent to.
@@ -1,6 +1,10 @@
 Hello {{ name }},
 
-You have been invited by {{ other_user }} to join the team {{ team_name }}.
+You have been invited by {{ other_user }} to join the team {{ team_name }} as {{ role }}.
+{% if message %}
+{{ other_user }} wrote:
+{{ message }}
+{% endif %}
 
 To review this invitation, please visit this link:
 {{ invitation_url }}
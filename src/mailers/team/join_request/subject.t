@@ -0,0 +1 @@
+{{ other_user }} asks to join team {{ team_name }}
@@ -0,0 +1,8 @@
+Hello {{ name }},
+
+{{ other_user }} ({{ other_email }}) found the team {{ team_name }} in the team directory and asks to join it.
+
+To approve or deny this request, please visit the team page:
+{{ team_url }}
+
+This is an automated email, please do not reply.
@@ -0,0 +1 @@
+{% if approved %}Welcome to team {{ team_name }}{% else %}Your request to join team {{ team_name }}{% endif %}
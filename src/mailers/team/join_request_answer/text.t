@@ -0,0 +1,10 @@
+Hello {{ name }},
+{% if approved %}
+{{ other_user }} approved your request to join the team {{ team_name }}. You are now a member of the team as {{ role }}.
+
+To visit the team page, please follow this link:
+{{ team_url }}
+{% else %}
+Your request to join the team {{ team_name }} was denied.
+{% endif %}
+This is an automated email, please do not reply.
@@ -0,0 +1,26 @@
+<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8">
+    <title>Team Ownership Transfer</title>
+</head>
+<body style="font-family: Arial, sans-serif; line-height: 1.6; color: #333; max-width: 600px; margin: 0 auto; padding: 20px;">
+    <div style="background-color: #f8f9fa; padding: 20px; border-radius: 5px; margin-bottom: 20px;">
+        <h1 style="color: #0d6efd; margin-top: 0;">Team Ownership Transfer</h1>
+    </div>
+
+    <p>Hello {{ name }},</p>
+
+    <p>{{ other_user }} would like to hand over the ownership of the team <strong>{{ team_name }}</strong> to you. If you accept, you will become an Owner of the team and {{ other_user }} will take your current role.</p>
+
+    <div style="text-align: center; margin: 30px 0;">
+        <a href="{{ team_url }}" style="background-color: #0d6efd; color: white; padding: 10px 20px; text-decoration: none; border-radius: 5px; font-weight: bold;">Review The Transfer</a>
+    </div>
+
+    <p>This offer expires in {{ expires_in_days }} days.</p>
+
+    <div style="border-top: 1px solid #dee2e6; margin-top: 20px; padding-top: 20px; font-size: 0.9em; color: #6c757d;">
+        <p>This is an automated email, please do not reply.</p>
+    </div>
+</body>
+</html>
@@ -0,0 +1 @@
+Ownership of team {{ team_name }} offered to you
@@ -0,0 +1,10 @@
+Hello {{ name }},
+
+{{ other_user }} would like to hand over the ownership of the team {{ team_name }} to you. If you accept, you will become an Owner of the team and {{ other_user }} will take your current role.
+
+To accept or decline, please visit the team page:
+{{ team_url }}
+
+This offer expires in {{ expires_in_days }} days.
+
+This is an automated email, please do not reply.
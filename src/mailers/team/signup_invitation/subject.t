@@ -0,0 +1 @@
+Invitation to join team {{ team_name }} on the Hosting Farm
\ No newline at end of file
@@ -0,0 +1,16 @@
+Hello,
+
+You have been invited by {{ other_user }} to join the team {{ team_name }} on the Hosting Farm as {{ role }}.
+{% if message %}
+{{ other_user }} wrote:
+{{ message }}
+{% endif %}
+
+To accept this invitation, please create an account using this e-mail address:
+{{ signup_url }}
+
+You will join the team once you have verified your e-mail address.
+
+If you received this invitation by mistake, you can simply ignore this email.
+
+This is an automated email, please do not reply.
@@ -14,6 +14,8 @@ use loco_rs::{
     model::Authenticable,
 };
 
+use crate::models::sessions;
+
 //use tracing::error; // Import tracing macros
 
 // ---------------------------------------
@@ -53,6 +55,17 @@ where
                         let jwt_secret = ctx.config.get_jwt_config()?;
                         match auth::jwt::JWT::new(&jwt_secret.secret).validate(&token) {
                             Ok(claims) => {
+                                if let Err(err) = check_session(&ctx, &claims.claims).await {
+                                    tracing::info!(
+                                        message = "Rejected auth token without an active session",
+                                        user_pid = &claims.claims.pid,
+                                        error = err.to_string(),
+                                    );
+                                    return Ok(Self {
+                                        claims: None,
+                                        user: None,
+                                    });
+                                }
                                 let user = T::find_by_claims_key(&ctx.db, &claims.claims.pid).await;
                                 match user {
                                     Ok(user) => Ok(Self {
@@ -166,12 +179,32 @@ where
     }
 }
 
+/// Checks that the token claims refer to a server-side session that has not
+/// been revoked, and records the activity on that session
+///
+/// # Errors
+/// When the token carries no jti, or its session is unknown or revoked
+pub async fn check_session(ctx: &AppContext, claims: &auth::jwt::UserClaims) -> LocoResult<()> {
+    let jti = sessions::jti_from_claims(claims)
+        .ok_or_else(|| Error::Unauthorized("token has no session id".to_string()))?;
+    let session = sessions::Model::find_active(&ctx.db, jti, &claims.pid)
+        .await
+        .map_err(|_| Error::Unauthorized("session is not active".to_string()))?;
+    if let Err(err) = session.touch(&ctx.db).await {
+        tracing::warn!(
+            message = "Failed to update session last activity",
+            error = err.to_string(),
+        );
+    }
+    Ok(())
+}
+
 /// extract JWT token from context configuration
 ///
 /// # Errors
 /// Return an error when JWT token not configured
 #[allow(clippy::result_large_err)]
-fn get_jwt_from_config(ctx: &AppContext) -> LocoResult<&JWTConfig> {
+pub(crate) fn get_jwt_from_config(ctx: &AppContext) -> LocoResult<&JWTConfig> {
     ctx.config
         .auth
         .as_ref()
@@ -182,7 +215,7 @@ fn get_jwt_from_config(ctx: &AppContext) -> LocoResult<&JWTConfig> {
 }
 /// extract token from the configured jwt location settings
 #[allow(clippy::result_large_err)]
-fn extract_token(jwt_config: &JWTConfig, parts: &Parts) -> LocoResult<String> {
+pub(crate) fn extract_token(jwt_config: &JWTConfig, parts: &Parts) -> LocoResult<String> {
     loco_rs::controller::extractor::auth::extract_token(jwt_config, parts)
 }
 /// Function to extract a token from the authorization header
@@ -0,0 +1,45 @@
+//! Axum extractor for JWT authentication backed by the server-side session
+//! store. Unlike the `loco_rs` JWT extractor, tokens whose session has been
+//! revoked are rejected.
+
+use axum::{
+    extract::{FromRef, FromRequestParts},
+    http::request::Parts,
+};
+use serde::{Deserialize, Serialize};
+
+use loco_rs::{app::AppContext, auth, errors::Error};
+
+use super::auth_no_error::{check_session, extract_token, get_jwt_from_config};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct JWTWithSession {
+    pub claims: auth::jwt::UserClaims,
+}
+
+impl<S> FromRequestParts<S> for JWTWithSession
+where
+    AppContext: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Error> {
+        let ctx: AppContext = AppContext::from_ref(state);
+
+        let token = extract_token(get_jwt_from_config(&ctx)?, parts)?;
+        let jwt_secret = ctx.config.get_jwt_config()?;
+
+        let claims = match auth::jwt::JWT::new(&jwt_secret.secret).validate(&token) {
+            Ok(claims) => claims.claims,
+            Err(err) => {
+                tracing::error!("JWT validation error: {}", err);
+                return Err(Error::Unauthorized("token is not valid".to_string()));
+            }
+        };
+
+        check_session(&ctx, &claims).await?;
+
+        Ok(Self { claims })
+    }
+}
@@ -0,0 +1,75 @@
+//! Axum extractor for the JSON API. Requests are authenticated either with a
+//! personal access token sent as a bearer token, which only grants its
+//! scopes, or with a session JWT, which grants every scope.
+
+use axum::{
+    extract::{FromRef, FromRequestParts},
+    http::{StatusCode, request::Parts},
+};
+
+use loco_rs::{app::AppContext, controller::ErrorDetail, errors::Error};
+
+use super::{auth_no_error::extract_token_from_header, auth_session::JWTWithSession};
+use crate::models::{personal_access_tokens, users};
+
+#[derive(Debug)]
+pub struct ApiAuth {
+    pub user: users::Model,
+    /// The personal access token used, `None` for a session
+    pub token: Option<personal_access_tokens::Model>,
+}
+
+impl ApiAuth {
+    /// Checks that the request may use the given scope
+    ///
+    /// # Errors
+    ///
+    /// `403 Forbidden` when the personal access token lacks the scope
+    #[allow(clippy::result_large_err)]
+    pub fn require_scope(&self, scope: &str) -> Result<(), Error> {
+        match &self.token {
+            Some(token) if !token.has_scope(scope) => Err(Error::CustomError(
+                StatusCode::FORBIDDEN,
+                ErrorDetail::new(
+                    "insufficient_scope",
+                    &format!("This personal access token lacks the {scope} scope"),
+                ),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<S> FromRequestParts<S> for ApiAuth
+where
+    AppContext: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Error> {
+        let ctx: AppContext = AppContext::from_ref(state);
+
+        if let Ok(token) = extract_token_from_header(&parts.headers)
+            && token.starts_with(personal_access_tokens::TOKEN_PREFIX)
+        {
+            return match personal_access_tokens::Model::authenticate(&ctx.db, &token).await {
+                Ok((token, user)) => Ok(Self {
+                    user,
+                    token: Some(token),
+                }),
+                Err(err) => {
+                    tracing::info!("Personal access token rejected: {}", err);
+                    Err(Error::Unauthorized("token is not valid".to_string()))
+                }
+            };
+        }
+
+        let auth = JWTWithSession::from_request_parts(parts, state).await?;
+        let user = users::Model::find_by_pid(&ctx.db, &auth.claims.pid).await?;
+        if !user.is_active() {
+            return Err(Error::Unauthorized("account is not active".to_string()));
+        }
+        Ok(Self { user, token: None })
+    }
+}
@@ -1 +1,4 @@
 pub mod auth_no_error;
+pub mod auth_session;
+pub mod auth_token;
+pub mod session_device;
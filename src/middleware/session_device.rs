@@ -0,0 +1,29 @@
+//! Axum extractor for the client details recorded with a session
+
+use std::net::SocketAddr;
+
+use axum::{
+    extract::{ConnectInfo, FromRef, FromRequestParts},
+    http::request::Parts,
+};
+use loco_rs::{app::AppContext, errors::Error};
+
+use crate::models::sessions::{self, SessionDevice};
+
+impl<S> FromRequestParts<S> for SessionDevice
+where
+    AppContext: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let ctx: AppContext = AppContext::from_ref(state);
+        let trusted_proxies = sessions::trusted_proxies(&ctx)?;
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        Ok(Self::from_request(&parts.headers, peer, &trusted_proxies))
+    }
+}
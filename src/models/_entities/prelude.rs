@@ -1,6 +1,23 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.7
 
+pub use super::email_failures::Entity as EmailFailures;
+pub use super::impersonation_events::Entity as ImpersonationEvents;
+pub use super::login_attempts::Entity as LoginAttempts;
+pub use super::oidc_identities::Entity as OidcIdentities;
+pub use super::organization_memberships::Entity as OrganizationMemberships;
+pub use super::organizations::Entity as Organizations;
+pub use super::ownership_transfers::Entity as OwnershipTransfers;
+pub use super::permissions::Entity as Permissions;
+pub use super::personal_access_tokens::Entity as PersonalAccessTokens;
+pub use super::recovery_codes::Entity as RecoveryCodes;
+pub use super::role_permissions::Entity as RolePermissions;
+pub use super::roles::Entity as Roles;
+pub use super::service_accounts::Entity as ServiceAccounts;
+pub use super::sessions::Entity as Sessions;
 pub use super::ssh_keys::Entity as SshKeys;
+pub use super::team_join_links::Entity as TeamJoinLinks;
 pub use super::team_memberships::Entity as TeamMemberships;
 pub use super::teams::Entity as Teams;
 pub use super::users::Entity as Users;
+pub use super::webauthn_challenges::Entity as WebauthnChallenges;
+pub use super::webauthn_credentials::Entity as WebauthnCredentials;
@@ -10,11 +10,17 @@ pub struct Model {
     pub id: i32,
     pub pid: Uuid,
     pub team_id: i32,
-    pub user_id: i32,
+    pub user_id: Option<i32>,
     pub role: String,
     pub pending: bool,
     pub invitation_token: Option<String>,
     pub invitation_sent_at: Option<DateTimeWithTimeZone>,
+    pub invitation_expires_at: Option<DateTimeWithTimeZone>,
+    pub invited_email: Option<String>,
+    pub invited_by_id: Option<i32>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub invitation_message: Option<String>,
+    pub requested: bool,
     pub created_at: DateTimeWithTimeZone,
     pub updated_at: DateTimeWithTimeZone,
 }
@@ -11,16 +11,56 @@ pub struct Model {
     pub pid: Uuid,
     pub name: String,
     pub description: Option<String>,
+    pub organization_id: Option<i32>,
+    pub listed: bool,
     pub created_at: DateTimeWithTimeZone,
     pub updated_at: DateTimeWithTimeZone,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::organizations::Entity",
+        from = "Column::OrganizationId",
+        to = "super::organizations::Column::Id",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    Organizations,
+    #[sea_orm(has_many = "super::ownership_transfers::Entity")]
+    OwnershipTransfers,
+    #[sea_orm(has_many = "super::service_accounts::Entity")]
+    ServiceAccounts,
+    #[sea_orm(has_many = "super::team_join_links::Entity")]
+    TeamJoinLinks,
     #[sea_orm(has_many = "super::team_memberships::Entity")]
     TeamMemberships,
 }
 
+impl Related<super::organizations::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Organizations.def()
+    }
+}
+
+impl Related<super::ownership_transfers::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::OwnershipTransfers.def()
+    }
+}
+
+impl Related<super::service_accounts::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ServiceAccounts.def()
+    }
+}
+
+impl Related<super::team_join_links::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::TeamJoinLinks.def()
+    }
+}
+
 impl Related<super::team_memberships::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::TeamMemberships.def()
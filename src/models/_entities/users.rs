@@ -27,14 +27,68 @@ pub struct Model {
     pub created_at: DateTimeWithTimeZone,
     pub updated_at: DateTimeWithTimeZone,
     pub pgp_key: Option<String>,
+    pub totp_secret: Option<String>,
+    pub totp_enabled_at: Option<DateTimeWithTimeZone>,
+    pub failed_login_count: i32,
+    pub last_failed_login_at: Option<DateTimeWithTimeZone>,
+    pub locked_until: Option<DateTimeWithTimeZone>,
+    pub reset_expires_at: Option<DateTimeWithTimeZone>,
+    pub email_verification_expires_at: Option<DateTimeWithTimeZone>,
+    pub pgp_verification_expires_at: Option<DateTimeWithTimeZone>,
+    pub status: String,
+    pub totp_last_step: Option<i64>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::oidc_identities::Entity")]
+    OidcIdentities,
+    #[sea_orm(has_many = "super::organization_memberships::Entity")]
+    OrganizationMemberships,
+    #[sea_orm(has_many = "super::personal_access_tokens::Entity")]
+    PersonalAccessTokens,
+    #[sea_orm(has_many = "super::recovery_codes::Entity")]
+    RecoveryCodes,
+    #[sea_orm(has_many = "super::sessions::Entity")]
+    Sessions,
     #[sea_orm(has_many = "super::ssh_keys::Entity")]
     SshKeys,
     #[sea_orm(has_many = "super::team_memberships::Entity")]
     TeamMemberships,
+    #[sea_orm(has_many = "super::webauthn_challenges::Entity")]
+    WebauthnChallenges,
+    #[sea_orm(has_many = "super::webauthn_credentials::Entity")]
+    WebauthnCredentials,
+}
+
+impl Related<super::oidc_identities::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::OidcIdentities.def()
+    }
+}
+
+impl Related<super::organization_memberships::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::OrganizationMemberships.def()
+    }
+}
+
+impl Related<super::personal_access_tokens::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PersonalAccessTokens.def()
+    }
+}
+
+impl Related<super::recovery_codes::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::RecoveryCodes.def()
+    }
+}
+
+impl Related<super::sessions::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Sessions.def()
+    }
 }
 
 impl Related<super::ssh_keys::Entity> for Entity {
@@ -48,3 +102,15 @@ impl Related<super::team_memberships::Entity> for Entity {
         Relation::TeamMemberships.def()
     }
 }
+
+impl Related<super::webauthn_challenges::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::WebauthnChallenges.def()
+    }
+}
+
+impl Related<super::webauthn_credentials::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::WebauthnCredentials.def()
+    }
+}
@@ -0,0 +1,74 @@
+use chrono::{Duration, Utc};
+use loco_rs::prelude::*;
+use sea_orm::{ActiveValue, PaginatorTrait, QueryOrder, QuerySelect};
+
+pub use super::_entities::email_failures::{self, ActiveModel, Entity, Model};
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    async fn before_save<C>(self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        if !insert && self.updated_at.is_unchanged() {
+            let mut this = self;
+            this.updated_at = ActiveValue::Set(Utc::now().into());
+            Ok(this)
+        } else {
+            Ok(self)
+        }
+    }
+}
+
+impl Model {
+    /// Records an email that could not be delivered
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn record(
+        db: &DatabaseConnection,
+        recipient: &str,
+        subject: &str,
+        error: &str,
+    ) -> ModelResult<Self> {
+        let failure = ActiveModel {
+            recipient: ActiveValue::Set(recipient.to_string()),
+            subject: ActiveValue::Set(subject.to_string()),
+            error: ActiveValue::Set(error.to_string()),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+        Ok(failure)
+    }
+
+    /// Lists the most recent delivery failures, newest first
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn find_recent(db: &DatabaseConnection, limit: u64) -> ModelResult<Vec<Self>> {
+        let failures = Entity::find()
+            .order_by_desc(email_failures::Column::CreatedAt)
+            .order_by_desc(email_failures::Column::Id)
+            .limit(limit)
+            .all(db)
+            .await?;
+        Ok(failures)
+    }
+
+    /// Counts the delivery failures of the last `days` days
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn count_since_days(db: &DatabaseConnection, days: i64) -> ModelResult<u64> {
+        let since = Utc::now() - Duration::days(days);
+        let count = Entity::find()
+            .filter(email_failures::Column::CreatedAt.gt(since))
+            .count(db)
+            .await?;
+        Ok(count)
+    }
+}
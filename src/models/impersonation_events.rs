@@ -0,0 +1,99 @@
+use chrono::Utc;
+use loco_rs::prelude::*;
+use sea_orm::{ActiveValue, QueryOrder, QuerySelect};
+
+pub use super::_entities::impersonation_events::{self, ActiveModel, Entity, Model};
+use super::users;
+
+/// An administrator started browsing the console as another user
+pub const EVENT_START: &str = "start";
+/// An administrator went back to their own account
+pub const EVENT_STOP: &str = "stop";
+
+/// An impersonation event with the administrator and the user involved,
+/// `None` for a user deleted since
+#[derive(Debug)]
+pub struct ImpersonationEventDetails {
+    pub event: Model,
+    pub admin: Option<users::Model>,
+    pub target: Option<users::Model>,
+}
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    async fn before_save<C>(self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        if !insert && self.updated_at.is_unchanged() {
+            let mut this = self;
+            this.updated_at = ActiveValue::Set(Utc::now().into());
+            Ok(this)
+        } else {
+            Ok(self)
+        }
+    }
+}
+
+impl Model {
+    /// Records the start or the end of an impersonation session
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn record(
+        db: &DatabaseConnection,
+        event: &str,
+        admin_id: i32,
+        target_id: i32,
+        session_jti: &str,
+        ip: Option<&str>,
+    ) -> ModelResult<Self> {
+        let event = ActiveModel {
+            event: ActiveValue::Set(event.to_string()),
+            session_jti: ActiveValue::Set(session_jti.to_string()),
+            ip: ActiveValue::Set(ip.map(ToString::to_string)),
+            admin_id: ActiveValue::Set(Some(admin_id)),
+            target_id: ActiveValue::Set(Some(target_id)),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+        Ok(event)
+    }
+
+    /// Lists the most recent impersonation events, newest first
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn find_recent(
+        db: &DatabaseConnection,
+        limit: u64,
+    ) -> ModelResult<Vec<ImpersonationEventDetails>> {
+        let events = Entity::find()
+            .order_by_desc(impersonation_events::Column::CreatedAt)
+            .order_by_desc(impersonation_events::Column::Id)
+            .limit(limit)
+            .all(db)
+            .await?;
+
+        let mut details = Vec::with_capacity(events.len());
+        for event in events {
+            let admin = match event.admin_id {
+                Some(id) => users::Entity::find_by_id(id).one(db).await?,
+                None => None,
+            };
+            let target = match event.target_id {
+                Some(id) => users::Entity::find_by_id(id).one(db).await?,
+                None => None,
+            };
+            details.push(ImpersonationEventDetails {
+                event,
+                admin,
+                target,
+            });
+        }
+        Ok(details)
+    }
+}
@@ -0,0 +1,166 @@
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use loco_rs::prelude::*;
+use sea_orm::ActiveValue;
+
+pub use super::_entities::login_attempts::{self, ActiveModel, Entity, Model};
+
+/// Failed logins tolerated on an account before each new attempt is delayed
+pub const ACCOUNT_FREE_ATTEMPTS: i32 = 3;
+/// Failed logins after which an account is locked
+pub const ACCOUNT_LOCKOUT_THRESHOLD: i32 = 10;
+/// Failed logins tolerated from one IP address (across all accounts) before
+/// each new attempt is delayed
+pub const IP_FREE_ATTEMPTS: i32 = 10;
+/// Failed logins after which an IP address is blocked
+pub const IP_LOCKOUT_THRESHOLD: i32 = 50;
+/// Duration of an account or IP lockout
+pub const LOCKOUT_DURATION_MIN: i64 = 15;
+/// Failure counters are reset when no failure happened for this long
+pub const FAILURE_WINDOW_MIN: i64 = 60;
+
+/// Delay imposed after `failures` consecutive failures: nothing for the first
+/// `free_attempts`, then 1, 2, 4, 8... seconds, capped at the lockout duration
+#[must_use]
+pub fn backoff_delay(failures: i32, free_attempts: i32) -> Duration {
+    let exponent = failures - free_attempts;
+    if exponent <= 0 {
+        return Duration::zero();
+    }
+    let cap = Duration::minutes(LOCKOUT_DURATION_MIN);
+    // 2^(exponent - 1) seconds, computed without overflowing for long streaks
+    let delay = 2_i64
+        .checked_pow(u32::try_from(exponent - 1).unwrap_or(u32::MAX))
+        .map_or(cap, Duration::seconds);
+    delay.min(cap)
+}
+
+/// Returns the number of seconds to wait before the next login attempt is
+/// accepted, given a failure counter, the time of the last failure and an
+/// optional lockout end, or `None` when an attempt can be made now
+#[must_use]
+pub fn retry_after(
+    failures: i32,
+    free_attempts: i32,
+    last_failure_at: Option<DateTime<FixedOffset>>,
+    locked_until: Option<DateTime<FixedOffset>>,
+) -> Option<i64> {
+    let now = Utc::now();
+    let mut next_attempt = locked_until.map(|until| until.with_timezone(&Utc));
+    if let Some(last_failure_at) = last_failure_at {
+        let backoff_end =
+            last_failure_at.with_timezone(&Utc) + backoff_delay(failures, free_attempts);
+        next_attempt = Some(next_attempt.map_or(backoff_end, |until| until.max(backoff_end)));
+    }
+    next_attempt
+        .filter(|next_attempt| *next_attempt > now)
+        .map(|next_attempt| (next_attempt - now).num_seconds().max(1))
+}
+
+/// Returns true when the last failure is old enough for the counter to start
+/// again from zero
+#[must_use]
+pub fn failure_window_expired(last_failure_at: Option<DateTime<FixedOffset>>) -> bool {
+    last_failure_at.is_none_or(|last_failure_at| {
+        Utc::now().signed_duration_since(last_failure_at) > Duration::minutes(FAILURE_WINDOW_MIN)
+    })
+}
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    async fn before_save<C>(self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        if !insert && self.updated_at.is_unchanged() {
+            let mut this = self;
+            this.updated_at = ActiveValue::Set(Utc::now().into());
+            Ok(this)
+        } else {
+            Ok(self)
+        }
+    }
+}
+
+impl Model {
+    /// Finds the failure counter of an IP address
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn find_by_ip(db: &DatabaseConnection, ip: &str) -> ModelResult<Option<Self>> {
+        Ok(Entity::find()
+            .filter(login_attempts::Column::Ip.eq(ip))
+            .one(db)
+            .await?)
+    }
+
+    /// Returns the number of seconds the given IP address has to wait before
+    /// it may try to log in again, or `None` when it is not throttled
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn retry_after_for_ip(db: &DatabaseConnection, ip: &str) -> ModelResult<Option<i64>> {
+        Ok(Self::find_by_ip(db, ip).await?.and_then(|attempts| {
+            retry_after(
+                attempts.failure_count,
+                IP_FREE_ATTEMPTS,
+                Some(attempts.last_failure_at),
+                attempts.blocked_until,
+            )
+        }))
+    }
+
+    /// Records a failed login coming from the given IP address, blocking it
+    /// once it reaches `IP_LOCKOUT_THRESHOLD` failures
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn record_failure(db: &DatabaseConnection, ip: &str) -> ModelResult<Self> {
+        let now = Utc::now();
+        let Some(attempts) = Self::find_by_ip(db, ip).await? else {
+            let attempts = ActiveModel {
+                ip: ActiveValue::Set(ip.to_string()),
+                failure_count: ActiveValue::Set(1),
+                last_failure_at: ActiveValue::Set(now.into()),
+                ..Default::default()
+            }
+            .insert(db)
+            .await?;
+            return Ok(attempts);
+        };
+
+        let failure_count = if failure_window_expired(Some(attempts.last_failure_at)) {
+            1
+        } else {
+            attempts.failure_count + 1
+        };
+        let mut attempts = attempts.into_active_model();
+        attempts.failure_count = ActiveValue::Set(failure_count);
+        attempts.last_failure_at = ActiveValue::Set(now.into());
+        if failure_count >= IP_LOCKOUT_THRESHOLD {
+            attempts.blocked_until =
+                ActiveValue::Set(Some((now + Duration::minutes(LOCKOUT_DURATION_MIN)).into()));
+            tracing::warn!(ip, failure_count, "IP address blocked after failed logins");
+        }
+        Ok(attempts.update(db).await?)
+    }
+}
+
+/// Formats a retry delay for error messages ("42 seconds", "15 minutes")
+#[must_use]
+pub fn describe_retry_after(retry_after_sec: i64) -> String {
+    if retry_after_sec < 60 {
+        let unit = if retry_after_sec == 1 {
+            "second"
+        } else {
+            "seconds"
+        };
+        format!("{retry_after_sec} {unit}")
+    } else {
+        let minutes = (retry_after_sec + 59) / 60;
+        let unit = if minutes == 1 { "minute" } else { "minutes" };
+        format!("{minutes} {unit}")
+    }
+}
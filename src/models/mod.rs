@@ -1,5 +1,28 @@
 pub mod _entities;
+pub mod email_failures;
+pub mod impersonation_events;
+pub mod login_attempts;
+pub mod oidc;
+pub mod oidc_identities;
+pub mod organization_memberships;
+pub mod organizations;
+pub mod ownership_transfers;
+pub mod password_policy;
+pub mod permissions;
+pub mod personal_access_tokens;
+pub mod recovery_codes;
+pub mod registration;
+pub mod role_permissions;
+pub mod roles;
+pub mod service_accounts;
+pub mod sessions;
 pub mod ssh_keys;
+pub mod statistics;
+pub mod team_join_links;
 pub mod team_memberships;
 pub mod teams;
+pub mod tokens;
+pub mod user_imports;
 pub mod users;
+pub mod webauthn_challenges;
+pub mod webauthn_credentials;
@@ -0,0 +1,591 @@
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use loco_rs::{auth::jwt, hash, prelude::*};
+use openidconnect::{
+    AuthenticationFlow, AuthorizationCode, ClientId, ClientSecret, CsrfToken, EndpointMaybeSet,
+    EndpointNotSet, EndpointSet, IssuerUrl, Nonce, PkceCodeChallenge, PkceCodeVerifier,
+    RedirectUrl, Scope, TokenResponse,
+    core::{CoreClient, CoreProviderMetadata, CoreResponseType},
+    reqwest,
+};
+use sea_orm::ActiveValue;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    _entities::{team_memberships, teams, users},
+    oidc_identities,
+    password_policy::PasswordPolicy,
+    registration::RegistrationPolicy,
+    roles,
+    users::RegisterParams,
+};
+
+/// Key of the single sign-on providers in the `initializers` section of the
+/// configuration
+pub const OIDC_INITIALIZER: &str = "oauth2";
+/// Time given to the user to log in at the identity provider
+pub const OIDC_LOGIN_EXPIRATION_SEC: u64 = 600;
+/// Name of the claim holding the pending login in the state token
+const OIDC_LOGIN_CLAIM: &str = "oidc_login";
+
+/// Client built from the metadata published by a provider
+type DiscoveredClient = CoreClient<
+    EndpointSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointMaybeSet,
+    EndpointMaybeSet,
+>;
+
+fn default_scopes() -> Vec<String> {
+    vec!["email".to_string(), "profile".to_string()]
+}
+
+/// Places the users whose ID token holds a claim value into a team
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ClaimTeamMapping {
+    /// Name of the claim, such as `groups`
+    pub claim: String,
+    /// Value the claim must be equal to, or contain when it is a list
+    pub value: String,
+    /// Name of the team the user is added to, one outside of any organization
+    pub team: String,
+    /// Role given in the team, the name of one of the [`roles`]
+    pub role: String,
+}
+
+impl ClaimTeamMapping {
+    /// Returns true when the claims of a user match this mapping
+    #[must_use]
+    pub fn matches(&self, claims: &serde_json::Map<String, serde_json::Value>) -> bool {
+        let matches_value = |value: &serde_json::Value| match value {
+            serde_json::Value::String(value) => value == &self.value,
+            serde_json::Value::Bool(_) | serde_json::Value::Number(_) => self
+                .value
+                .parse::<serde_json::Value>()
+                .is_ok_and(|expected| &expected == value),
+            _ => false,
+        };
+        match claims.get(&self.claim) {
+            Some(serde_json::Value::Array(values)) => values.iter().any(matches_value),
+            Some(value) => matches_value(value),
+            None => false,
+        }
+    }
+}
+
+/// An OpenID Connect provider users can log in with, using the authorization
+/// code flow with PKCE
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OidcProvider {
+    /// Identifier of the provider in the login URLs, unique within the
+    /// configuration
+    pub client_identifier: String,
+    /// Name shown on the login button, defaults to the identifier
+    #[serde(default)]
+    pub display_name: Option<String>,
+    /// Issuer URL, the discovery document is read from
+    /// `{issuer_url}/.well-known/openid-configuration`
+    pub issuer_url: String,
+    pub client_id: String,
+    /// Secret of confidential clients; public clients only rely on PKCE
+    #[serde(default)]
+    pub client_secret: Option<String>,
+    /// Callback URL registered at the provider, defaults to
+    /// `{server URL}/auth/oidc/{client_identifier}/callback`
+    #[serde(default)]
+    pub redirect_url: Option<String>,
+    /// Scopes requested in addition to `openid`
+    #[serde(default = "default_scopes")]
+    pub scopes: Vec<String>,
+    #[serde(default)]
+    pub team_mappings: Vec<ClaimTeamMapping>,
+}
+
+/// Single sign-on providers read from `initializers.oauth2`
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct OidcConfig {
+    #[serde(default)]
+    pub authorization_code: Vec<OidcProvider>,
+}
+
+/// What the provider tells about the user who logged in
+#[derive(Debug, Clone)]
+pub struct OidcIdentity {
+    pub subject: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub name: Option<String>,
+    /// Every claim of the ID token, used by the team mappings
+    pub claims: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Login started at a provider, kept in a signed cookie until the provider
+/// redirects the user back
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct OidcLoginState {
+    csrf_state: String,
+    nonce: String,
+    pkce_verifier: String,
+}
+
+impl OidcConfig {
+    /// Reads the providers from `initializers.oauth2`, there are none when
+    /// the block is missing
+    ///
+    /// # Errors
+    ///
+    /// When the configuration is invalid
+    pub fn from_context(ctx: &AppContext) -> ModelResult<Self> {
+        let settings = ctx
+            .config
+            .initializers
+            .as_ref()
+            .and_then(|initializers| initializers.get(OIDC_INITIALIZER));
+        let config: Self = match settings {
+            Some(config) => serde_json::from_value(config.clone()).map_err(|e| {
+                ModelError::Message(format!("Invalid single sign-on configuration: {e}"))
+            })?,
+            None => Self::default(),
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks that the provider identifiers can be used in URLs and are
+    /// unique, and that the mapped roles exist
+    ///
+    /// # Errors
+    ///
+    /// When the configuration is invalid
+    pub fn validate(&self) -> ModelResult<()> {
+        for (index, provider) in self.authorization_code.iter().enumerate() {
+            let identifier = &provider.client_identifier;
+            if identifier.is_empty()
+                || !identifier
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                return Err(ModelError::Message(format!(
+                    "Invalid single sign-on provider identifier '{identifier}': only letters, digits, '-' and '_' are allowed"
+                )));
+            }
+            if self.authorization_code[..index]
+                .iter()
+                .any(|other| &other.client_identifier == identifier)
+            {
+                return Err(ModelError::Message(format!(
+                    "Duplicate single sign-on provider identifier '{identifier}'"
+                )));
+            }
+            // Roles are stored, so only their existence is checked at sign-in
+            if provider
+                .team_mappings
+                .iter()
+                .any(|mapping| mapping.role.trim().is_empty())
+            {
+                return Err(ModelError::Message(format!(
+                    "Missing role in the team mappings of single sign-on provider '{identifier}'"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Finds a configured provider by its identifier
+    ///
+    /// # Errors
+    ///
+    /// When no provider has this identifier
+    pub fn provider(&self, client_identifier: &str) -> ModelResult<&OidcProvider> {
+        self.authorization_code
+            .iter()
+            .find(|provider| provider.client_identifier == client_identifier)
+            .ok_or_else(|| ModelError::EntityNotFound)
+    }
+}
+
+/// HTTP client used to talk to the providers. Redirects are not followed,
+/// as recommended by the `openidconnect` crate to prevent SSRF.
+fn http_client() -> ModelResult<reqwest::Client> {
+    reqwest::ClientBuilder::new()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .map_err(|e| ModelError::Any(e.into()))
+}
+
+fn jwt_secret(ctx: &AppContext) -> ModelResult<String> {
+    ctx.config
+        .get_jwt_config()
+        .map(|config| config.secret.clone())
+        .map_err(|e| ModelError::Any(e.into()))
+}
+
+/// Reads the payload of an ID token whose signature was already verified
+fn id_token_claims(id_token: &str) -> ModelResult<serde_json::Map<String, serde_json::Value>> {
+    let payload = id_token
+        .split('.')
+        .nth(1)
+        .ok_or_else(|| ModelError::msg("malformed ID token"))?;
+    let payload = URL_SAFE_NO_PAD
+        .decode(payload)
+        .map_err(|e| ModelError::Any(e.into()))?;
+    serde_json::from_slice(&payload).map_err(|e| ModelError::Any(e.into()))
+}
+
+/// Finds a user name that is not taken yet, adding a number to the wanted
+/// one when needed
+async fn available_user_name(db: &DatabaseConnection, wanted: &str) -> ModelResult<String> {
+    let wanted = wanted.trim();
+    let mut name = wanted.to_string();
+    let mut suffix = 1;
+    while users::Entity::find()
+        .filter(users::Column::Name.eq(&name))
+        .one(db)
+        .await?
+        .is_some()
+    {
+        suffix += 1;
+        name = format!("{wanted}-{suffix}");
+    }
+    Ok(name)
+}
+
+impl OidcProvider {
+    /// Name shown to users
+    #[must_use]
+    pub fn name(&self) -> &str {
+        self.display_name
+            .as_deref()
+            .unwrap_or(&self.client_identifier)
+    }
+
+    /// Callback URL the provider redirects users to after they logged in
+    #[must_use]
+    pub fn redirect_url(&self, ctx: &AppContext) -> String {
+        self.redirect_url.clone().unwrap_or_else(|| {
+            format!(
+                "{}/auth/oidc/{}/callback",
+                ctx.config.server.full_url(),
+                self.client_identifier
+            )
+        })
+    }
+
+    /// Reads the discovery document of the provider and builds a client
+    async fn client(
+        &self,
+        ctx: &AppContext,
+        http_client: &reqwest::Client,
+    ) -> ModelResult<DiscoveredClient> {
+        let issuer_url =
+            IssuerUrl::new(self.issuer_url.clone()).map_err(|e| ModelError::Any(e.into()))?;
+        let metadata = CoreProviderMetadata::discover_async(issuer_url, http_client)
+            .await
+            .map_err(|e| ModelError::Any(e.into()))?;
+        let redirect_url =
+            RedirectUrl::new(self.redirect_url(ctx)).map_err(|e| ModelError::Any(e.into()))?;
+        Ok(CoreClient::from_provider_metadata(
+            metadata,
+            ClientId::new(self.client_id.clone()),
+            self.client_secret.clone().map(ClientSecret::new),
+        )
+        .set_redirect_uri(redirect_url))
+    }
+
+    /// Starts a login at the provider. Returns the authorization URL the
+    /// user must be redirected to, and a signed state token that must be
+    /// given back to [`OidcProvider::finish_login`] with the callback.
+    ///
+    /// # Errors
+    ///
+    /// When the provider cannot be discovered or the state could not be
+    /// signed
+    pub async fn start_login(&self, ctx: &AppContext) -> ModelResult<(String, String)> {
+        let client = self.client(ctx, &http_client()?).await?;
+        let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+        let mut request = client
+            .authorize_url(
+                AuthenticationFlow::<CoreResponseType>::AuthorizationCode,
+                CsrfToken::new_random,
+                Nonce::new_random,
+            )
+            .set_pkce_challenge(pkce_challenge);
+        for scope in &self.scopes {
+            request = request.add_scope(Scope::new(scope.clone()));
+        }
+        let (authorize_url, csrf_state, nonce) = request.url();
+
+        let state = OidcLoginState {
+            csrf_state: csrf_state.secret().clone(),
+            nonce: nonce.secret().clone(),
+            pkce_verifier: pkce_verifier.secret().clone(),
+        };
+        let mut claims = serde_json::Map::new();
+        claims.insert(
+            OIDC_LOGIN_CLAIM.to_string(),
+            serde_json::to_value(state).map_err(|e| ModelError::Any(e.into()))?,
+        );
+        let state_token = jwt::JWT::new(&jwt_secret(ctx)?).generate_token(
+            OIDC_LOGIN_EXPIRATION_SEC,
+            self.client_identifier.clone(),
+            claims,
+        )?;
+        Ok((authorize_url.to_string(), state_token))
+    }
+
+    /// Completes a login started with [`OidcProvider::start_login`]: the
+    /// authorization code is exchanged for an ID token, which is verified
+    /// against the provider keys and the nonce of the login.
+    ///
+    /// # Errors
+    ///
+    /// When the state token is invalid, expired or issued for another
+    /// provider, when the CSRF state does not match, when the code exchange
+    /// fails or the ID token does not verify
+    pub async fn finish_login(
+        &self,
+        ctx: &AppContext,
+        state_token: &str,
+        csrf_state: &str,
+        code: &str,
+    ) -> ModelResult<OidcIdentity> {
+        let claims = jwt::JWT::new(&jwt_secret(ctx)?)
+            .validate(state_token)
+            .map_err(|_| ModelError::msg("invalid or expired single sign-on login"))?
+            .claims;
+        if claims.pid != self.client_identifier {
+            return Err(ModelError::msg(
+                "single sign-on login started with another provider",
+            ));
+        }
+        let state: OidcLoginState = claims
+            .claims
+            .get(OIDC_LOGIN_CLAIM)
+            .cloned()
+            .ok_or_else(|| ModelError::msg("invalid single sign-on login"))
+            .and_then(|state| {
+                serde_json::from_value(state).map_err(|e| ModelError::Any(e.into()))
+            })?;
+        if state.csrf_state != csrf_state {
+            return Err(ModelError::msg("single sign-on state mismatch"));
+        }
+
+        let http_client = http_client()?;
+        let client = self.client(ctx, &http_client).await?;
+        let token_response = client
+            .exchange_code(AuthorizationCode::new(code.to_string()))
+            .map_err(|e| ModelError::Any(e.into()))?
+            .set_pkce_verifier(PkceCodeVerifier::new(state.pkce_verifier))
+            .request_async(&http_client)
+            .await
+            .map_err(|e| ModelError::Any(e.into()))?;
+        let id_token = token_response
+            .id_token()
+            .ok_or_else(|| ModelError::msg("the provider did not return an ID token"))?;
+        let verified = id_token
+            .claims(&client.id_token_verifier(), &Nonce::new(state.nonce))
+            .map_err(|e| ModelError::Any(e.into()))?;
+
+        Ok(OidcIdentity {
+            subject: verified.subject().to_string(),
+            email: verified.email().map(|email| email.to_string()),
+            email_verified: verified.email_verified() == Some(true),
+            name: verified
+                .name()
+                .and_then(|name| name.get(None))
+                .map(|name| name.to_string()),
+            claims: id_token_claims(&id_token.to_string())?,
+        })
+    }
+
+    /// Finds the user an identity of this provider belongs to.
+    ///
+    /// On the first login of an identity, it is linked to the user with the
+    /// same email address, or a new user is created. The email address must
+    /// be verified by the provider. The team mappings are applied on every
+    /// login.
+    ///
+    /// # Errors
+    ///
+    /// When the email address is missing or not verified, the registration
+    /// mode refuses a new user, or DB query error
+    pub async fn sign_in(
+        &self,
+        ctx: &AppContext,
+        identity: &OidcIdentity,
+    ) -> ModelResult<users::Model> {
+        let db = &ctx.db;
+        let user = match oidc_identities::Model::find_by_provider_subject(
+            db,
+            &self.client_identifier,
+            &identity.subject,
+        )
+        .await
+        {
+            Ok(linked) => {
+                let linked = linked.record_login(db).await?;
+                users::Entity::find_by_id(linked.user_id)
+                    .one(db)
+                    .await?
+                    .ok_or_else(|| ModelError::EntityNotFound)?
+            }
+            Err(ModelError::EntityNotFound) => self.link_or_create_user(ctx, identity).await?,
+            Err(e) => return Err(e),
+        };
+
+        self.apply_team_mappings(db, &user, identity).await?;
+        Ok(user)
+    }
+
+    /// Links a new identity to the user with its verified email address,
+    /// creating the user when there is none. Users are only created when the
+    /// registration mode accepts their email address, and are left pending
+    /// when it requires an approval.
+    async fn link_or_create_user(
+        &self,
+        ctx: &AppContext,
+        identity: &OidcIdentity,
+    ) -> ModelResult<users::Model> {
+        let db = &ctx.db;
+        let email = match &identity.email {
+            Some(email) if identity.email_verified => email.trim(),
+            _ => {
+                return Err(ModelError::msg(
+                    "The identity provider did not return a verified email address",
+                ));
+            }
+        };
+
+        let user = match users::Model::find_by_email(db, email).await {
+            Ok(user) => {
+                tracing::info!(
+                    user_email = email,
+                    provider = self.client_identifier,
+                    "Linking single sign-on identity to existing user"
+                );
+                user
+            }
+            Err(ModelError::EntityNotFound) => {
+                let registration = RegistrationPolicy::from_context(ctx)?;
+                registration.check(db, email).await?;
+                let wanted_name = identity
+                    .name
+                    .clone()
+                    .filter(|name| !name.trim().is_empty())
+                    .unwrap_or_else(|| email.split('@').next().unwrap_or(email).to_string());
+                // The random password is never shown to the user, so the
+                // configured policy does not apply to it
+                let password = hash::random_string(32);
+                let user = users::Model::create_with_password(
+                    db,
+                    &RegisterParams {
+                        email: email.to_string(),
+                        password: password.clone(),
+                        name: available_user_name(db, &wanted_name).await?,
+                        password_confirmation: password,
+                    },
+                    &PasswordPolicy::default(),
+                )
+                .await?;
+                tracing::info!(
+                    user_email = email,
+                    provider = self.client_identifier,
+                    "Created user on first single sign-on login"
+                );
+                user.create_admin_team_if_needed(db, ctx).await?;
+                registration.admit(db, user).await?
+            }
+            Err(e) => return Err(e),
+        };
+
+        // The provider verified the email address, which binds the
+        // invitations sent to it, as when verifying it by email
+        let user = if user.email_verified_at.is_none() {
+            let user = user.into_active_model().verified(db).await?;
+            super::team_memberships::Model::attach_email_invitations(db, &user).await?;
+            user
+        } else {
+            user
+        };
+        oidc_identities::Model::link(db, &self.client_identifier, &identity.subject, user.id)
+            .await?;
+        Ok(user)
+    }
+
+    /// Adds the user to the teams whose mapping matches the claims of the
+    /// identity. Existing memberships are left unchanged, pending
+    /// invitations are accepted with the mapped role.
+    async fn apply_team_mappings(
+        &self,
+        db: &DatabaseConnection,
+        user: &users::Model,
+        identity: &OidcIdentity,
+    ) -> ModelResult<()> {
+        for mapping in &self.team_mappings {
+            if !mapping.matches(&identity.claims) {
+                continue;
+            }
+            let Some(team) = teams::Entity::find()
+                .filter(teams::Column::Name.eq(&mapping.team))
+                .filter(teams::Column::OrganizationId.is_null())
+                .one(db)
+                .await?
+            else {
+                tracing::warn!(
+                    team = mapping.team,
+                    provider = self.client_identifier,
+                    "Team of a single sign-on mapping does not exist"
+                );
+                continue;
+            };
+            match roles::Model::find_by_name(db, &mapping.role).await {
+                Ok(_) => {}
+                Err(ModelError::EntityNotFound) => {
+                    tracing::warn!(
+                        role = mapping.role,
+                        provider = self.client_identifier,
+                        "Role of a single sign-on mapping does not exist"
+                    );
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+
+            let membership = team_memberships::Entity::find()
+                .filter(team_memberships::Column::TeamId.eq(team.id))
+                .filter(team_memberships::Column::UserId.eq(user.id))
+                .one(db)
+                .await?;
+            match membership {
+                Some(membership) if !membership.pending => {}
+                Some(invitation) => {
+                    let mut membership = invitation.into_active_model();
+                    membership.pending = ActiveValue::Set(false);
+                    membership.role = ActiveValue::Set(mapping.role.clone());
+                    membership.invitation_token = ActiveValue::Set(None);
+                    membership.invitation_expires_at = ActiveValue::Set(None);
+                    membership.update(db).await?;
+                }
+                None => {
+                    team_memberships::ActiveModel {
+                        team_id: ActiveValue::Set(team.id),
+                        user_id: ActiveValue::Set(Some(user.id)),
+                        role: ActiveValue::Set(mapping.role.clone()),
+                        pending: ActiveValue::Set(false),
+                        ..Default::default()
+                    }
+                    .insert(db)
+                    .await?;
+                    tracing::info!(
+                        user_email = user.email,
+                        team = team.name,
+                        role = mapping.role,
+                        "Added user to team from single sign-on claims"
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+use chrono::Utc;
+use loco_rs::prelude::*;
+use sea_orm::ActiveValue;
+
+pub use super::_entities::oidc_identities::{self, ActiveModel, Entity, Model};
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    async fn before_save<C>(self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        if !insert && self.updated_at.is_unchanged() {
+            let mut this = self;
+            this.updated_at = ActiveValue::Set(Utc::now().into());
+            Ok(this)
+        } else {
+            Ok(self)
+        }
+    }
+}
+
+impl Model {
+    /// Finds the identity issued by a provider to one of its users
+    ///
+    /// # Errors
+    ///
+    /// When could not find identity or DB query error
+    pub async fn find_by_provider_subject(
+        db: &DatabaseConnection,
+        provider: &str,
+        subject: &str,
+    ) -> ModelResult<Self> {
+        let identity = Entity::find()
+            .filter(oidc_identities::Column::Provider.eq(provider))
+            .filter(oidc_identities::Column::Subject.eq(subject))
+            .one(db)
+            .await?;
+        identity.ok_or_else(|| ModelError::EntityNotFound)
+    }
+
+    /// Links an identity of a provider to a user
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn link(
+        db: &DatabaseConnection,
+        provider: &str,
+        subject: &str,
+        user_id: i32,
+    ) -> ModelResult<Self> {
+        let identity = ActiveModel {
+            provider: ActiveValue::Set(provider.to_string()),
+            subject: ActiveValue::Set(subject.to_string()),
+            last_login_at: ActiveValue::Set(Some(Utc::now().into())),
+            user_id: ActiveValue::Set(user_id),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+        Ok(identity)
+    }
+
+    /// Records that the identity was just used to log in
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn record_login(self, db: &DatabaseConnection) -> ModelResult<Self> {
+        let mut identity = self.into_active_model();
+        identity.last_login_at = ActiveValue::Set(Some(Utc::now().into()));
+        Ok(identity.update(db).await?)
+    }
+}
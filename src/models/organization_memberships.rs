@@ -0,0 +1,163 @@
+use chrono::Utc;
+use loco_rs::prelude::*;
+use sea_orm::{ActiveValue, PaginatorTrait, TransactionTrait};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub use super::_entities::organization_memberships::{self, ActiveModel, Entity, Model};
+use super::_entities::users;
+use super::roles::{self, OWNER_ROLE};
+
+/// Sent by an Owner to add a user to an organization
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AddOrganizationMemberParams {
+    pub user_name: String,
+    pub role: String,
+}
+
+/// Refusal to demote the last Owner of an organization
+pub const LAST_OWNER_ROLE_CHANGE: &str =
+    "Cannot change the role of the last owner. Make another member an Owner first.";
+/// Refusal to remove the last Owner of an organization, or to let them leave
+pub const LAST_OWNER_REMOVAL: &str = "The last owner cannot leave the organization or be removed from it. Either delete the organization or make another member an Owner first.";
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    async fn before_save<C>(self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        if insert {
+            let mut this = self;
+            this.pid = ActiveValue::Set(Uuid::new_v4());
+            Ok(this)
+        } else if self.updated_at.is_unchanged() {
+            let mut this = self;
+            this.updated_at = ActiveValue::Set(Utc::now().into());
+            Ok(this)
+        } else {
+            Ok(self)
+        }
+    }
+}
+
+impl Model {
+    /// Finds the membership of a user in an organization
+    ///
+    /// # Errors
+    ///
+    /// When could not find the membership or DB query error
+    pub async fn find_by_organization_and_user(
+        db: &DatabaseConnection,
+        organization_id: i32,
+        user_id: i32,
+    ) -> ModelResult<Self> {
+        let membership = Entity::find()
+            .filter(organization_memberships::Column::OrganizationId.eq(organization_id))
+            .filter(organization_memberships::Column::UserId.eq(user_id))
+            .one(db)
+            .await?;
+        membership.ok_or_else(|| ModelError::EntityNotFound)
+    }
+
+    /// Adds a user to an organization with the given role, which the user
+    /// then has in every team of the organization
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the role is invalid, the user is a service
+    /// account or already a member, or DB query error
+    pub async fn add_member(
+        db: &DatabaseConnection,
+        organization_id: i32,
+        user: &users::Model,
+        role: &str,
+    ) -> ModelResult<Self> {
+        roles::Model::find_assignable(db, role).await?;
+        if user.is_service_account() {
+            return Err(ModelError::msg(
+                "Service accounts cannot join an organization",
+            ));
+        }
+        if Self::find_by_organization_and_user(db, organization_id, user.id)
+            .await
+            .is_ok()
+        {
+            return Err(ModelError::msg(
+                "User is already a member of this organization",
+            ));
+        }
+
+        let membership = ActiveModel {
+            organization_id: ActiveValue::Set(organization_id),
+            user_id: ActiveValue::Set(user.id),
+            role: ActiveValue::Set(role.to_string()),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+        Ok(membership)
+    }
+
+    /// Changes the role of a member of the organization
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the role is invalid or this is the last
+    /// Owner, or DB query error
+    pub async fn update_role(&self, db: &DatabaseConnection, new_role: &str) -> ModelResult<Self> {
+        roles::Model::find_assignable(db, new_role).await?;
+
+        let txn = db.begin().await?;
+        if new_role != OWNER_ROLE {
+            self.check_not_last_owner(&txn, LAST_OWNER_ROLE_CHANGE)
+                .await?;
+        }
+        let mut membership = self.clone().into_active_model();
+        membership.role = ActiveValue::Set(new_role.to_string());
+        let membership = membership.update(&txn).await?;
+        txn.commit().await?;
+        Ok(membership)
+    }
+
+    /// Removes the member from the organization, when they leave or are
+    /// removed. Their memberships in the teams of the organization stay.
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when this is the last Owner, or DB query error
+    pub async fn remove(&self, db: &DatabaseConnection) -> ModelResult<()> {
+        let txn = db.begin().await?;
+        self.check_not_last_owner(&txn, LAST_OWNER_REMOVAL).await?;
+        Entity::delete_by_id(self.id).exec(&txn).await?;
+        txn.commit().await?;
+        Ok(())
+    }
+
+    /// Ensures that the organization keeps an Owner when this membership
+    /// stops being an Owner one
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` with the given message when this is the last
+    /// Owner of the organization, or DB query error
+    async fn check_not_last_owner<C: ConnectionTrait>(
+        &self,
+        db: &C,
+        message: &str,
+    ) -> ModelResult<()> {
+        if self.role != OWNER_ROLE {
+            return Ok(());
+        }
+        let other_owners = Entity::find()
+            .filter(organization_memberships::Column::OrganizationId.eq(self.organization_id))
+            .filter(organization_memberships::Column::Role.eq(OWNER_ROLE))
+            .filter(organization_memberships::Column::Id.ne(self.id))
+            .count(db)
+            .await?;
+        if other_owners == 0 {
+            return Err(ModelError::msg(message));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,297 @@
+//! Organizations group the teams of a department. Members of an organization
+//! have their role in every team of the organization, on top of the role of
+//! their own membership in a team, and its Owners manage the organization.
+
+use chrono::Utc;
+use loco_rs::prelude::*;
+use sea_orm::{ActiveValue, PaginatorTrait, QueryOrder, TransactionTrait};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+pub use super::_entities::organizations::{self, ActiveModel, Entity, Model};
+use super::_entities::{organization_memberships, teams, users};
+use super::roles::{self, OWNER_ROLE};
+
+/// Sent to create an organization or change its details
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct OrganizationParams {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Validate, Deserialize)]
+pub struct Validator {
+    #[validate(length(min = 2, message = "Name must be at least 2 characters long."))]
+    pub name: String,
+}
+
+impl Validatable for ActiveModel {
+    fn validator(&self) -> Box<dyn Validate> {
+        Box::new(Validator {
+            name: self.name.as_ref().to_owned(),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    async fn before_save<C>(self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        if insert {
+            let mut this = self;
+            this.pid = ActiveValue::Set(Uuid::new_v4());
+            Ok(this)
+        } else if self.updated_at.is_unchanged() {
+            let mut this = self;
+            this.updated_at = ActiveValue::Set(Utc::now().into());
+            Ok(this)
+        } else {
+            Ok(self)
+        }
+    }
+}
+
+/// Trims the description of an organization, an empty one being none
+fn clean_description(description: Option<&str>) -> Option<String> {
+    description
+        .map(str::trim)
+        .filter(|description| !description.is_empty())
+        .map(ToString::to_string)
+}
+
+impl Model {
+    /// Finds an organization by its pid
+    ///
+    /// # Errors
+    ///
+    /// When could not find the organization or DB query error
+    pub async fn find_by_pid(db: &DatabaseConnection, pid: &str) -> ModelResult<Self> {
+        let pid = Uuid::parse_str(pid).map_err(|_| ModelError::EntityNotFound)?;
+        let organization = Entity::find()
+            .filter(organizations::Column::Pid.eq(pid))
+            .one(db)
+            .await?;
+        organization.ok_or_else(|| ModelError::EntityNotFound)
+    }
+
+    /// Returns true when another organization has this name
+    async fn name_taken<C: ConnectionTrait>(
+        db: &C,
+        name: &str,
+        except_id: Option<i32>,
+    ) -> ModelResult<bool> {
+        let mut query = Entity::find().filter(organizations::Column::Name.eq(name));
+        if let Some(id) = except_id {
+            query = query.filter(organizations::Column::Id.ne(id));
+        }
+        Ok(query.one(db).await?.is_some())
+    }
+
+    /// Creates an organization with its creator as the Owner
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the name is taken, validation error, or DB
+    /// query error
+    pub async fn create(
+        db: &DatabaseConnection,
+        user_id: i32,
+        params: &OrganizationParams,
+    ) -> ModelResult<Self> {
+        let name = params.name.trim();
+        let txn = db.begin().await?;
+        if Self::name_taken(&txn, name, None).await? {
+            return Err(ModelError::Message(format!(
+                "Organization name '{name}' already exists."
+            )));
+        }
+
+        let organization = ActiveModel {
+            name: ActiveValue::Set(name.to_string()),
+            description: ActiveValue::Set(clean_description(params.description.as_deref())),
+            ..Default::default()
+        };
+        organization.validate()?;
+        let organization = organization.insert(&txn).await?;
+        organization_memberships::ActiveModel {
+            organization_id: ActiveValue::Set(organization.id),
+            user_id: ActiveValue::Set(user_id),
+            role: ActiveValue::Set(OWNER_ROLE.to_string()),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+        txn.commit().await?;
+
+        tracing::info!(organization = organization.name, "Organization created");
+        Ok(organization)
+    }
+
+    /// Changes the name and description of the organization
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the new name is taken, validation error, or
+    /// DB query error
+    pub async fn update(
+        &self,
+        db: &DatabaseConnection,
+        params: &OrganizationParams,
+    ) -> ModelResult<Self> {
+        let name = params.name.trim();
+        if name != self.name && Self::name_taken(db, name, Some(self.id)).await? {
+            return Err(ModelError::Message(format!(
+                "Organization name '{name}' already exists."
+            )));
+        }
+        let mut organization = self.clone().into_active_model();
+        organization.name = ActiveValue::Set(name.to_string());
+        organization.description =
+            ActiveValue::Set(clean_description(params.description.as_deref()));
+        organization.validate()?;
+        Ok(organization.update(db).await?)
+    }
+
+    /// Deletes the organization and its memberships. Its teams must be
+    /// deleted or moved out of it first.
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the organization still has teams, or DB
+    /// query error
+    pub async fn delete(self, db: &DatabaseConnection) -> ModelResult<()> {
+        let team_count = teams::Entity::find()
+            .filter(teams::Column::OrganizationId.eq(self.id))
+            .count(db)
+            .await?;
+        if team_count > 0 {
+            return Err(ModelError::msg(
+                "This organization still has teams. Delete them or move them out of it first.",
+            ));
+        }
+
+        let txn = db.begin().await?;
+        organization_memberships::Entity::delete_many()
+            .filter(organization_memberships::Column::OrganizationId.eq(self.id))
+            .exec(&txn)
+            .await?;
+        Entity::delete_by_id(self.id).exec(&txn).await?;
+        txn.commit().await?;
+
+        tracing::info!(organization = self.name, "Organization deleted");
+        Ok(())
+    }
+
+    /// Lists the organizations of a user with the role of the user in each,
+    /// ordered by name
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn list_for_user(
+        db: &DatabaseConnection,
+        user_id: i32,
+    ) -> ModelResult<Vec<(Self, String)>> {
+        let memberships = organization_memberships::Entity::find()
+            .filter(organization_memberships::Column::UserId.eq(user_id))
+            .find_also_related(Entity)
+            .all(db)
+            .await?;
+        let mut organizations = memberships
+            .into_iter()
+            .filter_map(|(membership, organization)| {
+                organization.map(|organization| (organization, membership.role))
+            })
+            .collect::<Vec<_>>();
+        organizations.sort_by(|(a, _), (b, _)| a.name.cmp(&b.name));
+        Ok(organizations)
+    }
+
+    /// Finds the membership of a user in the organization
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn membership<C: ConnectionTrait>(
+        &self,
+        db: &C,
+        user_id: i32,
+    ) -> ModelResult<Option<organization_memberships::Model>> {
+        Ok(organization_memberships::Entity::find()
+            .filter(organization_memberships::Column::OrganizationId.eq(self.id))
+            .filter(organization_memberships::Column::UserId.eq(user_id))
+            .one(db)
+            .await?)
+    }
+
+    /// Finds the role a member of the organization has in its teams
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn member_role<C: ConnectionTrait>(
+        &self,
+        db: &C,
+        user_id: i32,
+    ) -> ModelResult<Option<roles::Model>> {
+        let Some(membership) = self.membership(db, user_id).await? else {
+            return Ok(None);
+        };
+        match roles::Model::find_by_name(db, &membership.role).await {
+            Ok(role) => Ok(Some(role)),
+            // A role that no longer exists grants nothing
+            Err(ModelError::EntityNotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns true when the user is an Owner of the organization
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn is_owner<C: ConnectionTrait>(&self, db: &C, user_id: i32) -> ModelResult<bool> {
+        Ok(self
+            .membership(db, user_id)
+            .await?
+            .is_some_and(|membership| membership.role == OWNER_ROLE))
+    }
+
+    /// Lists the members of the organization with their membership, ordered
+    /// by name
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn get_members(
+        &self,
+        db: &DatabaseConnection,
+    ) -> ModelResult<Vec<(users::Model, organization_memberships::Model)>> {
+        let memberships = organization_memberships::Entity::find()
+            .filter(organization_memberships::Column::OrganizationId.eq(self.id))
+            .find_also_related(users::Entity)
+            .order_by_asc(users::Column::Name)
+            .all(db)
+            .await?;
+        Ok(memberships
+            .into_iter()
+            .filter_map(|(membership, user)| user.map(|user| (user, membership)))
+            .collect())
+    }
+
+    /// Lists the teams of the organization, ordered by name
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn teams(&self, db: &DatabaseConnection) -> ModelResult<Vec<teams::Model>> {
+        Ok(teams::Entity::find()
+            .filter(teams::Column::OrganizationId.eq(self.id))
+            .order_by_asc(teams::Column::Name)
+            .all(db)
+            .await?)
+    }
+}
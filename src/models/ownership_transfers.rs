@@ -0,0 +1,214 @@
+use chrono::Utc;
+use loco_rs::prelude::*;
+use sea_orm::{ActiveValue, TransactionTrait};
+use uuid::Uuid;
+
+pub use super::_entities::ownership_transfers::{self, ActiveModel, Entity, Model};
+use super::_entities::{team_memberships, teams, users};
+use super::{
+    roles::OWNER_ROLE,
+    tokens::{self, TokenLifetimes},
+};
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    async fn before_save<C>(self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        if insert {
+            let mut this = self;
+            this.pid = ActiveValue::Set(Uuid::new_v4());
+            Ok(this)
+        } else if self.updated_at.is_unchanged() {
+            let mut this = self;
+            this.updated_at = ActiveValue::Set(Utc::now().into());
+            Ok(this)
+        } else {
+            Ok(self)
+        }
+    }
+}
+
+/// Finds the active membership of a user in a team
+async fn find_membership<C: ConnectionTrait>(
+    db: &C,
+    team_id: i32,
+    user_id: i32,
+) -> ModelResult<Option<team_memberships::Model>> {
+    Ok(team_memberships::Entity::find()
+        .filter(team_memberships::Column::TeamId.eq(team_id))
+        .filter(team_memberships::Column::UserId.eq(user_id))
+        .filter(team_memberships::Column::Pending.eq(false))
+        .one(db)
+        .await?)
+}
+
+impl Model {
+    /// Offers the ownership of a team to another member. The roles of the two
+    /// members swap once the receiving member accepts; until then, nothing
+    /// changes. A team has at most one transfer waiting, so a new offer
+    /// replaces the previous one.
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the offering user is not an Owner or the
+    /// receiving user cannot become one, or DB query error
+    pub async fn create(
+        db: &DatabaseConnection,
+        team: &teams::Model,
+        from: &users::Model,
+        to: &users::Model,
+        lifetimes: &TokenLifetimes,
+    ) -> ModelResult<Self> {
+        if from.id == to.id {
+            return Err(ModelError::msg(
+                "You cannot transfer the ownership to yourself",
+            ));
+        }
+        match find_membership(db, team.id, from.id).await? {
+            Some(membership) if membership.role == OWNER_ROLE => {}
+            _ => {
+                return Err(ModelError::msg(
+                    "Only team owners can transfer the ownership",
+                ));
+            }
+        }
+        if to.is_service_account() {
+            return Err(ModelError::msg(
+                "The ownership cannot be transferred to a service account",
+            ));
+        }
+        match find_membership(db, team.id, to.id).await? {
+            None => {
+                return Err(ModelError::msg("User is not a member of this team"));
+            }
+            Some(membership) if membership.role == OWNER_ROLE => {
+                return Err(ModelError::msg("This member is already an Owner"));
+            }
+            Some(_) => {}
+        }
+
+        let txn = db.begin().await?;
+        Entity::delete_many()
+            .filter(ownership_transfers::Column::TeamId.eq(team.id))
+            .exec(&txn)
+            .await?;
+        let transfer = ActiveModel {
+            team_id: ActiveValue::Set(team.id),
+            from_user_id: ActiveValue::Set(from.id),
+            to_user_id: ActiveValue::Set(to.id),
+            expires_at: ActiveValue::Set(tokens::expires_at(lifetimes.team_invitation_min)),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+        txn.commit().await?;
+
+        tracing::info!(
+            team_pid = %team.pid,
+            from_user_pid = %from.pid,
+            to_user_pid = %to.pid,
+            "Ownership transfer offered"
+        );
+        Ok(transfer)
+    }
+
+    /// Finds the transfer waiting for an answer in a team, if any
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn find_pending_for_team(
+        db: &DatabaseConnection,
+        team_id: i32,
+    ) -> ModelResult<Option<Self>> {
+        let transfer = Entity::find()
+            .filter(ownership_transfers::Column::TeamId.eq(team_id))
+            .one(db)
+            .await?;
+        Ok(transfer.filter(|transfer| !transfer.is_expired()))
+    }
+
+    /// Returns true when the transfer can no longer be accepted
+    #[must_use]
+    pub fn is_expired(&self) -> bool {
+        tokens::check_not_expired(Some(self.expires_at)).is_err()
+    }
+
+    /// Accepts the transfer: the receiving member becomes an Owner and the
+    /// offering member takes their previous role
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the user is not the receiving member, the
+    /// transfer has expired or the memberships changed since it was offered,
+    /// or DB query error
+    pub async fn accept(self, db: &DatabaseConnection, user: &users::Model) -> ModelResult<()> {
+        if user.id != self.to_user_id {
+            return Err(ModelError::msg(
+                "This ownership transfer is not addressed to you",
+            ));
+        }
+        if self.is_expired() {
+            return Err(ModelError::msg("This ownership transfer has expired"));
+        }
+
+        let txn = db.begin().await?;
+        let from_membership = find_membership(&txn, self.team_id, self.from_user_id).await?;
+        let to_membership = find_membership(&txn, self.team_id, self.to_user_id).await?;
+        let (Some(from_membership), Some(to_membership)) = (from_membership, to_membership) else {
+            return Err(ModelError::msg(
+                "This ownership transfer is no longer valid because a member left the team",
+            ));
+        };
+        if from_membership.role != OWNER_ROLE {
+            return Err(ModelError::msg(
+                "This ownership transfer is no longer valid because its sender is no longer an Owner",
+            ));
+        }
+
+        let previous_role = to_membership.role.clone();
+        let mut to_membership = to_membership.into_active_model();
+        to_membership.role = ActiveValue::Set(OWNER_ROLE.to_string());
+        to_membership.update(&txn).await?;
+        let mut from_membership = from_membership.into_active_model();
+        from_membership.role = ActiveValue::Set(previous_role);
+        from_membership.update(&txn).await?;
+        Entity::delete_by_id(self.id).exec(&txn).await?;
+        txn.commit().await?;
+
+        tracing::info!(
+            transfer_pid = %self.pid,
+            team_id = self.team_id,
+            "Ownership transfer accepted"
+        );
+        Ok(())
+    }
+
+    /// Drops the transfer, when the receiving member declines it or an Owner
+    /// cancels it
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the user may not drop the transfer, or DB
+    /// query error
+    pub async fn decline(self, db: &DatabaseConnection, user: &users::Model) -> ModelResult<()> {
+        let may_drop = user.id == self.to_user_id
+            || find_membership(db, self.team_id, user.id)
+                .await?
+                .is_some_and(|membership| membership.role == OWNER_ROLE);
+        if !may_drop {
+            return Err(ModelError::msg(
+                "Only the receiving member or a team owner can drop this ownership transfer",
+            ));
+        }
+        Entity::delete_by_id(self.id).exec(db).await?;
+        tracing::info!(
+            transfer_pid = %self.pid,
+            dropped_by = %user.pid,
+            "Ownership transfer dropped"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,357 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+};
+
+use loco_rs::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+/// Labels of the strength scores, from 0 (too guessable) to 4 (very unguessable)
+pub const STRENGTH_LABELS: [&str; 5] = ["Very weak", "Weak", "Fair", "Strong", "Very strong"];
+
+/// Passwords and words that are among the first ones tried by attackers
+const COMMON_WORDS: &[&str] = &[
+    "password",
+    "passw0rd",
+    "p@ssword",
+    "letmein",
+    "welcome",
+    "admin",
+    "administrator",
+    "login",
+    "qwerty",
+    "azerty",
+    "iloveyou",
+    "monkey",
+    "dragon",
+    "master",
+    "shadow",
+    "sunshine",
+    "princess",
+    "football",
+    "baseball",
+    "soccer",
+    "hockey",
+    "superman",
+    "batman",
+    "starwars",
+    "trustno1",
+    "whatever",
+    "freedom",
+    "secret",
+    "hello",
+    "computer",
+    "internet",
+    "michael",
+    "jordan",
+    "charlie",
+    "summer",
+    "winter",
+    "spring",
+    "autumn",
+    "flower",
+    "cookie",
+    "cheese",
+    "access",
+    "changeme",
+    "default",
+    "root",
+    "test",
+    "guest",
+    "user",
+    "abc123",
+    "hosting",
+    "farm",
+    "server",
+];
+
+/// Keyboard rows: consecutive keys are as easy to guess as alphabetical runs
+const KEYBOARD_ROWS: &[&str] = &[
+    "1234567890",
+    "qwertyuiop",
+    "asdfghjkl",
+    "zxcvbnm",
+    "azertyuiop",
+    "qsdfghjklm",
+    "wxcvbn",
+];
+
+/// Password rules read from `settings.app.password_policy`.
+///
+/// Every key is optional; when the block is missing only the minimum length
+/// is enforced.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PasswordPolicy {
+    /// Minimum number of characters
+    pub min_length: usize,
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    /// Minimum strength score, from 0 (no check) to 4
+    pub min_strength: u8,
+    /// Path of a file of breached password SHA-1 hashes, one per line in
+    /// hexadecimal, optionally followed by `:count` (Have I Been Pwned format)
+    pub breached_hashes_file: Option<String>,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: 8,
+            require_lowercase: false,
+            require_uppercase: false,
+            require_digit: false,
+            require_symbol: false,
+            min_strength: 0,
+            breached_hashes_file: None,
+        }
+    }
+}
+
+/// Estimated strength of a password, shown next to password fields
+#[derive(Debug, Clone, Serialize)]
+pub struct PasswordStrength {
+    /// Score from 0 to 4, with the same meaning as the zxcvbn scores
+    pub score: u8,
+    pub label: &'static str,
+}
+
+/// Returns true when `next` follows `previous` in an alphabetical or numeric
+/// run, or on a keyboard row, in either direction
+fn is_sequential(previous: char, next: char) -> bool {
+    let (previous, next) = (previous.to_ascii_lowercase(), next.to_ascii_lowercase());
+    if previous.is_ascii_alphanumeric() && next.is_ascii_alphanumeric() {
+        let distance = i32::from(next as u8) - i32::from(previous as u8);
+        if distance.abs() == 1 {
+            return true;
+        }
+    }
+    KEYBOARD_ROWS.iter().any(|row| {
+        let pair: String = [previous, next].iter().collect();
+        let reversed: String = [next, previous].iter().collect();
+        row.contains(&pair) || row.contains(&reversed)
+    })
+}
+
+/// Size of the alphabet an attacker has to try for the given password
+fn charset_size(password: &str) -> f64 {
+    let mut size = 0.0;
+    if password.chars().any(|c| c.is_ascii_lowercase()) {
+        size += 26.0;
+    }
+    if password.chars().any(|c| c.is_ascii_uppercase()) {
+        size += 26.0;
+    }
+    if password.chars().any(|c| c.is_ascii_digit()) {
+        size += 10.0;
+    }
+    if password
+        .chars()
+        .any(|c| c.is_ascii_punctuation() || c == ' ')
+    {
+        size += 33.0;
+    }
+    if !password.is_ascii() {
+        size += 100.0;
+    }
+    size
+}
+
+/// Estimates how strong a password is, in the spirit of zxcvbn: the number
+/// of guesses needed to find it is estimated from its alphabet, discounting
+/// repeated characters, sequences, keyboard runs, common passwords and the
+/// user's own name or email, then mapped to a score from 0 to 4.
+#[must_use]
+pub fn estimate_strength(password: &str, user_inputs: &[&str]) -> PasswordStrength {
+    let user_words = user_inputs.iter().flat_map(|input| {
+        input
+            .to_lowercase()
+            .split(['@', '.', ' ', '-', '_'])
+            .filter(|word| word.chars().count() >= 3)
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+    });
+    let words: Vec<String> = COMMON_WORDS
+        .iter()
+        .map(ToString::to_string)
+        .chain(user_words)
+        .collect();
+    #[allow(clippy::cast_precision_loss)]
+    let word_bits = (words.len() as f64).log2() + 1.0;
+
+    // Dictionary words only cost as many guesses as the size of the
+    // dictionary; they are replaced by a marker splitting the remaining runs
+    let mut remaining = password.to_lowercase();
+    let mut bits = 0.0;
+    for word in &words {
+        while let Some(index) = remaining.find(word.as_str()) {
+            remaining.replace_range(index..index + word.len(), "\0");
+            bits += word_bits;
+        }
+    }
+
+    let charset_bits = charset_size(password).log2();
+    let mut previous: Option<char> = None;
+    for c in remaining.chars() {
+        if c == '\0' {
+            previous = None;
+            continue;
+        }
+        bits += match previous {
+            Some(p) if p == c || is_sequential(p, c) => 1.0,
+            _ => charset_bits,
+        };
+        previous = Some(c);
+    }
+
+    let guesses_log10 = bits * std::f64::consts::LOG10_2;
+    let score = match guesses_log10 {
+        g if g < 3.0 => 0,
+        g if g < 6.0 => 1,
+        g if g < 8.0 => 2,
+        g if g < 10.0 => 3,
+        _ => 4,
+    };
+    PasswordStrength {
+        score,
+        label: STRENGTH_LABELS[usize::from(score)],
+    }
+}
+
+/// Hashes a password the way breached password lists store it: SHA-1 in
+/// uppercase hexadecimal
+fn breached_hash(password: &str) -> String {
+    Sha1::digest(password.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect()
+}
+
+impl PasswordPolicy {
+    /// Reads the policy from `settings.app.password_policy`, falling back to
+    /// the defaults when the block is missing
+    ///
+    /// # Errors
+    ///
+    /// When the configured policy is invalid
+    pub fn from_context(ctx: &AppContext) -> ModelResult<Self> {
+        let settings = ctx
+            .config
+            .settings
+            .as_ref()
+            .and_then(|settings| settings.get("app"))
+            .and_then(|app_settings| app_settings.get("password_policy"));
+        match settings {
+            Some(policy) => serde_json::from_value(policy.clone()).map_err(|e| {
+                ModelError::Message(format!("Invalid password policy configuration: {e}"))
+            }),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Lists the rules of the policy a password breaks, as messages that can
+    /// be shown to the user. The breached password list is not checked here.
+    ///
+    /// `user_inputs` are the name and email of the user, which make a
+    /// password weaker when it contains them.
+    #[must_use]
+    pub fn violations(&self, password: &str, user_inputs: &[&str]) -> Vec<String> {
+        let mut violations = Vec::new();
+        if password.chars().count() < self.min_length {
+            violations.push(format!(
+                "Password must be at least {} characters long.",
+                self.min_length
+            ));
+        }
+        let classes = [
+            (
+                self.require_lowercase,
+                password.chars().any(|c| c.is_lowercase()),
+                "a lowercase letter",
+            ),
+            (
+                self.require_uppercase,
+                password.chars().any(|c| c.is_uppercase()),
+                "an uppercase letter",
+            ),
+            (
+                self.require_digit,
+                password.chars().any(|c| c.is_ascii_digit()),
+                "a digit",
+            ),
+            (
+                self.require_symbol,
+                password.chars().any(|c| !c.is_alphanumeric()),
+                "a symbol",
+            ),
+        ];
+        for (required, present, class) in classes {
+            if required && !present {
+                violations.push(format!("Password must contain {class}."));
+            }
+        }
+        if self.min_strength > 0 {
+            let strength = estimate_strength(password, user_inputs);
+            if strength.score < self.min_strength {
+                violations.push(format!(
+                    "Password is too easy to guess ({}). Use a longer password, avoiding common words, sequences and your name or email.",
+                    strength.label.to_lowercase()
+                ));
+            }
+        }
+        violations
+    }
+
+    /// Returns true when the password appears in the configured breached
+    /// password list
+    ///
+    /// # Errors
+    ///
+    /// When the list could not be read
+    pub async fn is_breached(&self, password: &str) -> ModelResult<bool> {
+        let Some(path) = self.breached_hashes_file.clone() else {
+            return Ok(false);
+        };
+        let hash = breached_hash(password);
+        // The list can hold hundreds of millions of lines, scan it off the
+        // async runtime
+        tokio::task::spawn_blocking(move || -> std::io::Result<bool> {
+            for line in BufReader::new(File::open(path)?).lines() {
+                let line = line?;
+                let candidate = line.split(':').next().unwrap_or_default().trim();
+                if candidate.eq_ignore_ascii_case(&hash) {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        })
+        .await
+        .map_err(|e| ModelError::Any(e.into()))?
+        .map_err(|e| ModelError::Any(e.into()))
+    }
+
+    /// Checks a password against every rule of the policy, including the
+    /// breached password list.
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` with the reasons the password is rejected, or
+    /// when the breached password list could not be read
+    pub async fn check(&self, password: &str, user_inputs: &[&str]) -> ModelResult<()> {
+        let mut violations = self.violations(password, user_inputs);
+        if violations.is_empty() && self.is_breached(password).await? {
+            violations.push(
+                "This password appeared in a data breach and must not be used. Please choose another one."
+                    .to_string(),
+            );
+        }
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(ModelError::Message(violations.join(" ")))
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use loco_rs::prelude::*;
+use sea_orm::QueryOrder;
+
+pub use super::_entities::permissions::{self, ActiveModel, Entity, Model};
+
+/// View the team, its members and its service accounts
+pub const TEAM_VIEW: &str = "team.view";
+/// Change the name and description of the team
+pub const TEAM_UPDATE: &str = "team.update";
+/// Delete the team
+pub const TEAM_DELETE: &str = "team.delete";
+/// Invite users to the team, resend and cancel invitations, approve or deny
+/// requests to join, and manage join links
+pub const TEAM_INVITE: &str = "team.invite";
+/// Remove the members whose role grants fewer permissions
+pub const MEMBERS_REMOVE: &str = "members.remove";
+/// Change the role of members and remove any member
+pub const MEMBERS_MANAGE: &str = "members.manage";
+/// Create, change and delete the service accounts of the team
+pub const SERVICE_ACCOUNTS_MANAGE: &str = "service_accounts.manage";
+
+/// The catalogue only changes through migrations
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Model {
+    /// Lists the catalogue of permissions, by name
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn list(db: &DatabaseConnection) -> ModelResult<Vec<Self>> {
+        Ok(Entity::find()
+            .order_by_asc(permissions::Column::Name)
+            .all(db)
+            .await?)
+    }
+}
@@ -0,0 +1,281 @@
+use chrono::{Duration, Utc};
+use loco_rs::{hash, prelude::*};
+use sea_orm::{ActiveValue, QueryOrder};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub use super::_entities::personal_access_tokens::{self, ActiveModel, Entity, Model};
+use super::{tokens, users};
+
+/// Prefix of personal access tokens, telling them apart from session JWTs
+pub const TOKEN_PREFIX: &str = "hfpat_";
+/// Number of random characters following the prefix
+const TOKEN_SECRET_LENGTH: usize = 40;
+/// Number of characters of the token kept in clear, to recognize it in lists
+const TOKEN_DISPLAY_LENGTH: usize = TOKEN_PREFIX.len() + 4;
+/// Maximum length of the name given to a token
+pub const TOKEN_NAME_MAX_LENGTH: usize = 64;
+/// Longest lifetime, in days, a token can be created with
+pub const TOKEN_MAX_LIFETIME_DAYS: i64 = 3650;
+
+pub const SCOPE_TEAMS_READ: &str = "teams:read";
+pub const SCOPE_TEAMS_WRITE: &str = "teams:write";
+pub const SCOPE_SSH_KEYS_READ: &str = "ssh_keys:read";
+pub const SCOPE_SSH_KEYS_WRITE: &str = "ssh_keys:write";
+
+/// Scopes a token can be granted, with their description. A `write` scope
+/// also grants the matching `read` scope.
+pub const SCOPES: [(&str, &str); 4] = [
+    (
+        SCOPE_TEAMS_READ,
+        "List your teams, their members and your invitations",
+    ),
+    (
+        SCOPE_TEAMS_WRITE,
+        "Create, update and delete teams and manage their members",
+    ),
+    (SCOPE_SSH_KEYS_READ, "List your SSH keys"),
+    (SCOPE_SSH_KEYS_WRITE, "Add and remove your SSH keys"),
+];
+
+/// Sent by the user to create a token
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateTokenParams {
+    pub name: String,
+    pub scopes: Vec<String>,
+    /// Number of days the token is valid for, `None` for a token that does
+    /// not expire
+    pub expires_in_days: Option<i64>,
+}
+
+impl CreateTokenParams {
+    /// Reads the parameters from the fields of an HTML form, which repeats
+    /// the `scopes` field for each selected scope and sends an empty
+    /// `expires_in_days` for a token that does not expire
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the lifetime is not a number
+    pub fn from_form_fields(fields: Vec<(String, String)>) -> ModelResult<Self> {
+        let mut params = Self {
+            name: String::new(),
+            scopes: Vec::new(),
+            expires_in_days: None,
+        };
+        for (key, value) in fields {
+            match key.as_str() {
+                "name" => params.name = value,
+                "scopes" => params.scopes.push(value),
+                "expires_in_days" if !value.is_empty() => {
+                    params.expires_in_days = Some(
+                        value
+                            .parse()
+                            .map_err(|_| ModelError::msg("Invalid token lifetime."))?,
+                    );
+                }
+                _ => {}
+            }
+        }
+        Ok(params)
+    }
+}
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    async fn before_save<C>(self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        if insert {
+            let mut this = self;
+            this.pid = ActiveValue::Set(Uuid::new_v4());
+            Ok(this)
+        } else if self.updated_at.is_unchanged() {
+            let mut this = self;
+            this.updated_at = ActiveValue::Set(Utc::now().into());
+            Ok(this)
+        } else {
+            Ok(self)
+        }
+    }
+}
+
+impl Model {
+    /// Lists the tokens of a user, newest first
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn find_for_user(db: &DatabaseConnection, user_id: i32) -> ModelResult<Vec<Self>> {
+        let tokens = Entity::find()
+            .filter(personal_access_tokens::Column::UserId.eq(user_id))
+            .order_by_desc(personal_access_tokens::Column::CreatedAt)
+            .all(db)
+            .await?;
+        Ok(tokens)
+    }
+
+    /// Finds a token by its pid, making sure it belongs to the given user
+    ///
+    /// # Errors
+    ///
+    /// When could not find token or DB query error
+    pub async fn find_by_pid_for_user(
+        db: &DatabaseConnection,
+        pid: &str,
+        user_id: i32,
+    ) -> ModelResult<Self> {
+        let pid = Uuid::parse_str(pid).map_err(|_| ModelError::EntityNotFound)?;
+        let token = Entity::find()
+            .filter(personal_access_tokens::Column::Pid.eq(pid))
+            .filter(personal_access_tokens::Column::UserId.eq(user_id))
+            .one(db)
+            .await?;
+        token.ok_or_else(|| ModelError::EntityNotFound)
+    }
+
+    /// Creates a token for a user. Only the hash of the token is stored; the
+    /// plaintext token, to be shown once to the user, is returned along with
+    /// the token.
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the name, scopes or lifetime are invalid,
+    /// or DB query error
+    pub async fn create_for_user(
+        db: &DatabaseConnection,
+        user_id: i32,
+        params: &CreateTokenParams,
+    ) -> ModelResult<(Self, String)> {
+        let name = params.name.trim();
+        if name.is_empty() || name.chars().count() > TOKEN_NAME_MAX_LENGTH {
+            return Err(ModelError::Message(format!(
+                "Token name must be between 1 and {TOKEN_NAME_MAX_LENGTH} characters long."
+            )));
+        }
+        if params.scopes.is_empty() {
+            return Err(ModelError::msg("Select at least one scope."));
+        }
+        if let Some(scope) = params
+            .scopes
+            .iter()
+            .find(|scope| !SCOPES.iter().any(|(known, _)| known == scope))
+        {
+            return Err(ModelError::Message(format!("Unknown scope: {scope}")));
+        }
+        let expires_at = match params.expires_in_days {
+            Some(days) if !(1..=TOKEN_MAX_LIFETIME_DAYS).contains(&days) => {
+                return Err(ModelError::Message(format!(
+                    "Token lifetime must be between 1 and {TOKEN_MAX_LIFETIME_DAYS} days."
+                )));
+            }
+            Some(days) => Some(
+                Utc::now()
+                    .checked_add_signed(Duration::days(days))
+                    .ok_or_else(|| ModelError::msg("Token lifetime is too long."))?
+                    .into(),
+            ),
+            None => None,
+        };
+
+        let token = format!("{TOKEN_PREFIX}{}", hash::random_string(TOKEN_SECRET_LENGTH));
+        let mut scopes = params.scopes.clone();
+        scopes.sort();
+        scopes.dedup();
+
+        let model = ActiveModel {
+            name: ActiveValue::Set(name.to_string()),
+            token_hash: ActiveValue::Set(tokens::hash_token(&token)),
+            token_prefix: ActiveValue::Set(token[..TOKEN_DISPLAY_LENGTH].to_string()),
+            scopes: ActiveValue::Set(scopes.join(" ")),
+            expires_at: ActiveValue::Set(expires_at),
+            user_id: ActiveValue::Set(user_id),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+        Ok((model, token))
+    }
+
+    /// Finds the token and the user behind a plaintext token sent with a
+    /// request, and records that the token was used.
+    ///
+    /// # Errors
+    ///
+    /// When the token is unknown, revoked or expired, when its user may not
+    /// log in, or DB query error
+    pub async fn authenticate(
+        db: &DatabaseConnection,
+        token: &str,
+    ) -> ModelResult<(Self, users::Model)> {
+        if !token.starts_with(TOKEN_PREFIX) {
+            return Err(ModelError::EntityNotFound);
+        }
+        let found = Entity::find()
+            .filter(personal_access_tokens::Column::TokenHash.eq(tokens::hash_token(token)))
+            .one(db)
+            .await?
+            .ok_or_else(|| ModelError::EntityNotFound)?;
+        if !found.is_usable() {
+            return Err(ModelError::msg(
+                "Personal access token is revoked or expired",
+            ));
+        }
+        let user = users::Entity::find_by_id(found.user_id)
+            .one(db)
+            .await?
+            .ok_or_else(|| ModelError::EntityNotFound)?;
+        if !user.can_use_api() {
+            return Err(ModelError::msg("Account is not active"));
+        }
+
+        let mut used = found.into_active_model();
+        used.last_used_at = ActiveValue::Set(Some(Utc::now().into()));
+        Ok((used.update(db).await?, user))
+    }
+
+    /// Revokes the token, which can no longer be used
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn revoke(self, db: &DatabaseConnection) -> ModelResult<Self> {
+        if self.revoked_at.is_some() {
+            return Ok(self);
+        }
+        let mut token = self.into_active_model();
+        token.revoked_at = ActiveValue::Set(Some(Utc::now().into()));
+        Ok(token.update(db).await?)
+    }
+
+    /// Returns true when the token has expired
+    #[must_use]
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= Utc::now())
+    }
+
+    /// Returns true when the token can be used to authenticate
+    #[must_use]
+    pub fn is_usable(&self) -> bool {
+        self.revoked_at.is_none() && !self.is_expired()
+    }
+
+    /// Lists the scopes granted to the token
+    #[must_use]
+    pub fn scope_list(&self) -> Vec<&str> {
+        self.scopes.split_whitespace().collect()
+    }
+
+    /// Returns true when the token grants the scope, directly or through the
+    /// matching `write` scope for a `read` scope
+    #[must_use]
+    pub fn has_scope(&self, scope: &str) -> bool {
+        let implied = scope
+            .strip_suffix(":read")
+            .map(|resource| format!("{resource}:write"));
+        self.scope_list()
+            .iter()
+            .any(|granted| *granted == scope || implied.as_deref() == Some(*granted))
+    }
+}
@@ -0,0 +1,133 @@
+use chrono::Utc;
+use loco_rs::{hash, prelude::*};
+use sea_orm::{ActiveValue, PaginatorTrait, TransactionTrait};
+
+pub use super::_entities::recovery_codes::{self, ActiveModel, Entity, Model};
+
+/// Number of recovery codes generated when two-factor authentication is enabled
+pub const RECOVERY_CODE_COUNT: usize = 10;
+/// Number of characters in a recovery code, excluding the separator
+pub const RECOVERY_CODE_LENGTH: usize = 10;
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    async fn before_save<C>(self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        if !insert && self.updated_at.is_unchanged() {
+            let mut this = self;
+            this.updated_at = ActiveValue::Set(Utc::now().into());
+            Ok(this)
+        } else {
+            Ok(self)
+        }
+    }
+}
+
+/// Normalizes a recovery code typed by a user: separators, whitespace and
+/// case are ignored
+fn normalize_code(code: &str) -> String {
+    code.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+impl Model {
+    /// Replaces the recovery codes of a user with a new set.
+    ///
+    /// Only the hashes are stored; the plain codes are returned so they can
+    /// be shown to the user once.
+    ///
+    /// # Errors
+    ///
+    /// When DB query error or could not hash a code
+    pub async fn regenerate_for_user(
+        db: &DatabaseConnection,
+        user_id: i32,
+    ) -> ModelResult<Vec<String>> {
+        let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+            .map(|_| {
+                let code = hash::random_string(RECOVERY_CODE_LENGTH).to_ascii_lowercase();
+                let (left, right) = code.split_at(RECOVERY_CODE_LENGTH / 2);
+                format!("{left}-{right}")
+            })
+            .collect();
+
+        let txn = db.begin().await?;
+        Entity::delete_many()
+            .filter(recovery_codes::Column::UserId.eq(user_id))
+            .exec(&txn)
+            .await?;
+        for code in &codes {
+            ActiveModel {
+                user_id: ActiveValue::Set(user_id),
+                code_hash: ActiveValue::Set(
+                    hash::hash_password(&normalize_code(code))
+                        .map_err(|e| ModelError::Any(e.into()))?,
+                ),
+                ..Default::default()
+            }
+            .insert(&txn)
+            .await?;
+        }
+        txn.commit().await?;
+
+        Ok(codes)
+    }
+
+    /// Marks the matching unused recovery code of the user as used.
+    ///
+    /// Returns `false` when the code does not match any unused recovery code.
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn consume(db: &DatabaseConnection, user_id: i32, code: &str) -> ModelResult<bool> {
+        let code = normalize_code(code);
+        if code.len() != RECOVERY_CODE_LENGTH {
+            return Ok(false);
+        }
+        let unused = Entity::find()
+            .filter(recovery_codes::Column::UserId.eq(user_id))
+            .filter(recovery_codes::Column::UsedAt.is_null())
+            .all(db)
+            .await?;
+        for recovery_code in unused {
+            if hash::verify_password(&code, &recovery_code.code_hash) {
+                let mut recovery_code = recovery_code.into_active_model();
+                recovery_code.used_at = ActiveValue::Set(Some(Utc::now().into()));
+                recovery_code.update(db).await?;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Counts the recovery codes of a user that have not been used yet
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn count_unused(db: &DatabaseConnection, user_id: i32) -> ModelResult<u64> {
+        Ok(Entity::find()
+            .filter(recovery_codes::Column::UserId.eq(user_id))
+            .filter(recovery_codes::Column::UsedAt.is_null())
+            .count(db)
+            .await?)
+    }
+
+    /// Deletes every recovery code of a user
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn delete_for_user(db: &DatabaseConnection, user_id: i32) -> ModelResult<()> {
+        Entity::delete_many()
+            .filter(recovery_codes::Column::UserId.eq(user_id))
+            .exec(db)
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,134 @@
+use loco_rs::prelude::*;
+use sea_orm::{ActiveValue, PaginatorTrait};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    team_memberships,
+    users::{self, email_domain},
+};
+
+/// Who may create an account
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegistrationMode {
+    /// Anyone may register
+    #[default]
+    Open,
+    /// Only email addresses with a pending team invitation may register
+    InviteOnly,
+    /// Only email addresses from the allowed domains may register
+    Domains,
+    /// Anyone may register, but the account cannot log in until an
+    /// administrator approves it
+    Approval,
+}
+
+/// Registration rules read from `settings.app.registration`.
+///
+/// When the block is missing, registrations are open to anyone.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RegistrationPolicy {
+    pub mode: RegistrationMode,
+    /// Email domains allowed to register and to request magic links in
+    /// `domains` mode, e.g. `example.com`
+    pub allowed_domains: Vec<String>,
+}
+
+impl RegistrationPolicy {
+    /// Reads the policy from `settings.app.registration`, falling back to
+    /// open registrations when the block is missing
+    ///
+    /// # Errors
+    ///
+    /// When the configured policy is invalid
+    pub fn from_context(ctx: &AppContext) -> ModelResult<Self> {
+        let settings = ctx
+            .config
+            .settings
+            .as_ref()
+            .and_then(|settings| settings.get("app"))
+            .and_then(|app_settings| app_settings.get("registration"));
+        match settings {
+            Some(policy) => serde_json::from_value(policy.clone()).map_err(|e| {
+                ModelError::Message(format!("Invalid registration configuration: {e}"))
+            }),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Returns true when the domain of the email address is allowed. Every
+    /// domain is allowed unless the policy is in `domains` mode.
+    #[must_use]
+    pub fn allows_domain(&self, email: &str) -> bool {
+        if self.mode != RegistrationMode::Domains {
+            return true;
+        }
+        email_domain(email).is_some_and(|domain| {
+            self.allowed_domains.iter().any(|allowed| {
+                allowed
+                    .trim()
+                    .trim_start_matches('@')
+                    .eq_ignore_ascii_case(&domain)
+            })
+        })
+    }
+
+    /// Returns true when new accounts must be approved by an administrator
+    /// before they can log in
+    #[must_use]
+    pub fn requires_approval(&self) -> bool {
+        self.mode == RegistrationMode::Approval
+    }
+
+    /// Checks that an account may be registered with this email address.
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` with the reason the registration is refused, or
+    /// DB query error
+    pub async fn check(&self, db: &DatabaseConnection, email: &str) -> ModelResult<()> {
+        match self.mode {
+            RegistrationMode::Open | RegistrationMode::Approval => Ok(()),
+            RegistrationMode::Domains => {
+                if self.allows_domain(email) {
+                    Ok(())
+                } else {
+                    Err(ModelError::msg(
+                        "Registrations are restricted to approved email domains.",
+                    ))
+                }
+            }
+            RegistrationMode::InviteOnly => {
+                if team_memberships::Model::has_pending_invitation_for_email(db, email).await? {
+                    Ok(())
+                } else {
+                    Err(ModelError::msg(
+                        "Registrations are by invitation only. Ask a team administrator to invite you.",
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Applies the policy to an account that was just registered: in
+    /// `approval` mode the account is left pending until an administrator
+    /// approves it. The very first account, which becomes the application
+    /// administrator, is always active.
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn admit(
+        &self,
+        db: &DatabaseConnection,
+        user: users::Model,
+    ) -> ModelResult<users::Model> {
+        if !self.requires_approval() || users::Entity::find().count(db).await? <= 1 {
+            return Ok(user);
+        }
+        let mut user = user.into_active_model();
+        user.status = ActiveValue::Set(users::STATUS_PENDING_APPROVAL.to_string());
+        Ok(user.update(db).await?)
+    }
+}
@@ -0,0 +1,4 @@
+pub use super::_entities::role_permissions::{self, ActiveModel, Entity, Model};
+use sea_orm::entity::prelude::*;
+
+impl ActiveModelBehavior for ActiveModel {}
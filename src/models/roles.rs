@@ -0,0 +1,386 @@
+//! Roles of team members and the permissions they grant. Memberships refer
+//! to roles by name. The built-in roles are seeded by their migration, and
+//! application administrators can define custom ones.
+
+use std::collections::BTreeSet;
+
+use chrono::Utc;
+use loco_rs::prelude::*;
+use sea_orm::{ActiveValue, PaginatorTrait, QueryOrder, TransactionTrait};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub use super::_entities::roles::{self, ActiveModel, Entity, Model};
+use super::_entities::{
+    organization_memberships, permissions, role_permissions, team_join_links, team_memberships,
+};
+
+/// Role with every permission, which the ownership of a team is about: a
+/// team always keeps at least one member with this role
+pub const OWNER_ROLE: &str = "Owner";
+/// Name shown instead of a role for the pending invitations of a team
+const RESERVED_ROLE_NAMES: [&str; 1] = ["Invited"];
+/// Maximum length of the name of a role
+pub const ROLE_NAME_MAX_LENGTH: usize = 32;
+
+/// Sent by an application administrator to define or change a role
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct RoleParams {
+    /// Ignored when changing a role, which cannot be renamed
+    pub name: String,
+    pub description: Option<String>,
+    pub permissions: Vec<String>,
+}
+
+impl RoleParams {
+    /// Reads the parameters from the fields of an HTML form, which repeats
+    /// the `permissions` field for each selected permission
+    #[must_use]
+    pub fn from_form_fields(fields: Vec<(String, String)>) -> Self {
+        let mut params = Self::default();
+        for (key, value) in fields {
+            match key.as_str() {
+                "name" => params.name = value,
+                "description" => params.description = Some(value),
+                "permissions" => params.permissions.push(value),
+                _ => {}
+            }
+        }
+        params
+    }
+}
+
+/// A role with the names of the permissions it grants
+#[derive(Debug, Serialize)]
+pub struct RoleDetails {
+    #[serde(flatten)]
+    pub role: Model,
+    pub permissions: BTreeSet<String>,
+    /// Number of team memberships with this role
+    pub member_count: u64,
+}
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    async fn before_save<C>(self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        if insert {
+            let mut this = self;
+            this.pid = ActiveValue::Set(Uuid::new_v4());
+            Ok(this)
+        } else if self.updated_at.is_unchanged() {
+            let mut this = self;
+            this.updated_at = ActiveValue::Set(Utc::now().into());
+            Ok(this)
+        } else {
+            Ok(self)
+        }
+    }
+}
+
+/// Checks the permission names and returns their IDs
+async fn permission_ids<C: ConnectionTrait>(db: &C, names: &[String]) -> ModelResult<Vec<i32>> {
+    let names = names.iter().collect::<BTreeSet<_>>();
+    let permissions = permissions::Entity::find()
+        .filter(permissions::Column::Name.is_in(names.iter().map(|name| name.as_str())))
+        .all(db)
+        .await?;
+    if permissions.len() != names.len() {
+        return Err(ModelError::msg("Unknown permission"));
+    }
+    Ok(permissions
+        .into_iter()
+        .map(|permission| permission.id)
+        .collect())
+}
+
+/// Replaces the permissions granted by a role
+async fn set_permissions<C: ConnectionTrait>(
+    db: &C,
+    role_id: i32,
+    permission_ids: Vec<i32>,
+) -> ModelResult<()> {
+    role_permissions::Entity::delete_many()
+        .filter(role_permissions::Column::RoleId.eq(role_id))
+        .exec(db)
+        .await?;
+    for permission_id in permission_ids {
+        role_permissions::ActiveModel {
+            role_id: ActiveValue::Set(role_id),
+            permission_id: ActiveValue::Set(permission_id),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Trims the description of a role, an empty one being none
+fn clean_description(description: Option<&str>) -> Option<String> {
+    description
+        .map(str::trim)
+        .filter(|description| !description.is_empty())
+        .map(ToString::to_string)
+}
+
+impl Model {
+    /// Finds a role by its name
+    ///
+    /// # Errors
+    ///
+    /// When could not find the role or DB query error
+    pub async fn find_by_name<C: ConnectionTrait>(db: &C, name: &str) -> ModelResult<Self> {
+        let role = Entity::find()
+            .filter(roles::Column::Name.eq(name))
+            .one(db)
+            .await?;
+        role.ok_or_else(|| ModelError::EntityNotFound)
+    }
+
+    /// Finds a role by its name, to be given to a member
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when no role has this name, or DB query error
+    pub async fn find_assignable<C: ConnectionTrait>(db: &C, name: &str) -> ModelResult<Self> {
+        match Self::find_by_name(db, name).await {
+            Err(ModelError::EntityNotFound) => Err(ModelError::msg("Invalid role")),
+            result => result,
+        }
+    }
+
+    /// Finds a role by its pid
+    ///
+    /// # Errors
+    ///
+    /// When could not find the role or DB query error
+    pub async fn find_by_pid(db: &DatabaseConnection, pid: &str) -> ModelResult<Self> {
+        let pid = Uuid::parse_str(pid).map_err(|_| ModelError::EntityNotFound)?;
+        let role = Entity::find()
+            .filter(roles::Column::Pid.eq(pid))
+            .one(db)
+            .await?;
+        role.ok_or_else(|| ModelError::EntityNotFound)
+    }
+
+    /// Lists the roles with their permissions, those granting the most
+    /// permissions first
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn list(db: &DatabaseConnection) -> ModelResult<Vec<RoleDetails>> {
+        let roles = Entity::find()
+            .order_by_asc(roles::Column::Name)
+            .find_with_related(permissions::Entity)
+            .all(db)
+            .await?;
+        let mut details = Vec::with_capacity(roles.len());
+        for (role, permissions) in roles {
+            let member_count = team_memberships::Entity::find()
+                .filter(team_memberships::Column::Role.eq(&role.name))
+                .count(db)
+                .await?;
+            details.push(RoleDetails {
+                role,
+                permissions: permissions
+                    .into_iter()
+                    .map(|permission| permission.name)
+                    .collect(),
+                member_count,
+            });
+        }
+        details.sort_by_key(|details| std::cmp::Reverse(details.permissions.len()));
+        Ok(details)
+    }
+
+    /// Lists the names of the roles, those granting the most permissions
+    /// first
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn names(db: &DatabaseConnection) -> ModelResult<Vec<String>> {
+        Ok(Self::list(db)
+            .await?
+            .into_iter()
+            .map(|details| details.role.name)
+            .collect())
+    }
+
+    /// Returns the names of the permissions granted by the role
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn permissions<C: ConnectionTrait>(&self, db: &C) -> ModelResult<BTreeSet<String>> {
+        Ok(self
+            .find_related(permissions::Entity)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|permission| permission.name)
+            .collect())
+    }
+
+    /// Returns true when the role grants the permission
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn grants<C: ConnectionTrait>(&self, db: &C, permission: &str) -> ModelResult<bool> {
+        Ok(self
+            .find_related(permissions::Entity)
+            .filter(permissions::Column::Name.eq(permission))
+            .one(db)
+            .await?
+            .is_some())
+    }
+
+    /// Defines a custom role
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the name is invalid or taken or a
+    /// permission is unknown, or DB query error
+    pub async fn create(db: &DatabaseConnection, params: &RoleParams) -> ModelResult<Self> {
+        let name = params.name.trim();
+        if name.chars().count() < 2 || name.chars().count() > ROLE_NAME_MAX_LENGTH {
+            return Err(ModelError::Message(format!(
+                "Role name must be between 2 and {ROLE_NAME_MAX_LENGTH} characters long."
+            )));
+        }
+        if RESERVED_ROLE_NAMES
+            .iter()
+            .any(|reserved| reserved.eq_ignore_ascii_case(name))
+            || Self::find_by_name(db, name).await.is_ok()
+        {
+            return Err(ModelError::msg("A role with this name already exists"));
+        }
+
+        let txn = db.begin().await?;
+        let permission_ids = permission_ids(&txn, &params.permissions).await?;
+        let role = ActiveModel {
+            name: ActiveValue::Set(name.to_string()),
+            description: ActiveValue::Set(clean_description(params.description.as_deref())),
+            builtin: ActiveValue::Set(false),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+        set_permissions(&txn, role.id, permission_ids).await?;
+        txn.commit().await?;
+
+        tracing::info!(role = role.name, "Role created");
+        Ok(role)
+    }
+
+    /// Changes the description and permissions of a role. The Owner role
+    /// always grants every permission.
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the role is the Owner one or a permission
+    /// is unknown, or DB query error
+    pub async fn update(self, db: &DatabaseConnection, params: &RoleParams) -> ModelResult<Self> {
+        if self.name == OWNER_ROLE {
+            return Err(ModelError::msg(
+                "The Owner role always grants every permission",
+            ));
+        }
+
+        let txn = db.begin().await?;
+        let permission_ids = permission_ids(&txn, &params.permissions).await?;
+        let mut role = self.into_active_model();
+        role.description = ActiveValue::Set(clean_description(params.description.as_deref()));
+        let role = role.update(&txn).await?;
+        set_permissions(&txn, role.id, permission_ids).await?;
+        txn.commit().await?;
+
+        tracing::info!(
+            role = role.name,
+            permissions = ?params.permissions,
+            "Role permissions changed"
+        );
+        Ok(role)
+    }
+
+    /// Deletes a custom role that is no longer used: neither by a team member
+    /// (service accounts included), an organization member nor a team join
+    /// link
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the role is built in or still used, or DB
+    /// query error
+    pub async fn delete(self, db: &DatabaseConnection) -> ModelResult<()> {
+        if self.builtin {
+            return Err(ModelError::msg("Built-in roles cannot be deleted"));
+        }
+        let member_count = team_memberships::Entity::find()
+            .filter(team_memberships::Column::Role.eq(&self.name))
+            .count(db)
+            .await?;
+        if member_count > 0 {
+            return Err(ModelError::msg(
+                "This role is still given to members. Give them another role first.",
+            ));
+        }
+        let organization_member_count = organization_memberships::Entity::find()
+            .filter(organization_memberships::Column::Role.eq(&self.name))
+            .count(db)
+            .await?;
+        if organization_member_count > 0 {
+            return Err(ModelError::msg(
+                "This role is still given to organization members. Give them another role first.",
+            ));
+        }
+        let join_link_count = team_join_links::Entity::find()
+            .filter(team_join_links::Column::Role.eq(&self.name))
+            .count(db)
+            .await?;
+        if join_link_count > 0 {
+            return Err(ModelError::msg(
+                "This role is still given by team join links. Revoke them first.",
+            ));
+        }
+
+        let txn = db.begin().await?;
+        role_permissions::Entity::delete_many()
+            .filter(role_permissions::Column::RoleId.eq(self.id))
+            .exec(&txn)
+            .await?;
+        Entity::delete_by_id(self.id).exec(&txn).await?;
+        txn.commit().await?;
+
+        tracing::info!(role = self.name, "Role deleted");
+        Ok(())
+    }
+
+    /// Deletes the custom roles, leaving the built-in ones
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn delete_custom_roles(db: &DatabaseConnection) -> ModelResult<()> {
+        let custom_role_ids = Entity::find()
+            .filter(roles::Column::Builtin.eq(false))
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|role| role.id)
+            .collect::<Vec<_>>();
+        role_permissions::Entity::delete_many()
+            .filter(role_permissions::Column::RoleId.is_in(custom_role_ids.clone()))
+            .exec(db)
+            .await?;
+        Entity::delete_many()
+            .filter(roles::Column::Id.is_in(custom_role_ids))
+            .exec(db)
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,365 @@
+use chrono::Utc;
+use loco_rs::{hash, prelude::*};
+use sea_orm::{ActiveValue, QueryOrder, TransactionTrait};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub use super::_entities::service_accounts::{self, ActiveModel, Entity, Model};
+use super::_entities::{team_memberships, teams};
+use super::{
+    permissions,
+    personal_access_tokens::{self, CreateTokenParams},
+    roles, users,
+};
+
+/// Domain of the placeholder email addresses of service accounts, which
+/// never receive emails
+const SERVICE_ACCOUNT_EMAIL_DOMAIN: &str = "service-accounts.invalid";
+/// Maximum length of the name of a service account
+pub const SERVICE_ACCOUNT_NAME_MAX_LENGTH: usize = 64;
+
+/// Sent by a team administrator to create a service account
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateServiceAccountParams {
+    pub name: String,
+    pub description: Option<String>,
+    pub role: String,
+}
+
+/// A service account with its account, role in the team and creator
+#[derive(Debug)]
+pub struct ServiceAccountDetails {
+    pub service_account: Model,
+    pub user: users::Model,
+    pub role: String,
+    /// `None` when the member who created it has since been deleted
+    pub created_by: Option<users::Model>,
+}
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    async fn before_save<C>(self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        if insert {
+            let mut this = self;
+            this.pid = ActiveValue::Set(Uuid::new_v4());
+            Ok(this)
+        } else if self.updated_at.is_unchanged() {
+            let mut this = self;
+            this.updated_at = ActiveValue::Set(Utc::now().into());
+            Ok(this)
+        } else {
+            Ok(self)
+        }
+    }
+}
+
+/// Checks that the member may manage the service accounts of the team and,
+/// when given, grant them the role: members may only grant the roles their
+/// permissions include.
+async fn check_can_manage(
+    db: &DatabaseConnection,
+    team: &teams::Model,
+    member_id: i32,
+    role: Option<&str>,
+) -> ModelResult<()> {
+    if !team
+        .has_permission(db, member_id, permissions::SERVICE_ACCOUNTS_MANAGE)
+        .await?
+    {
+        return Err(ModelError::msg(
+            "You are not allowed to manage the service accounts of this team",
+        ));
+    }
+    if let Some(role) = role {
+        let role = roles::Model::find_assignable(db, role).await?;
+        if !team.member_includes(db, member_id, &role).await? {
+            return Err(ModelError::msg(
+                "You cannot give a role granting permissions you do not have",
+            ));
+        }
+    }
+    Ok(())
+}
+
+impl Model {
+    /// Creates a service account owned by the team, with the given role in
+    /// the team. The account behind it cannot log in and only authenticates
+    /// with the personal access tokens created for it.
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the creator may not create it or the
+    /// parameters are invalid, or DB query error
+    pub async fn create(
+        db: &DatabaseConnection,
+        team: &teams::Model,
+        creator: &users::Model,
+        params: &CreateServiceAccountParams,
+    ) -> ModelResult<Self> {
+        check_can_manage(db, team, creator.id, Some(&params.role)).await?;
+
+        let name = params.name.trim();
+        if name.chars().count() < 2 || name.chars().count() > SERVICE_ACCOUNT_NAME_MAX_LENGTH {
+            return Err(ModelError::Message(format!(
+                "Service account name must be between 2 and {SERVICE_ACCOUNT_NAME_MAX_LENGTH} characters long."
+            )));
+        }
+        if users::Entity::find()
+            .filter(users::users::Column::Name.eq(name))
+            .one(db)
+            .await?
+            .is_some()
+        {
+            return Err(ModelError::msg(
+                "A user or service account with this name already exists",
+            ));
+        }
+
+        let txn = db.begin().await?;
+        let pid = Uuid::new_v4();
+        // The random password is never shown to anyone; the status keeps the
+        // account from logging in anyway
+        let password =
+            hash::hash_password(&hash::random_string(32)).map_err(|e| ModelError::Any(e.into()))?;
+        let user = users::ActiveModel {
+            pid: ActiveValue::Set(pid),
+            email: ActiveValue::Set(format!("{pid}@{SERVICE_ACCOUNT_EMAIL_DOMAIN}")),
+            password: ActiveValue::Set(password),
+            name: ActiveValue::Set(name.to_string()),
+            status: ActiveValue::Set(users::STATUS_SERVICE_ACCOUNT.to_string()),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+        team_memberships::ActiveModel {
+            team_id: ActiveValue::Set(team.id),
+            user_id: ActiveValue::Set(Some(user.id)),
+            role: ActiveValue::Set(params.role.clone()),
+            pending: ActiveValue::Set(false),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+        let service_account = ActiveModel {
+            description: ActiveValue::Set(
+                params
+                    .description
+                    .as_deref()
+                    .map(str::trim)
+                    .filter(|description| !description.is_empty())
+                    .map(ToString::to_string),
+            ),
+            user_id: ActiveValue::Set(user.id),
+            team_id: ActiveValue::Set(team.id),
+            created_by_id: ActiveValue::Set(Some(creator.id)),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+        txn.commit().await?;
+
+        tracing::info!(
+            team_pid = %team.pid,
+            service_account_pid = %service_account.pid,
+            created_by = %creator.pid,
+            role = params.role,
+            "Service account created"
+        );
+        Ok(service_account)
+    }
+
+    /// Lists the service accounts of a team with their role and creator,
+    /// oldest first
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn find_for_team(
+        db: &DatabaseConnection,
+        team_id: i32,
+    ) -> ModelResult<Vec<ServiceAccountDetails>> {
+        let service_accounts = Entity::find()
+            .filter(service_accounts::Column::TeamId.eq(team_id))
+            .order_by_asc(service_accounts::Column::CreatedAt)
+            .all(db)
+            .await?;
+
+        let mut details = Vec::with_capacity(service_accounts.len());
+        for service_account in service_accounts {
+            let user = users::Entity::find_by_id(service_account.user_id)
+                .one(db)
+                .await?
+                .ok_or_else(|| ModelError::EntityNotFound)?;
+            let role = team_memberships::Entity::find()
+                .filter(team_memberships::Column::TeamId.eq(team_id))
+                .filter(team_memberships::Column::UserId.eq(user.id))
+                .one(db)
+                .await?
+                .map(|membership| membership.role)
+                .unwrap_or_default();
+            let created_by = match service_account.created_by_id {
+                Some(creator_id) => users::Entity::find_by_id(creator_id).one(db).await?,
+                None => None,
+            };
+            details.push(ServiceAccountDetails {
+                service_account,
+                user,
+                role,
+                created_by,
+            });
+        }
+        Ok(details)
+    }
+
+    /// Finds a service account of a team by its pid
+    ///
+    /// # Errors
+    ///
+    /// When could not find the service account or DB query error
+    pub async fn find_by_pid_for_team(
+        db: &DatabaseConnection,
+        pid: &str,
+        team_id: i32,
+    ) -> ModelResult<Self> {
+        let pid = Uuid::parse_str(pid).map_err(|_| ModelError::EntityNotFound)?;
+        let service_account = Entity::find()
+            .filter(service_accounts::Column::Pid.eq(pid))
+            .filter(service_accounts::Column::TeamId.eq(team_id))
+            .one(db)
+            .await?;
+        service_account.ok_or_else(|| ModelError::EntityNotFound)
+    }
+
+    /// Changes the role of the service account in its team
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the member may not grant the role, or DB
+    /// query error
+    pub async fn update_role(
+        &self,
+        db: &DatabaseConnection,
+        team: &teams::Model,
+        member: &users::Model,
+        role: &str,
+    ) -> ModelResult<()> {
+        check_can_manage(db, team, member.id, Some(role)).await?;
+        let membership = team_memberships::Entity::find()
+            .filter(team_memberships::Column::TeamId.eq(self.team_id))
+            .filter(team_memberships::Column::UserId.eq(self.user_id))
+            .one(db)
+            .await?
+            .ok_or_else(|| ModelError::EntityNotFound)?;
+        let current_role = roles::Model::find_by_name(db, &membership.role).await?;
+        if !team.member_includes(db, member.id, &current_role).await? {
+            return Err(ModelError::msg(
+                "You cannot change the role of a service account whose role grants permissions you do not have",
+            ));
+        }
+        membership.update_role(db, role).await?;
+        tracing::info!(
+            service_account_pid = %self.pid,
+            changed_by = %member.pid,
+            role,
+            "Service account role changed"
+        );
+        Ok(())
+    }
+
+    /// Creates a personal access token for the service account
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the member may not manage the service
+    /// account or the token parameters are invalid, or DB query error
+    pub async fn create_token(
+        &self,
+        db: &DatabaseConnection,
+        team: &teams::Model,
+        member: &users::Model,
+        params: &CreateTokenParams,
+    ) -> ModelResult<(personal_access_tokens::Model, String)> {
+        check_can_manage(db, team, member.id, None).await?;
+        let (token, plaintext) =
+            personal_access_tokens::Model::create_for_user(db, self.user_id, params).await?;
+        tracing::info!(
+            service_account_pid = %self.pid,
+            token_pid = %token.pid,
+            created_by = %member.pid,
+            "Service account token created"
+        );
+        Ok((token, plaintext))
+    }
+
+    /// Deletes the service account, its account, membership and tokens
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the member may not manage the service
+    /// account, or DB query error
+    pub async fn delete(
+        self,
+        db: &DatabaseConnection,
+        team: &teams::Model,
+        member: &users::Model,
+    ) -> ModelResult<()> {
+        check_can_manage(db, team, member.id, None).await?;
+        let txn = db.begin().await?;
+        Self::delete_accounts(&txn, &[self.user_id]).await?;
+        txn.commit().await?;
+        tracing::info!(
+            service_account_pid = %self.pid,
+            deleted_by = %member.pid,
+            "Service account deleted"
+        );
+        Ok(())
+    }
+
+    /// Deletes every service account of a team, when the team is deleted
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn delete_for_team<C: ConnectionTrait>(db: &C, team_id: i32) -> ModelResult<()> {
+        let user_ids = Entity::find()
+            .filter(service_accounts::Column::TeamId.eq(team_id))
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|service_account| service_account.user_id)
+            .collect::<Vec<_>>();
+        Self::delete_accounts(db, &user_ids).await
+    }
+
+    /// Deletes the accounts behind service accounts along with everything
+    /// referencing them
+    async fn delete_accounts<C: ConnectionTrait>(db: &C, user_ids: &[i32]) -> ModelResult<()> {
+        if user_ids.is_empty() {
+            return Ok(());
+        }
+        personal_access_tokens::Entity::delete_many()
+            .filter(
+                personal_access_tokens::personal_access_tokens::Column::UserId
+                    .is_in(user_ids.to_vec()),
+            )
+            .exec(db)
+            .await?;
+        team_memberships::Entity::delete_many()
+            .filter(team_memberships::Column::UserId.is_in(user_ids.to_vec()))
+            .exec(db)
+            .await?;
+        Entity::delete_many()
+            .filter(service_accounts::Column::UserId.is_in(user_ids.to_vec()))
+            .exec(db)
+            .await?;
+        users::Entity::delete_many()
+            .filter(users::users::Column::Id.is_in(user_ids.to_vec()))
+            .filter(users::users::Column::Status.eq(users::STATUS_SERVICE_ACCOUNT))
+            .exec(db)
+            .await?;
+        Ok(())
+    }
+}
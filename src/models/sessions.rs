@@ -0,0 +1,366 @@
+use std::net::IpAddr;
+
+use axum::http::HeaderMap;
+use chrono::{Duration, Utc};
+use ipnet::IpNet;
+use loco_rs::prelude::*;
+use sea_orm::{ActiveValue, Condition, QueryOrder, sea_query::Expr};
+use uuid::Uuid;
+
+pub use super::_entities::sessions::{self, ActiveModel, Entity, Model};
+use super::_entities::users;
+
+/// Minimum delay between two updates of `last_seen` for the same session,
+/// to avoid writing to the database on every authenticated request.
+pub const LAST_SEEN_UPDATE_INTERVAL_SEC: i64 = 60;
+/// Claim marking the tokens issued to an administrator impersonating a user
+pub const IMPERSONATOR_CLAIM: &str = "impersonator";
+
+/// Client details recorded when a session is opened, so that the user can
+/// recognise the device/browser in the list of active sessions
+#[derive(Debug, Default, Clone)]
+pub struct SessionDevice {
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+}
+
+impl SessionDevice {
+    /// Reads the user agent and client IP address of a request.
+    ///
+    /// The client address is the peer address of the connection. The
+    /// `X-Forwarded-For` and `X-Real-IP` headers are only honoured when the
+    /// peer is one of the `trusted_proxies`, since any client can set them.
+    /// `X-Forwarded-For` is then read from the right, skipping the trusted
+    /// proxies, so that addresses prepended by the client are ignored.
+    #[must_use]
+    pub fn from_request(
+        headers: &HeaderMap,
+        peer: Option<IpAddr>,
+        trusted_proxies: &[IpNet],
+    ) -> Self {
+        let header_str = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        };
+        let is_trusted = |ip: &IpAddr| trusted_proxies.iter().any(|net| net.contains(ip));
+        let ip = match peer {
+            Some(peer) if is_trusted(&peer) => {
+                let forwarded = header_str("x-forwarded-for").map(|value| {
+                    value
+                        .rsplit(',')
+                        .map_while(|hop| hop.trim().parse::<IpAddr>().ok())
+                        .collect::<Vec<_>>()
+                });
+                let client = match forwarded {
+                    Some(hops) => hops
+                        .iter()
+                        .find(|hop| !is_trusted(hop))
+                        .or_else(|| hops.last())
+                        .copied(),
+                    None => header_str("x-real-ip").and_then(|value| value.parse().ok()),
+                };
+                Some(client.unwrap_or(peer))
+            }
+            peer => peer,
+        };
+        Self {
+            user_agent: header_str("user-agent").map(ToString::to_string),
+            ip: ip.map(|ip| ip.to_string()),
+        }
+    }
+}
+
+/// Reads the reverse proxies trusted to report the client address from
+/// `settings.app.trusted_proxies`, a list of IP addresses or CIDR ranges.
+/// No proxy is trusted when the setting is missing.
+///
+/// # Errors
+///
+/// When an entry is neither an IP address nor a CIDR range
+pub fn trusted_proxies(ctx: &AppContext) -> ModelResult<Vec<IpNet>> {
+    let Some(entries) = ctx
+        .config
+        .settings
+        .as_ref()
+        .and_then(|settings| settings.get("app"))
+        .and_then(|app_settings| app_settings.get("trusted_proxies"))
+    else {
+        return Ok(Vec::new());
+    };
+    let entries: Vec<String> = serde_json::from_value(entries.clone())
+        .map_err(|e| ModelError::Message(format!("Invalid trusted proxies configuration: {e}")))?;
+    entries
+        .iter()
+        .map(|entry| {
+            entry
+                .parse::<IpNet>()
+                .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|_| ModelError::Message(format!("Invalid trusted proxy: {entry}")))
+        })
+        .collect()
+}
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    async fn before_save<C>(self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        if !insert && self.updated_at.is_unchanged() {
+            let mut this = self;
+            this.updated_at = ActiveValue::Set(Utc::now().into());
+            Ok(this)
+        } else {
+            Ok(self)
+        }
+    }
+}
+
+impl Model {
+    /// Opens a new session for the given user, with a freshly generated jti.
+    /// The session expires along with its token, `expiration` seconds from now.
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn create_for_user(
+        db: &DatabaseConnection,
+        user_id: i32,
+        expiration: u64,
+        device: &SessionDevice,
+    ) -> ModelResult<Self> {
+        let session = ActiveModel {
+            jti: ActiveValue::Set(Uuid::new_v4().to_string()),
+            user_id: ActiveValue::Set(user_id),
+            last_seen: ActiveValue::Set(Utc::now().into()),
+            expires_at: ActiveValue::Set(Some(expires_at(expiration)?)),
+            user_agent: ActiveValue::Set(device.user_agent.clone()),
+            ip: ActiveValue::Set(device.ip.clone()),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+        Ok(session)
+    }
+
+    /// Opens a session for an administrator browsing the console as the given
+    /// user. The session belongs to the user, but remembers the administrator
+    /// behind it.
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn create_impersonation(
+        db: &DatabaseConnection,
+        user_id: i32,
+        impersonator_id: i32,
+        expiration: u64,
+        device: &SessionDevice,
+    ) -> ModelResult<Self> {
+        let session = ActiveModel {
+            jti: ActiveValue::Set(Uuid::new_v4().to_string()),
+            user_id: ActiveValue::Set(user_id),
+            impersonator_id: ActiveValue::Set(Some(impersonator_id)),
+            last_seen: ActiveValue::Set(Utc::now().into()),
+            expires_at: ActiveValue::Set(Some(expires_at(expiration)?)),
+            user_agent: ActiveValue::Set(device.user_agent.clone()),
+            ip: ActiveValue::Set(device.ip.clone()),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+        Ok(session)
+    }
+
+    /// Finds a session that has not been revoked by its jti, making sure it
+    /// belongs to the user with the given pid
+    ///
+    /// # Errors
+    ///
+    /// When could not find an active session or DB query error
+    pub async fn find_active(db: &DatabaseConnection, jti: &str, pid: &str) -> ModelResult<Self> {
+        let pid = Uuid::parse_str(pid).map_err(|e| ModelError::Any(e.into()))?;
+        let session = Entity::find()
+            .inner_join(users::Entity)
+            .filter(sessions::Column::Jti.eq(jti))
+            .filter(sessions::Column::RevokedAt.is_null())
+            .filter(users::Column::Pid.eq(pid))
+            .one(db)
+            .await?;
+        session.ok_or_else(|| ModelError::EntityNotFound)
+    }
+
+    /// Finds a session by its jti, whether it is revoked or not
+    ///
+    /// # Errors
+    ///
+    /// When could not find session or DB query error
+    pub async fn find_by_jti(db: &DatabaseConnection, jti: &str) -> ModelResult<Self> {
+        let session = Entity::find()
+            .filter(sessions::Column::Jti.eq(jti))
+            .one(db)
+            .await?;
+        session.ok_or_else(|| ModelError::EntityNotFound)
+    }
+
+    /// Finds a session by its id, making sure it belongs to the given user
+    ///
+    /// # Errors
+    ///
+    /// When could not find session or DB query error
+    pub async fn find_by_id_for_user(
+        db: &DatabaseConnection,
+        id: i32,
+        user_id: i32,
+    ) -> ModelResult<Self> {
+        let session = Entity::find_by_id(id)
+            .filter(sessions::Column::UserId.eq(user_id))
+            .one(db)
+            .await?;
+        session.ok_or_else(|| ModelError::EntityNotFound)
+    }
+
+    /// Lists the sessions of a user that have neither been revoked nor
+    /// expired, most recently used first
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn find_active_for_user(
+        db: &DatabaseConnection,
+        user_id: i32,
+    ) -> ModelResult<Vec<Self>> {
+        let sessions = Entity::find()
+            .filter(sessions::Column::UserId.eq(user_id))
+            .filter(sessions::Column::RevokedAt.is_null())
+            .filter(sessions::Column::ExpiresAt.gt(Utc::now()))
+            .order_by_desc(sessions::Column::LastSeen)
+            .all(db)
+            .await?;
+        Ok(sessions)
+    }
+
+    /// Updates the last activity time of the session, at most once every
+    /// `LAST_SEEN_UPDATE_INTERVAL_SEC` seconds
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn touch(self, db: &DatabaseConnection) -> ModelResult<Self> {
+        let now = Utc::now();
+        if now.signed_duration_since(self.last_seen)
+            < Duration::seconds(LAST_SEEN_UPDATE_INTERVAL_SEC)
+        {
+            return Ok(self);
+        }
+        let mut session = self.into_active_model();
+        session.last_seen = ActiveValue::Set(now.into());
+        Ok(session.update(db).await?)
+    }
+
+    /// Revokes this session. Tokens carrying its jti are rejected from now on.
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn revoke(self, db: &DatabaseConnection) -> ModelResult<Self> {
+        if self.revoked_at.is_some() {
+            return Ok(self);
+        }
+        let mut session = self.into_active_model();
+        session.revoked_at = ActiveValue::Set(Some(Utc::now().into()));
+        Ok(session.update(db).await?)
+    }
+
+    /// Revokes the session with the given jti, if it exists
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn revoke_by_jti(db: &DatabaseConnection, jti: &str) -> ModelResult<()> {
+        match Self::find_by_jti(db, jti).await {
+            Ok(session) => {
+                session.revoke(db).await?;
+                Ok(())
+            }
+            Err(ModelError::EntityNotFound) => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Revokes every active session of a user, except the one with the jti
+    /// given in `keep_jti` (used to keep the current session alive)
+    ///
+    /// Returns the number of revoked sessions.
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn revoke_all_for_user(
+        db: &DatabaseConnection,
+        user_id: i32,
+        keep_jti: Option<&str>,
+    ) -> ModelResult<u64> {
+        let mut query = Entity::update_many()
+            .col_expr(
+                sessions::Column::RevokedAt,
+                Expr::value(chrono::DateTime::<chrono::FixedOffset>::from(Utc::now())),
+            )
+            .filter(sessions::Column::UserId.eq(user_id))
+            .filter(sessions::Column::RevokedAt.is_null());
+        if let Some(jti) = keep_jti {
+            query = query.filter(sessions::Column::Jti.ne(jti));
+        }
+        let result = query.exec(db).await?;
+        Ok(result.rows_affected)
+    }
+
+    /// Deletes the sessions whose token expired, as well as the sessions
+    /// opened before their expiry was recorded. Meant to be run periodically.
+    ///
+    /// Returns the number of deleted sessions.
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn purge_expired(db: &DatabaseConnection) -> ModelResult<u64> {
+        let result = Entity::delete_many()
+            .filter(
+                Condition::any()
+                    .add(sessions::Column::ExpiresAt.is_null())
+                    .add(sessions::Column::ExpiresAt.lte(Utc::now())),
+            )
+            .exec(db)
+            .await?;
+        Ok(result.rows_affected)
+    }
+}
+
+/// Computes the expiry of a session whose token lasts `expiration` seconds
+fn expires_at(expiration: u64) -> ModelResult<DateTimeWithTimeZone> {
+    i64::try_from(expiration)
+        .ok()
+        .and_then(Duration::try_seconds)
+        .and_then(|lifetime| Utc::now().checked_add_signed(lifetime))
+        .map(Into::into)
+        .ok_or_else(|| ModelError::msg("Invalid session expiration"))
+}
+
+/// Reads the jti embedded in the custom claims of a token
+#[must_use]
+pub fn jti_from_claims(claims: &loco_rs::auth::jwt::UserClaims) -> Option<&str> {
+    claims.claims.get("jti").and_then(|value| value.as_str())
+}
+
+/// Reads the pid of the administrator behind an impersonation token, `None`
+/// for a regular token
+#[must_use]
+pub fn impersonator_from_claims(claims: &loco_rs::auth::jwt::UserClaims) -> Option<&str> {
+    claims
+        .claims
+        .get(IMPERSONATOR_CLAIM)
+        .and_then(|value| value.as_str())
+}
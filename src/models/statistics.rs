@@ -0,0 +1,206 @@
+//! System-wide figures shown to administrators on the dashboard
+
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+};
+
+use chrono::Utc;
+use loco_rs::prelude::*;
+use sea_orm::{PaginatorTrait, QueryOrder, QuerySelect};
+use serde::Serialize;
+
+use super::_entities::{ssh_keys, team_memberships, teams, users};
+use super::users::{
+    STATUS_ACTIVE, STATUS_PENDING_APPROVAL, STATUS_SERVICE_ACCOUNT, STATUS_SUSPENDED,
+};
+
+/// Number of largest teams listed on the dashboard
+const LARGEST_TEAMS_SHOWN: usize = 5;
+
+/// Accounts by state. Service accounts are only counted in
+/// `service_accounts`.
+#[derive(Debug, Default, Serialize)]
+pub struct UserStatistics {
+    pub total: u64,
+    pub active: u64,
+    pub pending_approval: u64,
+    pub suspended: u64,
+    pub service_accounts: u64,
+    pub email_verified: u64,
+    pub pgp_verified: u64,
+    pub two_factor_enabled: u64,
+    /// Invitations waiting for an answer, not expired
+    pub pending_invitations: u64,
+    /// Invitations never answered and now expired
+    pub expired_invitations: u64,
+}
+
+/// A team with its number of members
+#[derive(Debug, Serialize)]
+pub struct TeamSize {
+    pub pid: String,
+    pub name: String,
+    pub members: u64,
+}
+
+/// Teams and their sizes
+#[derive(Debug, Default, Serialize)]
+pub struct TeamStatistics {
+    pub total: u64,
+    /// Members who accepted their invitation, service accounts included
+    pub memberships: u64,
+    pub average_size: f64,
+    pub empty: u64,
+    pub largest: Vec<TeamSize>,
+}
+
+/// SSH keys by algorithm, most used first
+#[derive(Debug, Default, Serialize)]
+pub struct SshKeyStatistics {
+    pub total: u64,
+    pub by_algorithm: Vec<(String, u64)>,
+}
+
+/// Counts the accounts by state, along with the pending invitations
+///
+/// # Errors
+///
+/// When DB query error
+pub async fn user_statistics(db: &DatabaseConnection) -> ModelResult<UserStatistics> {
+    let people = || users::Entity::find().filter(users::Column::Status.ne(STATUS_SERVICE_ACCOUNT));
+    let with_status =
+        |status: &str| users::Entity::find().filter(users::Column::Status.eq(status.to_string()));
+    let now = Utc::now();
+    Ok(UserStatistics {
+        total: people().count(db).await?,
+        active: with_status(STATUS_ACTIVE).count(db).await?,
+        pending_approval: with_status(STATUS_PENDING_APPROVAL).count(db).await?,
+        suspended: with_status(STATUS_SUSPENDED).count(db).await?,
+        service_accounts: with_status(STATUS_SERVICE_ACCOUNT).count(db).await?,
+        email_verified: people()
+            .filter(users::Column::EmailVerifiedAt.is_not_null())
+            .count(db)
+            .await?,
+        pgp_verified: people()
+            .filter(users::Column::PgpVerifiedAt.is_not_null())
+            .count(db)
+            .await?,
+        two_factor_enabled: people()
+            .filter(users::Column::TotpEnabledAt.is_not_null())
+            .count(db)
+            .await?,
+        pending_invitations: team_memberships::Entity::find()
+            .filter(team_memberships::Column::Pending.eq(true))
+            .filter(team_memberships::Column::InvitationExpiresAt.gt(now))
+            .count(db)
+            .await?,
+        expired_invitations: team_memberships::Entity::find()
+            .filter(team_memberships::Column::Pending.eq(true))
+            .filter(team_memberships::Column::InvitationExpiresAt.lte(now))
+            .count(db)
+            .await?,
+    })
+}
+
+/// Counts the teams and their members, and lists the largest teams
+///
+/// # Errors
+///
+/// When DB query error
+pub async fn team_statistics(db: &DatabaseConnection) -> ModelResult<TeamStatistics> {
+    let all_teams = teams::Entity::find()
+        .order_by_asc(teams::Column::Name)
+        .all(db)
+        .await?;
+    let member_team_ids: Vec<i32> = team_memberships::Entity::find()
+        .select_only()
+        .column(team_memberships::Column::TeamId)
+        .filter(team_memberships::Column::Pending.eq(false))
+        .into_tuple()
+        .all(db)
+        .await?;
+
+    let mut sizes: HashMap<i32, u64> = HashMap::new();
+    for team_id in &member_team_ids {
+        *sizes.entry(*team_id).or_default() += 1;
+    }
+    let mut team_sizes = all_teams
+        .iter()
+        .map(|team| TeamSize {
+            pid: team.pid.to_string(),
+            name: team.name.clone(),
+            members: sizes.get(&team.id).copied().unwrap_or(0),
+        })
+        .collect::<Vec<_>>();
+    let empty = team_sizes.iter().filter(|team| team.members == 0).count() as u64;
+    // Stable sort: teams of the same size stay sorted by name
+    team_sizes.sort_by_key(|team| Reverse(team.members));
+    team_sizes.truncate(LARGEST_TEAMS_SHOWN);
+
+    let total = all_teams.len() as u64;
+    let memberships = member_team_ids.len() as u64;
+    #[allow(clippy::cast_precision_loss)]
+    let average_size = if total == 0 {
+        0.0
+    } else {
+        (memberships as f64 / total as f64 * 10.0).round() / 10.0
+    };
+    Ok(TeamStatistics {
+        total,
+        memberships,
+        average_size,
+        empty,
+        largest: team_sizes,
+    })
+}
+
+/// Counts the SSH keys by algorithm, read from the first field of the
+/// OpenSSH public key
+///
+/// # Errors
+///
+/// When DB query error
+pub async fn ssh_key_statistics(db: &DatabaseConnection) -> ModelResult<SshKeyStatistics> {
+    let public_keys: Vec<String> = ssh_keys::Entity::find()
+        .select_only()
+        .column(ssh_keys::Column::PublicKey)
+        .into_tuple()
+        .all(db)
+        .await?;
+
+    let mut counts: BTreeMap<String, u64> = BTreeMap::new();
+    for public_key in &public_keys {
+        let algorithm = public_key
+            .split_whitespace()
+            .next()
+            .unwrap_or("unknown")
+            .to_string();
+        *counts.entry(algorithm).or_default() += 1;
+    }
+    let mut by_algorithm = counts.into_iter().collect::<Vec<_>>();
+    by_algorithm.sort_by_key(|(_, count)| Reverse(*count));
+    Ok(SshKeyStatistics {
+        total: public_keys.len() as u64,
+        by_algorithm,
+    })
+}
+
+/// Lists the most recently registered accounts, service accounts excluded
+///
+/// # Errors
+///
+/// When DB query error
+pub async fn recent_registrations(
+    db: &DatabaseConnection,
+    limit: u64,
+) -> ModelResult<Vec<users::Model>> {
+    let users = users::Entity::find()
+        .filter(users::Column::Status.ne(STATUS_SERVICE_ACCOUNT))
+        .order_by_desc(users::Column::CreatedAt)
+        .order_by_desc(users::Column::Id)
+        .limit(limit)
+        .all(db)
+        .await?;
+    Ok(users)
+}
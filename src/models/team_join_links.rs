@@ -0,0 +1,422 @@
+use chrono::{Duration, Utc};
+use loco_rs::prelude::*;
+use sea_orm::{
+    ActiveValue, QueryOrder, TransactionTrait,
+    sea_query::{Condition, Expr},
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub use super::_entities::team_join_links::{self, ActiveModel, Entity, Model};
+use super::_entities::{team_memberships, teams, users};
+use super::{
+    permissions,
+    roles::{self, OWNER_ROLE},
+    team_memberships::DEFAULT_INVITATION_ROLE,
+    tokens,
+    users::email_domain,
+};
+
+/// Longest lifetime, in days, a join link can be created with
+pub const JOIN_LINK_MAX_LIFETIME_DAYS: i64 = 365;
+
+/// Sent by a team member who invites users to create a join link
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CreateJoinLinkParams {
+    pub role: String,
+    /// Number of times the link can be used, `None` for no limit
+    pub max_uses: Option<i32>,
+    /// Number of days the link is valid for, `None` for a link that does not
+    /// expire
+    pub expires_in_days: Option<i64>,
+    /// Domain the email address of the joining users must belong to, `None`
+    /// for any address
+    pub allowed_domain: Option<String>,
+}
+
+impl CreateJoinLinkParams {
+    /// Reads the parameters from the fields of an HTML form, which sends
+    /// empty values for the limits that are not set
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when a limit is not a number
+    pub fn from_form_fields(fields: Vec<(String, String)>) -> ModelResult<Self> {
+        let mut params = Self {
+            role: DEFAULT_INVITATION_ROLE.to_string(),
+            max_uses: None,
+            expires_in_days: None,
+            allowed_domain: None,
+        };
+        for (key, value) in fields {
+            let value = value.trim();
+            match key.as_str() {
+                "role" if !value.is_empty() => params.role = value.to_string(),
+                "max_uses" if !value.is_empty() => {
+                    params.max_uses = Some(
+                        value
+                            .parse()
+                            .map_err(|_| ModelError::msg("Invalid number of uses."))?,
+                    );
+                }
+                "expires_in_days" if !value.is_empty() => {
+                    params.expires_in_days = Some(
+                        value
+                            .parse()
+                            .map_err(|_| ModelError::msg("Invalid link lifetime."))?,
+                    );
+                }
+                "allowed_domain" if !value.is_empty() => {
+                    params.allowed_domain = Some(value.to_string());
+                }
+                _ => {}
+            }
+        }
+        Ok(params)
+    }
+}
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    async fn before_save<C>(self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        if insert {
+            let mut this = self;
+            this.pid = ActiveValue::Set(Uuid::new_v4());
+            Ok(this)
+        } else if self.updated_at.is_unchanged() {
+            let mut this = self;
+            this.updated_at = ActiveValue::Set(Utc::now().into());
+            Ok(this)
+        } else {
+            Ok(self)
+        }
+    }
+}
+
+/// Returns the form in which allowed domains are stored and compared, `None`
+/// when the domain is not valid
+fn normalize_domain(domain: &str) -> Option<String> {
+    let domain = domain.trim().trim_start_matches('@').to_lowercase();
+    let valid = domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && domain
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.');
+    valid.then_some(domain)
+}
+
+/// Checks that the user may manage the join links of the team: like
+/// invitations, they need the `team.invite` permission
+///
+/// # Errors
+///
+/// `ModelError::Message` when the user may not invite users to the team, or
+/// DB query error
+async fn check_can_manage(
+    db: &DatabaseConnection,
+    team: &teams::Model,
+    user_id: i32,
+) -> ModelResult<()> {
+    if !team
+        .has_permission(db, user_id, permissions::TEAM_INVITE)
+        .await?
+    {
+        return Err(ModelError::msg(
+            "You are not allowed to manage the join links of this team",
+        ));
+    }
+    Ok(())
+}
+
+impl Model {
+    /// Lists the join links of a team, newest first
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn find_for_team(db: &DatabaseConnection, team_id: i32) -> ModelResult<Vec<Self>> {
+        let links = Entity::find()
+            .filter(team_join_links::Column::TeamId.eq(team_id))
+            .order_by_desc(team_join_links::Column::CreatedAt)
+            .all(db)
+            .await?;
+        Ok(links)
+    }
+
+    /// Finds a join link of a team by its pid
+    ///
+    /// # Errors
+    ///
+    /// When could not find the link or DB query error
+    pub async fn find_by_pid_for_team(
+        db: &DatabaseConnection,
+        pid: &str,
+        team_id: i32,
+    ) -> ModelResult<Self> {
+        let pid = Uuid::parse_str(pid).map_err(|_| ModelError::EntityNotFound)?;
+        let link = Entity::find()
+            .filter(team_join_links::Column::Pid.eq(pid))
+            .filter(team_join_links::Column::TeamId.eq(team_id))
+            .one(db)
+            .await?;
+        link.ok_or_else(|| ModelError::EntityNotFound)
+    }
+
+    /// Finds a join link by the token of its URL
+    ///
+    /// # Errors
+    ///
+    /// When could not find the link or DB query error
+    pub async fn find_by_token(db: &DatabaseConnection, token: &str) -> ModelResult<Self> {
+        let link = Entity::find()
+            .filter(team_join_links::Column::TokenHash.eq(tokens::hash_token(token)))
+            .one(db)
+            .await?;
+        link.ok_or_else(|| ModelError::EntityNotFound)
+    }
+
+    /// Creates a join link of a team, giving the role to the users who open
+    /// it. Only members who may invite users create links; a link cannot make
+    /// anyone an Owner nor grant more than the role of its creator.
+    ///
+    /// Only a hash of the token is stored; the token is returned with the
+    /// link so the URL can be shown once to its creator.
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the user may not invite users to the team
+    /// or the parameters are invalid, or DB query error
+    pub async fn create(
+        db: &DatabaseConnection,
+        team: &teams::Model,
+        creator: &users::Model,
+        params: &CreateJoinLinkParams,
+    ) -> ModelResult<(Self, String)> {
+        check_can_manage(db, team, creator.id).await?;
+        let role = roles::Model::find_assignable(db, &params.role).await?;
+        if role.name == OWNER_ROLE {
+            return Err(ModelError::msg("A join link cannot make users Owners."));
+        }
+        if !team.member_includes(db, creator.id, &role).await? {
+            return Err(ModelError::msg(
+                "You cannot create a join link with a role granting permissions you do not have",
+            ));
+        }
+        if params.max_uses.is_some_and(|max_uses| max_uses <= 0) {
+            return Err(ModelError::msg(
+                "The number of uses must be a positive number.",
+            ));
+        }
+        let expires_at = match params.expires_in_days {
+            Some(days) if !(1..=JOIN_LINK_MAX_LIFETIME_DAYS).contains(&days) => {
+                return Err(ModelError::Message(format!(
+                    "Link lifetime must be between 1 and {JOIN_LINK_MAX_LIFETIME_DAYS} days."
+                )));
+            }
+            Some(days) => Some(
+                Utc::now()
+                    .checked_add_signed(Duration::days(days))
+                    .ok_or_else(|| ModelError::msg("Link lifetime is too long."))?
+                    .into(),
+            ),
+            None => None,
+        };
+        let allowed_domain = match params.allowed_domain.as_deref() {
+            Some(domain) => Some(
+                normalize_domain(domain).ok_or_else(|| ModelError::msg("Invalid email domain."))?,
+            ),
+            None => None,
+        };
+
+        let (token, token_hash) = tokens::generate_token();
+        let link = ActiveModel {
+            team_id: ActiveValue::Set(team.id),
+            created_by_id: ActiveValue::Set(Some(creator.id)),
+            token_hash: ActiveValue::Set(token_hash),
+            role: ActiveValue::Set(role.name),
+            max_uses: ActiveValue::Set(params.max_uses),
+            use_count: ActiveValue::Set(0),
+            expires_at: ActiveValue::Set(expires_at),
+            allowed_domain: ActiveValue::Set(allowed_domain),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+
+        tracing::info!(
+            team_pid = %team.pid,
+            link_pid = %link.pid,
+            created_by = %creator.pid,
+            "Team join link created"
+        );
+        Ok((link, token))
+    }
+
+    /// Returns true when the link has expired
+    #[must_use]
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| tokens::check_not_expired(Some(expires_at)).is_err())
+    }
+
+    /// Returns true when the link was used as many times as allowed
+    #[must_use]
+    pub fn is_used_up(&self) -> bool {
+        self.max_uses
+            .is_some_and(|max_uses| self.use_count >= max_uses)
+    }
+
+    /// Returns true when the link can still be used to join the team
+    #[must_use]
+    pub fn is_usable(&self) -> bool {
+        !self.is_expired() && !self.is_used_up()
+    }
+
+    /// Checks that the user may join the team with the link. A link
+    /// restricted to a domain only admits verified email addresses, as
+    /// anyone can register an address they do not own.
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the link can no longer be used or the
+    /// email address of the user is not verified or not in the allowed domain
+    pub fn check_usable_by(&self, user: &users::Model) -> ModelResult<()> {
+        if self.is_expired() {
+            return Err(ModelError::msg("This join link has expired."));
+        }
+        if self.is_used_up() {
+            return Err(ModelError::msg(
+                "This join link has been used as many times as allowed.",
+            ));
+        }
+        if user.is_service_account() {
+            return Err(ModelError::msg(
+                "Service accounts cannot be added to another team",
+            ));
+        }
+        if let Some(domain) = &self.allowed_domain {
+            if email_domain(&user.email).as_ref() != Some(domain) {
+                return Err(ModelError::Message(format!(
+                    "This join link is restricted to {domain} email addresses."
+                )));
+            }
+            if user.email_verified_at.is_none() {
+                return Err(ModelError::msg(
+                    "Verify your email address before using this join link.",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Joins the team with the link. The user gets an invitation from the
+    /// creator of the link with its role, which is accepted right away. An
+    /// invitation or request to join the user already had, expired or not,
+    /// is accepted instead, with the role of the link rather than its own.
+    /// Each use counts against the limit of the link.
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the user cannot use the link or already
+    /// belongs to the team, or DB query error
+    pub async fn join(
+        &self,
+        db: &DatabaseConnection,
+        user: &users::Model,
+    ) -> ModelResult<team_memberships::Model> {
+        self.check_usable_by(user)?;
+
+        let txn = db.begin().await?;
+        let existing = team_memberships::Entity::find()
+            .filter(team_memberships::Column::TeamId.eq(self.team_id))
+            .filter(team_memberships::Column::UserId.eq(user.id))
+            .one(&txn)
+            .await?;
+        let invitation = match existing {
+            Some(membership) if !membership.pending => {
+                return Err(ModelError::msg("You are already a member of this team."));
+            }
+            // The link grants its own role, whatever the pending invitation
+            // or request said, even when the invitation has expired
+            Some(pending) => {
+                let mut pending: team_memberships::ActiveModel = pending.into();
+                pending.role = ActiveValue::set(self.role.clone());
+                pending.invited_by_id = ActiveValue::set(self.created_by_id);
+                pending.update(&txn).await?
+            }
+            None => {
+                team_memberships::ActiveModel {
+                    team_id: ActiveValue::set(self.team_id),
+                    user_id: ActiveValue::set(Some(user.id)),
+                    invited_by_id: ActiveValue::set(self.created_by_id),
+                    role: ActiveValue::set(self.role.clone()),
+                    pending: ActiveValue::set(true),
+                    invitation_sent_at: ActiveValue::set(Some(Utc::now().into())),
+                    ..Default::default()
+                }
+                .insert(&txn)
+                .await?
+            }
+        };
+
+        // Counted with a conditional update, so that concurrent uses cannot
+        // go over the limit
+        let counted = Entity::update_many()
+            .col_expr(
+                team_join_links::Column::UseCount,
+                Expr::col(team_join_links::Column::UseCount).add(1),
+            )
+            .filter(team_join_links::Column::Id.eq(self.id))
+            .filter(
+                Condition::any()
+                    .add(team_join_links::Column::MaxUses.is_null())
+                    .add(
+                        Expr::col(team_join_links::Column::UseCount)
+                            .lt(Expr::col(team_join_links::Column::MaxUses)),
+                    ),
+            )
+            .exec(&txn)
+            .await?;
+        if counted.rows_affected == 0 {
+            return Err(ModelError::msg(
+                "This join link has been used as many times as allowed.",
+            ));
+        }
+        let membership = invitation.accept_invitation(&txn).await?;
+        txn.commit().await?;
+
+        tracing::info!(
+            link_pid = %self.pid,
+            user_pid = %user.pid,
+            team_id = self.team_id,
+            "Team joined with a join link"
+        );
+        Ok(membership)
+    }
+
+    /// Revokes the link: it stops working at once
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the user may not invite users to the team,
+    /// or DB query error
+    pub async fn revoke(
+        self,
+        db: &DatabaseConnection,
+        team: &teams::Model,
+        user: &users::Model,
+    ) -> ModelResult<()> {
+        check_can_manage(db, team, user.id).await?;
+        Entity::delete_by_id(self.id).exec(db).await?;
+        tracing::info!(
+            link_pid = %self.pid,
+            revoked_by = %user.pid,
+            "Team join link revoked"
+        );
+        Ok(())
+    }
+}
@@ -1,16 +1,102 @@
 use chrono::Utc;
 use loco_rs::prelude::*;
-use sea_orm::{ActiveModelTrait, ActiveValue, EntityTrait, QueryFilter};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, Condition, ConnectionTrait, EntityTrait, PaginatorTrait,
+    QueryFilter, QueryOrder, QuerySelect, TransactionTrait,
+    sea_query::{Expr, Func},
+};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use uuid::Uuid;
+use validator::ValidateEmail;
 
 pub use super::_entities::team_memberships::{self, ActiveModel, Entity, Model};
 use super::_entities::teams;
 use super::_entities::users;
+use super::{
+    permissions,
+    roles::{self, OWNER_ROLE},
+    tokens::{self, TokenLifetimes},
+    users::normalize_email,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InviteMemberParams {
+    /// Name of the invited user, or an email address, which may not belong
+    /// to a registered user yet
     pub user_name: String,
+    /// Role given to the invited user, [`DEFAULT_INVITATION_ROLE`] when none
+    /// is chosen
+    pub role: Option<String>,
+    /// Message of the inviter, sent with the invitation
+    pub message: Option<String>,
+}
+
+/// Role given to invited users when the inviter does not choose one
+pub const DEFAULT_INVITATION_ROLE: &str = "Observer";
+
+/// Member sending an invitation, with the role and the message they chose
+#[derive(Debug, Clone)]
+pub struct InvitationParams {
+    pub invited_by_id: i32,
+    pub role: String,
+    pub message: Option<String>,
+}
+
+/// Longest message, in characters, an invitation can carry
+pub const INVITATION_MESSAGE_MAX_LENGTH: usize = 1000;
+
+impl InvitationParams {
+    /// Returns the invitation sent by a member with the role and the message
+    /// of an invite form, blank values being left out
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the message is too long
+    pub fn new(invited_by_id: i32, params: &InviteMemberParams) -> ModelResult<Self> {
+        let non_blank = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(ToString::to_string)
+        };
+        let message = non_blank(&params.message);
+        if message
+            .as_ref()
+            .is_some_and(|message| message.chars().count() > INVITATION_MESSAGE_MAX_LENGTH)
+        {
+            return Err(ModelError::Message(format!(
+                "The invitation message cannot be longer than {INVITATION_MESSAGE_MAX_LENGTH} characters."
+            )));
+        }
+        Ok(Self {
+            invited_by_id,
+            role: non_blank(&params.role).unwrap_or_else(|| DEFAULT_INVITATION_ROLE.to_string()),
+            message,
+        })
+    }
+
+    /// Checks that the inviter may give the role of the invitation in the
+    /// team: its permissions must be included in those of the inviter
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the role is invalid or grants permissions
+    /// the inviter does not have, or DB query error
+    async fn check_role(&self, db: &DatabaseConnection, team_id: i32) -> ModelResult<()> {
+        let role = roles::Model::find_assignable(db, &self.role).await?;
+        let team = teams::Entity::find_by_id(team_id)
+            .one(db)
+            .await?
+            .ok_or_else(|| ModelError::EntityNotFound)?;
+        if !team.member_includes(db, self.invited_by_id, &role).await? {
+            return Err(ModelError::msg(
+                "You cannot invite with a role granting permissions you do not have",
+            ));
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -18,7 +104,21 @@ pub struct UpdateRoleParams {
     pub role: String,
 }
 
-pub const VALID_ROLES: [&str; 4] = ["Owner", "Administrator", "Developer", "Observer"];
+/// Pending invitation of a team, as listed to the members who manage the
+/// invitations
+#[derive(Debug)]
+pub struct TeamInvitation {
+    pub invitation: Model,
+    /// Invited user, `None` for an email address nobody registered with yet
+    pub invited_user: Option<users::Model>,
+    /// Member who sent the invitation, `None` when unknown
+    pub invited_by: Option<users::Model>,
+}
+
+/// Refusal to demote the last Owner of a team
+pub const LAST_OWNER_ROLE_CHANGE: &str = "Cannot change the role of the last owner. Make another member an Owner or transfer ownership first.";
+/// Refusal to remove the last Owner of a team, or to let them leave
+pub const LAST_OWNER_REMOVAL: &str = "The last owner cannot leave the team or be removed from it. Either delete the team or transfer ownership first.";
 
 #[async_trait::async_trait]
 impl ActiveModelBehavior for ActiveModel {
@@ -37,11 +137,12 @@ impl ActiveModelBehavior for ActiveModel {
 }
 
 impl Model {
-    /// Finds a membership by the invitation token
+    /// Finds a pending invitation by the token sent in the invitation email
     ///
     /// # Errors
     ///
-    /// When could not find membership or DB query error
+    /// When could not find a pending invitation, when the invitation has
+    /// expired (see [`tokens::is_expired`]) or DB query error
     pub async fn find_by_invitation_token(
         db: &DatabaseConnection,
         token: &str,
@@ -49,7 +150,33 @@ impl Model {
         let membership = Entity::find()
             .filter(
                 model::query::condition()
-                    .eq(team_memberships::Column::InvitationToken, token)
+                    .eq(
+                        team_memberships::Column::InvitationToken,
+                        tokens::hash_token(token),
+                    )
+                    .eq(team_memberships::Column::Pending, true)
+                    .build(),
+            )
+            .one(db)
+            .await?
+            .ok_or_else(|| ModelError::EntityNotFound)?;
+        tokens::check_not_expired(membership.invitation_expires_at)?;
+        Ok(membership)
+    }
+
+    /// Finds a pending invitation by its pid
+    ///
+    /// # Errors
+    ///
+    /// When could not find a pending invitation or DB query error
+    pub async fn find_pending_by_pid(db: &DatabaseConnection, pid: &str) -> ModelResult<Self> {
+        let pid = Uuid::parse_str(pid).map_err(|_| ModelError::EntityNotFound)?;
+        let membership = Entity::find()
+            .filter(
+                model::query::condition()
+                    .eq(team_memberships::Column::Pid, pid)
+                    .eq(team_memberships::Column::Pending, true)
+                    .eq(team_memberships::Column::Requested, false)
                     .build(),
             )
             .one(db)
@@ -57,6 +184,40 @@ impl Model {
         membership.ok_or_else(|| ModelError::EntityNotFound)
     }
 
+    /// Returns true when the invitation can no longer be accepted
+    #[must_use]
+    pub fn is_invitation_expired(&self) -> bool {
+        tokens::check_not_expired(self.invitation_expires_at).is_err()
+    }
+
+    /// Returns true when a pending team invitation that has not expired was
+    /// sent to this email address
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn has_pending_invitation_for_email(
+        db: &DatabaseConnection,
+        email: &str,
+    ) -> ModelResult<bool> {
+        let invitations = Entity::find()
+            .left_join(users::Entity)
+            .filter(
+                Condition::any()
+                    .add(
+                        Expr::expr(Func::lower(Expr::col(users::Column::Email)))
+                            .eq(normalize_email(email)),
+                    )
+                    .add(team_memberships::Column::InvitedEmail.eq(normalize_email(email))),
+            )
+            .filter(team_memberships::Column::Pending.eq(true))
+            .all(db)
+            .await?;
+        Ok(invitations
+            .iter()
+            .any(|invitation| !invitation.is_invitation_expired()))
+    }
+
     /// Finds a membership by team and user IDs
     ///
     /// # Errors
@@ -80,21 +241,29 @@ impl Model {
         membership.ok_or_else(|| ModelError::EntityNotFound)
     }
 
-    /// Creates an invitation to join a team
+    /// Creates an invitation to join a team with the role chosen by the
+    /// inviter.
+    ///
+    /// Only a hash of the invitation token is stored; the token is returned
+    /// with the membership so it can be sent to the invited user.
     ///
     /// # Errors
     ///
-    /// When could not save the membership into the DB or user not found
+    /// When could not save the membership into the DB, user not found or
+    /// the inviter may not give the role of the invitation
     pub async fn create_invitation(
         db: &DatabaseConnection,
         team_id: i32,
         user_name: &str,
-    ) -> ModelResult<Self> {
+        params: &InvitationParams,
+        lifetimes: &TokenLifetimes,
+    ) -> ModelResult<(Self, String)> {
         // Find user by name
         let user = users::Entity::find()
             .filter(
                 model::query::condition()
                     .eq(users::Column::Name, user_name)
+                    .ne(users::Column::Status, super::users::STATUS_SERVICE_ACCOUNT)
                     .build(),
             )
             .one(db)
@@ -112,35 +281,452 @@ impl Model {
             .one(db)
             .await?;
 
-        if existing.is_some() {
-            return Err(ModelError::msg("User is already a member of this team"));
+        match existing {
+            Some(request) if request.requested => {
+                return Err(ModelError::msg(
+                    "User has requested to join this team. Approve their request instead",
+                ));
+            }
+            Some(_) => {
+                return Err(ModelError::msg("User is already a member of this team"));
+            }
+            None => {}
         }
+        params.check_role(db, team_id).await?;
 
         // Create invitation
+        let (token, token_hash) = tokens::generate_token();
         let membership = ActiveModel {
             team_id: ActiveValue::set(team_id),
-            user_id: ActiveValue::set(user.id),
-            role: ActiveValue::set("Observer".to_string()), // Default role
+            user_id: ActiveValue::set(Some(user.id)),
+            invited_by_id: ActiveValue::set(Some(params.invited_by_id)),
+            role: ActiveValue::set(params.role.clone()),
+            invitation_message: ActiveValue::set(params.message.clone()),
             pending: ActiveValue::set(true),
-            invitation_token: ActiveValue::set(Some(Uuid::new_v4().to_string())),
+            invitation_token: ActiveValue::set(Some(token_hash)),
             invitation_sent_at: ActiveValue::set(Some(Utc::now().into())),
+            invitation_expires_at: ActiveValue::set(Some(tokens::expires_at(
+                lifetimes.team_invitation_min,
+            ))),
             ..Default::default()
         }
         .insert(db)
         .await?;
 
+        Ok((membership, token))
+    }
+
+    /// Creates an invitation to join a team for an email address no account
+    /// is registered with. The invitation is bound to the account registered
+    /// with this address once the address is verified, see
+    /// [`Self::attach_email_invitations`].
+    ///
+    /// Only a hash of the invitation token is stored; the token is returned
+    /// with the membership so it can be sent to the invited address.
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the email address is invalid, belongs to a
+    /// registered user or was already invited to the team, when the inviter
+    /// may not give the role, or DB query error
+    pub async fn create_email_invitation(
+        db: &DatabaseConnection,
+        team_id: i32,
+        email: &str,
+        params: &InvitationParams,
+        lifetimes: &TokenLifetimes,
+    ) -> ModelResult<(Self, String)> {
+        let email = normalize_email(email);
+        if !email.validate_email() {
+            return Err(ModelError::msg("Invalid email address"));
+        }
+        match super::users::Model::find_by_email_ignoring_case(db, &email).await {
+            Ok(_) => {
+                return Err(ModelError::msg(
+                    "A user is already registered with this email address",
+                ));
+            }
+            Err(ModelError::EntityNotFound) => {}
+            Err(e) => return Err(e),
+        }
+        let existing = Entity::find()
+            .filter(team_memberships::Column::TeamId.eq(team_id))
+            .filter(team_memberships::Column::InvitedEmail.eq(email.as_str()))
+            .one(db)
+            .await?;
+        if existing.is_some() {
+            return Err(ModelError::msg(
+                "This email address already has a pending invitation to this team",
+            ));
+        }
+        params.check_role(db, team_id).await?;
+
+        let (token, token_hash) = tokens::generate_token();
+        let membership = ActiveModel {
+            team_id: ActiveValue::set(team_id),
+            user_id: ActiveValue::set(None),
+            invited_email: ActiveValue::set(Some(email)),
+            invited_by_id: ActiveValue::set(Some(params.invited_by_id)),
+            role: ActiveValue::set(params.role.clone()),
+            invitation_message: ActiveValue::set(params.message.clone()),
+            pending: ActiveValue::set(true),
+            invitation_token: ActiveValue::set(Some(token_hash)),
+            invitation_sent_at: ActiveValue::set(Some(Utc::now().into())),
+            invitation_expires_at: ActiveValue::set(Some(tokens::expires_at(
+                lifetimes.team_invitation_min,
+            ))),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+
+        Ok((membership, token))
+    }
+
+    /// Sends a pending invitation again: a new token replaces the one sent
+    /// before, and the invitation expires a full lifetime from now.
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the invitation was already accepted, or DB
+    /// query error
+    pub async fn resend_invitation(
+        &self,
+        db: &DatabaseConnection,
+        lifetimes: &TokenLifetimes,
+    ) -> ModelResult<(Self, String)> {
+        if !self.pending {
+            return Err(ModelError::msg("This invitation was already accepted"));
+        }
+        let (token, token_hash) = tokens::generate_token();
+        let mut invitation: ActiveModel = self.clone().into();
+        invitation.invitation_token = ActiveValue::set(Some(token_hash));
+        invitation.invitation_sent_at = ActiveValue::set(Some(Utc::now().into()));
+        invitation.invitation_expires_at =
+            ActiveValue::set(Some(tokens::expires_at(lifetimes.team_invitation_min)));
+        let invitation = invitation.update(db).await?;
+        Ok((invitation, token))
+    }
+
+    /// Lists the pending invitations of a team, oldest first, with the
+    /// invited user when they are registered and the member who sent the
+    /// invitation when known
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn get_team_invitations(
+        db: &DatabaseConnection,
+        team_id: i32,
+    ) -> ModelResult<Vec<TeamInvitation>> {
+        let invitations = Entity::find()
+            .filter(team_memberships::Column::TeamId.eq(team_id))
+            .filter(team_memberships::Column::Pending.eq(true))
+            .filter(team_memberships::Column::Requested.eq(false))
+            .order_by_asc(team_memberships::Column::InvitationSentAt)
+            .all(db)
+            .await?;
+        let user_ids: Vec<i32> = invitations
+            .iter()
+            .flat_map(|invitation| [invitation.user_id, invitation.invited_by_id])
+            .flatten()
+            .collect();
+        let users: HashMap<i32, users::Model> = users::Entity::find()
+            .filter(users::Column::Id.is_in(user_ids))
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|user| (user.id, user))
+            .collect();
+
+        Ok(invitations
+            .into_iter()
+            .map(|invitation| TeamInvitation {
+                invited_user: invitation.user_id.and_then(|id| users.get(&id).cloned()),
+                invited_by: invitation
+                    .invited_by_id
+                    .and_then(|id| users.get(&id).cloned()),
+                invitation,
+            })
+            .collect())
+    }
+
+    /// Deletes the pending invitations that expired, returning how many were
+    /// deleted
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn purge_expired_invitations(db: &DatabaseConnection) -> ModelResult<u64> {
+        let result = Entity::delete_many()
+            .filter(team_memberships::Column::Pending.eq(true))
+            .filter(team_memberships::Column::InvitationExpiresAt.lt(Utc::now()))
+            .exec(db)
+            .await?;
+        Ok(result.rows_affected)
+    }
+
+    /// Binds the invitations sent to the email address of a user who just
+    /// verified it to that user. They stay pending, so that the user can
+    /// accept or decline them like any other invitation. Expired invitations
+    /// are left aside, as are teams the user already belongs to or is already
+    /// invited to.
+    ///
+    /// Returns the teams the user is invited to.
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn attach_email_invitations(
+        db: &DatabaseConnection,
+        user: &users::Model,
+    ) -> ModelResult<Vec<teams::Model>> {
+        let invitations = Entity::find()
+            .filter(team_memberships::Column::InvitedEmail.eq(normalize_email(&user.email)))
+            .filter(team_memberships::Column::UserId.is_null())
+            .filter(team_memberships::Column::Pending.eq(true))
+            .find_also_related(teams::Entity)
+            .all(db)
+            .await?;
+
+        let txn = db.begin().await?;
+        let mut invited_to = Vec::new();
+        for (invitation, team) in invitations {
+            let Some(team) = team else {
+                continue;
+            };
+            if invitation.is_invitation_expired() {
+                continue;
+            }
+            let already_member = Entity::find()
+                .filter(team_memberships::Column::TeamId.eq(team.id))
+                .filter(team_memberships::Column::UserId.eq(user.id))
+                .count(&txn)
+                .await?
+                > 0;
+            if already_member {
+                continue;
+            }
+            let mut membership: ActiveModel = invitation.into();
+            membership.user_id = ActiveValue::set(Some(user.id));
+            membership.invited_email = ActiveValue::set(None);
+            membership.update(&txn).await?;
+            invited_to.push(team);
+        }
+        txn.commit().await?;
+        Ok(invited_to)
+    }
+
+    /// Adds a user to a team with the given role, without an invitation.
+    /// Used by application administrators; a pending invitation or request
+    /// of the user is turned into a membership.
+    ///
+    /// # Errors
+    ///
+    /// When the role is invalid, the user is a service account or already a
+    /// member, or DB query error
+    pub async fn add_member<C: ConnectionTrait>(
+        db: &C,
+        team_id: i32,
+        user: &users::Model,
+        role: &str,
+    ) -> ModelResult<Self> {
+        roles::Model::find_assignable(db, role).await?;
+        if user.is_service_account() {
+            return Err(ModelError::msg(
+                "Service accounts cannot be added to another team",
+            ));
+        }
+
+        let existing = Entity::find()
+            .filter(team_memberships::Column::TeamId.eq(team_id))
+            .filter(team_memberships::Column::UserId.eq(user.id))
+            .one(db)
+            .await?;
+        let membership = match existing {
+            Some(membership) if !membership.pending => {
+                return Err(ModelError::msg("User is already a member of this team"));
+            }
+            Some(invitation) => {
+                let mut membership: ActiveModel = invitation.into();
+                membership.role = ActiveValue::set(role.to_string());
+                membership.pending = ActiveValue::set(false);
+                membership.requested = ActiveValue::set(false);
+                membership.invitation_token = ActiveValue::set(None);
+                membership.invitation_expires_at = ActiveValue::set(None);
+                membership.update(db).await?
+            }
+            None => {
+                ActiveModel {
+                    team_id: ActiveValue::set(team_id),
+                    user_id: ActiveValue::set(Some(user.id)),
+                    role: ActiveValue::set(role.to_string()),
+                    pending: ActiveValue::set(false),
+                    ..Default::default()
+                }
+                .insert(db)
+                .await?
+            }
+        };
         Ok(membership)
     }
 
+    /// Requests to join a team listed in the team directory. The request is
+    /// a pending membership with the default role, until a team
+    /// administrator approves or denies it.
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the team is not listed, the user is a
+    /// service account, already belongs to the team, already asked to join
+    /// it or was invited to it, or DB query error
+    pub async fn create_request(
+        db: &DatabaseConnection,
+        team: &teams::Model,
+        user: &users::Model,
+    ) -> ModelResult<Self> {
+        if !team.listed {
+            return Err(ModelError::msg(
+                "This team does not accept requests to join",
+            ));
+        }
+        if user.is_service_account() {
+            return Err(ModelError::msg(
+                "Service accounts cannot be added to another team",
+            ));
+        }
+        let existing = Entity::find()
+            .filter(team_memberships::Column::TeamId.eq(team.id))
+            .filter(team_memberships::Column::UserId.eq(user.id))
+            .one(db)
+            .await?;
+        match existing {
+            Some(membership) if !membership.pending => {
+                return Err(ModelError::msg("You are already a member of this team"));
+            }
+            Some(request) if request.requested => {
+                return Err(ModelError::msg("You already requested to join this team"));
+            }
+            Some(_) => {
+                return Err(ModelError::msg(
+                    "You were invited to this team. Accept the invitation from your invitations",
+                ));
+            }
+            None => {}
+        }
+
+        let request = ActiveModel {
+            team_id: ActiveValue::set(team.id),
+            user_id: ActiveValue::set(Some(user.id)),
+            role: ActiveValue::set(DEFAULT_INVITATION_ROLE.to_string()),
+            pending: ActiveValue::set(true),
+            requested: ActiveValue::set(true),
+            invitation_sent_at: ActiveValue::set(Some(Utc::now().into())),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+        Ok(request)
+    }
+
+    /// Finds a request to join a team by its pid
+    ///
+    /// # Errors
+    ///
+    /// When could not find the request or DB query error
+    pub async fn find_request_by_pid(
+        db: &DatabaseConnection,
+        pid: &str,
+        team_id: i32,
+    ) -> ModelResult<Self> {
+        let pid = Uuid::parse_str(pid).map_err(|_| ModelError::EntityNotFound)?;
+        let request = Entity::find()
+            .filter(
+                model::query::condition()
+                    .eq(team_memberships::Column::Pid, pid)
+                    .eq(team_memberships::Column::TeamId, team_id)
+                    .eq(team_memberships::Column::Pending, true)
+                    .eq(team_memberships::Column::Requested, true)
+                    .build(),
+            )
+            .one(db)
+            .await?;
+        request.ok_or_else(|| ModelError::EntityNotFound)
+    }
+
+    /// Lists the requests to join a team, oldest first, with the users who
+    /// sent them
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn get_team_requests(
+        db: &DatabaseConnection,
+        team_id: i32,
+    ) -> ModelResult<Vec<(Self, users::Model)>> {
+        let requests = Entity::find()
+            .filter(team_memberships::Column::TeamId.eq(team_id))
+            .filter(team_memberships::Column::Pending.eq(true))
+            .filter(team_memberships::Column::Requested.eq(true))
+            .order_by_asc(team_memberships::Column::InvitationSentAt)
+            .find_also_related(users::Entity)
+            .all(db)
+            .await?;
+        Ok(requests
+            .into_iter()
+            .filter_map(|(request, user)| user.map(|user| (request, user)))
+            .collect())
+    }
+
+    /// Approves a request to join a team: the user becomes a member with
+    /// the role chosen by the approving member
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the role is invalid or grants permissions
+    /// the approving member does not have, or DB query error
+    pub async fn approve_request(
+        &self,
+        db: &DatabaseConnection,
+        approved_by_id: i32,
+        role: &str,
+    ) -> ModelResult<Self> {
+        let params = InvitationParams {
+            invited_by_id: approved_by_id,
+            role: role.to_string(),
+            message: None,
+        };
+        params.check_role(db, self.team_id).await?;
+
+        let mut membership: ActiveModel = self.clone().into();
+        membership.role = ActiveValue::set(params.role);
+        membership.invited_by_id = ActiveValue::set(Some(approved_by_id));
+        membership.pending = ActiveValue::set(false);
+        membership.requested = ActiveValue::set(false);
+        Ok(membership.update(db).await?)
+    }
+
+    /// Denies a request to join a team by deleting it
+    ///
+    /// # Errors
+    ///
+    /// When could not delete the request
+    pub async fn deny_request(&self, db: &DatabaseConnection) -> ModelResult<()> {
+        Entity::delete_by_id(self.id).exec(db).await?;
+        Ok(())
+    }
+
     /// Accepts an invitation to join a team
     ///
     /// # Errors
     ///
     /// When could not update the membership
-    pub async fn accept_invitation(&self, db: &DatabaseConnection) -> ModelResult<Self> {
+    pub async fn accept_invitation<C: ConnectionTrait>(&self, db: &C) -> ModelResult<Self> {
         let mut membership: ActiveModel = self.clone().into();
         membership.pending = ActiveValue::set(false);
+        membership.requested = ActiveValue::set(false);
         membership.invitation_token = ActiveValue::set(None);
+        membership.invitation_expires_at = ActiveValue::set(None);
 
         membership
             .update(db)
@@ -167,18 +753,137 @@ impl Model {
     ///
     /// When could not update the membership or invalid role
     pub async fn update_role(&self, db: &DatabaseConnection, new_role: &str) -> ModelResult<Self> {
-        // Validate role
-        if !VALID_ROLES.contains(&new_role) {
-            return Err(ModelError::msg("Invalid role"));
-        }
+        roles::Model::find_assignable(db, new_role).await?;
 
+        let txn = db.begin().await?;
+        if new_role != OWNER_ROLE {
+            self.check_not_last_owner(&txn, LAST_OWNER_ROLE_CHANGE)
+                .await?;
+        }
         let mut membership: ActiveModel = self.clone().into();
         membership.role = ActiveValue::set(new_role.to_string());
-
-        membership
-            .update(db)
+        let membership = membership
+            .update(&txn)
             .await
-            .map_err(|e| ModelError::Any(e.into()))
+            .map_err(|e| ModelError::Any(e.into()))?;
+        txn.commit().await?;
+        Ok(membership)
+    }
+
+    /// Ensures that the team keeps an Owner when this membership stops being
+    /// an Owner one: every team must have at least one Owner.
+    ///
+    /// Must run in the transaction that changes the membership: the Owner
+    /// rows of the team stay locked until it ends, so that concurrent
+    /// changes of the last Owners cannot all pass the check.
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` with the given message when this is the last
+    /// Owner of the team, or DB query error
+    async fn check_not_last_owner<C: ConnectionTrait>(
+        &self,
+        db: &C,
+        message: &str,
+    ) -> ModelResult<()> {
+        if self.pending || self.role != OWNER_ROLE {
+            return Ok(());
+        }
+        // Row locks cannot be combined with a count, so the rows are loaded
+        let owners = Entity::find()
+            .filter(team_memberships::Column::TeamId.eq(self.team_id))
+            .filter(team_memberships::Column::Role.eq(OWNER_ROLE))
+            .filter(team_memberships::Column::Pending.eq(false))
+            .lock_exclusive()
+            .all(db)
+            .await?;
+        if !owners.iter().any(|owner| owner.id != self.id) {
+            return Err(ModelError::msg(message));
+        }
+        Ok(())
+    }
+
+    /// Checks that a member may give another role to this member: the member
+    /// needs the `members.manage` permission, and their permissions in the
+    /// team must include those of both the current and the new role.
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the member may not change the role, or DB
+    /// query error
+    pub async fn check_role_change_allowed(
+        &self,
+        db: &DatabaseConnection,
+        team: &teams::Model,
+        member_id: i32,
+        new_role: &roles::Model,
+    ) -> ModelResult<()> {
+        let member_permissions = team.member_permissions(db, member_id).await?;
+        if member_permissions.is_empty() {
+            return Err(ModelError::msg("You are not a member of this team"));
+        }
+        if !member_permissions.contains(permissions::MEMBERS_MANAGE) {
+            return Err(ModelError::msg(
+                "You are not allowed to change the role of members",
+            ));
+        }
+        let includes_current_role = match roles::Model::find_by_name(db, &self.role).await {
+            // A role that no longer exists grants nothing
+            Err(ModelError::EntityNotFound) => true,
+            Err(e) => return Err(e),
+            Ok(current_role) => team.member_includes(db, member_id, &current_role).await?,
+        };
+        if !includes_current_role {
+            return Err(ModelError::msg(
+                "You cannot change the role of a member whose role grants permissions you do not have",
+            ));
+        }
+        if !team.member_includes(db, member_id, new_role).await? {
+            return Err(ModelError::msg(
+                "You cannot give a role granting permissions you do not have",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks that a member may remove this member from the team: members
+    /// with the `members.manage` permission remove anyone whose role their
+    /// permissions include, members with the `members.remove` permission
+    /// only those whose role grants fewer permissions than they have.
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the member may not remove this member, or
+    /// DB query error
+    pub async fn check_removal_allowed(
+        &self,
+        db: &DatabaseConnection,
+        team: &teams::Model,
+        member_id: i32,
+    ) -> ModelResult<()> {
+        let member_permissions = team.member_permissions(db, member_id).await?;
+        if member_permissions.is_empty() {
+            return Err(ModelError::msg("You are not a member of this team"));
+        }
+        let may_manage = member_permissions.contains(permissions::MEMBERS_MANAGE);
+        if !may_manage && !member_permissions.contains(permissions::MEMBERS_REMOVE) {
+            return Err(ModelError::msg("You are not allowed to remove members"));
+        }
+        let allowed = match roles::Model::find_by_name(db, &self.role).await {
+            // A role that no longer exists grants nothing
+            Err(ModelError::EntityNotFound) => true,
+            Err(e) => return Err(e),
+            Ok(removed_role) if may_manage => {
+                team.member_includes(db, member_id, &removed_role).await?
+            }
+            Ok(removed_role) => team.member_outranks(db, member_id, &removed_role).await?,
+        };
+        if allowed {
+            return Ok(());
+        }
+        Err(ModelError::msg(
+            "You can only remove members whose role grants fewer permissions than yours",
+        ))
     }
 
     /// Gets all pending invitations for a user
@@ -195,6 +900,7 @@ impl Model {
                 model::query::condition()
                     .eq(team_memberships::Column::UserId, user_id)
                     .eq(team_memberships::Column::Pending, true)
+                    .eq(team_memberships::Column::Requested, false)
                     .build(),
             )
             .find_with_related(teams::Entity)
@@ -215,16 +921,21 @@ impl Model {
         Ok(result)
     }
 
-    /// Removes a user from a team by deleting the membership
+    /// Removes a user from a team by deleting the membership, when the user
+    /// leaves or is removed
     ///
     /// # Errors
     ///
-    /// When could not delete the membership
+    /// `ModelError::Message` when this is the last Owner of the team, or
+    /// when could not delete the membership
     pub async fn remove_from_team(&self, db: &DatabaseConnection) -> ModelResult<()> {
+        let txn = db.begin().await?;
+        self.check_not_last_owner(&txn, LAST_OWNER_REMOVAL).await?;
         Entity::delete_by_id(self.id)
-            .exec(db)
+            .exec(&txn)
             .await
             .map_err(|e| ModelError::Any(e.into()))?;
+        txn.commit().await?;
         Ok(())
     }
 }
@@ -1,7 +1,9 @@
+use std::collections::BTreeSet;
+
 use loco_rs::prelude::*;
 use sea_orm::{
     ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr,
-    EntityTrait, QueryFilter, TransactionTrait,
+    EntityTrait, QueryFilter, QueryOrder, TransactionTrait,
 }; // Removed QuerySelect
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -10,6 +12,10 @@ use validator::Validate;
 pub use super::_entities::team_memberships;
 pub use super::_entities::teams::{self, ActiveModel, Model};
 pub use super::_entities::users::{self, Model as UserModel};
+use super::{
+    _entities::{organization_memberships, organizations, ownership_transfers},
+    roles, service_accounts,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateTeamParams {
@@ -73,25 +79,58 @@ impl Model {
         }
     }
 
-    /// Creates a new team and adds the creator as the Owner
+    /// Returns true when another team of the same organization, or another
+    /// team outside of any organization, has this name
+    async fn name_taken<C: ConnectionTrait>(
+        db: &C,
+        name: &str,
+        organization_id: Option<i32>,
+        except_id: Option<i32>,
+    ) -> ModelResult<bool> {
+        let mut query = teams::Entity::find().filter(teams::Column::Name.eq(name));
+        query = match organization_id {
+            Some(organization_id) => {
+                query.filter(teams::Column::OrganizationId.eq(organization_id))
+            }
+            None => query.filter(teams::Column::OrganizationId.is_null()),
+        };
+        if let Some(id) = except_id {
+            query = query.filter(teams::Column::Id.ne(id));
+        }
+        Ok(query.one(db).await?.is_some())
+    }
+
+    /// Creates a new team outside of any organization and adds the creator
+    /// as the Owner
     ///
     /// # Errors
     ///
     /// When could not save the team or team membership into the DB, or if name is not unique
-    pub async fn create_team(
-        db: &DatabaseConnection,
+    pub async fn create_team<C: ConnectionTrait + TransactionTrait>(
+        db: &C,
         user_id: i32,
         params: &CreateTeamParams,
+    ) -> ModelResult<Self> {
+        Self::create_team_in(db, user_id, params, None).await
+    }
+
+    /// Creates a new team in an organization, or outside of any when none is
+    /// given, and adds the creator as the Owner
+    ///
+    /// # Errors
+    ///
+    /// When could not save the team or team membership into the DB, or if
+    /// name is not unique in the organization
+    pub async fn create_team_in<C: ConnectionTrait + TransactionTrait>(
+        db: &C,
+        user_id: i32,
+        params: &CreateTeamParams,
+        organization_id: Option<i32>,
     ) -> ModelResult<Self> {
         let txn = db.begin().await?;
 
         // Check for name uniqueness before creating
-        if teams::Entity::find()
-            .filter(teams::Column::Name.eq(&params.name))
-            .one(&txn)
-            .await?
-            .is_some()
-        {
+        if Self::name_taken(&txn, &params.name, organization_id, None).await? {
             // Return ModelError::Message for uniqueness constraints
             return Err(ModelError::Message(format!(
                 "Team name '{}' already exists.",
@@ -107,6 +146,7 @@ impl Model {
             name: ActiveValue::set(params.name.to_string()),
             description: ActiveValue::set(params.description.clone()),
             pid: ActiveValue::set(team_pid), // Set PID explicitly here
+            organization_id: ActiveValue::set(organization_id),
             ..Default::default()
         };
 
@@ -119,8 +159,8 @@ impl Model {
         // Add creator as owner
         let membership = team_memberships::ActiveModel {
             team_id: ActiveValue::set(team.id),
-            user_id: ActiveValue::set(user_id),
-            role: ActiveValue::set("Owner".to_string()),
+            user_id: ActiveValue::set(Some(user_id)),
+            role: ActiveValue::set(roles::OWNER_ROLE.to_string()),
             pending: ActiveValue::set(false),
             ..Default::default()
         }
@@ -151,13 +191,8 @@ impl Model {
         // Note: params.name is the *new* proposed name from the form
         if params.name != self.name {
             // Only check if the name is actually different
-            if teams::Entity::find()
-                .filter(teams::Column::Name.eq(&params.name)) // Check against the new name
-                .filter(teams::Column::Id.ne(self.id)) // Exclude the current team
-                .one(db)
-                .await?
-                .is_some()
-            {
+            // Check against the new name, excluding the current team
+            if Self::name_taken(db, &params.name, self.organization_id, Some(self.id)).await? {
                 // Return ModelError::Message for uniqueness constraints
                 return Err(ModelError::Message(format!(
                     "Team name '{}' already exists.",
@@ -177,6 +212,148 @@ impl Model {
             .map_err(|e| ModelError::Any(e.into())) // Use standard update
     }
 
+    /// Moves the team into an organization, or out of any when none is given
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the organization already has a team with
+    /// this name, or DB query error
+    pub async fn move_to_organization(
+        &self,
+        db: &DatabaseConnection,
+        organization_id: Option<i32>,
+    ) -> ModelResult<Self> {
+        if Self::name_taken(db, &self.name, organization_id, Some(self.id)).await? {
+            return Err(ModelError::Message(format!(
+                "Team name '{}' already exists in the destination.",
+                self.name
+            )));
+        }
+        let mut team: teams::ActiveModel = self.clone().into();
+        team.organization_id = ActiveValue::set(organization_id);
+        Ok(team.update(db).await?)
+    }
+
+    /// Checks that a user may move the team into the organization, or out of
+    /// any when none is given: the user needs the `team.delete` permission in
+    /// the team and must be an Owner of both the organization the team
+    /// leaves and the one it joins.
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when the user may not move the team, or DB query
+    /// error
+    pub async fn check_move_allowed(
+        &self,
+        db: &DatabaseConnection,
+        user_id: i32,
+        destination: Option<&organizations::Model>,
+    ) -> ModelResult<()> {
+        if !self
+            .has_permission(db, user_id, super::permissions::TEAM_DELETE)
+            .await?
+        {
+            return Err(ModelError::msg(
+                "You are not allowed to move this team to another organization",
+            ));
+        }
+        if let Some(organization) = self.organization(db).await?
+            && !organization.is_owner(db, user_id).await?
+        {
+            return Err(ModelError::msg(
+                "Only the owners of its organization can move this team out of it",
+            ));
+        }
+        if let Some(organization) = destination
+            && !organization.is_owner(db, user_id).await?
+        {
+            return Err(ModelError::msg(
+                "Only the owners of an organization can move teams into it",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Finds the organization of the team, if any
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn organization(
+        &self,
+        db: &DatabaseConnection,
+    ) -> ModelResult<Option<organizations::Model>> {
+        let Some(organization_id) = self.organization_id else {
+            return Ok(None);
+        };
+        Ok(organizations::Entity::find_by_id(organization_id)
+            .one(db)
+            .await?)
+    }
+
+    /// Returns true when this is the team of the application administrators,
+    /// which never belongs to an organization
+    #[must_use]
+    pub fn is_admin_team(&self, ctx: &AppContext) -> bool {
+        self.organization_id.is_none() && self.name == UserModel::get_admin_team_name(ctx)
+    }
+
+    /// Lists the teams a user is an active member of, directly or through
+    /// the organization of the team, ordered by name
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn list_visible_to(db: &DatabaseConnection, user_id: i32) -> ModelResult<Vec<Self>> {
+        let team_ids = team_memberships::Entity::find()
+            .filter(team_memberships::Column::UserId.eq(user_id))
+            .filter(team_memberships::Column::Pending.eq(false))
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|membership| membership.team_id);
+        let organization_ids = organization_memberships::Entity::find()
+            .filter(organization_memberships::Column::UserId.eq(user_id))
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|membership| membership.organization_id);
+        Ok(teams::Entity::find()
+            .filter(
+                sea_orm::Condition::any()
+                    .add(teams::Column::Id.is_in(team_ids))
+                    .add(teams::Column::OrganizationId.is_in(organization_ids)),
+            )
+            .order_by_asc(teams::Column::Name)
+            .all(db)
+            .await?)
+    }
+
+    /// Lists the teams listed in the team directory, ordered by name
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn list_directory(db: &DatabaseConnection) -> ModelResult<Vec<Self>> {
+        Ok(teams::Entity::find()
+            .filter(teams::Column::Listed.eq(true))
+            .order_by_asc(teams::Column::Name)
+            .all(db)
+            .await?)
+    }
+
+    /// Lists the team in the team directory, where users can request to
+    /// join it, or removes it from there. Requests already sent are kept.
+    ///
+    /// # Errors
+    ///
+    /// When could not save the team into the DB
+    pub async fn set_listed(&self, db: &DatabaseConnection, listed: bool) -> ModelResult<Self> {
+        let mut team: teams::ActiveModel = self.clone().into();
+        team.listed = ActiveValue::set(listed);
+        Ok(team.update(db).await?)
+    }
+
     /// Gets all team members with their roles
     ///
     /// # Errors
@@ -211,25 +388,39 @@ impl Model {
         Ok(result)
     }
 
-    /// Checks if a user has a specific role or higher in the team
+    /// Lists the active members of the team who have the permission, one of
+    /// the constants of [`super::permissions`]. Service accounts are left
+    /// out.
     ///
     /// # Errors
     ///
     /// When DB query error
-    pub async fn has_role(
+    pub async fn members_with_permission(
         &self,
         db: &DatabaseConnection,
-        user_id: i32,
-        role: &str,
-    ) -> ModelResult<bool> {
-        let role_level = match role {
-            "Owner" => 4,
-            "Administrator" => 3,
-            "Developer" => 2,
-            "Observer" => 1,
-            _ => return Ok(false),
-        };
+        permission: &str,
+    ) -> ModelResult<Vec<UserModel>> {
+        let mut members = Vec::new();
+        for (member, _) in self.get_members(db).await? {
+            if !member.is_service_account()
+                && self.has_permission(db, member.id, permission).await?
+            {
+                members.push(member);
+            }
+        }
+        Ok(members)
+    }
 
+    /// Finds the role of an active member of the team
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn member_role(
+        &self,
+        db: &DatabaseConnection,
+        user_id: i32,
+    ) -> ModelResult<Option<roles::Model>> {
         let membership = team_memberships::Entity::find()
             .filter(
                 model::query::condition()
@@ -241,20 +432,129 @@ impl Model {
             .one(db)
             .await?;
 
-        if let Some(membership) = membership {
-            let member_role_level = match membership.role.as_str() {
-                "Owner" => 4,
-                "Administrator" => 3,
-                "Developer" => 2,
-                "Observer" => 1,
-                _ => 0,
-            };
-            Ok(member_role_level >= role_level)
-        } else {
-            Ok(false)
+        let Some(membership) = membership else {
+            return Ok(None);
+        };
+        match roles::Model::find_by_name(db, &membership.role).await {
+            Ok(role) => Ok(Some(role)),
+            Err(ModelError::EntityNotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Finds the role a user has in every team of the organization of the
+    /// team, if any
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn organization_role(
+        &self,
+        db: &DatabaseConnection,
+        user_id: i32,
+    ) -> ModelResult<Option<roles::Model>> {
+        match self.organization(db).await? {
+            Some(organization) => organization.member_role(db, user_id).await,
+            None => Ok(None),
         }
     }
 
+    /// Checks if a user has the permission in the team, one of the constants
+    /// of [`super::permissions`], see [`Self::member_permissions`]
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn has_permission(
+        &self,
+        db: &DatabaseConnection,
+        user_id: i32,
+        permission: &str,
+    ) -> ModelResult<bool> {
+        Ok(self
+            .member_permissions(db, user_id)
+            .await?
+            .contains(permission))
+    }
+
+    /// Lists the permissions a user has in the team: those of their role as
+    /// an active member of the team and those of their role in the
+    /// organization of the team. None when the user is neither.
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn member_permissions(
+        &self,
+        db: &DatabaseConnection,
+        user_id: i32,
+    ) -> ModelResult<BTreeSet<String>> {
+        let mut permissions = BTreeSet::new();
+        if let Some(role) = self.member_role(db, user_id).await? {
+            permissions.extend(role.permissions(db).await?);
+        }
+        if let Some(role) = self.organization_role(db, user_id).await? {
+            permissions.extend(role.permissions(db).await?);
+        }
+        Ok(permissions)
+    }
+
+    /// Returns true when the permissions of a user in the team include every
+    /// permission of the role. A member may only give the roles their
+    /// permissions include.
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn member_includes(
+        &self,
+        db: &DatabaseConnection,
+        user_id: i32,
+        role: &roles::Model,
+    ) -> ModelResult<bool> {
+        Ok(role
+            .permissions(db)
+            .await?
+            .is_subset(&self.member_permissions(db, user_id).await?))
+    }
+
+    /// Returns true when the permissions of a user in the team include every
+    /// permission of the role and more
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn member_outranks(
+        &self,
+        db: &DatabaseConnection,
+        user_id: i32,
+        role: &roles::Model,
+    ) -> ModelResult<bool> {
+        let permissions = self.member_permissions(db, user_id).await?;
+        let role_permissions = role.permissions(db).await?;
+        Ok(role_permissions.is_subset(&permissions) && role_permissions.len() < permissions.len())
+    }
+
+    /// Lists the names of the roles a member may give in the team, those
+    /// their permissions include, granting the most permissions first
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn grantable_roles(
+        &self,
+        db: &DatabaseConnection,
+        user_id: i32,
+    ) -> ModelResult<Vec<String>> {
+        let permissions = self.member_permissions(db, user_id).await?;
+        Ok(roles::Model::list(db)
+            .await?
+            .into_iter()
+            .filter(|details| details.permissions.is_subset(&permissions))
+            .map(|details| details.role.name)
+            .collect())
+    }
+
     /// Deletes the team and all associated memberships
     ///
     /// # Errors
@@ -262,6 +562,12 @@ impl Model {
     /// When could not delete the team from the DB
     pub async fn delete(&self, db: &DatabaseConnection) -> ModelResult<()> {
         let txn = db.begin().await?;
+        // Service accounts belong to the team and go away with it
+        service_accounts::Model::delete_for_team(&txn, self.id).await?;
+        ownership_transfers::Entity::delete_many()
+            .filter(ownership_transfers::Column::TeamId.eq(self.id))
+            .exec(&txn)
+            .await?;
         // Delete memberships first due to foreign key constraint
         team_memberships::Entity::delete_many()
             .filter(team_memberships::Column::TeamId.eq(self.id))
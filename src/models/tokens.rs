@@ -0,0 +1,106 @@
+use chrono::{Duration, Utc};
+use loco_rs::{hash, prelude::*};
+use sea_orm::prelude::DateTimeWithTimeZone;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Number of characters of the tokens sent by email
+pub const TOKEN_LENGTH: usize = 32;
+/// Message of the error returned by the finders when a token is found but
+/// has expired
+pub const TOKEN_EXPIRED: &str = "token expired";
+
+/// Lifetimes, in minutes, of the tokens sent by email, read from
+/// `settings.app.token_lifetimes`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TokenLifetimes {
+    pub password_reset_min: i64,
+    pub email_verification_min: i64,
+    pub pgp_verification_min: i64,
+    pub team_invitation_min: i64,
+    /// Lifetime of the link sent to accounts created by an administrator to
+    /// choose their password
+    pub account_setup_min: i64,
+}
+
+impl Default for TokenLifetimes {
+    fn default() -> Self {
+        Self {
+            password_reset_min: 60,
+            email_verification_min: 2 * 24 * 60,
+            pgp_verification_min: 24 * 60,
+            team_invitation_min: 7 * 24 * 60,
+            account_setup_min: 7 * 24 * 60,
+        }
+    }
+}
+
+impl TokenLifetimes {
+    /// Reads the lifetimes from `settings.app.token_lifetimes`, falling back
+    /// to the defaults for missing keys
+    ///
+    /// # Errors
+    ///
+    /// When the configured lifetimes are invalid
+    pub fn from_context(ctx: &AppContext) -> ModelResult<Self> {
+        let settings = ctx
+            .config
+            .settings
+            .as_ref()
+            .and_then(|settings| settings.get("app"))
+            .and_then(|app_settings| app_settings.get("token_lifetimes"));
+        match settings {
+            Some(lifetimes) => serde_json::from_value(lifetimes.clone()).map_err(|e| {
+                ModelError::Message(format!("Invalid token lifetimes configuration: {e}"))
+            }),
+            None => Ok(Self::default()),
+        }
+    }
+}
+
+/// Hashes a token the way it is stored in the database. Tokens are long
+/// random strings, so a fast unsalted hash is enough and allows lookups.
+#[must_use]
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Generates a new random token. Returns the plaintext token, to be sent to
+/// the user, and its hash, to be stored.
+#[must_use]
+pub fn generate_token() -> (String, String) {
+    let token = hash::random_string(TOKEN_LENGTH);
+    let token_hash = hash_token(&token);
+    (token, token_hash)
+}
+
+/// Returns the expiration time of a token issued now and valid for the given
+/// number of minutes
+#[must_use]
+pub fn expires_at(lifetime_min: i64) -> DateTimeWithTimeZone {
+    (Utc::now() + Duration::minutes(lifetime_min)).into()
+}
+
+/// Checks that a token has not expired. A token without an expiration time
+/// was issued before expiry was enforced and is treated as expired.
+///
+/// # Errors
+///
+/// [`TOKEN_EXPIRED`] when the token has expired
+pub fn check_not_expired(expires_at: Option<DateTimeWithTimeZone>) -> ModelResult<()> {
+    match expires_at {
+        Some(expires_at) if expires_at > Utc::now() => Ok(()),
+        _ => Err(ModelError::msg(TOKEN_EXPIRED)),
+    }
+}
+
+/// Returns true when the error was returned by a finder for a token that
+/// exists but has expired
+#[must_use]
+pub fn is_expired(err: &ModelError) -> bool {
+    matches!(err, ModelError::Message(msg) if msg == TOKEN_EXPIRED)
+}
@@ -0,0 +1,512 @@
+//! Bulk import of users by administrators, from CSV or JSON, and the matching
+//! export. A record is a user with an optional team, role and SSH keys; a
+//! user who belongs to several teams spans several records. Records name
+//! teams outside of any organization, whose names are unique.
+
+use std::collections::{HashMap, HashSet};
+
+use loco_rs::{hash, prelude::*};
+use sea_orm::{QueryOrder, TransactionTrait};
+use serde::{Deserialize, Serialize};
+use validator::ValidateEmail;
+
+use super::{
+    _entities::{ssh_keys, team_memberships, teams},
+    password_policy::PasswordPolicy,
+    roles,
+    teams::CreateTeamParams,
+    users::{self, RegisterParams},
+};
+use crate::controllers::ssh_key_api::is_valid_ssh_public_key;
+
+/// Role given to an imported member when the record names a team but no role
+pub const DEFAULT_IMPORT_ROLE: &str = "Developer";
+/// Maximum number of records of an import
+pub const MAX_IMPORT_RECORDS: usize = 1000;
+
+/// Format of an import or export file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DataFormat {
+    Csv,
+    Json,
+}
+
+impl DataFormat {
+    /// Extension of the files of this format
+    #[must_use]
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Json => "json",
+        }
+    }
+
+    /// Media type of the files of this format
+    #[must_use]
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Json => "application/json",
+        }
+    }
+}
+
+/// A user, with one of their team memberships and their SSH keys
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct UserRecord {
+    pub name: String,
+    pub email: String,
+    #[serde(default)]
+    pub team: Option<String>,
+    #[serde(default)]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub ssh_keys: Vec<String>,
+}
+
+/// A row of a CSV file, where the SSH keys are one per line of their cell
+#[derive(Debug, Deserialize, Serialize)]
+struct CsvRecord {
+    name: String,
+    email: String,
+    #[serde(default)]
+    team: Option<String>,
+    #[serde(default)]
+    role: Option<String>,
+    #[serde(default)]
+    ssh_keys: Option<String>,
+}
+
+/// What importing a record will do, or why it cannot be imported
+#[derive(Debug, Serialize)]
+pub struct PlannedRecord {
+    /// Number of the record in the file, starting at 1
+    pub number: usize,
+    /// The record, trimmed and with its default role
+    pub record: UserRecord,
+    /// Whether this record creates the user, rather than adding a membership
+    /// to a user created by a previous record
+    pub creates_user: bool,
+    /// Whether this record creates its team, with the user as its Owner
+    pub creates_team: bool,
+    pub errors: Vec<String>,
+}
+
+/// Outcome of a dry run of an import
+#[derive(Debug, Serialize)]
+pub struct ImportPlan {
+    pub records: Vec<PlannedRecord>,
+    pub user_count: usize,
+    pub team_count: usize,
+    pub error_count: usize,
+}
+
+impl ImportPlan {
+    /// Whether every record can be imported
+    #[must_use]
+    pub fn is_valid(&self) -> bool {
+        self.error_count == 0
+    }
+}
+
+/// Parses the records of an import file
+///
+/// # Errors
+///
+/// `ModelError::Message` when the file is malformed, empty or has too many
+/// records
+pub fn parse(format: DataFormat, data: &str) -> ModelResult<Vec<UserRecord>> {
+    let records = match format {
+        DataFormat::Csv => {
+            let mut reader = csv::ReaderBuilder::new()
+                .trim(csv::Trim::All)
+                .from_reader(data.as_bytes());
+            let mut records = Vec::new();
+            for (index, row) in reader.deserialize::<CsvRecord>().enumerate() {
+                let row = row.map_err(|e| {
+                    ModelError::Message(format!("Invalid CSV record {}: {e}", index + 1))
+                })?;
+                records.push(UserRecord {
+                    name: row.name,
+                    email: row.email,
+                    team: row.team,
+                    role: row.role,
+                    ssh_keys: row
+                        .ssh_keys
+                        .unwrap_or_default()
+                        .lines()
+                        .map(ToString::to_string)
+                        .collect(),
+                });
+            }
+            records
+        }
+        DataFormat::Json => serde_json::from_str::<Vec<UserRecord>>(data)
+            .map_err(|e| ModelError::Message(format!("Invalid JSON: {e}")))?,
+    };
+    if records.is_empty() {
+        return Err(ModelError::msg("The file has no records"));
+    }
+    if records.len() > MAX_IMPORT_RECORDS {
+        return Err(ModelError::Message(format!(
+            "An import is limited to {MAX_IMPORT_RECORDS} records"
+        )));
+    }
+    Ok(records)
+}
+
+/// Writes records in the format of the import files
+///
+/// # Errors
+///
+/// When the records could not be serialized
+pub fn serialize(format: DataFormat, records: &[UserRecord]) -> ModelResult<String> {
+    match format {
+        DataFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for record in records {
+                writer
+                    .serialize(CsvRecord {
+                        name: record.name.clone(),
+                        email: record.email.clone(),
+                        team: record.team.clone(),
+                        role: record.role.clone(),
+                        ssh_keys: Some(record.ssh_keys.join("\n")),
+                    })
+                    .map_err(|e| ModelError::Any(e.into()))?;
+            }
+            let data = writer
+                .into_inner()
+                .map_err(|e| ModelError::Any(e.into_error().into()))?;
+            String::from_utf8(data).map_err(|e| ModelError::Any(e.into()))
+        }
+        DataFormat::Json => {
+            serde_json::to_string_pretty(records).map_err(|e| ModelError::Any(e.into()))
+        }
+    }
+}
+
+/// Trims the fields of a record, drops its empty values and gives it the
+/// default role when it names a team without a role
+fn normalize(record: UserRecord) -> UserRecord {
+    let non_empty = |value: Option<String>| {
+        value
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+    let team = non_empty(record.team);
+    let role =
+        non_empty(record.role).or_else(|| team.as_ref().map(|_| DEFAULT_IMPORT_ROLE.to_string()));
+    UserRecord {
+        name: record.name.trim().to_string(),
+        email: record.email.trim().to_string(),
+        team,
+        role,
+        ssh_keys: record
+            .ssh_keys
+            .iter()
+            .map(|key| key.trim().to_string())
+            .filter(|key| !key.is_empty())
+            .collect(),
+    }
+}
+
+/// Checks every record against the database and the previous records,
+/// without changing anything
+///
+/// # Errors
+///
+/// When DB query error
+pub async fn plan(
+    db: &DatabaseConnection,
+    admin_team_name: &str,
+    records: Vec<UserRecord>,
+) -> ModelResult<ImportPlan> {
+    // Name of the user created by the first record of each email address
+    let mut names_by_email: HashMap<String, String> = HashMap::new();
+    let mut emails_by_name: HashMap<String, String> = HashMap::new();
+    let mut memberships: HashSet<(String, String)> = HashSet::new();
+    // Whether each team named by a record already exists
+    let mut existing_teams: HashMap<String, bool> = HashMap::new();
+    let mut created_teams: HashSet<String> = HashSet::new();
+    let role_names = roles::Model::names(db).await?;
+
+    let mut planned = Vec::with_capacity(records.len());
+    for (index, record) in records.into_iter().enumerate() {
+        let record = normalize(record);
+        let mut errors = Vec::new();
+
+        if record.name.chars().count() < 2 {
+            errors.push("Name must be at least 2 characters long.".to_string());
+        }
+        if !record.email.validate_email() {
+            errors.push("Invalid email format.".to_string());
+        }
+
+        let creates_user = match names_by_email.get(&record.email) {
+            Some(name) => {
+                if *name != record.name {
+                    errors.push(format!(
+                        "This email address is used by {name} in a previous record."
+                    ));
+                }
+                false
+            }
+            None => {
+                if let Some(email) = emails_by_name.get(&record.name) {
+                    errors.push(format!(
+                        "This name is used by {email} in a previous record."
+                    ));
+                }
+                if users::Entity::find()
+                    .filter(users::users::Column::Email.eq(&record.email))
+                    .one(db)
+                    .await?
+                    .is_some()
+                {
+                    errors.push("Email already registered.".to_string());
+                }
+                if users::Entity::find()
+                    .filter(users::users::Column::Name.eq(&record.name))
+                    .one(db)
+                    .await?
+                    .is_some()
+                {
+                    errors.push("Username already taken.".to_string());
+                }
+                names_by_email.insert(record.email.clone(), record.name.clone());
+                emails_by_name.insert(record.name.clone(), record.email.clone());
+                true
+            }
+        };
+
+        let mut creates_team = false;
+        match (&record.team, &record.role) {
+            (None, Some(_)) => errors.push("A role needs a team.".to_string()),
+            (Some(team), Some(role)) => {
+                if !role_names.contains(role) {
+                    errors.push(format!("Invalid role {role}."));
+                }
+                if team == admin_team_name {
+                    errors
+                        .push("Members of the administrators team cannot be imported.".to_string());
+                } else if team.chars().count() < 2 {
+                    errors.push("Team name must be at least 2 characters long.".to_string());
+                }
+                if !memberships.insert((record.email.clone(), team.clone())) {
+                    errors.push(
+                        "This user is already added to this team by a previous record.".to_string(),
+                    );
+                }
+                let exists = match existing_teams.get(team) {
+                    Some(exists) => *exists,
+                    None => {
+                        let exists = teams::Entity::find()
+                            .filter(teams::Column::Name.eq(team))
+                            .filter(teams::Column::OrganizationId.is_null())
+                            .one(db)
+                            .await?
+                            .is_some();
+                        existing_teams.insert(team.clone(), exists);
+                        exists
+                    }
+                };
+                creates_team = !exists && created_teams.insert(team.clone());
+            }
+            _ => {}
+        }
+
+        if let Some(position) = record
+            .ssh_keys
+            .iter()
+            .position(|key| !is_valid_ssh_public_key(key))
+        {
+            errors.push(format!(
+                "SSH key {} is not a valid public key.",
+                position + 1
+            ));
+        }
+
+        planned.push(PlannedRecord {
+            number: index + 1,
+            record,
+            creates_user,
+            creates_team,
+            errors,
+        });
+    }
+
+    Ok(ImportPlan {
+        user_count: planned.iter().filter(|record| record.creates_user).count(),
+        team_count: planned.iter().filter(|record| record.creates_team).count(),
+        error_count: planned.iter().map(|record| record.errors.len()).sum(),
+        records: planned,
+    })
+}
+
+/// Imports the records of a plan without errors. The accounts are created
+/// like registrations, with a random password, and their email addresses
+/// are trusted since an administrator provided them. A new team gets the
+/// user of its first record as Owner.
+///
+/// The import runs in a single transaction: when a record fails, nothing is
+/// imported.
+///
+/// Returns the created users, who still have to choose a password.
+///
+/// # Errors
+///
+/// `ModelError::Message` when the plan has errors, or DB query error
+pub async fn apply(db: &DatabaseConnection, plan: &ImportPlan) -> ModelResult<Vec<users::Model>> {
+    if !plan.is_valid() {
+        return Err(ModelError::msg("The import has errors"));
+    }
+
+    let txn = db.begin().await?;
+    let mut created: Vec<users::Model> = Vec::new();
+    for planned in &plan.records {
+        let record = &planned.record;
+        let user = if planned.creates_user {
+            // The random password is never shown to the user, so the
+            // configured policy does not apply to it
+            let password = hash::random_string(32);
+            let user = users::Model::create_with_password(
+                &txn,
+                &RegisterParams {
+                    email: record.email.clone(),
+                    password: password.clone(),
+                    name: record.name.clone(),
+                    password_confirmation: password,
+                },
+                &PasswordPolicy::default(),
+            )
+            .await?
+            .into_active_model()
+            .verified(&txn)
+            .await?;
+            created.push(user.clone());
+            user
+        } else {
+            created
+                .iter()
+                .find(|user| user.email == record.email)
+                .cloned()
+                .ok_or_else(|| ModelError::EntityNotFound)?
+        };
+
+        for key in &record.ssh_keys {
+            let exists = ssh_keys::Entity::find()
+                .filter(ssh_keys::Column::UserId.eq(user.id))
+                .filter(ssh_keys::Column::PublicKey.eq(key))
+                .one(&txn)
+                .await?
+                .is_some();
+            if !exists {
+                ssh_keys::ActiveModel {
+                    user_id: ActiveValue::Set(user.id),
+                    public_key: ActiveValue::Set(key.clone()),
+                    ..Default::default()
+                }
+                .insert(&txn)
+                .await?;
+            }
+        }
+
+        if let (Some(team_name), Some(role)) = (&record.team, &record.role) {
+            if planned.creates_team {
+                teams::Model::create_team(
+                    &txn,
+                    user.id,
+                    &CreateTeamParams {
+                        name: team_name.clone(),
+                        description: None,
+                    },
+                )
+                .await?;
+            } else {
+                let team = teams::Entity::find()
+                    .filter(teams::Column::Name.eq(team_name))
+                    .filter(teams::Column::OrganizationId.is_null())
+                    .one(&txn)
+                    .await?
+                    .ok_or_else(|| ModelError::EntityNotFound)?;
+                super::team_memberships::Model::add_member(&txn, team.id, &user, role).await?;
+            }
+        }
+    }
+
+    txn.commit().await?;
+
+    tracing::info!(
+        user_count = created.len(),
+        team_count = plan.team_count,
+        "Users imported"
+    );
+    Ok(created)
+}
+
+/// Lists every user, except service accounts, with their team memberships
+/// and SSH keys, in the format of the import files. A user gets one record
+/// per team, and their SSH keys are on their first record only.
+///
+/// # Errors
+///
+/// When DB query error
+pub async fn export(db: &DatabaseConnection) -> ModelResult<Vec<UserRecord>> {
+    let users = users::Entity::find()
+        .filter(users::users::Column::Status.ne(users::STATUS_SERVICE_ACCOUNT))
+        .order_by_asc(users::users::Column::Name)
+        .all(db)
+        .await?;
+    let team_names = teams::Entity::find()
+        .filter(teams::Column::OrganizationId.is_null())
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|team| (team.id, team.name))
+        .collect::<HashMap<_, _>>();
+
+    let mut records = Vec::new();
+    for user in users {
+        let ssh_keys = ssh_keys::Entity::find()
+            .filter(ssh_keys::Column::UserId.eq(user.id))
+            .order_by_asc(ssh_keys::Column::CreatedAt)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|key| key.public_key)
+            .collect::<Vec<_>>();
+        let mut memberships = team_memberships::Entity::find()
+            .filter(team_memberships::Column::UserId.eq(user.id))
+            .filter(team_memberships::Column::Pending.eq(false))
+            .all(db)
+            .await?
+            .into_iter()
+            .filter_map(|membership| {
+                team_names
+                    .get(&membership.team_id)
+                    .map(|team| (team.clone(), membership.role))
+            })
+            .collect::<Vec<_>>();
+        memberships.sort();
+
+        let mut record = UserRecord {
+            name: user.name,
+            email: user.email,
+            ssh_keys,
+            ..Default::default()
+        };
+        if memberships.is_empty() {
+            records.push(record);
+            continue;
+        }
+        for (team, role) in memberships {
+            record.team = Some(team);
+            record.role = Some(role);
+            records.push(record.clone());
+            record.ssh_keys = Vec::new();
+        }
+    }
+    Ok(records)
+}
@@ -3,18 +3,77 @@ use chrono::{DateTime, Duration, Utc, offset::Local};
 use loco_rs::{auth::jwt, hash, prelude::*};
 use reqwest;
 use sea_orm::{
-    ActiveValue, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, PaginatorTrait, QueryFilter, Set,
+    ActiveValue, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, PaginatorTrait, QueryFilter,
+    QueryOrder, Set, TransactionTrait,
 };
 use sequoia_openpgp::{self as openpgp, parse::Parse, policy::StandardPolicy};
 use serde::{Deserialize, Serialize};
+use totp_rs::{Algorithm, Secret, TOTP};
 use uuid::Uuid;
 use validator::Validate;
 
 pub use super::_entities::users::{self, ActiveModel, Entity, Model};
-use super::_entities::{team_memberships, teams};
+use super::_entities::{
+    impersonation_events, oidc_identities, organization_memberships, ownership_transfers,
+    service_accounts, ssh_keys, team_memberships, teams, webauthn_challenges,
+};
+use super::{
+    login_attempts,
+    password_policy::PasswordPolicy,
+    personal_access_tokens, recovery_codes, roles, sessions,
+    tokens::{self, TokenLifetimes},
+    webauthn_credentials,
+};
 
 pub const MAGIC_LINK_LENGTH: i8 = 32;
 pub const MAGIC_LINK_EXPIRATION_MIN: i8 = 5;
+/// Issuer shown by authenticator apps next to the account name
+pub const TOTP_ISSUER: &str = "Hosting Farm";
+/// Lifetime of the token identifying a login waiting for its second factor
+pub const MFA_CHALLENGE_EXPIRATION_SEC: u64 = 300;
+/// Lifetime of the token of an administrator impersonating a user
+pub const IMPERSONATION_EXPIRATION_SEC: u64 = 3600;
+
+/// Status of an account that can log in
+pub const STATUS_ACTIVE: &str = "active";
+/// Status of an account registered while registrations require the approval
+/// of an administrator, until it is approved
+pub const STATUS_PENDING_APPROVAL: &str = "pending_approval";
+/// Status of an account an administrator suspended
+pub const STATUS_SUSPENDED: &str = "suspended";
+/// Status of the account behind a team service account, which cannot log in
+/// and only authenticates with personal access tokens
+pub const STATUS_SERVICE_ACCOUNT: &str = "service_account";
+
+/// Error message of a registration with an email address already in use
+pub const EMAIL_TAKEN: &str = "Email already registered.";
+/// Error message of a registration with a name already in use
+pub const NAME_TAKEN: &str = "Username already taken. Please choose another.";
+
+/// Returns true when the registration was refused because the email address
+/// or the name belongs to an existing account
+#[must_use]
+pub fn is_already_registered(err: &ModelError) -> bool {
+    matches!(err, ModelError::Message(msg) if msg == EMAIL_TAKEN || msg == NAME_TAKEN)
+}
+
+/// Returns the form in which email addresses are compared: trimmed and
+/// lowercased
+#[must_use]
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Returns the domain of an email address, lowercased, `None` when the
+/// address has no domain
+#[must_use]
+pub fn email_domain(email: &str) -> Option<String> {
+    email
+        .trim()
+        .rsplit_once('@')
+        .map(|(_, domain)| domain.to_lowercase())
+        .filter(|domain| !domain.is_empty())
+}
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct LoginParams {
@@ -61,6 +120,22 @@ pub struct Validator {
     pub pgp_key: Option<String>,
 }
 
+/// Result of a login step (password or second factor) guarded by the
+/// brute-force protection
+#[derive(Debug)]
+pub enum LoginOutcome {
+    /// The credentials are valid
+    Success(Model),
+    /// The credentials are invalid. Carries the user when this failure just
+    /// locked the account, so that the owner can be notified.
+    Failed { locked: Option<Model> },
+    /// Too many failed attempts for this account or IP address: the
+    /// credentials were not checked
+    Throttled { retry_after_sec: i64 },
+    /// The credentials are valid but the account may not log in yet
+    Inactive(Model),
+}
+
 #[derive(Clone, Debug, Serialize)]
 pub struct LayoutContext {
     pub is_app_admin: bool,
@@ -103,6 +178,8 @@ impl ActiveModelBehavior for super::_entities::users::ActiveModel {
             if matches!(this.pid, ActiveValue::NotSet) {
                 this.pid = ActiveValue::Set(Uuid::new_v4());
             }
+            // The legacy API key is superseded by personal access tokens but
+            // the column cannot be dropped, so it still gets a value
             if matches!(this.api_key, ActiveValue::NotSet) {
                 this.api_key = ActiveValue::Set(format!("lo-{}", Uuid::new_v4()));
             }
@@ -115,20 +192,20 @@ impl ActiveModelBehavior for super::_entities::users::ActiveModel {
 
 #[async_trait]
 impl Authenticable for Model {
+    /// API keys are the personal access tokens of the user; the legacy
+    /// `api_key` column is no longer accepted
     async fn find_by_api_key(db: &DatabaseConnection, api_key: &str) -> ModelResult<Self> {
-        let user = users::Entity::find()
-            .filter(
-                model::query::condition()
-                    .eq(users::Column::ApiKey, api_key)
-                    .build(),
-            )
-            .one(db)
-            .await?;
-        user.ok_or_else(|| ModelError::EntityNotFound)
+        let (_, user) = personal_access_tokens::Model::authenticate(db, api_key).await?;
+        Ok(user)
     }
 
+    /// Suspended accounts are refused even while a session is still open
     async fn find_by_claims_key(db: &DatabaseConnection, claims_key: &str) -> ModelResult<Self> {
-        Self::find_by_pid(db, claims_key).await
+        let user = Self::find_by_pid(db, claims_key).await?;
+        if !user.is_active() {
+            return Err(ModelError::msg("Account is not active"));
+        }
+        Ok(user)
     }
 }
 
@@ -146,6 +223,28 @@ impl Model {
         user.ok_or_else(|| ModelError::EntityNotFound)
     }
 
+    /// finds a user by the provided email, whatever the case of either
+    /// address
+    ///
+    /// # Errors
+    ///
+    /// When could not find user by the given email or DB query error
+    pub async fn find_by_email_ignoring_case<C: ConnectionTrait>(
+        db: &C,
+        email: &str,
+    ) -> ModelResult<Self> {
+        let user = users::Entity::find()
+            .filter(
+                sea_orm::sea_query::Expr::expr(sea_orm::sea_query::Func::lower(
+                    sea_orm::sea_query::Expr::col(users::Column::Email),
+                ))
+                .eq(normalize_email(email)),
+            )
+            .one(db)
+            .await?;
+        user.ok_or_else(|| ModelError::EntityNotFound)
+    }
+
     /// finds a user by the provided name
     ///
     /// # Errors
@@ -159,20 +258,24 @@ impl Model {
         user.ok_or_else(|| ModelError::EntityNotFound)
     }
 
-    /// finds a user by the provided verification token
+    /// finds a user by the provided email verification token and checks that
+    /// the token has not expired
     ///
     /// # Errors
     ///
-    /// When could not find user by the given token or DB query error
+    /// When could not find user by the given token, [`tokens::TOKEN_EXPIRED`]
+    /// when the token expired or DB query error
     pub async fn find_by_verification_token(
         db: &DatabaseConnection,
         token: &str,
     ) -> ModelResult<Self> {
         let user = users::Entity::find()
-            .filter(users::Column::EmailVerificationToken.eq(token))
+            .filter(users::Column::EmailVerificationToken.eq(tokens::hash_token(token)))
             .one(db)
-            .await?;
-        user.ok_or_else(|| ModelError::EntityNotFound)
+            .await?
+            .ok_or_else(|| ModelError::EntityNotFound)?;
+        tokens::check_not_expired(user.email_verification_expires_at)?;
+        Ok(user)
     }
 
     /// finds a user by the magic token and verify and token expiration
@@ -207,41 +310,52 @@ impl Model {
         }
     }
 
-    /// finds a user by the provided reset token
+    /// finds a user by the provided reset token and checks that the token has
+    /// not expired
     ///
     /// # Errors
     ///
-    /// When could not find user by the given token or DB query error
+    /// When could not find user by the given token, [`tokens::TOKEN_EXPIRED`]
+    /// when the token expired or DB query error
     pub async fn find_by_reset_token(db: &DatabaseConnection, token: &str) -> ModelResult<Self> {
         let user = users::Entity::find()
-            .filter(users::Column::ResetToken.eq(token))
+            .filter(users::Column::ResetToken.eq(tokens::hash_token(token)))
             .one(db)
-            .await?;
-        user.ok_or_else(|| ModelError::EntityNotFound)
+            .await?
+            .ok_or_else(|| ModelError::EntityNotFound)?;
+        tokens::check_not_expired(user.reset_expires_at)?;
+        Ok(user)
     }
 
-    /// finds a user by the provided pid
+    /// finds a user by the provided PGP verification token and checks that the
+    /// token has not expired
     ///
     /// # Errors
     ///
-    /// When could not find user  or DB query error
-    pub async fn find_by_pid(db: &DatabaseConnection, pid: &str) -> ModelResult<Self> {
-        let parse_uuid = Uuid::parse_str(pid).map_err(|e| ModelError::Any(e.into()))?;
+    /// When could not find user by the given token, [`tokens::TOKEN_EXPIRED`]
+    /// when the token expired or DB query error
+    pub async fn find_by_pgp_verification_token(
+        db: &DatabaseConnection,
+        token: &str,
+    ) -> ModelResult<Self> {
         let user = users::Entity::find()
-            .filter(users::Column::Pid.eq(parse_uuid))
+            .filter(users::Column::PgpVerificationToken.eq(tokens::hash_token(token)))
             .one(db)
-            .await?;
-        user.ok_or_else(|| ModelError::EntityNotFound)
+            .await?
+            .ok_or_else(|| ModelError::EntityNotFound)?;
+        tokens::check_not_expired(user.pgp_verification_expires_at)?;
+        Ok(user)
     }
 
-    /// finds a user by the provided api key
+    /// finds a user by the provided pid
     ///
     /// # Errors
     ///
-    /// When could not find user by the given token or DB query error
-    pub async fn find_by_api_key(db: &DatabaseConnection, api_key: &str) -> ModelResult<Self> {
+    /// When could not find user  or DB query error
+    pub async fn find_by_pid(db: &DatabaseConnection, pid: &str) -> ModelResult<Self> {
+        let parse_uuid = Uuid::parse_str(pid).map_err(|e| ModelError::Any(e.into()))?;
         let user = users::Entity::find()
-            .filter(users::Column::ApiKey.eq(api_key))
+            .filter(users::Column::Pid.eq(parse_uuid))
             .one(db)
             .await?;
         user.ok_or_else(|| ModelError::EntityNotFound)
@@ -262,33 +376,35 @@ impl Model {
     ///
     /// # Errors
     ///
-    /// When could not save the user into the DB or if email/name is not unique
-    pub async fn create_with_password(
-        db: &DatabaseConnection,
+    /// When could not save the user into the DB, if email/name is not unique
+    /// or if the password does not comply with the password policy
+    pub async fn create_with_password<C: ConnectionTrait>(
+        db: &C,
         params: &RegisterParams,
+        policy: &PasswordPolicy,
     ) -> ModelResult<Self> {
-        let txn = db.begin().await?;
+        policy
+            .check(&params.password, &[&params.email, params.name.trim()])
+            .await?;
 
         // Check for email uniqueness
         if users::Entity::find()
             .filter(users::Column::Email.eq(&params.email))
-            .one(&txn)
+            .one(db)
             .await?
             .is_some()
         {
-            return Err(ModelError::Message("Email already registered.".to_string()));
+            return Err(ModelError::msg(EMAIL_TAKEN));
         }
 
         // Check for name uniqueness
         if users::Entity::find()
             .filter(users::Column::Name.eq(params.name.trim())) // Trim username for the check
-            .one(&txn)
+            .one(db)
             .await?
             .is_some()
         {
-            return Err(ModelError::Message(
-                "Username already taken. Please choose another.".to_string(),
-            ));
+            return Err(ModelError::msg(NAME_TAKEN));
         }
 
         let password_hash =
@@ -299,27 +415,666 @@ impl Model {
             name: ActiveValue::set(params.name.trim().to_string()),
             ..Default::default()
         }
-        .insert(&txn)
+        .insert(db)
         .await?;
 
-        txn.commit().await?;
-
         Ok(user)
     }
 
-    /// Creates a JWT
+    /// Creates a JWT bound to a new server-side session, recording the device
+    /// the user signed in from.
+    ///
+    /// The session jti is embedded in the token claims so that the token can
+    /// be revoked before it expires (logout, password change, admin action).
+    /// Opening a session means the user completed every login step, so the
+    /// failed login counter of the account is reset.
+    ///
+    /// # Errors
+    ///
+    /// when the account is not active, could not create the session or
+    /// convert user claims to jwt token
+    pub async fn generate_jwt(
+        &self,
+        db: &DatabaseConnection,
+        secret: &str,
+        expiration: &u64,
+        device: &sessions::SessionDevice,
+    ) -> ModelResult<String> {
+        if !self.is_active() {
+            return Err(ModelError::msg("Account is not active"));
+        }
+        if self.failed_login_count > 0 || self.locked_until.is_some() {
+            self.clone()
+                .into_active_model()
+                .clear_failed_logins(db)
+                .await?;
+        }
+        let session = sessions::Model::create_for_user(db, self.id, *expiration, device).await?;
+        let mut claims = serde_json::Map::new();
+        claims.insert("jti".to_string(), serde_json::Value::String(session.jti));
+        Ok(jwt::JWT::new(secret).generate_token(*expiration, self.pid.to_string(), claims)?)
+    }
+
+    /// Creates a JWT letting the administrator browse the console as this
+    /// user. The token is marked with the administrator pid, and its session
+    /// records the administrator, so that the impersonation is visible and
+    /// can be stopped.
+    ///
+    /// # Errors
+    ///
+    /// when the account cannot be impersonated, could not create the session
+    /// or convert user claims to jwt token
+    pub async fn generate_impersonation_jwt(
+        &self,
+        db: &DatabaseConnection,
+        admin: &Self,
+        secret: &str,
+        device: &sessions::SessionDevice,
+    ) -> ModelResult<(String, sessions::Model)> {
+        if admin.id == self.id {
+            return Err(ModelError::msg("You cannot impersonate yourself"));
+        }
+        if !self.is_active() {
+            return Err(ModelError::msg("Only active accounts can be impersonated"));
+        }
+        let session = sessions::Model::create_impersonation(
+            db,
+            self.id,
+            admin.id,
+            IMPERSONATION_EXPIRATION_SEC,
+            device,
+        )
+        .await?;
+        let mut claims = serde_json::Map::new();
+        claims.insert(
+            "jti".to_string(),
+            serde_json::Value::String(session.jti.clone()),
+        );
+        claims.insert(
+            sessions::IMPERSONATOR_CLAIM.to_string(),
+            serde_json::Value::String(admin.pid.to_string()),
+        );
+        let token = jwt::JWT::new(secret).generate_token(
+            IMPERSONATION_EXPIRATION_SEC,
+            self.pid.to_string(),
+            claims,
+        )?;
+        Ok((token, session))
+    }
+
+    /// Creates a short-lived token identifying a login that passed the
+    /// password check but still has to provide its second factor.
+    ///
+    /// This token carries no session id, so it is not accepted by the auth
+    /// extractors.
     ///
     /// # Errors
     ///
     /// when could not convert user claims to jwt token
-    pub fn generate_jwt(&self, secret: &str, expiration: &u64) -> ModelResult<String> {
+    pub fn generate_mfa_challenge(&self, secret: &str) -> ModelResult<String> {
+        let mut claims = serde_json::Map::new();
+        claims.insert("mfa_pending".to_string(), serde_json::Value::Bool(true));
         Ok(jwt::JWT::new(secret).generate_token(
-            *expiration,
+            MFA_CHALLENGE_EXPIRATION_SEC,
             self.pid.to_string(),
-            serde_json::Map::new(),
+            claims,
         )?)
     }
 
+    /// finds the user of a login waiting for its second factor
+    ///
+    /// # Errors
+    ///
+    /// When the challenge token is invalid or expired, could not find user
+    /// or DB query error
+    pub async fn find_by_mfa_challenge(
+        db: &DatabaseConnection,
+        secret: &str,
+        token: &str,
+    ) -> ModelResult<Self> {
+        let claims = jwt::JWT::new(secret)
+            .validate(token)
+            .map_err(|_| ModelError::msg("invalid or expired two-factor challenge"))?
+            .claims;
+        if claims.claims.get("mfa_pending") != Some(&serde_json::Value::Bool(true)) {
+            return Err(ModelError::msg("invalid two-factor challenge"));
+        }
+        Self::find_by_pid(db, &claims.pid).await
+    }
+
+    /// Returns true when the user completed the TOTP enrolment
+    #[must_use]
+    pub fn totp_enabled(&self) -> bool {
+        self.totp_enabled_at.is_some() && self.totp_secret.is_some()
+    }
+
+    /// Builds the RFC 6238 generator (SHA1, 6 digits, 30 seconds) from the
+    /// stored secret, enrolled or not
+    fn totp(&self) -> ModelResult<TOTP> {
+        let secret = self
+            .totp_secret
+            .as_ref()
+            .ok_or_else(|| ModelError::msg("two-factor authentication is not set up"))?;
+        let secret = Secret::Encoded(secret.clone())
+            .to_bytes()
+            .map_err(|e| ModelError::Any(format!("{e:?}").into()))?;
+        TOTP::new(
+            Algorithm::SHA1,
+            6,
+            1,
+            30,
+            secret,
+            Some(TOTP_ISSUER.to_string()),
+            self.email.clone(),
+        )
+        .map_err(|e| ModelError::Any(e.into()))
+    }
+
+    /// Returns the `otpauth://` URI to register the TOTP secret in an
+    /// authenticator app
+    ///
+    /// # Errors
+    ///
+    /// When the user has no TOTP secret
+    pub fn totp_provisioning_uri(&self) -> ModelResult<String> {
+        Ok(self.totp()?.get_url())
+    }
+
+    /// Returns the provisioning URI encoded as an SVG QR code
+    ///
+    /// # Errors
+    ///
+    /// When the user has no TOTP secret or the QR code could not be built
+    pub fn totp_qr_svg(&self) -> ModelResult<String> {
+        let uri = self.totp_provisioning_uri()?;
+        let code = qrcode::QrCode::new(uri.as_bytes()).map_err(|e| ModelError::Any(e.into()))?;
+        Ok(code
+            .render::<qrcode::render::svg::Color>()
+            .min_dimensions(200, 200)
+            .build())
+    }
+
+    /// Finds the time step a TOTP code was generated for, allowing one step
+    /// of clock skew. `None` when the code is not valid.
+    fn matching_totp_step(&self, code: &str) -> Option<i64> {
+        let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+        let mut totp = self.totp().ok()?;
+        let skew = u64::from(totp.skew);
+        totp.skew = 0;
+        let current = Utc::now().timestamp().unsigned_abs() / totp.step;
+        (current.saturating_sub(skew)..=current + skew)
+            .find(|step| totp.check(&code, step * totp.step))
+            .and_then(|step| i64::try_from(step).ok())
+    }
+
+    /// Checks a TOTP code against the user's secret, allowing one step of
+    /// clock skew. The time step of an accepted code is recorded, and codes
+    /// of that step or an earlier one are rejected afterwards, so that a code
+    /// cannot be replayed.
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn verify_totp(&self, db: &DatabaseConnection, code: &str) -> ModelResult<bool> {
+        let Some(step) = self.matching_totp_step(code) else {
+            return Ok(false);
+        };
+        // Conditional update, so that two concurrent logins cannot both use
+        // the same code
+        let result = Entity::update_many()
+            .col_expr(
+                users::Column::TotpLastStep,
+                sea_orm::sea_query::Expr::value(step),
+            )
+            .filter(users::Column::Id.eq(self.id))
+            .filter(
+                sea_orm::Condition::any()
+                    .add(users::Column::TotpLastStep.is_null())
+                    .add(users::Column::TotpLastStep.lt(step)),
+            )
+            .exec(db)
+            .await?;
+        Ok(result.rows_affected == 1)
+    }
+
+    /// Lists the second factors the user can complete a login with:
+    /// `"totp"` when an authenticator app is enrolled and `"webauthn"` when a
+    /// passkey or security key is registered. An empty list means the
+    /// password (or magic link) alone is enough.
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn second_factor_methods(
+        &self,
+        db: &DatabaseConnection,
+    ) -> ModelResult<Vec<&'static str>> {
+        let mut methods = Vec::new();
+        if self.totp_enabled() {
+            methods.push("totp");
+        }
+        if webauthn_credentials::Model::exists_for_user(db, self.id).await? {
+            methods.push("webauthn");
+        }
+        Ok(methods)
+    }
+
+    /// Verifies the second factor of a login: either a TOTP code or one of
+    /// the unused recovery codes, which is then consumed
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn verify_second_factor(
+        &self,
+        db: &DatabaseConnection,
+        code: &str,
+    ) -> ModelResult<bool> {
+        if !self.totp_enabled() {
+            return Ok(false);
+        }
+        if self.verify_totp(db, code).await? {
+            return Ok(true);
+        }
+        recovery_codes::Model::consume(db, self.id, code).await
+    }
+
+    /// Returns true when the account may log in, i.e. it is neither waiting
+    /// for the approval of an administrator nor suspended
+    #[must_use]
+    pub fn is_active(&self) -> bool {
+        self.status == STATUS_ACTIVE
+    }
+
+    /// Returns true when the account belongs to a team service account
+    #[must_use]
+    pub fn is_service_account(&self) -> bool {
+        self.status == STATUS_SERVICE_ACCOUNT
+    }
+
+    /// Returns true when the account may use the API with a personal access
+    /// token: active accounts and service accounts
+    #[must_use]
+    pub fn can_use_api(&self) -> bool {
+        self.is_active() || self.is_service_account()
+    }
+
+    /// Returns true when the account is waiting for the approval of an
+    /// administrator
+    #[must_use]
+    pub fn is_pending_approval(&self) -> bool {
+        self.status == STATUS_PENDING_APPROVAL
+    }
+
+    /// Lists the accounts waiting for the approval of an administrator,
+    /// oldest first
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn find_pending_approval(db: &DatabaseConnection) -> ModelResult<Vec<Self>> {
+        let users = Entity::find()
+            .filter(users::Column::Status.eq(STATUS_PENDING_APPROVAL))
+            .order_by_asc(users::Column::CreatedAt)
+            .all(db)
+            .await?;
+        Ok(users)
+    }
+
+    /// Counts the accounts waiting for the approval of an administrator
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn count_pending_approval(db: &DatabaseConnection) -> ModelResult<u64> {
+        let count = Entity::find()
+            .filter(users::Column::Status.eq(STATUS_PENDING_APPROVAL))
+            .count(db)
+            .await?;
+        Ok(count)
+    }
+
+    /// Approves an account waiting for the approval of an administrator, so
+    /// that it can log in
+    ///
+    /// # Errors
+    ///
+    /// When the account is not pending approval or DB query error
+    pub async fn approve(self, db: &DatabaseConnection) -> ModelResult<Self> {
+        if !self.is_pending_approval() {
+            return Err(ModelError::msg("Account is not waiting for an approval"));
+        }
+        let mut user = self.into_active_model();
+        user.status = ActiveValue::Set(STATUS_ACTIVE.to_string());
+        Ok(user.update(db).await?)
+    }
+
+    /// Rejects an account waiting for the approval of an administrator: the
+    /// account is deleted, so the email address can register again later
+    ///
+    /// # Errors
+    ///
+    /// When the account is not pending approval or DB query error
+    pub async fn reject(self, db: &DatabaseConnection) -> ModelResult<()> {
+        if !self.is_pending_approval() {
+            return Err(ModelError::msg("Account is not waiting for an approval"));
+        }
+        Entity::delete_by_id(self.id).exec(db).await?;
+        Ok(())
+    }
+
+    /// Suspends an active account: it can no longer log in nor use the API,
+    /// and all its sessions are revoked
+    ///
+    /// # Errors
+    ///
+    /// When the account is not active or DB query error
+    pub async fn suspend(self, db: &DatabaseConnection) -> ModelResult<Self> {
+        if !self.is_active() {
+            return Err(ModelError::msg("Only active accounts can be suspended"));
+        }
+        let mut user = self.into_active_model();
+        user.status = ActiveValue::Set(STATUS_SUSPENDED.to_string());
+        let user = user.update(db).await?;
+        sessions::Model::revoke_all_for_user(db, user.id, None).await?;
+        Ok(user)
+    }
+
+    /// Reactivates a suspended account, so that it can log in again
+    ///
+    /// # Errors
+    ///
+    /// When the account is not suspended or DB query error
+    pub async fn reactivate(self, db: &DatabaseConnection) -> ModelResult<Self> {
+        if self.status != STATUS_SUSPENDED {
+            return Err(ModelError::msg("Account is not suspended"));
+        }
+        let mut user = self.into_active_model();
+        user.status = ActiveValue::Set(STATUS_ACTIVE.to_string());
+        Ok(user.update(db).await?)
+    }
+
+    /// Lists the teams in which the user is the only Owner, which would be
+    /// left without an Owner if the user were deleted
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn find_sole_owned_teams(
+        &self,
+        db: &DatabaseConnection,
+    ) -> ModelResult<Vec<teams::Model>> {
+        let owned_teams = team_memberships::Entity::find()
+            .filter(team_memberships::Column::UserId.eq(self.id))
+            .filter(team_memberships::Column::Role.eq(roles::OWNER_ROLE))
+            .filter(team_memberships::Column::Pending.eq(false))
+            .find_also_related(teams::Entity)
+            .all(db)
+            .await?;
+
+        let mut sole_owned = Vec::new();
+        for (_, team) in owned_teams {
+            let Some(team) = team else { continue };
+            let owners = team_memberships::Entity::find()
+                .filter(team_memberships::Column::TeamId.eq(team.id))
+                .filter(team_memberships::Column::Role.eq(roles::OWNER_ROLE))
+                .filter(team_memberships::Column::Pending.eq(false))
+                .count(db)
+                .await?;
+            if owners <= 1 {
+                sole_owned.push(team);
+            }
+        }
+        Ok(sole_owned)
+    }
+
+    /// Deletes the account along with its SSH keys, team memberships,
+    /// credentials and tokens, in one transaction.
+    ///
+    /// Teams in which the user is the only Owner must not be left without
+    /// one: `new_owners` maps the id of each of these teams to the id of the
+    /// member who becomes its Owner. The deletion is refused when one of them
+    /// has no new Owner, or when the user is the only Owner of an
+    /// organization.
+    ///
+    /// # Errors
+    ///
+    /// `ModelError::Message` when a team or an organization would be left
+    /// without an Owner or a new Owner is not a member of the team, or DB
+    /// query error
+    pub async fn delete_account(
+        self,
+        db: &DatabaseConnection,
+        new_owners: &std::collections::HashMap<i32, i32>,
+    ) -> ModelResult<()> {
+        for (organization, role) in super::organizations::Model::list_for_user(db, self.id).await? {
+            if role != roles::OWNER_ROLE {
+                continue;
+            }
+            let owners = organization_memberships::Entity::find()
+                .filter(organization_memberships::Column::OrganizationId.eq(organization.id))
+                .filter(organization_memberships::Column::Role.eq(role))
+                .count(db)
+                .await?;
+            if owners <= 1 {
+                return Err(ModelError::Message(format!(
+                    "{} is the only Owner of organization {}; make another member an Owner or delete the organization first",
+                    self.name, organization.name
+                )));
+            }
+        }
+        let sole_owned = self.find_sole_owned_teams(db).await?;
+        let mut transfers = Vec::with_capacity(sole_owned.len());
+        for team in &sole_owned {
+            let Some(new_owner_id) = new_owners.get(&team.id).copied() else {
+                return Err(ModelError::Message(format!(
+                    "{} is the only Owner of team {}; choose a new Owner first",
+                    self.name, team.name
+                )));
+            };
+            let membership = team_memberships::Entity::find()
+                .filter(team_memberships::Column::TeamId.eq(team.id))
+                .filter(team_memberships::Column::UserId.eq(new_owner_id))
+                .filter(team_memberships::Column::Pending.eq(false))
+                .inner_join(users::Entity)
+                .filter(users::Column::Status.ne(STATUS_SERVICE_ACCOUNT))
+                .one(db)
+                .await?;
+            match membership {
+                Some(membership) if new_owner_id != self.id => transfers.push(membership),
+                _ => {
+                    return Err(ModelError::Message(format!(
+                        "The new Owner of team {} must be one of its members",
+                        team.name
+                    )));
+                }
+            }
+        }
+
+        let txn = db.begin().await?;
+        for membership in transfers {
+            let mut membership = membership.into_active_model();
+            membership.role = ActiveValue::Set(roles::OWNER_ROLE.to_string());
+            membership.update(&txn).await?;
+        }
+        ssh_keys::Entity::delete_many()
+            .filter(ssh_keys::Column::UserId.eq(self.id))
+            .exec(&txn)
+            .await?;
+        team_memberships::Entity::delete_many()
+            .filter(team_memberships::Column::UserId.eq(self.id))
+            .exec(&txn)
+            .await?;
+        organization_memberships::Entity::delete_many()
+            .filter(organization_memberships::Column::UserId.eq(self.id))
+            .exec(&txn)
+            .await?;
+        ownership_transfers::Entity::delete_many()
+            .filter(
+                sea_orm::Condition::any()
+                    .add(ownership_transfers::Column::FromUserId.eq(self.id))
+                    .add(ownership_transfers::Column::ToUserId.eq(self.id)),
+            )
+            .exec(&txn)
+            .await?;
+        personal_access_tokens::Entity::delete_many()
+            .filter(personal_access_tokens::personal_access_tokens::Column::UserId.eq(self.id))
+            .exec(&txn)
+            .await?;
+        // Including the sessions this user opened while impersonating others
+        sessions::Entity::delete_many()
+            .filter(
+                sea_orm::Condition::any()
+                    .add(sessions::sessions::Column::UserId.eq(self.id))
+                    .add(sessions::sessions::Column::ImpersonatorId.eq(self.id)),
+            )
+            .exec(&txn)
+            .await?;
+        recovery_codes::Entity::delete_many()
+            .filter(recovery_codes::recovery_codes::Column::UserId.eq(self.id))
+            .exec(&txn)
+            .await?;
+        webauthn_credentials::Entity::delete_many()
+            .filter(webauthn_credentials::webauthn_credentials::Column::UserId.eq(self.id))
+            .exec(&txn)
+            .await?;
+        webauthn_challenges::Entity::delete_many()
+            .filter(webauthn_challenges::Column::UserId.eq(self.id))
+            .exec(&txn)
+            .await?;
+        oidc_identities::Entity::delete_many()
+            .filter(oidc_identities::Column::UserId.eq(self.id))
+            .exec(&txn)
+            .await?;
+        // Service accounts the user created stay with their team
+        service_accounts::Entity::update_many()
+            .col_expr(
+                service_accounts::Column::CreatedById,
+                sea_orm::sea_query::Expr::value(Option::<i32>::None),
+            )
+            .filter(service_accounts::Column::CreatedById.eq(self.id))
+            .exec(&txn)
+            .await?;
+        // The impersonation audit trail outlives the accounts involved
+        for column in [
+            impersonation_events::Column::AdminId,
+            impersonation_events::Column::TargetId,
+        ] {
+            impersonation_events::Entity::update_many()
+                .col_expr(column, sea_orm::sea_query::Expr::value(Option::<i32>::None))
+                .filter(column.eq(self.id))
+                .exec(&txn)
+                .await?;
+        }
+        Entity::delete_by_id(self.id).exec(&txn).await?;
+        txn.commit().await?;
+        Ok(())
+    }
+
+    /// Returns true when the account is locked after too many failed logins
+    #[must_use]
+    pub fn is_locked(&self) -> bool {
+        self.locked_until.is_some_and(|until| until > Utc::now())
+    }
+
+    /// Returns the number of seconds to wait before a new login attempt is
+    /// accepted for this account (lockout or exponential back-off), or `None`
+    /// when an attempt can be made now
+    #[must_use]
+    pub fn login_retry_after(&self) -> Option<i64> {
+        login_attempts::retry_after(
+            self.failed_login_count,
+            login_attempts::ACCOUNT_FREE_ATTEMPTS,
+            self.last_failed_login_at,
+            self.locked_until,
+        )
+    }
+
+    /// Checks an email and password login attempt, enforcing the per-account
+    /// and per-IP back-off and lockout, and records the failure if any.
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn check_password_login(
+        db: &DatabaseConnection,
+        email: &str,
+        password: &str,
+        ip: Option<&str>,
+    ) -> ModelResult<LoginOutcome> {
+        if let Some(ip) = ip
+            && let Some(retry_after_sec) = login_attempts::Model::retry_after_for_ip(db, ip).await?
+        {
+            return Ok(LoginOutcome::Throttled { retry_after_sec });
+        }
+
+        let user = match Self::find_by_email(db, email).await {
+            Ok(user) => user,
+            Err(ModelError::EntityNotFound) => {
+                if let Some(ip) = ip {
+                    login_attempts::Model::record_failure(db, ip).await?;
+                }
+                return Ok(LoginOutcome::Failed { locked: None });
+            }
+            Err(err) => return Err(err),
+        };
+
+        if let Some(retry_after_sec) = user.login_retry_after() {
+            return Ok(LoginOutcome::Throttled { retry_after_sec });
+        }
+        if user.verify_password(password) {
+            if !user.is_active() {
+                return Ok(LoginOutcome::Inactive(user));
+            }
+            return Ok(LoginOutcome::Success(user));
+        }
+        Self::record_login_failure(db, user, ip).await
+    }
+
+    /// Records a failed login step for the account and the IP address
+    async fn record_login_failure(
+        db: &DatabaseConnection,
+        user: Self,
+        ip: Option<&str>,
+    ) -> ModelResult<LoginOutcome> {
+        if let Some(ip) = ip {
+            login_attempts::Model::record_failure(db, ip).await?;
+        }
+        let (user, locked) = user.into_active_model().record_failed_login(db).await?;
+        Ok(LoginOutcome::Failed {
+            locked: locked.then_some(user),
+        })
+    }
+
+    /// Checks the second factor of a login, enforcing the same back-off and
+    /// lockout as the password step, so that codes cannot be guessed either.
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn check_second_factor_login(
+        self,
+        db: &DatabaseConnection,
+        code: &str,
+        ip: Option<&str>,
+    ) -> ModelResult<LoginOutcome> {
+        if let Some(ip) = ip
+            && let Some(retry_after_sec) = login_attempts::Model::retry_after_for_ip(db, ip).await?
+        {
+            return Ok(LoginOutcome::Throttled { retry_after_sec });
+        }
+        if let Some(retry_after_sec) = self.login_retry_after() {
+            return Ok(LoginOutcome::Throttled { retry_after_sec });
+        }
+        if self.verify_second_factor(db, code).await? {
+            if !self.is_active() {
+                return Ok(LoginOutcome::Inactive(self));
+            }
+            return Ok(LoginOutcome::Success(self));
+        }
+        Self::record_login_failure(db, self, ip).await
+    }
+
     /// finds a user by the provided id
     ///
     /// # Errors
@@ -408,6 +1163,7 @@ impl Model {
         // Find the admin team by name
         let admin_team = teams::Entity::find()
             .filter(teams::Column::Name.eq(admin_team_name))
+            .filter(teams::Column::OrganizationId.is_null())
             .one(db)
             .await?;
 
@@ -473,9 +1229,11 @@ impl Model {
             active_user.email_verified_at = Set(None);
             active_user.email_verification_token = Set(None);
             active_user.email_verification_sent_at = Set(None);
+            active_user.email_verification_expires_at = Set(None);
             active_user.pgp_key = Set(None);
             active_user.pgp_verified_at = Set(None);
             active_user.pgp_verification_token = Set(None);
+            active_user.pgp_verification_expires_at = Set(None);
 
             email_changed = true;
         }
@@ -494,19 +1252,52 @@ impl Model {
 
     /// Generates and saves a password reset token and its expiration time for the user.
     ///
+    /// Only the hash of the token is stored; the plaintext token to put in
+    /// the reset link is returned along with the updated user.
+    ///
     /// # Arguments
     ///
     /// * `db` - The database connection.
+    /// * `lifetimes` - The configured token lifetimes.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ModelError` if the database update fails.
+    pub async fn initiate_password_reset(
+        &self,
+        db: &DatabaseConnection,
+        lifetimes: &TokenLifetimes,
+    ) -> ModelResult<(Self, String)> {
+        let mut user_model: ActiveModel = self.clone().into();
+        let (token, token_hash) = tokens::generate_token();
+        user_model.reset_token = ActiveValue::Set(Some(token_hash));
+        user_model.reset_sent_at = ActiveValue::Set(Some(Utc::now().into()));
+        user_model.reset_expires_at =
+            ActiveValue::Set(Some(tokens::expires_at(lifetimes.password_reset_min)));
+        let user = user_model.update(db).await?;
+        Ok((user, token))
+    }
+
+    /// Sets a link for an account created by an administrator to choose its
+    /// password. It is a password reset token that lives longer, since the
+    /// owner of the account did not ask for it.
     ///
     /// # Errors
     ///
     /// Returns a `ModelError` if the database update fails.
-    pub async fn initiate_password_reset(&self, db: &DatabaseConnection) -> ModelResult<Self> {
+    pub async fn initiate_account_setup(
+        &self,
+        db: &DatabaseConnection,
+        lifetimes: &TokenLifetimes,
+    ) -> ModelResult<(Self, String)> {
         let mut user_model: ActiveModel = self.clone().into();
-        let token = Uuid::new_v4().to_string();
-        user_model.reset_token = ActiveValue::Set(Some(token));
+        let (token, token_hash) = tokens::generate_token();
+        user_model.reset_token = ActiveValue::Set(Some(token_hash));
         user_model.reset_sent_at = ActiveValue::Set(Some(Utc::now().into()));
-        user_model.update(db).await.map_err(Into::into)
+        user_model.reset_expires_at =
+            ActiveValue::Set(Some(tokens::expires_at(lifetimes.account_setup_min)));
+        let user = user_model.update(db).await?;
+        Ok((user, token))
     }
 
     pub async fn get_base_layout_context(
@@ -520,14 +1311,22 @@ impl Model {
         let invitation_count = team_memberships::Entity::find()
             .filter(team_memberships::Column::UserId.eq(self.id))
             .filter(team_memberships::Column::Pending.eq(true))
+            .filter(team_memberships::Column::InvitationExpiresAt.gt(Utc::now()))
             .count(db)
             .await
             .unwrap_or(0);
 
+        // Only administrators see the accounts waiting for their approval
+        let pending_user_count = if is_admin {
+            Self::count_pending_approval(db).await.unwrap_or(0)
+        } else {
+            0
+        };
+
         LayoutContext {
             is_app_admin: is_admin,
             invitation_count: invitation_count as i64,
-            pending_user_count: 0,
+            pending_user_count,
         }
     }
 
@@ -588,6 +1387,8 @@ impl ActiveModel {
     /// updates it in the database.
     ///
     /// This method is used to generate a unique verification token for the user.
+    /// Only its hash is stored; the plaintext token to put in the verification
+    /// link is returned along with the updated user.
     ///
     /// # Errors
     ///
@@ -595,11 +1396,14 @@ impl ActiveModel {
     pub async fn generate_email_verification_token(
         mut self,
         db: &DatabaseConnection,
-    ) -> ModelResult<Model> {
-        let token = Uuid::new_v4().to_string();
-        self.email_verification_token = Set(Some(token));
+        lifetimes: &TokenLifetimes,
+    ) -> ModelResult<(Model, String)> {
+        let (token, token_hash) = tokens::generate_token();
+        self.email_verification_token = Set(Some(token_hash));
         self.email_verification_sent_at = Set(None);
-        Ok(self.update(db).await?)
+        self.email_verification_expires_at =
+            Set(Some(tokens::expires_at(lifetimes.email_verification_min)));
+        Ok((self.update(db).await?, token))
     }
 
     /// Sets the email verification send timestamp for the user and
@@ -634,29 +1438,34 @@ impl ActiveModel {
     /// # Errors
     ///
     /// when has DB query error
-    pub async fn verified(mut self, db: &DatabaseConnection) -> ModelResult<Model> {
+    pub async fn verified<C: ConnectionTrait>(mut self, db: &C) -> ModelResult<Model> {
         self.email_verified_at = Set(Some(Utc::now().into()));
         self.email_verification_token = Set(None);
+        self.email_verification_expires_at = Set(None);
         Ok(self.update(db).await?)
     }
 
     /// Resets the current user password with a new password and
     /// updates it in the database.
     ///
-    /// This method hashes the provided password and sets it as the new password
-    /// for the user.
+    /// This method checks the provided password against the password policy,
+    /// hashes it and sets it as the new password for the user.
     ///
     /// # Errors
     ///
-    /// when has DB query error or could not hashed the given password
+    /// when the password does not comply with the policy, has DB query error
+    /// or could not hashed the given password
     pub async fn reset_password(
         mut self,
         db: &DatabaseConnection,
         password: &str,
+        policy: &PasswordPolicy,
     ) -> ModelResult<Model> {
+        self.check_password_policy(password, policy).await?;
         self.password = Set(hash::hash_password(password).map_err(|e| ModelError::Any(e.into()))?);
         self.reset_token = Set(None);
         self.reset_sent_at = Set(None);
+        self.reset_expires_at = Set(None);
         Ok(self.update(db).await?)
     }
 
@@ -664,6 +1473,8 @@ impl ActiveModel {
     /// updates it in the database.
     ///
     /// This method is used to generate a unique PGP verification token for the user.
+    /// Only its hash is stored; the plaintext token to put in the verification
+    /// link is returned along with the updated user.
     ///
     /// # Errors
     ///
@@ -671,9 +1482,13 @@ impl ActiveModel {
     pub async fn generate_pgp_verification_token(
         mut self,
         db: &DatabaseConnection,
-    ) -> ModelResult<Model> {
-        self.pgp_verification_token = Set(Some(Uuid::new_v4().to_string()));
-        Ok(self.update(db).await?)
+        lifetimes: &TokenLifetimes,
+    ) -> ModelResult<(Model, String)> {
+        let (token, token_hash) = tokens::generate_token();
+        self.pgp_verification_token = Set(Some(token_hash));
+        self.pgp_verification_expires_at =
+            Set(Some(tokens::expires_at(lifetimes.pgp_verification_min)));
+        Ok((self.update(db).await?, token))
     }
 
     /// Records the PGP verification time when a user verifies their
@@ -688,6 +1503,7 @@ impl ActiveModel {
     pub async fn set_pgp_verified(mut self, db: &DatabaseConnection) -> ModelResult<Model> {
         self.pgp_verified_at = Set(Some(Utc::now().naive_utc()));
         self.pgp_verification_token = Set(None);
+        self.pgp_verification_expires_at = Set(None);
         Ok(self.update(db).await?)
     }
 
@@ -720,6 +1536,109 @@ impl ActiveModel {
         Ok(self.update(db).await?)
     }
 
+    /// Counts a failed login on the account and locks it for
+    /// `LOCKOUT_DURATION_MIN` minutes once `ACCOUNT_LOCKOUT_THRESHOLD`
+    /// consecutive failures are reached.
+    ///
+    /// Returns the updated user and whether this failure locked the account.
+    ///
+    /// # Errors
+    ///
+    /// when has DB query error
+    pub async fn record_failed_login(
+        mut self,
+        db: &DatabaseConnection,
+    ) -> ModelResult<(Model, bool)> {
+        let now = Utc::now();
+        let previous_lock = self.locked_until.clone().unwrap();
+        let lock_expired = previous_lock.is_some_and(|until| until <= now);
+        let failed_login_count = if lock_expired
+            || login_attempts::failure_window_expired(self.last_failed_login_at.clone().unwrap())
+        {
+            1
+        } else {
+            self.failed_login_count.clone().unwrap() + 1
+        };
+
+        let locked = failed_login_count >= login_attempts::ACCOUNT_LOCKOUT_THRESHOLD;
+        self.failed_login_count = Set(failed_login_count);
+        self.last_failed_login_at = Set(Some(now.into()));
+        self.locked_until = Set(if locked {
+            Some((now + Duration::minutes(login_attempts::LOCKOUT_DURATION_MIN)).into())
+        } else if lock_expired {
+            None
+        } else {
+            previous_lock
+        });
+        let user = self.update(db).await?;
+        if locked {
+            tracing::warn!(
+                user_pid = user.pid.to_string(),
+                failed_login_count,
+                "account locked after failed logins"
+            );
+        }
+        Ok((user, locked))
+    }
+
+    /// Resets the failed login counter and lifts any lockout of the account
+    ///
+    /// # Errors
+    ///
+    /// when has DB query error
+    pub async fn clear_failed_logins(mut self, db: &DatabaseConnection) -> ModelResult<Model> {
+        self.failed_login_count = Set(0);
+        self.last_failed_login_at = Set(None);
+        self.locked_until = Set(None);
+        Ok(self.update(db).await?)
+    }
+
+    /// Starts the TOTP enrolment by generating a new secret. Two-factor
+    /// authentication is only enforced once `enable_totp` is called with a
+    /// verified code.
+    ///
+    /// # Errors
+    ///
+    /// when has DB query error
+    pub async fn begin_totp_enrolment(mut self, db: &DatabaseConnection) -> ModelResult<Model> {
+        let secret = match Secret::generate_secret().to_encoded() {
+            Secret::Encoded(secret) => secret,
+            Secret::Raw(_) => return Err(ModelError::msg("could not encode TOTP secret")),
+        };
+        self.totp_secret = Set(Some(secret));
+        self.totp_enabled_at = Set(None);
+        Ok(self.update(db).await?)
+    }
+
+    /// Completes the TOTP enrolment and generates a fresh set of recovery
+    /// codes, returned in plain text so they can be shown once.
+    ///
+    /// # Errors
+    ///
+    /// when has DB query error
+    pub async fn enable_totp(
+        mut self,
+        db: &DatabaseConnection,
+    ) -> ModelResult<(Model, Vec<String>)> {
+        self.totp_enabled_at = Set(Some(Utc::now().into()));
+        let user = self.update(db).await?;
+        let codes = recovery_codes::Model::regenerate_for_user(db, user.id).await?;
+        Ok((user, codes))
+    }
+
+    /// Disables two-factor authentication and deletes the recovery codes.
+    ///
+    /// # Errors
+    ///
+    /// when has DB query error
+    pub async fn disable_totp(mut self, db: &DatabaseConnection) -> ModelResult<Model> {
+        self.totp_secret = Set(None);
+        self.totp_enabled_at = Set(None);
+        let user = self.update(db).await?;
+        recovery_codes::Model::delete_for_user(db, user.id).await?;
+        Ok(user)
+    }
+
     /// Fetches PGP key from keys.openpgp.org based on the user's email,
     /// validates it, and updates the user record.
     /// Returns the updated Model if successful.
@@ -797,16 +1716,30 @@ impl ActiveModel {
         }
     }
 
+    /// Checks a new password against the password policy, taking the name and
+    /// email of the user into account
+    async fn check_password_policy(
+        &self,
+        password: &str,
+        policy: &PasswordPolicy,
+    ) -> ModelResult<()> {
+        let email = self.email.try_as_ref().map_or("", String::as_str);
+        let name = self.name.try_as_ref().map_or("", String::as_str);
+        policy.check(password, &[email, name]).await
+    }
+
     /// Sets the password for the user, hashing it before saving.
     ///
     /// # Errors
     ///
-    /// when has DB query error
+    /// when the password does not comply with the policy or has DB query error
     pub async fn set_password(
         mut self,
         db: &DatabaseConnection,
         password: &str,
+        policy: &PasswordPolicy,
     ) -> ModelResult<Model> {
+        self.check_password_policy(password, policy).await?;
         self.password = Set(hash::hash_password(password).map_err(|e| ModelError::Any(e.into()))?);
         Ok(self.update(db).await?)
     }
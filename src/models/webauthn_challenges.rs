@@ -0,0 +1,99 @@
+use chrono::{Duration, Utc};
+use loco_rs::prelude::*;
+use sea_orm::ActiveValue;
+use serde::{Serialize, de::DeserializeOwned};
+use uuid::Uuid;
+
+pub use super::_entities::webauthn_challenges::{self, ActiveModel, Entity, Model};
+
+/// Time given to the user to complete a WebAuthn ceremony
+pub const WEBAUTHN_CHALLENGE_EXPIRATION_SEC: i64 = 300;
+
+/// Kind of a pending credential registration ceremony
+pub const KIND_REGISTRATION: &str = "registration";
+/// Kind of a pending authentication (assertion) ceremony
+pub const KIND_AUTHENTICATION: &str = "authentication";
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    async fn before_save<C>(self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        if !insert && self.updated_at.is_unchanged() {
+            let mut this = self;
+            this.updated_at = ActiveValue::Set(Utc::now().into());
+            Ok(this)
+        } else {
+            Ok(self)
+        }
+    }
+}
+
+impl Model {
+    /// Stores the server side state of a WebAuthn ceremony started by a user,
+    /// and returns it with the random token the client must send back to
+    /// finish the ceremony.
+    ///
+    /// Expired ceremonies of the user are cleaned up at the same time.
+    ///
+    /// # Errors
+    ///
+    /// When DB query error or the state could not be serialized
+    pub async fn create_for_user<T: Serialize + Sync>(
+        db: &DatabaseConnection,
+        user_id: i32,
+        kind: &str,
+        state: &T,
+    ) -> ModelResult<Self> {
+        Entity::delete_many()
+            .filter(webauthn_challenges::Column::UserId.eq(user_id))
+            .filter(webauthn_challenges::Column::ExpiresAt.lt(Utc::now()))
+            .exec(db)
+            .await?;
+
+        let state = serde_json::to_string(state).map_err(|e| ModelError::Any(e.into()))?;
+        let challenge = ActiveModel {
+            token: ActiveValue::Set(Uuid::new_v4().to_string()),
+            kind: ActiveValue::Set(kind.to_string()),
+            state: ActiveValue::Set(state),
+            expires_at: ActiveValue::Set(
+                (Utc::now() + Duration::seconds(WEBAUTHN_CHALLENGE_EXPIRATION_SEC)).into(),
+            ),
+            user_id: ActiveValue::Set(user_id),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+        Ok(challenge)
+    }
+
+    /// Consumes a pending WebAuthn ceremony: the challenge is deleted so it
+    /// can only be answered once, then its state is returned along with the
+    /// id of the user who started it.
+    ///
+    /// # Errors
+    ///
+    /// When the token is unknown, of another kind or expired, when the state
+    /// could not be deserialized or DB query error
+    pub async fn take<T: DeserializeOwned>(
+        db: &DatabaseConnection,
+        token: &str,
+        kind: &str,
+    ) -> ModelResult<(i32, T)> {
+        let challenge = Entity::find()
+            .filter(webauthn_challenges::Column::Token.eq(token))
+            .filter(webauthn_challenges::Column::Kind.eq(kind))
+            .one(db)
+            .await?
+            .ok_or_else(|| ModelError::EntityNotFound)?;
+        Entity::delete_by_id(challenge.id).exec(db).await?;
+
+        if challenge.expires_at < Utc::now() {
+            return Err(ModelError::msg("WebAuthn challenge has expired"));
+        }
+        let state =
+            serde_json::from_str(&challenge.state).map_err(|e| ModelError::Any(e.into()))?;
+        Ok((challenge.user_id, state))
+    }
+}
@@ -0,0 +1,346 @@
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::Utc;
+use loco_rs::prelude::*;
+use sea_orm::{ActiveValue, QueryOrder};
+use serde::{Deserialize, Serialize};
+use webauthn_rs::prelude::{
+    AuthenticationResult, CreationChallengeResponse, CredentialID, Passkey, PasskeyAuthentication,
+    PasskeyRegistration, PublicKeyCredential, RegisterPublicKeyCredential,
+    RequestChallengeResponse, Url, Webauthn, WebauthnBuilder,
+};
+
+pub use super::_entities::webauthn_credentials::{self, ActiveModel, Entity, Model};
+use super::{_entities::users, webauthn_challenges};
+
+/// Relying party name shown by the browser during WebAuthn ceremonies
+pub const WEBAUTHN_RP_NAME: &str = "Hosting Farm";
+/// Maximum length of the name given to a credential
+pub const CREDENTIAL_NAME_MAX_LENGTH: usize = 64;
+
+/// Sent by the client to complete the registration of a new credential
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RegisterFinishParams {
+    pub challenge_id: String,
+    pub name: String,
+    pub credential: RegisterPublicKeyCredential,
+}
+
+/// Sent by the client to start a passwordless login
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LoginStartParams {
+    pub email: String,
+}
+
+/// Sent by the client to complete a WebAuthn authentication
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LoginFinishParams {
+    pub challenge_id: String,
+    pub credential: PublicKeyCredential,
+}
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    async fn before_save<C>(self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        if !insert && self.updated_at.is_unchanged() {
+            let mut this = self;
+            this.updated_at = ActiveValue::Set(Utc::now().into());
+            Ok(this)
+        } else {
+            Ok(self)
+        }
+    }
+}
+
+/// Encodes a credential id the way it is stored in the database
+fn encode_credential_id(credential_id: &CredentialID) -> String {
+    URL_SAFE_NO_PAD.encode(credential_id)
+}
+
+/// Builds the WebAuthn relying party from the configuration.
+///
+/// The origin is read from `settings.app.webauthn.rp_origin` and defaults to
+/// the server URL; the relying party id is read from
+/// `settings.app.webauthn.rp_id` and defaults to the host of the origin.
+///
+/// # Errors
+///
+/// When the configured origin or relying party id is invalid
+pub fn webauthn(ctx: &AppContext) -> ModelResult<Webauthn> {
+    let settings = ctx
+        .config
+        .settings
+        .as_ref()
+        .and_then(|settings| settings.get("app"))
+        .and_then(|app_settings| app_settings.get("webauthn"));
+    let setting = |key: &str| {
+        settings
+            .and_then(|webauthn| webauthn.get(key))
+            .and_then(|value| value.as_str())
+            .map(ToString::to_string)
+    };
+
+    let origin = setting("rp_origin").unwrap_or_else(|| ctx.config.server.full_url());
+    let origin = Url::parse(&origin).map_err(|e| ModelError::Any(e.into()))?;
+    let rp_id = match setting("rp_id") {
+        Some(rp_id) => rp_id,
+        None => origin
+            .host_str()
+            .ok_or_else(|| ModelError::msg("WebAuthn origin has no host"))?
+            .to_string(),
+    };
+
+    WebauthnBuilder::new(&rp_id, &origin)
+        .and_then(|builder| builder.rp_name(WEBAUTHN_RP_NAME).build())
+        .map_err(|e| ModelError::Any(e.into()))
+}
+
+impl Model {
+    /// Lists the credentials of a user, oldest first
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn find_for_user(db: &DatabaseConnection, user_id: i32) -> ModelResult<Vec<Self>> {
+        let credentials = Entity::find()
+            .filter(webauthn_credentials::Column::UserId.eq(user_id))
+            .order_by_asc(webauthn_credentials::Column::CreatedAt)
+            .all(db)
+            .await?;
+        Ok(credentials)
+    }
+
+    /// Finds a credential by its id, making sure it belongs to the given user
+    ///
+    /// # Errors
+    ///
+    /// When could not find credential or DB query error
+    pub async fn find_by_id_for_user(
+        db: &DatabaseConnection,
+        id: i32,
+        user_id: i32,
+    ) -> ModelResult<Self> {
+        let credential = Entity::find_by_id(id)
+            .filter(webauthn_credentials::Column::UserId.eq(user_id))
+            .one(db)
+            .await?;
+        credential.ok_or_else(|| ModelError::EntityNotFound)
+    }
+
+    /// Returns true when the user registered at least one credential
+    ///
+    /// # Errors
+    ///
+    /// When DB query error
+    pub async fn exists_for_user(db: &DatabaseConnection, user_id: i32) -> ModelResult<bool> {
+        let credential = Entity::find()
+            .filter(webauthn_credentials::Column::UserId.eq(user_id))
+            .one(db)
+            .await?;
+        Ok(credential.is_some())
+    }
+
+    /// Deserializes the stored credential
+    ///
+    /// # Errors
+    ///
+    /// When the stored credential is corrupted
+    pub fn passkey(&self) -> ModelResult<Passkey> {
+        serde_json::from_str(&self.passkey).map_err(|e| ModelError::Any(e.into()))
+    }
+
+    /// Starts the registration of a new credential for a user. The returned
+    /// options must be passed to `navigator.credentials.create()` and the
+    /// result sent back with the challenge token to
+    /// [`Model::finish_registration`].
+    ///
+    /// # Errors
+    ///
+    /// When the relying party is misconfigured or DB query error
+    pub async fn start_registration(
+        ctx: &AppContext,
+        user: &users::Model,
+    ) -> ModelResult<(String, CreationChallengeResponse)> {
+        let webauthn = webauthn(ctx)?;
+        let exclude_credentials = Self::find_for_user(&ctx.db, user.id)
+            .await?
+            .iter()
+            .map(|credential| {
+                credential
+                    .passkey()
+                    .map(|passkey| passkey.cred_id().clone())
+            })
+            .collect::<ModelResult<Vec<_>>>()?;
+
+        let (options, state) = webauthn
+            .start_passkey_registration(
+                user.pid,
+                &user.email,
+                &user.name,
+                Some(exclude_credentials),
+            )
+            .map_err(|e| ModelError::Any(e.into()))?;
+
+        let challenge = webauthn_challenges::Model::create_for_user(
+            &ctx.db,
+            user.id,
+            webauthn_challenges::KIND_REGISTRATION,
+            &state,
+        )
+        .await?;
+        Ok((challenge.token, options))
+    }
+
+    /// Completes the registration of a credential started with
+    /// [`Model::start_registration`] and stores it under the given name
+    ///
+    /// # Errors
+    ///
+    /// When the challenge is invalid, expired or started by another user,
+    /// when the authenticator response does not verify, when the credential
+    /// is already registered or DB query error
+    pub async fn finish_registration(
+        ctx: &AppContext,
+        user: &users::Model,
+        params: &RegisterFinishParams,
+    ) -> ModelResult<Self> {
+        let name = params.name.trim();
+        if name.is_empty() || name.chars().count() > CREDENTIAL_NAME_MAX_LENGTH {
+            return Err(ModelError::msg(&format!(
+                "Passkey name must be between 1 and {CREDENTIAL_NAME_MAX_LENGTH} characters long"
+            )));
+        }
+
+        let (user_id, state) = webauthn_challenges::Model::take::<PasskeyRegistration>(
+            &ctx.db,
+            &params.challenge_id,
+            webauthn_challenges::KIND_REGISTRATION,
+        )
+        .await?;
+        if user_id != user.id {
+            return Err(ModelError::msg(
+                "WebAuthn challenge belongs to another user",
+            ));
+        }
+
+        let passkey = webauthn(ctx)?
+            .finish_passkey_registration(&params.credential, &state)
+            .map_err(|e| ModelError::Any(e.into()))?;
+        let credential_id = encode_credential_id(passkey.cred_id());
+        let existing = Entity::find()
+            .filter(webauthn_credentials::Column::CredentialId.eq(&credential_id))
+            .one(&ctx.db)
+            .await?;
+        if existing.is_some() {
+            return Err(ModelError::EntityAlreadyExists {});
+        }
+
+        let credential = ActiveModel {
+            name: ActiveValue::Set(name.to_string()),
+            credential_id: ActiveValue::Set(credential_id),
+            passkey: ActiveValue::Set(
+                serde_json::to_string(&passkey).map_err(|e| ModelError::Any(e.into()))?,
+            ),
+            user_id: ActiveValue::Set(user.id),
+            ..Default::default()
+        }
+        .insert(&ctx.db)
+        .await?;
+        Ok(credential)
+    }
+
+    /// Starts an authentication ceremony against the credentials of a user,
+    /// either for a passwordless login or as the second factor of a login.
+    ///
+    /// # Errors
+    ///
+    /// When the user has no credential, the relying party is misconfigured
+    /// or DB query error
+    pub async fn start_authentication(
+        ctx: &AppContext,
+        user: &users::Model,
+    ) -> ModelResult<(String, RequestChallengeResponse)> {
+        let passkeys = Self::find_for_user(&ctx.db, user.id)
+            .await?
+            .iter()
+            .map(Self::passkey)
+            .collect::<ModelResult<Vec<_>>>()?;
+        if passkeys.is_empty() {
+            return Err(ModelError::msg("No passkey registered for this account"));
+        }
+
+        let (options, state) = webauthn(ctx)?
+            .start_passkey_authentication(&passkeys)
+            .map_err(|e| ModelError::Any(e.into()))?;
+
+        let challenge = webauthn_challenges::Model::create_for_user(
+            &ctx.db,
+            user.id,
+            webauthn_challenges::KIND_AUTHENTICATION,
+            &state,
+        )
+        .await?;
+        Ok((challenge.token, options))
+    }
+
+    /// Completes an authentication started with
+    /// [`Model::start_authentication`] and returns the authenticated user.
+    ///
+    /// The signature counter and last use time of the credential are updated.
+    ///
+    /// # Errors
+    ///
+    /// When the challenge is invalid or expired, when the assertion does not
+    /// verify or DB query error
+    pub async fn finish_authentication(
+        ctx: &AppContext,
+        params: &LoginFinishParams,
+    ) -> ModelResult<users::Model> {
+        let (user_id, state) = webauthn_challenges::Model::take::<PasskeyAuthentication>(
+            &ctx.db,
+            &params.challenge_id,
+            webauthn_challenges::KIND_AUTHENTICATION,
+        )
+        .await?;
+
+        let result = webauthn(ctx)?
+            .finish_passkey_authentication(&params.credential, &state)
+            .map_err(|e| ModelError::Any(e.into()))?;
+
+        let credential = Entity::find()
+            .filter(webauthn_credentials::Column::UserId.eq(user_id))
+            .filter(
+                webauthn_credentials::Column::CredentialId
+                    .eq(encode_credential_id(result.cred_id())),
+            )
+            .one(&ctx.db)
+            .await?
+            .ok_or_else(|| ModelError::EntityNotFound)?;
+        credential.record_use(&ctx.db, &result).await?;
+
+        let user = users::Entity::find_by_id(user_id).one(&ctx.db).await?;
+        user.ok_or_else(|| ModelError::EntityNotFound)
+    }
+
+    /// Stores the updated counter of the credential after a successful
+    /// authentication and records when it was used
+    async fn record_use(
+        self,
+        db: &DatabaseConnection,
+        result: &AuthenticationResult,
+    ) -> ModelResult<Self> {
+        let mut passkey = self.passkey()?;
+        let updated = passkey.update_credential(result) == Some(true);
+
+        let mut credential = self.into_active_model();
+        if updated {
+            credential.passkey = ActiveValue::Set(
+                serde_json::to_string(&passkey).map_err(|e| ModelError::Any(e.into()))?,
+            );
+        }
+        credential.last_used_at = ActiveValue::Set(Some(Utc::now().into()));
+        Ok(credential.update(db).await?)
+    }
+}
@@ -1 +1,2 @@
-
+pub mod purge_expired_invitations;
+pub mod purge_expired_sessions;
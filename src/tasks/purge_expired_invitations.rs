@@ -0,0 +1,24 @@
+use loco_rs::prelude::*;
+
+use crate::models::team_memberships;
+
+/// Deletes the team invitations that expired before being accepted, so that
+/// they no longer clutter the pending invitations of the teams. Meant to be
+/// run periodically by the scheduler.
+pub struct PurgeExpiredInvitations;
+
+#[async_trait]
+impl Task for PurgeExpiredInvitations {
+    fn task(&self) -> TaskInfo {
+        TaskInfo {
+            name: "purge_expired_invitations".to_string(),
+            detail: "Delete the team invitations that expired".to_string(),
+        }
+    }
+
+    async fn run(&self, ctx: &AppContext, _vars: &task::Vars) -> Result<()> {
+        let purged = team_memberships::Model::purge_expired_invitations(&ctx.db).await?;
+        tracing::info!(purged, "Purged expired team invitations");
+        Ok(())
+    }
+}
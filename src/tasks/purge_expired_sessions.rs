@@ -0,0 +1,24 @@
+use loco_rs::prelude::*;
+
+use crate::models::sessions;
+
+/// Deletes the sessions whose token expired, so that they no longer show up
+/// as signed in and do not pile up. Meant to be run periodically by the
+/// scheduler.
+pub struct PurgeExpiredSessions;
+
+#[async_trait]
+impl Task for PurgeExpiredSessions {
+    fn task(&self) -> TaskInfo {
+        TaskInfo {
+            name: "purge_expired_sessions".to_string(),
+            detail: "Delete the sessions whose token expired".to_string(),
+        }
+    }
+
+    async fn run(&self, ctx: &AppContext, _vars: &task::Vars) -> Result<()> {
+        let purged = sessions::Model::purge_expired(&ctx.db).await?;
+        tracing::info!(purged, "Purged expired sessions");
+        Ok(())
+    }
+}
@@ -22,6 +22,45 @@ impl LoginResponse {
     }
 }
 
+/// Returned instead of a [`LoginResponse`] when the user has two-factor
+/// authentication enabled. The `mfa_token` must be sent back with one of the
+/// second factors listed in `methods` to obtain the session token.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MfaChallengeResponse {
+    pub mfa_required: bool,
+    pub mfa_token: String,
+    pub methods: Vec<String>,
+}
+
+impl MfaChallengeResponse {
+    #[must_use]
+    pub fn new(mfa_token: &str, methods: &[&str]) -> Self {
+        Self {
+            mfa_required: true,
+            mfa_token: mfa_token.to_string(),
+            methods: methods.iter().map(ToString::to_string).collect(),
+        }
+    }
+}
+
+/// Options of a WebAuthn ceremony, to pass to `navigator.credentials`, and
+/// the id of the challenge to send back with the authenticator response
+#[derive(Debug, Deserialize, Serialize)]
+pub struct WebauthnChallengeResponse<T> {
+    pub challenge_id: String,
+    pub options: T,
+}
+
+impl<T> WebauthnChallengeResponse<T> {
+    #[must_use]
+    pub fn new(challenge_id: String, options: T) -> Self {
+        Self {
+            challenge_id,
+            options,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct CurrentResponse {
     pub pid: String,
@@ -0,0 +1,39 @@
+use loco_rs::{mailer::Email, mailer::MailerWorker, prelude::*};
+
+use crate::models::email_failures;
+
+/// Delivers the emails of the application mailers through the `loco_rs`
+/// mailer worker, recording the deliveries that fail so that administrators
+/// can see them on the dashboard
+pub struct EmailDeliveryWorker {
+    pub ctx: AppContext,
+}
+
+#[async_trait]
+impl BackgroundWorker<Email> for EmailDeliveryWorker {
+    fn queue() -> Option<String> {
+        Some("mailer".to_string())
+    }
+
+    fn build(ctx: &AppContext) -> Self {
+        Self { ctx: ctx.clone() }
+    }
+
+    async fn perform(&self, email: Email) -> Result<()> {
+        let recipient = email.to.clone();
+        let subject = email.subject.clone();
+        let result = MailerWorker::build(&self.ctx).perform(email).await;
+        if let Err(err) = &result
+            && let Err(db_err) =
+                email_failures::Model::record(&self.ctx.db, &recipient, &subject, &err.to_string())
+                    .await
+        {
+            tracing::error!(
+                message = "Failed to record an email delivery failure,",
+                recipient = &recipient,
+                error = db_err.to_string(),
+            );
+        }
+        result
+    }
+}
@@ -1 +1,2 @@
 pub mod downloader;
+pub mod email_delivery;
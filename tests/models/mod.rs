@@ -1,5 +1,14 @@
 mod users;
 
+mod oidc;
+mod organizations;
+mod ownership_transfers;
+mod personal_access_tokens;
+mod registration;
+mod roles;
+mod service_accounts;
 mod ssh_keys;
+mod team_join_links;
 mod team_memberships;
 mod teams;
+mod user_imports;
@@ -0,0 +1,215 @@
+use hosting_farm::{
+    app::App,
+    models::{
+        _entities::team_memberships,
+        oidc::{OidcConfig, OidcIdentity},
+        teams::{self, CreateTeamParams},
+        users,
+    },
+};
+use loco_rs::{app::AppContext, testing::prelude::*};
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use serial_test::serial;
+
+fn config() -> OidcConfig {
+    serde_json::from_value(serde_json::json!({
+        "authorization_code": [{
+            "client_identifier": "company",
+            "display_name": "Company SSO",
+            "issuer_url": "https://sso.example.com",
+            "client_id": "hosting-farm",
+            "team_mappings": [{
+                "claim": "groups",
+                "value": "ops",
+                "team": "Operations",
+                "role": "Developer",
+            }],
+        }],
+    }))
+    .expect("Failed to parse single sign-on configuration")
+}
+
+fn identity(subject: &str, email: &str, email_verified: bool) -> OidcIdentity {
+    OidcIdentity {
+        subject: subject.to_string(),
+        email: Some(email.to_string()),
+        email_verified,
+        name: Some("user1".to_string()),
+        claims: serde_json::json!({ "groups": ["staff", "ops"] })
+            .as_object()
+            .cloned()
+            .unwrap(),
+    }
+}
+
+/// Returns the context with another registration mode
+fn with_registration_mode(ctx: &AppContext, mode: &str) -> AppContext {
+    let mut ctx = ctx.clone();
+    let mut settings = ctx.config.settings.clone().unwrap_or_default();
+    settings["app"]["registration"] = serde_json::json!({ "mode": mode });
+    ctx.config.settings = Some(settings);
+    ctx
+}
+
+#[test]
+fn rejects_invalid_oidc_config() {
+    let config = config();
+    assert!(config.validate().is_ok(), "Valid configuration rejected");
+    assert_eq!(config.authorization_code[0].name(), "Company SSO");
+    assert_eq!(
+        config.authorization_code[0].scopes,
+        vec!["email".to_string(), "profile".to_string()],
+        "Default scopes should be requested"
+    );
+
+    let mut bad_role = config.clone();
+    bad_role.authorization_code[0].team_mappings[0].role = " ".to_string();
+    assert!(bad_role.validate().is_err(), "Missing role accepted");
+
+    let mut duplicate = config.clone();
+    duplicate
+        .authorization_code
+        .push(config.authorization_code[0].clone());
+    assert!(duplicate.validate().is_err(), "Duplicate provider accepted");
+
+    let mut bad_identifier = config;
+    bad_identifier.authorization_code[0].client_identifier = "my sso/1".to_string();
+    assert!(
+        bad_identifier.validate().is_err(),
+        "Identifier unusable in URLs accepted"
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn can_sign_in_with_oidc_identity() {
+    let boot = boot_test::<App>()
+        .await
+        .expect("Failed to boot test application");
+    seed::<App>(&boot.app_context)
+        .await
+        .expect("Failed to seed database");
+    let ctx = &boot.app_context;
+
+    let owner = users::Model::find_by_email(&ctx.db, "user1@example.com")
+        .await
+        .unwrap();
+    let team = teams::Model::create_team(
+        &ctx.db,
+        owner.id,
+        &CreateTeamParams {
+            name: "Operations".to_string(),
+            description: None,
+        },
+    )
+    .await
+    .unwrap();
+
+    let config = config();
+    let provider = config.provider("company").unwrap();
+
+    // Unknown verified email: a user is created, with a free name
+    let created = provider
+        .sign_in(ctx, &identity("sub-1", "sso@example.com", true))
+        .await
+        .expect("Failed to sign in new user");
+    assert_eq!(created.email, "sso@example.com");
+    assert_eq!(created.name, "user1-2", "Taken name should get a suffix");
+    assert!(created.email_verified_at.is_some());
+
+    let membership = team_memberships::Entity::find()
+        .filter(team_memberships::Column::TeamId.eq(team.id))
+        .filter(team_memberships::Column::UserId.eq(created.id))
+        .one(&ctx.db)
+        .await
+        .unwrap()
+        .expect("User should be added to the mapped team");
+    assert_eq!(membership.role, "Developer");
+    assert!(!membership.pending);
+
+    // Later logins find the linked identity, even if the email changed
+    let again = provider
+        .sign_in(ctx, &identity("sub-1", "renamed@example.com", true))
+        .await
+        .unwrap();
+    assert_eq!(again.id, created.id);
+
+    // Known verified email: the identity is linked to the existing user
+    let linked = provider
+        .sign_in(ctx, &identity("sub-2", "user2@example.com", true))
+        .await
+        .unwrap();
+    let existing = users::Model::find_by_email(&ctx.db, "user2@example.com")
+        .await
+        .unwrap();
+    assert_eq!(linked.id, existing.id);
+
+    // Unverified email addresses are not trusted
+    assert!(
+        provider
+            .sign_in(ctx, &identity("sub-3", "user2@example.com", false))
+            .await
+            .is_err(),
+        "Unverified email must not be linked"
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn applies_registration_mode_to_new_oidc_users() {
+    let boot = boot_test::<App>()
+        .await
+        .expect("Failed to boot test application");
+    seed::<App>(&boot.app_context)
+        .await
+        .expect("Failed to seed database");
+    let config = config();
+    let provider = config.provider("company").unwrap();
+
+    // The test configuration only accepts some domains
+    assert!(
+        provider
+            .sign_in(
+                &boot.app_context,
+                &identity("sub-1", "someone@elsewhere.net", true)
+            )
+            .await
+            .is_err(),
+        "Email domain refused by the registration mode accepted"
+    );
+
+    // Invite only: nobody without an invitation gets an account
+    let invite_only = with_registration_mode(&boot.app_context, "invite_only");
+    assert!(
+        provider
+            .sign_in(
+                &invite_only,
+                &identity("sub-2", "uninvited@example.com", true)
+            )
+            .await
+            .is_err(),
+        "Uninvited user created in invite only mode"
+    );
+    assert!(
+        users::Model::find_by_email(&invite_only.db, "uninvited@example.com")
+            .await
+            .is_err()
+    );
+
+    // Existing users are still linked, whatever the mode
+    assert!(
+        provider
+            .sign_in(&invite_only, &identity("sub-3", "user1@example.com", true))
+            .await
+            .is_ok()
+    );
+
+    // Approval: new users wait for an administrator
+    let approval = with_registration_mode(&boot.app_context, "approval");
+    let created = provider
+        .sign_in(&approval, &identity("sub-4", "newcomer@example.com", true))
+        .await
+        .unwrap();
+    assert!(created.is_pending_approval());
+    assert!(!created.is_active());
+}
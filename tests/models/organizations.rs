@@ -0,0 +1,277 @@
+use hosting_farm::{
+    app::App,
+    models::{
+        organization_memberships,
+        organizations::{self, OrganizationParams},
+        password_policy::PasswordPolicy,
+        permissions, team_memberships,
+        teams::{self, CreateTeamParams},
+        users::{self, RegisterParams},
+    },
+};
+use loco_rs::testing::prelude::*;
+use sea_orm::{ActiveModelTrait, ActiveValue};
+use serial_test::serial;
+
+async fn create_user(db: &sea_orm::DatabaseConnection, name: &str) -> users::Model {
+    users::Model::create_with_password(
+        db,
+        &RegisterParams {
+            email: format!("{name}@example.com"),
+            password: "correct horse battery".to_string(),
+            name: name.to_string(),
+            password_confirmation: "correct horse battery".to_string(),
+        },
+        &PasswordPolicy::default(),
+    )
+    .await
+    .unwrap()
+}
+
+fn team_params(name: &str) -> CreateTeamParams {
+    CreateTeamParams {
+        name: name.to_string(),
+        description: None,
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn team_names_are_unique_per_organization() {
+    let boot = boot_test::<App>()
+        .await
+        .expect("Failed to boot test application");
+    seed::<App>(&boot.app_context)
+        .await
+        .expect("Failed to seed database");
+    let db = &boot.app_context.db;
+
+    let owner = users::Model::find_by_email(db, "user1@example.com")
+        .await
+        .unwrap();
+    let engineering = organizations::Model::create(
+        db,
+        owner.id,
+        &OrganizationParams {
+            name: "Engineering".to_string(),
+            description: None,
+        },
+    )
+    .await
+    .unwrap();
+    let sales = organizations::Model::create(
+        db,
+        owner.id,
+        &OrganizationParams {
+            name: "Sales".to_string(),
+            description: Some(" ".to_string()),
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(sales.description, None);
+    assert!(
+        organizations::Model::create(
+            db,
+            owner.id,
+            &OrganizationParams {
+                name: "Sales".to_string(),
+                description: None,
+            },
+        )
+        .await
+        .is_err()
+    );
+
+    let platform =
+        teams::Model::create_team_in(db, owner.id, &team_params("Platform"), Some(engineering.id))
+            .await
+            .unwrap();
+    assert!(
+        teams::Model::create_team_in(db, owner.id, &team_params("Platform"), Some(engineering.id))
+            .await
+            .is_err()
+    );
+    teams::Model::create_team_in(db, owner.id, &team_params("Platform"), Some(sales.id))
+        .await
+        .unwrap();
+    let top_level = teams::Model::create_team(db, owner.id, &team_params("Platform"))
+        .await
+        .unwrap();
+    assert!(
+        teams::Model::create_team(db, owner.id, &team_params("Platform"))
+            .await
+            .is_err()
+    );
+    // The database enforces it too, outside of organizations as well
+    let duplicate = teams::ActiveModel {
+        pid: ActiveValue::Set(uuid::Uuid::new_v4()),
+        name: ActiveValue::Set("Platform".to_string()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await;
+    assert!(duplicate.is_err());
+
+    // Moving a team needs a free name in the destination
+    assert!(
+        top_level
+            .move_to_organization(db, Some(engineering.id))
+            .await
+            .is_err()
+    );
+    let moved = platform.move_to_organization(db, None).await;
+    assert!(moved.is_err());
+    let support = teams::Model::create_team(db, owner.id, &team_params("Support"))
+        .await
+        .unwrap();
+    let support = support
+        .move_to_organization(db, Some(sales.id))
+        .await
+        .unwrap();
+    assert_eq!(support.organization_id, Some(sales.id));
+
+    // Organizations with teams cannot be deleted
+    assert!(engineering.delete(db).await.is_err());
+}
+
+#[tokio::test]
+#[serial]
+async fn members_inherit_their_organization_role() {
+    let boot = boot_test::<App>()
+        .await
+        .expect("Failed to boot test application");
+    seed::<App>(&boot.app_context)
+        .await
+        .expect("Failed to seed database");
+    let db = &boot.app_context.db;
+
+    let owner = users::Model::find_by_email(db, "user1@example.com")
+        .await
+        .unwrap();
+    let organization = organizations::Model::create(
+        db,
+        owner.id,
+        &OrganizationParams {
+            name: "Engineering".to_string(),
+            description: None,
+        },
+    )
+    .await
+    .unwrap();
+    let team_owner = create_user(db, "team-owner").await;
+    let team = teams::Model::create_team_in(
+        db,
+        team_owner.id,
+        &team_params("Platform"),
+        Some(organization.id),
+    )
+    .await
+    .unwrap();
+    let outside_team = teams::Model::create_team(db, team_owner.id, &team_params("Outside"))
+        .await
+        .unwrap();
+
+    let developer = create_user(db, "developer").await;
+    let membership =
+        organization_memberships::Model::add_member(db, organization.id, &developer, "Developer")
+            .await
+            .unwrap();
+    assert!(
+        organization_memberships::Model::add_member(db, organization.id, &developer, "Observer")
+            .await
+            .is_err()
+    );
+
+    // The organization role applies to the teams of the organization only
+    assert!(
+        team.has_permission(db, developer.id, permissions::TEAM_VIEW)
+            .await
+            .unwrap()
+    );
+    assert!(
+        !team
+            .has_permission(db, developer.id, permissions::TEAM_INVITE)
+            .await
+            .unwrap()
+    );
+    assert!(
+        outside_team
+            .member_permissions(db, developer.id)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    let visible = teams::Model::list_visible_to(db, developer.id)
+        .await
+        .unwrap();
+    assert_eq!(
+        visible
+            .iter()
+            .map(|team| team.name.as_str())
+            .collect::<Vec<_>>(),
+        vec!["Platform"]
+    );
+
+    // Permissions of the team role add up with those of the organization
+    team_memberships::Model::add_member(db, team.id, &developer, "Administrator")
+        .await
+        .unwrap();
+    assert!(
+        team.has_permission(db, developer.id, permissions::TEAM_INVITE)
+            .await
+            .unwrap()
+    );
+
+    // Organization Owners are Owners of every team of the organization
+    assert!(
+        team.has_permission(db, owner.id, permissions::TEAM_DELETE)
+            .await
+            .unwrap()
+    );
+    let team_owner_membership =
+        team_memberships::Model::find_by_team_and_user(db, team.id, team_owner.id)
+            .await
+            .unwrap();
+    let developer_role = hosting_farm::models::roles::Model::find_by_name(db, "Developer")
+        .await
+        .unwrap();
+    team_owner_membership
+        .check_role_change_allowed(db, &team, owner.id, &developer_role)
+        .await
+        .unwrap();
+
+    // Only the Owners of both organizations move a team
+    team.check_move_allowed(db, owner.id, None).await.unwrap();
+    assert!(
+        team.check_move_allowed(db, team_owner.id, None)
+            .await
+            .is_err()
+    );
+
+    // The last Owner stays
+    let owner_membership = organization_memberships::Model::find_by_organization_and_user(
+        db,
+        organization.id,
+        owner.id,
+    )
+    .await
+    .unwrap();
+    assert!(owner_membership.remove(db).await.is_err());
+    assert!(owner_membership.update_role(db, "Developer").await.is_err());
+    assert!(
+        owner
+            .clone()
+            .delete_account(db, &Default::default())
+            .await
+            .is_err()
+    );
+    membership.update_role(db, "Owner").await.unwrap();
+    owner_membership.remove(db).await.unwrap();
+    assert!(
+        !team
+            .has_permission(db, owner.id, permissions::TEAM_VIEW)
+            .await
+            .unwrap()
+    );
+}
@@ -0,0 +1,111 @@
+use hosting_farm::{
+    app::App,
+    models::{
+        ownership_transfers,
+        password_policy::PasswordPolicy,
+        team_memberships::{self, LAST_OWNER_REMOVAL, LAST_OWNER_ROLE_CHANGE},
+        teams::{self, CreateTeamParams},
+        tokens::TokenLifetimes,
+        users::{self, RegisterParams},
+    },
+};
+use loco_rs::{model::ModelError, testing::prelude::*};
+use serial_test::serial;
+
+#[tokio::test]
+#[serial]
+async fn can_transfer_ownership() {
+    let boot = boot_test::<App>()
+        .await
+        .expect("Failed to boot test application");
+    seed::<App>(&boot.app_context)
+        .await
+        .expect("Failed to seed database");
+    let db = &boot.app_context.db;
+    let user1 = users::Model::find_by_email(db, "user1@example.com")
+        .await
+        .unwrap();
+    let user2 = users::Model::create_with_password(
+        db,
+        &RegisterParams {
+            email: "user2@example.com".to_string(),
+            password: "correct horse battery".to_string(),
+            name: "user2".to_string(),
+            password_confirmation: "correct horse battery".to_string(),
+        },
+        &PasswordPolicy::default(),
+    )
+    .await
+    .unwrap();
+    let team = teams::Model::create_team(
+        db,
+        user1.id,
+        &CreateTeamParams {
+            name: "Transfer".to_string(),
+            description: None,
+        },
+    )
+    .await
+    .unwrap();
+    team_memberships::Model::add_member(db, team.id, &user2, "Developer")
+        .await
+        .unwrap();
+
+    // The only Owner can neither be demoted nor removed
+    let owner = team_memberships::Model::find_by_team_and_user(db, team.id, user1.id)
+        .await
+        .unwrap();
+    let err = owner.update_role(db, "Developer").await.unwrap_err();
+    assert!(matches!(err, ModelError::Message(msg) if msg == LAST_OWNER_ROLE_CHANGE));
+    let err = owner.remove_from_team(db).await.unwrap_err();
+    assert!(matches!(err, ModelError::Message(msg) if msg == LAST_OWNER_REMOVAL));
+
+    let lifetimes = TokenLifetimes::default();
+    assert!(
+        ownership_transfers::Model::create(db, &team, &user2, &user1, &lifetimes)
+            .await
+            .is_err()
+    );
+    let transfer = ownership_transfers::Model::create(db, &team, &user1, &user2, &lifetimes)
+        .await
+        .unwrap();
+    assert!(!transfer.is_expired());
+
+    // Only the receiving member can accept it; the offering Owner can cancel it
+    assert!(transfer.clone().accept(db, &user1).await.is_err());
+    transfer.decline(db, &user1).await.unwrap();
+    assert!(
+        ownership_transfers::Model::find_pending_for_team(db, team.id)
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    ownership_transfers::Model::create(db, &team, &user1, &user2, &lifetimes)
+        .await
+        .unwrap();
+    let transfer = ownership_transfers::Model::find_pending_for_team(db, team.id)
+        .await
+        .unwrap()
+        .unwrap();
+    transfer.accept(db, &user2).await.unwrap();
+
+    let former_owner = team_memberships::Model::find_by_team_and_user(db, team.id, user1.id)
+        .await
+        .unwrap();
+    assert_eq!(former_owner.role, "Developer");
+    let new_owner = team_memberships::Model::find_by_team_and_user(db, team.id, user2.id)
+        .await
+        .unwrap();
+    assert_eq!(new_owner.role, "Owner");
+    assert!(
+        ownership_transfers::Model::find_pending_for_team(db, team.id)
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    // The former Owner can now leave, but the new one cannot
+    former_owner.remove_from_team(db).await.unwrap();
+    assert!(new_owner.remove_from_team(db).await.is_err());
+}
@@ -0,0 +1,132 @@
+use hosting_farm::{
+    app::App,
+    models::{
+        personal_access_tokens::{
+            self, CreateTokenParams, SCOPE_SSH_KEYS_READ, SCOPE_SSH_KEYS_WRITE, SCOPE_TEAMS_READ,
+            SCOPE_TEAMS_WRITE,
+        },
+        users,
+    },
+};
+use loco_rs::{prelude::Authenticable, testing::prelude::*};
+use sea_orm::{ActiveModelTrait, ActiveValue, IntoActiveModel};
+use serial_test::serial;
+
+fn params(scopes: &[&str], expires_in_days: Option<i64>) -> CreateTokenParams {
+    CreateTokenParams {
+        name: "Deployment script".to_string(),
+        scopes: scopes.iter().map(ToString::to_string).collect(),
+        expires_in_days,
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn can_create_and_authenticate_with_scoped_tokens() {
+    let boot = boot_test::<App>()
+        .await
+        .expect("Failed to boot test application");
+    seed::<App>(&boot.app_context)
+        .await
+        .expect("Failed to seed database");
+    let ctx = &boot.app_context;
+    let user = users::Model::find_by_email(&ctx.db, "user1@example.com")
+        .await
+        .unwrap();
+
+    // Invalid requests are refused
+    assert!(
+        personal_access_tokens::Model::create_for_user(&ctx.db, user.id, &params(&[], None))
+            .await
+            .is_err()
+    );
+    assert!(
+        personal_access_tokens::Model::create_for_user(
+            &ctx.db,
+            user.id,
+            &params(&["servers:write"], None)
+        )
+        .await
+        .is_err()
+    );
+    assert!(
+        personal_access_tokens::Model::create_for_user(
+            &ctx.db,
+            user.id,
+            &params(&[SCOPE_TEAMS_READ], Some(100_000_000))
+        )
+        .await
+        .is_err(),
+        "Lifetimes beyond the maximum are refused"
+    );
+
+    let (token, plaintext) = personal_access_tokens::Model::create_for_user(
+        &ctx.db,
+        user.id,
+        &params(&[SCOPE_SSH_KEYS_WRITE, SCOPE_TEAMS_READ], Some(30)),
+    )
+    .await
+    .unwrap();
+    assert!(plaintext.starts_with(personal_access_tokens::TOKEN_PREFIX));
+    assert!(plaintext.starts_with(&token.token_prefix));
+    assert_ne!(token.token_hash, plaintext, "Only the hash is stored");
+    assert!(token.last_used_at.is_none());
+
+    // Write scopes imply the matching read scope
+    assert!(token.has_scope(SCOPE_TEAMS_READ));
+    assert!(!token.has_scope(SCOPE_TEAMS_WRITE));
+    assert!(token.has_scope(SCOPE_SSH_KEYS_READ));
+    assert!(token.has_scope(SCOPE_SSH_KEYS_WRITE));
+
+    // The token authenticates its user and records its use
+    let found = <users::Model as Authenticable>::find_by_api_key(&ctx.db, &plaintext)
+        .await
+        .unwrap();
+    assert_eq!(found.id, user.id);
+    let (used, _) = personal_access_tokens::Model::authenticate(&ctx.db, &plaintext)
+        .await
+        .unwrap();
+    assert!(used.last_used_at.is_some());
+
+    // The legacy API key is no longer accepted
+    assert!(
+        <users::Model as Authenticable>::find_by_api_key(&ctx.db, &user.api_key)
+            .await
+            .is_err()
+    );
+
+    // Expired tokens are refused
+    let mut expired = used.clone().into_active_model();
+    expired.expires_at = ActiveValue::Set(Some(
+        (chrono::Utc::now() - chrono::Duration::days(1)).into(),
+    ));
+    let expired = expired.update(&ctx.db).await.unwrap();
+    assert!(expired.is_expired());
+    assert!(
+        personal_access_tokens::Model::authenticate(&ctx.db, &plaintext)
+            .await
+            .is_err()
+    );
+
+    // Revoked tokens are refused
+    let (token, plaintext) = personal_access_tokens::Model::create_for_user(
+        &ctx.db,
+        user.id,
+        &params(&[SCOPE_TEAMS_READ], None),
+    )
+    .await
+    .unwrap();
+    assert!(token.expires_at.is_none());
+    let revoked = token.revoke(&ctx.db).await.unwrap();
+    assert!(revoked.revoked_at.is_some());
+    assert!(
+        personal_access_tokens::Model::authenticate(&ctx.db, &plaintext)
+            .await
+            .is_err()
+    );
+
+    let tokens = personal_access_tokens::Model::find_for_user(&ctx.db, user.id)
+        .await
+        .unwrap();
+    assert_eq!(tokens.len(), 2);
+}
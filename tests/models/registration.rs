@@ -0,0 +1,119 @@
+use hosting_farm::{
+    app::App,
+    models::{
+        password_policy::PasswordPolicy,
+        registration::{RegistrationMode, RegistrationPolicy},
+        team_memberships::{self, DEFAULT_INVITATION_ROLE, InvitationParams},
+        teams::{self, CreateTeamParams},
+        tokens::TokenLifetimes,
+        users::{self, LoginOutcome, RegisterParams, STATUS_PENDING_APPROVAL},
+    },
+};
+use loco_rs::testing::prelude::*;
+use serial_test::serial;
+
+fn policy(mode: RegistrationMode) -> RegistrationPolicy {
+    RegistrationPolicy {
+        mode,
+        allowed_domains: vec!["example.com".to_string(), "@corp.example.org".to_string()],
+    }
+}
+
+#[test]
+fn can_restrict_email_domains() {
+    let open = policy(RegistrationMode::Open);
+    assert!(open.allows_domain("someone@elsewhere.net"));
+
+    let domains = policy(RegistrationMode::Domains);
+    assert!(domains.allows_domain("someone@example.com"));
+    assert!(domains.allows_domain("Someone@EXAMPLE.com"));
+    assert!(domains.allows_domain("someone@corp.example.org"));
+    assert!(!domains.allows_domain("someone@elsewhere.net"));
+    assert!(
+        !domains.allows_domain("someone@sub.example.com"),
+        "Subdomains must be listed explicitly"
+    );
+    assert!(!domains.allows_domain("not-an-email"));
+}
+
+#[tokio::test]
+#[serial]
+async fn can_apply_registration_modes() {
+    let boot = boot_test::<App>()
+        .await
+        .expect("Failed to boot test application");
+    seed::<App>(&boot.app_context)
+        .await
+        .expect("Failed to seed database");
+    let ctx = &boot.app_context;
+
+    // Invite only: an email is accepted once a team invitation is pending
+    let invite_only = policy(RegistrationMode::InviteOnly);
+    let invited = users::Model::create_with_password(
+        &ctx.db,
+        &RegisterParams {
+            email: "invited@example.com".to_string(),
+            password: "correct horse battery".to_string(),
+            name: "invited".to_string(),
+            password_confirmation: "correct horse battery".to_string(),
+        },
+        &PasswordPolicy::default(),
+    )
+    .await
+    .unwrap();
+    assert!(invite_only.check(&ctx.db, &invited.email).await.is_err());
+    let owner = users::Model::find_by_email(&ctx.db, "user1@example.com")
+        .await
+        .unwrap();
+    let team = teams::Model::create_team(
+        &ctx.db,
+        owner.id,
+        &CreateTeamParams {
+            name: "Operations".to_string(),
+            description: None,
+        },
+    )
+    .await
+    .unwrap();
+    team_memberships::Model::create_invitation(
+        &ctx.db,
+        team.id,
+        &invited.name,
+        &InvitationParams {
+            invited_by_id: owner.id,
+            role: DEFAULT_INVITATION_ROLE.to_string(),
+            message: None,
+        },
+        &TokenLifetimes::default(),
+    )
+    .await
+    .unwrap();
+    assert!(invite_only.check(&ctx.db, &invited.email).await.is_ok());
+
+    // Approval: new accounts cannot log in until they are approved
+    let approval = policy(RegistrationMode::Approval);
+    assert!(
+        approval
+            .check(&ctx.db, "someone@elsewhere.net")
+            .await
+            .is_ok()
+    );
+    let params = RegisterParams {
+        email: "someone@elsewhere.net".to_string(),
+        password: "correct horse battery".to_string(),
+        name: "someone".to_string(),
+        password_confirmation: "correct horse battery".to_string(),
+    };
+    let user = users::Model::create_with_password(&ctx.db, &params, &PasswordPolicy::default())
+        .await
+        .unwrap();
+    let user = approval.admit(&ctx.db, user).await.unwrap();
+    assert_eq!(user.status, STATUS_PENDING_APPROVAL);
+    assert!(!user.is_active());
+
+    let outcome =
+        users::Model::check_password_login(&ctx.db, &params.email, &params.password, None)
+            .await
+            .unwrap();
+    assert!(matches!(outcome, LoginOutcome::Inactive(_)));
+}
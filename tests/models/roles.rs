@@ -0,0 +1,271 @@
+use hosting_farm::{
+    app::App,
+    models::{
+        password_policy::PasswordPolicy,
+        permissions,
+        roles::{self, RoleParams},
+        team_join_links::{self, CreateJoinLinkParams},
+        team_memberships,
+        teams::{self, CreateTeamParams},
+        users::{self, RegisterParams},
+    },
+};
+use loco_rs::testing::prelude::*;
+use serial_test::serial;
+
+async fn create_user(db: &sea_orm::DatabaseConnection, name: &str) -> users::Model {
+    users::Model::create_with_password(
+        db,
+        &RegisterParams {
+            email: format!("{name}@example.com"),
+            password: "correct horse battery".to_string(),
+            name: name.to_string(),
+            password_confirmation: "correct horse battery".to_string(),
+        },
+        &PasswordPolicy::default(),
+    )
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+#[serial]
+async fn builtin_roles_grant_their_permissions() {
+    let boot = boot_test::<App>()
+        .await
+        .expect("Failed to boot test application");
+    seed::<App>(&boot.app_context)
+        .await
+        .expect("Failed to seed database");
+    let db = &boot.app_context.db;
+
+    assert_eq!(
+        roles::Model::names(db).await.unwrap(),
+        vec!["Owner", "Administrator", "Developer", "Observer"]
+    );
+
+    let owner = users::Model::find_by_email(db, "user1@example.com")
+        .await
+        .unwrap();
+    let team = teams::Model::create_team(
+        db,
+        owner.id,
+        &CreateTeamParams {
+            name: "Permissions".to_string(),
+            description: None,
+        },
+    )
+    .await
+    .unwrap();
+    let admin = create_user(db, "admin-member").await;
+    let developer = create_user(db, "developer-member").await;
+    let other_admin = create_user(db, "other-admin").await;
+    team_memberships::Model::add_member(db, team.id, &admin, "Administrator")
+        .await
+        .unwrap();
+    let developer_membership =
+        team_memberships::Model::add_member(db, team.id, &developer, "Developer")
+            .await
+            .unwrap();
+    let other_admin_membership =
+        team_memberships::Model::add_member(db, team.id, &other_admin, "Administrator")
+            .await
+            .unwrap();
+    assert!(
+        team_memberships::Model::add_member(db, team.id, &create_user(db, "boss").await, "Boss")
+            .await
+            .is_err()
+    );
+
+    assert!(
+        team.has_permission(db, owner.id, permissions::TEAM_DELETE)
+            .await
+            .unwrap()
+    );
+    assert!(
+        team.has_permission(db, admin.id, permissions::TEAM_INVITE)
+            .await
+            .unwrap()
+    );
+    assert!(
+        !team
+            .has_permission(db, admin.id, permissions::TEAM_DELETE)
+            .await
+            .unwrap()
+    );
+    assert!(
+        team.has_permission(db, developer.id, permissions::TEAM_VIEW)
+            .await
+            .unwrap()
+    );
+    assert!(
+        !team
+            .has_permission(db, developer.id, permissions::TEAM_INVITE)
+            .await
+            .unwrap()
+    );
+    assert_eq!(
+        team.grantable_roles(db, admin.id).await.unwrap(),
+        vec!["Administrator", "Developer", "Observer"]
+    );
+
+    // Administrators remove the members below them, and give no role
+    developer_membership
+        .check_removal_allowed(db, &team, admin.id)
+        .await
+        .unwrap();
+    assert!(
+        other_admin_membership
+            .check_removal_allowed(db, &team, admin.id)
+            .await
+            .is_err()
+    );
+    other_admin_membership
+        .check_removal_allowed(db, &team, owner.id)
+        .await
+        .unwrap();
+    let observer = roles::Model::find_by_name(db, "Observer").await.unwrap();
+    assert!(
+        developer_membership
+            .check_role_change_allowed(db, &team, admin.id, &observer)
+            .await
+            .is_err()
+    );
+    developer_membership
+        .check_role_change_allowed(db, &team, owner.id, &observer)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+#[serial]
+async fn can_define_custom_roles() {
+    let boot = boot_test::<App>()
+        .await
+        .expect("Failed to boot test application");
+    seed::<App>(&boot.app_context)
+        .await
+        .expect("Failed to seed database");
+    let db = &boot.app_context.db;
+
+    let owner_role = roles::Model::find_by_name(db, roles::OWNER_ROLE)
+        .await
+        .unwrap();
+    assert!(
+        owner_role
+            .clone()
+            .update(db, &RoleParams::default())
+            .await
+            .is_err()
+    );
+    assert!(owner_role.delete(db).await.is_err());
+    assert!(
+        roles::Model::create(
+            db,
+            &RoleParams {
+                name: "Invited".to_string(),
+                ..Default::default()
+            },
+        )
+        .await
+        .is_err()
+    );
+    assert!(
+        roles::Model::create(
+            db,
+            &RoleParams {
+                name: "Operator".to_string(),
+                permissions: vec!["server.explode".to_string()],
+                ..Default::default()
+            },
+        )
+        .await
+        .is_err()
+    );
+
+    let role = roles::Model::create(
+        db,
+        &RoleParams {
+            name: "Recruiter".to_string(),
+            description: Some(" Invites people ".to_string()),
+            permissions: vec![
+                permissions::TEAM_VIEW.to_string(),
+                permissions::TEAM_INVITE.to_string(),
+            ],
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(role.description.as_deref(), Some("Invites people"));
+
+    let owner = users::Model::find_by_email(db, "user1@example.com")
+        .await
+        .unwrap();
+    let team = teams::Model::create_team(
+        db,
+        owner.id,
+        &CreateTeamParams {
+            name: "Custom".to_string(),
+            description: None,
+        },
+    )
+    .await
+    .unwrap();
+    let recruiter = create_user(db, "recruiter").await;
+    let membership = team_memberships::Model::add_member(db, team.id, &recruiter, "Recruiter")
+        .await
+        .unwrap();
+    assert!(
+        team.has_permission(db, recruiter.id, permissions::TEAM_INVITE)
+            .await
+            .unwrap()
+    );
+    assert!(
+        !team
+            .has_permission(db, recruiter.id, permissions::MEMBERS_REMOVE)
+            .await
+            .unwrap()
+    );
+
+    // Roles given to members cannot be deleted
+    assert!(role.clone().delete(db).await.is_err());
+
+    let role = role
+        .update(
+            db,
+            &RoleParams {
+                permissions: vec![permissions::TEAM_VIEW.to_string()],
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert!(
+        !team
+            .has_permission(db, recruiter.id, permissions::TEAM_INVITE)
+            .await
+            .unwrap()
+    );
+
+    membership.remove_from_team(db).await.unwrap();
+
+    // Nor can roles given by join links
+    let (link, _) = team_join_links::Model::create(
+        db,
+        &team,
+        &owner,
+        &CreateJoinLinkParams {
+            role: "Recruiter".to_string(),
+            max_uses: None,
+            expires_in_days: None,
+            allowed_domain: None,
+        },
+    )
+    .await
+    .unwrap();
+    assert!(role.clone().delete(db).await.is_err());
+    link.revoke(db, &team, &owner).await.unwrap();
+
+    role.delete(db).await.unwrap();
+    assert!(roles::Model::find_by_name(db, "Recruiter").await.is_err());
+}
@@ -0,0 +1,179 @@
+use hosting_farm::{
+    app::App,
+    models::{
+        _entities::team_memberships,
+        password_policy::PasswordPolicy,
+        personal_access_tokens::{self, CreateTokenParams, SCOPE_TEAMS_WRITE},
+        service_accounts::{self, CreateServiceAccountParams},
+        teams::{self, CreateTeamParams},
+        users::{self, RegisterParams},
+    },
+};
+use loco_rs::testing::prelude::*;
+use sea_orm::{ActiveModelTrait, ActiveValue, EntityTrait};
+use serial_test::serial;
+
+fn params(name: &str, role: &str) -> CreateServiceAccountParams {
+    CreateServiceAccountParams {
+        name: name.to_string(),
+        description: Some("Deploys the team's services".to_string()),
+        role: role.to_string(),
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn can_manage_team_service_accounts() {
+    let boot = boot_test::<App>()
+        .await
+        .expect("Failed to boot test application");
+    seed::<App>(&boot.app_context)
+        .await
+        .expect("Failed to seed database");
+    let ctx = &boot.app_context;
+
+    let owner = users::Model::find_by_email(&ctx.db, "user1@example.com")
+        .await
+        .unwrap();
+    let team = teams::Model::create_team(
+        &ctx.db,
+        owner.id,
+        &CreateTeamParams {
+            name: "Automation".to_string(),
+            description: None,
+        },
+    )
+    .await
+    .unwrap();
+    let admin = users::Model::create_with_password(
+        &ctx.db,
+        &RegisterParams {
+            email: "team-admin@example.com".to_string(),
+            password: "correct horse battery".to_string(),
+            name: "team-admin".to_string(),
+            password_confirmation: "correct horse battery".to_string(),
+        },
+        &PasswordPolicy::default(),
+    )
+    .await
+    .unwrap();
+    team_memberships::ActiveModel {
+        team_id: ActiveValue::Set(team.id),
+        user_id: ActiveValue::Set(Some(admin.id)),
+        role: ActiveValue::Set("Developer".to_string()),
+        pending: ActiveValue::Set(false),
+        ..Default::default()
+    }
+    .insert(&ctx.db)
+    .await
+    .unwrap();
+
+    // Developers cannot manage service accounts
+    assert!(
+        service_accounts::Model::create(&ctx.db, &team, &admin, &params("deploy-bot", "Developer"))
+            .await
+            .is_err()
+    );
+    let membership = team_memberships::Entity::find()
+        .all(&ctx.db)
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|membership| membership.user_id == Some(admin.id))
+        .unwrap();
+    let mut membership: team_memberships::ActiveModel = membership.into();
+    membership.role = ActiveValue::Set("Administrator".to_string());
+    membership.update(&ctx.db).await.unwrap();
+
+    // Administrators cannot grant the Owner role, and names are unique
+    assert!(
+        service_accounts::Model::create(&ctx.db, &team, &admin, &params("deploy-bot", "Owner"))
+            .await
+            .is_err()
+    );
+    assert!(
+        service_accounts::Model::create(&ctx.db, &team, &admin, &params("team-admin", "Developer"))
+            .await
+            .is_err()
+    );
+
+    let service_account =
+        service_accounts::Model::create(&ctx.db, &team, &admin, &params("deploy-bot", "Developer"))
+            .await
+            .unwrap();
+    assert_eq!(service_account.created_by_id, Some(admin.id));
+    let accounts = service_accounts::Model::find_for_team(&ctx.db, team.id)
+        .await
+        .unwrap();
+    assert_eq!(accounts.len(), 1);
+    assert_eq!(accounts[0].role, "Developer");
+    assert_eq!(accounts[0].created_by.as_ref().unwrap().id, admin.id);
+    assert!(accounts[0].user.is_service_account());
+    assert!(
+        !accounts[0].user.is_active(),
+        "Service accounts cannot log in"
+    );
+    assert_eq!(
+        team.member_role(&ctx.db, accounts[0].user.id)
+            .await
+            .unwrap()
+            .map(|role| role.name)
+            .as_deref(),
+        Some("Developer")
+    );
+
+    // Its tokens authenticate it for the API
+    let (_, plaintext) = service_account
+        .create_token(
+            &ctx.db,
+            &team,
+            &admin,
+            &CreateTokenParams {
+                name: "CI".to_string(),
+                scopes: vec![SCOPE_TEAMS_WRITE.to_string()],
+                expires_in_days: None,
+            },
+        )
+        .await
+        .unwrap();
+    let (_, authenticated) = personal_access_tokens::Model::authenticate(&ctx.db, &plaintext)
+        .await
+        .unwrap();
+    assert_eq!(authenticated.id, service_account.user_id);
+
+    // Only owners may promote it to Owner
+    assert!(
+        service_account
+            .update_role(&ctx.db, &team, &admin, "Owner")
+            .await
+            .is_err()
+    );
+    service_account
+        .update_role(&ctx.db, &team, &owner, "Owner")
+        .await
+        .unwrap();
+    assert_eq!(
+        team.member_role(&ctx.db, service_account.user_id)
+            .await
+            .unwrap()
+            .map(|role| role.name)
+            .as_deref(),
+        Some("Owner")
+    );
+
+    // Deleting the team deletes its service accounts
+    let service_user_id = service_account.user_id;
+    team.delete(&ctx.db).await.unwrap();
+    assert!(
+        users::Entity::find_by_id(service_user_id)
+            .one(&ctx.db)
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        personal_access_tokens::Model::authenticate(&ctx.db, &plaintext)
+            .await
+            .is_err()
+    );
+}
@@ -0,0 +1,238 @@
+use hosting_farm::{
+    app::App,
+    models::{
+        password_policy::PasswordPolicy,
+        team_join_links::{self, CreateJoinLinkParams, JOIN_LINK_MAX_LIFETIME_DAYS},
+        team_memberships::{self, InvitationParams},
+        teams::{self, CreateTeamParams},
+        tokens::TokenLifetimes,
+        users::{self, RegisterParams},
+    },
+};
+use loco_rs::testing::prelude::*;
+use sea_orm::{ActiveModelTrait, ActiveValue, IntoActiveModel};
+use serial_test::serial;
+
+async fn register(db: &sea_orm::DatabaseConnection, name: &str, email: &str) -> users::Model {
+    users::Model::create_with_password(
+        db,
+        &RegisterParams {
+            email: email.to_string(),
+            password: "correct horse battery".to_string(),
+            name: name.to_string(),
+            password_confirmation: "correct horse battery".to_string(),
+        },
+        &PasswordPolicy::default(),
+    )
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+#[serial]
+async fn can_join_a_team_with_a_join_link() {
+    let boot = boot_test::<App>()
+        .await
+        .expect("Failed to boot test application");
+    seed::<App>(&boot.app_context)
+        .await
+        .expect("Failed to seed database");
+    let db = &boot.app_context.db;
+    let owner = users::Model::find_by_email(db, "user1@example.com")
+        .await
+        .unwrap();
+    let team = teams::Model::create_team(
+        db,
+        owner.id,
+        &CreateTeamParams {
+            name: "Workshop".to_string(),
+            description: None,
+        },
+    )
+    .await
+    .unwrap();
+    let developer = register(db, "developer", "developer@example.org").await;
+    team_memberships::Model::add_member(db, team.id, &developer, "Developer")
+        .await
+        .unwrap();
+
+    let params = CreateJoinLinkParams::from_form_fields(vec![
+        ("role".to_string(), "Developer".to_string()),
+        ("max_uses".to_string(), "1".to_string()),
+        ("expires_in_days".to_string(), "7".to_string()),
+        ("allowed_domain".to_string(), "@Example.com".to_string()),
+    ])
+    .unwrap();
+    assert_eq!(params.max_uses, Some(1));
+    assert!(
+        CreateJoinLinkParams::from_form_fields(vec![("max_uses".to_string(), "many".to_string())])
+            .is_err()
+    );
+
+    // Only members who may invite users create links, which cannot make
+    // anyone an Owner
+    assert!(
+        team_join_links::Model::create(db, &team, &developer, &params)
+            .await
+            .is_err()
+    );
+    let owner_params = CreateJoinLinkParams {
+        role: "Owner".to_string(),
+        max_uses: None,
+        expires_in_days: None,
+        allowed_domain: None,
+    };
+    assert!(
+        team_join_links::Model::create(db, &team, &owner, &owner_params)
+            .await
+            .is_err()
+    );
+
+    let (link, token) = team_join_links::Model::create(db, &team, &owner, &params)
+        .await
+        .unwrap();
+    assert_ne!(link.token_hash, token);
+    assert_eq!(link.allowed_domain.as_deref(), Some("example.com"));
+    let link = team_join_links::Model::find_by_token(db, &token)
+        .await
+        .unwrap();
+    assert!(link.is_usable());
+
+    // Users outside the allowed domain cannot join
+    let outsider = register(db, "outsider", "outsider@example.net").await;
+    assert!(link.join(db, &outsider).await.is_err());
+
+    let user = register(db, "attendee", "attendee@Example.COM").await;
+    let user = user.into_active_model().verified(db).await.unwrap();
+    let membership = link.join(db, &user).await.unwrap();
+    assert!(!membership.pending);
+    assert_eq!(membership.role, "Developer");
+    assert_eq!(membership.invited_by_id, Some(owner.id));
+
+    // The link was used as many times as allowed
+    let link = team_join_links::Model::find_by_token(db, &token)
+        .await
+        .unwrap();
+    assert_eq!(link.use_count, 1);
+    assert!(link.is_used_up());
+    let latecomer = register(db, "latecomer", "latecomer@example.com").await;
+    assert!(link.join(db, &latecomer).await.is_err());
+
+    // Revoked links stop working
+    let (link, token) = team_join_links::Model::create(
+        db,
+        &team,
+        &owner,
+        &CreateJoinLinkParams::from_form_fields(vec![]).unwrap(),
+    )
+    .await
+    .unwrap();
+    assert!(link.join(db, &user).await.is_err());
+    assert_eq!(
+        team_join_links::Model::find_for_team(db, team.id)
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+    assert!(link.clone().revoke(db, &team, &developer).await.is_err());
+    link.revoke(db, &team, &owner).await.unwrap();
+    assert!(
+        team_join_links::Model::find_by_token(db, &token)
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn join_links_are_bounded_by_the_permissions_of_their_creator() {
+    let boot = boot_test::<App>()
+        .await
+        .expect("Failed to boot test application");
+    seed::<App>(&boot.app_context)
+        .await
+        .expect("Failed to seed database");
+    let db = &boot.app_context.db;
+    let owner = users::Model::find_by_email(db, "user1@example.com")
+        .await
+        .unwrap();
+    let team = teams::Model::create_team(
+        db,
+        owner.id,
+        &CreateTeamParams {
+            name: "Foundry".to_string(),
+            description: None,
+        },
+    )
+    .await
+    .unwrap();
+    let administrator = register(db, "administrator", "administrator@example.org").await;
+    team_memberships::Model::add_member(db, team.id, &administrator, "Administrator")
+        .await
+        .unwrap();
+
+    // Administrators may invite users, so they create links, but only with
+    // roles their own permissions include
+    let params = |role: &str, expires_in_days: Option<i64>| CreateJoinLinkParams {
+        role: role.to_string(),
+        max_uses: None,
+        expires_in_days,
+        allowed_domain: None,
+    };
+    assert!(
+        team_join_links::Model::create(db, &team, &administrator, &params("Developer", None))
+            .await
+            .is_ok()
+    );
+    assert!(
+        team_join_links::Model::create(
+            db,
+            &team,
+            &administrator,
+            &params("Administrator", Some(30))
+        )
+        .await
+        .is_ok()
+    );
+
+    // Lifetimes beyond the limit are refused instead of overflowing
+    for days in [JOIN_LINK_MAX_LIFETIME_DAYS + 1, i64::MAX] {
+        assert!(
+            team_join_links::Model::create(db, &team, &owner, &params("Developer", Some(days)))
+                .await
+                .is_err()
+        );
+    }
+
+    // An expired invitation with another role is accepted with the role of
+    // the link
+    let (link, _) =
+        team_join_links::Model::create(db, &team, &administrator, &params("Developer", Some(7)))
+            .await
+            .unwrap();
+    let invitee = register(db, "invitee", "invitee@example.org").await;
+    let (invitation, _) = team_memberships::Model::create_invitation(
+        db,
+        team.id,
+        &invitee.name,
+        &InvitationParams {
+            invited_by_id: owner.id,
+            role: "Observer".to_string(),
+            message: None,
+        },
+        &TokenLifetimes::default(),
+    )
+    .await
+    .unwrap();
+    let mut invitation: team_memberships::ActiveModel = invitation.into();
+    invitation.invitation_expires_at = ActiveValue::set(Some(
+        (chrono::Utc::now() - chrono::Duration::days(1)).into(),
+    ));
+    invitation.update(db).await.unwrap();
+
+    let membership = link.join(db, &invitee).await.unwrap();
+    assert!(!membership.pending);
+    assert_eq!(membership.role, "Developer");
+    assert_eq!(membership.invited_by_id, Some(administrator.id));
+}
@@ -1,7 +1,29 @@
-use hosting_farm::app::App;
+use chrono::{Duration, Utc};
+use hosting_farm::{
+    app::App,
+    models::{
+        password_policy::PasswordPolicy,
+        team_memberships::{
+            self, DEFAULT_INVITATION_ROLE, INVITATION_MESSAGE_MAX_LENGTH, InvitationParams,
+            InviteMemberParams,
+        },
+        teams::{self, CreateTeamParams},
+        tokens::TokenLifetimes,
+        users::{self, RegisterParams},
+    },
+};
 use loco_rs::testing::prelude::*;
+use sea_orm::{ActiveModelTrait, ActiveValue};
 use serial_test::serial;
 
+fn observer_invitation(invited_by_id: i32) -> InvitationParams {
+    InvitationParams {
+        invited_by_id,
+        role: DEFAULT_INVITATION_ROLE.to_string(),
+        message: None,
+    }
+}
+
 macro_rules! configure_insta {
     ($($expr:expr),*) => {
         let mut settings = insta::Settings::clone_current();
@@ -29,3 +51,462 @@ async fn test_model() {
     // snapshot the result:
     // assert_debug_snapshot!(item);
 }
+
+#[tokio::test]
+#[serial]
+async fn can_invite_an_email_address() {
+    let boot = boot_test::<App>().await.unwrap();
+    seed::<App>(&boot.app_context).await.unwrap();
+    let ctx = &boot.app_context;
+
+    let owner = users::Model::find_by_email(&ctx.db, "user1@example.com")
+        .await
+        .unwrap();
+    let team = teams::Model::create_team(
+        &ctx.db,
+        owner.id,
+        &CreateTeamParams {
+            name: "Operations".to_string(),
+            description: None,
+        },
+    )
+    .await
+    .unwrap();
+    let lifetimes = TokenLifetimes::default();
+    let invitation_params = observer_invitation(owner.id);
+
+    // Registered users are invited by name
+    assert!(
+        team_memberships::Model::create_email_invitation(
+            &ctx.db,
+            team.id,
+            "user1@example.com",
+            &invitation_params,
+            &lifetimes
+        )
+        .await
+        .is_err()
+    );
+
+    let (invitation, token) = team_memberships::Model::create_email_invitation(
+        &ctx.db,
+        team.id,
+        " Newcomer@Example.com",
+        &invitation_params,
+        &lifetimes,
+    )
+    .await
+    .unwrap();
+    assert_eq!(invitation.user_id, None);
+    assert_eq!(
+        invitation.invited_email.as_deref(),
+        Some("newcomer@example.com")
+    );
+    assert!(
+        team_memberships::Model::create_email_invitation(
+            &ctx.db,
+            team.id,
+            "newcomer@example.com",
+            &invitation_params,
+            &lifetimes
+        )
+        .await
+        .is_err()
+    );
+    let found = team_memberships::Model::find_by_invitation_token(&ctx.db, &token)
+        .await
+        .unwrap();
+    assert_eq!(found.id, invitation.id);
+    assert!(
+        team_memberships::Model::has_pending_invitation_for_email(&ctx.db, "newcomer@example.com")
+            .await
+            .unwrap()
+    );
+
+    // The invitation is bound to the account once the address is verified,
+    // and stays pending until the user accepts it
+    let newcomer = users::Model::create_with_password(
+        &ctx.db,
+        &RegisterParams {
+            email: "NewComer@example.com".to_string(),
+            password: "correct horse battery".to_string(),
+            name: "newcomer".to_string(),
+            password_confirmation: "correct horse battery".to_string(),
+        },
+        &PasswordPolicy::default(),
+    )
+    .await
+    .unwrap();
+    let invited_to = team_memberships::Model::attach_email_invitations(&ctx.db, &newcomer)
+        .await
+        .unwrap();
+    assert_eq!(invited_to.len(), 1);
+    assert_eq!(invited_to[0].id, team.id);
+    assert!(
+        team_memberships::Model::find_by_team_and_user(&ctx.db, team.id, newcomer.id)
+            .await
+            .is_err(),
+        "The user is not a member before accepting the invitation"
+    );
+    let invitations = team_memberships::Model::get_user_invitations(&ctx.db, newcomer.id)
+        .await
+        .unwrap();
+    assert_eq!(invitations.len(), 1);
+    let (pending, _) = &invitations[0];
+    assert_eq!(pending.id, invitation.id);
+    assert_eq!(pending.invited_email, None);
+
+    // Addresses are compared whatever their case
+    assert!(
+        team_memberships::Model::has_pending_invitation_for_email(&ctx.db, "newcomer@EXAMPLE.com")
+            .await
+            .unwrap()
+    );
+    let found = users::Model::find_by_email_ignoring_case(&ctx.db, " newcomer@example.COM")
+        .await
+        .unwrap();
+    assert_eq!(found.id, newcomer.id);
+    assert!(
+        team_memberships::Model::create_email_invitation(
+            &ctx.db,
+            team.id,
+            "NEWCOMER@example.com",
+            &invitation_params,
+            &lifetimes
+        )
+        .await
+        .is_err()
+    );
+
+    pending.accept_invitation(&ctx.db).await.unwrap();
+    assert!(
+        !team_memberships::Model::has_pending_invitation_for_email(&ctx.db, "newcomer@example.com")
+            .await
+            .unwrap()
+    );
+    let membership = team_memberships::Model::find_by_team_and_user(&ctx.db, team.id, newcomer.id)
+        .await
+        .unwrap();
+    assert_eq!(membership.id, invitation.id);
+}
+
+#[tokio::test]
+#[serial]
+async fn can_resend_list_and_purge_invitations() {
+    let boot = boot_test::<App>().await.unwrap();
+    seed::<App>(&boot.app_context).await.unwrap();
+    let ctx = &boot.app_context;
+
+    let owner = users::Model::find_by_email(&ctx.db, "user1@example.com")
+        .await
+        .unwrap();
+    let team = teams::Model::create_team(
+        &ctx.db,
+        owner.id,
+        &CreateTeamParams {
+            name: "Operations".to_string(),
+            description: None,
+        },
+    )
+    .await
+    .unwrap();
+    let lifetimes = TokenLifetimes::default();
+    let invitation_params = observer_invitation(owner.id);
+    let (invitation, token) = team_memberships::Model::create_email_invitation(
+        &ctx.db,
+        team.id,
+        "first@example.com",
+        &invitation_params,
+        &lifetimes,
+    )
+    .await
+    .unwrap();
+    let (stale, _) = team_memberships::Model::create_email_invitation(
+        &ctx.db,
+        team.id,
+        "second@example.com",
+        &invitation_params,
+        &lifetimes,
+    )
+    .await
+    .unwrap();
+
+    let invitations = team_memberships::Model::get_team_invitations(&ctx.db, team.id)
+        .await
+        .unwrap();
+    assert_eq!(invitations.len(), 2);
+    assert!(invitations.iter().all(|listed| {
+        listed.invited_user.is_none()
+            && listed.invited_by.as_ref().map(|member| member.id) == Some(owner.id)
+    }));
+
+    // Resending replaces the link that was sent before
+    let (resent, new_token) = invitation
+        .resend_invitation(&ctx.db, &lifetimes)
+        .await
+        .unwrap();
+    assert_ne!(token, new_token);
+    assert!(
+        team_memberships::Model::find_by_invitation_token(&ctx.db, &token)
+            .await
+            .is_err()
+    );
+    let found = team_memberships::Model::find_by_invitation_token(&ctx.db, &new_token)
+        .await
+        .unwrap();
+    assert_eq!(found.id, resent.id);
+
+    // Only the expired invitations are purged
+    let mut expired: team_memberships::ActiveModel = stale.into();
+    expired.invitation_expires_at =
+        ActiveValue::Set(Some((Utc::now() - Duration::hours(1)).into()));
+    expired.update(&ctx.db).await.unwrap();
+    assert_eq!(
+        team_memberships::Model::purge_expired_invitations(&ctx.db)
+            .await
+            .unwrap(),
+        1
+    );
+    let invitations = team_memberships::Model::get_team_invitations(&ctx.db, team.id)
+        .await
+        .unwrap();
+    assert_eq!(invitations.len(), 1);
+    assert_eq!(invitations[0].invitation.id, resent.id);
+}
+
+#[tokio::test]
+#[serial]
+async fn can_invite_with_a_grantable_role_and_a_message() {
+    let boot = boot_test::<App>().await.unwrap();
+    seed::<App>(&boot.app_context).await.unwrap();
+    let ctx = &boot.app_context;
+
+    let owner = users::Model::find_by_email(&ctx.db, "user1@example.com")
+        .await
+        .unwrap();
+    let team = teams::Model::create_team(
+        &ctx.db,
+        owner.id,
+        &CreateTeamParams {
+            name: "Operations".to_string(),
+            description: None,
+        },
+    )
+    .await
+    .unwrap();
+    let developer = users::Model::create_with_password(
+        &ctx.db,
+        &RegisterParams {
+            email: "developer@example.com".to_string(),
+            password: "correct horse battery".to_string(),
+            name: "developer".to_string(),
+            password_confirmation: "correct horse battery".to_string(),
+        },
+        &PasswordPolicy::default(),
+    )
+    .await
+    .unwrap();
+    team_memberships::Model::add_member(&ctx.db, team.id, &developer, "Developer")
+        .await
+        .unwrap();
+    let lifetimes = TokenLifetimes::default();
+
+    // Blank form values fall back to the default role and no message
+    let params = InvitationParams::new(
+        developer.id,
+        &InviteMemberParams {
+            user_name: "newcomer@example.com".to_string(),
+            role: Some(String::new()),
+            message: Some("  ".to_string()),
+        },
+    )
+    .unwrap();
+    assert_eq!(params.role, DEFAULT_INVITATION_ROLE);
+    assert_eq!(params.message, None);
+
+    // Messages are limited in length
+    assert!(
+        InvitationParams::new(
+            developer.id,
+            &InviteMemberParams {
+                user_name: "newcomer@example.com".to_string(),
+                role: None,
+                message: Some("a".repeat(INVITATION_MESSAGE_MAX_LENGTH + 1)),
+            },
+        )
+        .is_err()
+    );
+
+    // A Developer cannot invite with a role granting more than theirs
+    let owner_params = InvitationParams {
+        invited_by_id: developer.id,
+        role: "Owner".to_string(),
+        message: None,
+    };
+    assert!(
+        team_memberships::Model::create_email_invitation(
+            &ctx.db,
+            team.id,
+            "newcomer@example.com",
+            &owner_params,
+            &lifetimes,
+        )
+        .await
+        .is_err()
+    );
+
+    let developer_params = InvitationParams::new(
+        developer.id,
+        &InviteMemberParams {
+            user_name: "newcomer@example.com".to_string(),
+            role: Some("Developer".to_string()),
+            message: Some("Welcome aboard!".to_string()),
+        },
+    )
+    .unwrap();
+    let (invitation, _) = team_memberships::Model::create_email_invitation(
+        &ctx.db,
+        team.id,
+        "newcomer@example.com",
+        &developer_params,
+        &lifetimes,
+    )
+    .await
+    .unwrap();
+    assert_eq!(invitation.role, "Developer");
+    assert_eq!(
+        invitation.invitation_message.as_deref(),
+        Some("Welcome aboard!")
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn can_request_to_join_a_listed_team() {
+    let boot = boot_test::<App>().await.unwrap();
+    seed::<App>(&boot.app_context).await.unwrap();
+    let ctx = &boot.app_context;
+
+    let owner = users::Model::find_by_email(&ctx.db, "user1@example.com")
+        .await
+        .unwrap();
+    let team = teams::Model::create_team(
+        &ctx.db,
+        owner.id,
+        &CreateTeamParams {
+            name: "Open Source".to_string(),
+            description: None,
+        },
+    )
+    .await
+    .unwrap();
+    let requester = users::Model::create_with_password(
+        &ctx.db,
+        &RegisterParams {
+            email: "requester@example.com".to_string(),
+            password: "correct horse battery".to_string(),
+            name: "requester".to_string(),
+            password_confirmation: "correct horse battery".to_string(),
+        },
+        &PasswordPolicy::default(),
+    )
+    .await
+    .unwrap();
+
+    // Only teams listed in the directory accept requests
+    assert!(
+        team_memberships::Model::create_request(&ctx.db, &team, &requester)
+            .await
+            .is_err()
+    );
+    let team = team.set_listed(&ctx.db, true).await.unwrap();
+    assert!(
+        teams::Model::list_directory(&ctx.db)
+            .await
+            .unwrap()
+            .iter()
+            .any(|listed| listed.id == team.id)
+    );
+
+    let request = team_memberships::Model::create_request(&ctx.db, &team, &requester)
+        .await
+        .unwrap();
+    assert!(request.pending);
+    assert!(request.requested);
+    assert!(
+        team_memberships::Model::create_request(&ctx.db, &team, &requester)
+            .await
+            .is_err()
+    );
+
+    // A request is not an invitation the requester could accept
+    assert!(
+        team_memberships::Model::find_pending_by_pid(&ctx.db, &request.pid.to_string())
+            .await
+            .is_err()
+    );
+    assert!(
+        team_memberships::Model::get_user_invitations(&ctx.db, requester.id)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert!(
+        team_memberships::Model::get_team_invitations(&ctx.db, team.id)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    // Team administrators see the request and are notified of it
+    let requests = team_memberships::Model::get_team_requests(&ctx.db, team.id)
+        .await
+        .unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].1.id, requester.id);
+    let admins = team
+        .members_with_permission(&ctx.db, "team.invite")
+        .await
+        .unwrap();
+    assert_eq!(
+        admins.iter().map(|admin| admin.id).collect::<Vec<_>>(),
+        vec![owner.id]
+    );
+
+    // The requester cannot grant themselves a role
+    assert!(
+        request
+            .approve_request(&ctx.db, requester.id, "Developer")
+            .await
+            .is_err()
+    );
+    let request =
+        team_memberships::Model::find_request_by_pid(&ctx.db, &request.pid.to_string(), team.id)
+            .await
+            .unwrap();
+    let membership = request
+        .approve_request(&ctx.db, owner.id, "Developer")
+        .await
+        .unwrap();
+    assert!(!membership.pending);
+    assert!(!membership.requested);
+    assert_eq!(membership.role, "Developer");
+    assert!(
+        team_memberships::Model::find_by_team_and_user(&ctx.db, team.id, requester.id)
+            .await
+            .is_ok()
+    );
+
+    // Denied requests are deleted
+    membership.remove_from_team(&ctx.db).await.unwrap();
+    let request = team_memberships::Model::create_request(&ctx.db, &team, &requester)
+        .await
+        .unwrap();
+    request.deny_request(&ctx.db).await.unwrap();
+    assert!(
+        team_memberships::Model::get_team_requests(&ctx.db, team.id)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+}
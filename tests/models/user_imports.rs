@@ -0,0 +1,215 @@
+use hosting_farm::{
+    app::App,
+    models::{
+        password_policy::PasswordPolicy,
+        teams::{self, CreateTeamParams},
+        user_imports::{self, DataFormat},
+        users::{self, RegisterParams},
+    },
+};
+use loco_rs::testing::prelude::*;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use serial_test::serial;
+
+const SSH_KEY: &str =
+    "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIBi7ljUV2aDFvK7LiCqjIBYD0Gj0Rmz5SR8Lz+XJu8VV alice@laptop";
+
+#[tokio::test]
+#[serial]
+async fn can_preview_and_import_users() {
+    let boot = boot_test::<App>()
+        .await
+        .expect("Failed to boot test application");
+    seed::<App>(&boot.app_context)
+        .await
+        .expect("Failed to seed database");
+    let ctx = &boot.app_context;
+    let user1 = users::Model::find_by_email(&ctx.db, "user1@example.com")
+        .await
+        .unwrap();
+    teams::Model::create_team(
+        &ctx.db,
+        user1.id,
+        &CreateTeamParams {
+            name: "Existing".to_string(),
+            description: None,
+        },
+    )
+    .await
+    .unwrap();
+
+    let csv = format!(
+        "name,email,team,role,ssh_keys\n\
+         alice,alice@example.com,Customer,,\"{SSH_KEY}\"\n\
+         alice,alice@example.com,Existing,Observer,\n\
+         bob,bob@example.com,Customer,Administrator,\n\
+         carol,carol@example.com,,,\n"
+    );
+    let records = user_imports::parse(DataFormat::Csv, &csv).unwrap();
+    assert_eq!(records.len(), 4);
+    assert_eq!(records[0].ssh_keys, vec![SSH_KEY.to_string()]);
+
+    let plan = user_imports::plan(&ctx.db, "Administrators", records)
+        .await
+        .unwrap();
+    assert!(plan.is_valid());
+    assert_eq!(plan.user_count, 3);
+    assert_eq!(plan.team_count, 1);
+    assert!(plan.records[0].creates_team);
+    assert!(!plan.records[1].creates_user);
+
+    let created = user_imports::apply(&ctx.db, &plan).await.unwrap();
+    assert_eq!(created.len(), 3);
+    let alice = users::Model::find_by_email(&ctx.db, "alice@example.com")
+        .await
+        .unwrap();
+    assert!(alice.email_verified_at.is_some());
+    let customer = teams::teams::Entity::find()
+        .filter(teams::teams::Column::Name.eq("Customer"))
+        .one(&ctx.db)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        customer
+            .member_role(&ctx.db, alice.id)
+            .await
+            .unwrap()
+            .map(|role| role.name)
+            .as_deref(),
+        Some("Owner")
+    );
+
+    // The export can be imported again, and every user now already exists
+    let exported = user_imports::export(&ctx.db).await.unwrap();
+    assert!(exported.iter().any(|record| {
+        record.email == "alice@example.com"
+            && record.team.as_deref() == Some("Existing")
+            && record.role.as_deref() == Some("Observer")
+    }));
+    for format in [DataFormat::Csv, DataFormat::Json] {
+        let data = user_imports::serialize(format, &exported).unwrap();
+        assert_eq!(user_imports::parse(format, &data).unwrap(), exported);
+    }
+    let plan = user_imports::plan(&ctx.db, "Administrators", exported)
+        .await
+        .unwrap();
+    assert!(!plan.is_valid());
+    assert!(
+        plan.records[0]
+            .errors
+            .contains(&"Email already registered.".to_string())
+    );
+    assert!(user_imports::apply(&ctx.db, &plan).await.is_err());
+}
+
+#[tokio::test]
+#[serial]
+async fn rejects_invalid_import_records() {
+    let boot = boot_test::<App>()
+        .await
+        .expect("Failed to boot test application");
+    seed::<App>(&boot.app_context)
+        .await
+        .expect("Failed to seed database");
+    let ctx = &boot.app_context;
+
+    assert!(user_imports::parse(DataFormat::Json, "[]").is_err());
+    assert!(user_imports::parse(DataFormat::Json, "{").is_err());
+
+    let records = user_imports::parse(
+        DataFormat::Json,
+        r#"[
+            {"name": "d", "email": "not-an-email"},
+            {"name": "dave", "email": "dave@example.com", "role": "Owner"},
+            {"name": "dave", "email": "other@example.com"},
+            {"name": "erin", "email": "erin@example.com", "team": "Administrators"},
+            {"name": "frank", "email": "frank@example.com", "team": "Ops", "role": "Boss", "ssh_keys": ["not a key"]},
+            {"name": "user1", "email": "user1@example.com"}
+        ]"#,
+    )
+    .unwrap();
+    let plan = user_imports::plan(&ctx.db, "Administrators", records)
+        .await
+        .unwrap();
+    let errors = plan
+        .records
+        .iter()
+        .map(|record| record.errors.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        errors[0],
+        vec![
+            "Name must be at least 2 characters long.",
+            "Invalid email format."
+        ]
+    );
+    assert_eq!(errors[1], vec!["A role needs a team."]);
+    assert_eq!(
+        errors[2],
+        vec!["This name is used by dave@example.com in a previous record."]
+    );
+    assert_eq!(
+        errors[3],
+        vec!["Members of the administrators team cannot be imported."]
+    );
+    assert_eq!(
+        errors[4],
+        vec!["Invalid role Boss.", "SSH key 1 is not a valid public key."]
+    );
+    assert_eq!(
+        errors[5],
+        vec!["Email already registered.", "Username already taken."]
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn imports_nothing_when_a_record_fails() {
+    let boot = boot_test::<App>()
+        .await
+        .expect("Failed to boot test application");
+    seed::<App>(&boot.app_context)
+        .await
+        .expect("Failed to seed database");
+    let ctx = &boot.app_context;
+
+    let csv = "name,email,team,role,ssh_keys\n\
+               grace,grace@example.com,Research,,\n\
+               heidi,heidi@example.com,Research,Developer,\n";
+    let records = user_imports::parse(DataFormat::Csv, csv).unwrap();
+    let plan = user_imports::plan(&ctx.db, "Administrators", records)
+        .await
+        .unwrap();
+    assert!(plan.is_valid());
+
+    // The second account is registered after the preview, so its record
+    // fails once the first one was imported
+    users::Model::create_with_password(
+        &ctx.db,
+        &RegisterParams {
+            email: "heidi@example.com".to_string(),
+            password: "correct horse battery".to_string(),
+            name: "heidi".to_string(),
+            password_confirmation: "correct horse battery".to_string(),
+        },
+        &PasswordPolicy::default(),
+    )
+    .await
+    .unwrap();
+    assert!(user_imports::apply(&ctx.db, &plan).await.is_err());
+
+    assert!(
+        users::Model::find_by_email(&ctx.db, "grace@example.com")
+            .await
+            .is_err()
+    );
+    assert!(
+        teams::teams::Entity::find()
+            .filter(teams::teams::Column::Name.eq("Research"))
+            .one(&ctx.db)
+            .await
+            .unwrap()
+            .is_none()
+    );
+}
@@ -1,12 +1,21 @@
 use chrono::{Duration, offset::Local};
 use hosting_farm::{
     app::App,
-    models::users::{self, Model, RegisterParams},
+    models::{
+        password_policy::PasswordPolicy,
+        recovery_codes, sessions,
+        team_memberships::{self, DEFAULT_INVITATION_ROLE, InvitationParams},
+        teams::{self, CreateTeamParams},
+        tokens::{self, TokenLifetimes},
+        users::{self, Model, RegisterParams},
+        webauthn_challenges,
+    },
 };
 use insta::assert_debug_snapshot;
-use loco_rs::testing::prelude::*;
+use loco_rs::{prelude::Authenticable, testing::prelude::*};
 use sea_orm::{ActiveModelTrait, ActiveValue, IntoActiveModel};
 use serial_test::serial;
+use std::collections::HashMap;
 
 macro_rules! configure_insta {
     ($($expr:expr),*) => {
@@ -48,12 +57,14 @@ async fn can_create_with_password() {
 
     let params = RegisterParams {
         email: "test@framework.com".to_string(),
-        password: "1234".to_string(),
+        password: "12341234".to_string(),
         name: "framework".to_string(),
-        password_confirmation: "1234".to_string(),
+        password_confirmation: "12341234".to_string(),
     };
 
-    let res = Model::create_with_password(&boot.app_context.db, &params).await;
+    let res =
+        Model::create_with_password(&boot.app_context.db, &params, &PasswordPolicy::default())
+            .await;
 
     insta::with_settings!({
         filters => cleanup_user_model()
@@ -77,10 +88,11 @@ async fn handle_create_with_password_with_duplicate() {
         &boot.app_context.db,
         &RegisterParams {
             email: "user1@example.com".to_string(),
-            password: "1234".to_string(),
+            password: "12341234".to_string(),
             name: "framework".to_string(),
-            password_confirmation: "1234".to_string(),
+            password_confirmation: "12341234".to_string(),
         },
+        &PasswordPolicy::default(),
     )
     .await;
 
@@ -155,7 +167,7 @@ async fn can_verification_token() {
 
     let result = user
         .into_active_model()
-        .generate_email_verification_token(&boot.app_context.db)
+        .generate_email_verification_token(&boot.app_context.db, &TokenLifetimes::default())
         .await;
 
     assert!(
@@ -163,8 +175,8 @@ async fn can_verification_token() {
         "Failed to generate email verification token"
     );
 
-    let result = result
-        .unwrap()
+    let (user_with_token, _) = result.unwrap();
+    let result = user_with_token
         .into_active_model()
         .set_email_verification_sent(&boot.app_context.db)
         .await;
@@ -207,8 +219,8 @@ async fn can_set_forgot_password_sent() {
     );
     assert!(user.reset_token.is_none(), "Expected no reset token");
 
-    let user_with_token = user
-        .initiate_password_reset(&boot.app_context.db)
+    let (user_with_token, _) = user
+        .initiate_password_reset(&boot.app_context.db, &TokenLifetimes::default())
         .await
         .expect("Failed to initiate password reset");
 
@@ -233,6 +245,50 @@ async fn can_set_forgot_password_sent() {
     );
 }
 
+#[tokio::test]
+#[serial]
+async fn can_expire_hashed_reset_token() {
+    configure_insta!();
+
+    let boot = boot_test::<App>()
+        .await
+        .expect("Failed to boot test application");
+    seed::<App>(&boot.app_context)
+        .await
+        .expect("Failed to seed database");
+
+    let user = Model::find_by_pid(&boot.app_context.db, "11111111-1111-1111-1111-111111111111")
+        .await
+        .expect("Failed to find user by PID");
+
+    let (user_with_token, reset_token) = user
+        .initiate_password_reset(&boot.app_context.db, &TokenLifetimes::default())
+        .await
+        .expect("Failed to initiate password reset");
+    assert_ne!(
+        user_with_token.reset_token.as_deref(),
+        Some(reset_token.as_str()),
+        "The reset token must not be stored in clear"
+    );
+    assert!(
+        Model::find_by_reset_token(&boot.app_context.db, &reset_token)
+            .await
+            .is_ok(),
+        "A fresh reset token should be accepted"
+    );
+
+    let mut user = user_with_token.into_active_model();
+    user.reset_expires_at = ActiveValue::Set(Some((Local::now() - Duration::minutes(1)).into()));
+    user.update(&boot.app_context.db)
+        .await
+        .expect("Failed to expire the reset token");
+
+    let err = Model::find_by_reset_token(&boot.app_context.db, &reset_token)
+        .await
+        .expect_err("An expired reset token should be rejected");
+    assert!(tokens::is_expired(&err), "Unexpected error: {err:?}");
+}
+
 #[tokio::test]
 #[serial]
 async fn can_verified() {
@@ -295,7 +351,11 @@ async fn can_reset_password() {
     let result = user
         .clone()
         .into_active_model()
-        .reset_password(&boot.app_context.db, "new-password")
+        .reset_password(
+            &boot.app_context.db,
+            "new-password",
+            &PasswordPolicy::default(),
+        )
         .await;
 
     assert!(result.is_ok(), "Failed to reset password");
@@ -310,6 +370,69 @@ async fn can_reset_password() {
     );
 }
 
+#[tokio::test]
+#[serial]
+async fn rejects_passwords_violating_policy() {
+    let boot = boot_test::<App>()
+        .await
+        .expect("Failed to boot test application");
+    seed::<App>(&boot.app_context)
+        .await
+        .expect("Failed to seed database");
+
+    // SHA-1 of "Breached-Horse-42-Battery", in the Have I Been Pwned format
+    let breached_file = std::env::temp_dir().join("hosting-farm-breached-passwords.txt");
+    std::fs::write(
+        &breached_file,
+        "0000000000000000000000000000000000000000:3\n\
+         F02BCB99C5EC9EDEE229704B6ED0E2049812C7DF:1\n",
+    )
+    .expect("Failed to write breached password list");
+    let policy = PasswordPolicy {
+        min_length: 12,
+        require_digit: true,
+        min_strength: 3,
+        breached_hashes_file: Some(breached_file.to_string_lossy().to_string()),
+        ..PasswordPolicy::default()
+    };
+
+    let user = Model::find_by_pid(&boot.app_context.db, "11111111-1111-1111-1111-111111111111")
+        .await
+        .expect("Failed to find user by PID");
+
+    for (password, reason) in [
+        ("short1", "at least 12 characters"),
+        ("no-digits-in-here", "must contain a digit"),
+        ("password12345678", "too easy to guess"),
+        ("Breached-Horse-42-Battery", "data breach"),
+    ] {
+        let result = user
+            .clone()
+            .into_active_model()
+            .set_password(&boot.app_context.db, password, &policy)
+            .await;
+        match result {
+            Err(err) => assert!(
+                err.to_string().contains(reason),
+                "Unexpected reason for {password}: {err}"
+            ),
+            Ok(_) => panic!("Password {password} should have been rejected"),
+        }
+    }
+
+    user.clone()
+        .into_active_model()
+        .set_password(&boot.app_context.db, "Quiet-Lantern-73-Orbit", &policy)
+        .await
+        .expect("Failed to set a password complying with the policy");
+    let user = Model::find_by_pid(&boot.app_context.db, "11111111-1111-1111-1111-111111111111")
+        .await
+        .expect("Failed to find user by PID after password change");
+    assert!(user.verify_password("Quiet-Lantern-73-Orbit"));
+
+    std::fs::remove_file(breached_file).ok();
+}
+
 #[tokio::test]
 #[serial]
 async fn magic_link() {
@@ -376,3 +499,341 @@ async fn magic_link() {
         "Magic link expiration exceeds expected maximum expiration time"
     );
 }
+
+#[tokio::test]
+#[serial]
+async fn can_enable_totp_and_use_recovery_code() {
+    let boot = boot_test::<App>().await.unwrap();
+    seed::<App>(&boot.app_context).await.unwrap();
+    let db = &boot.app_context.db;
+
+    let user = Model::find_by_pid(db, "11111111-1111-1111-1111-111111111111")
+        .await
+        .unwrap();
+    assert!(!user.totp_enabled(), "TOTP should be initially disabled");
+
+    let user = user
+        .into_active_model()
+        .begin_totp_enrolment(db)
+        .await
+        .expect("Failed to start TOTP enrolment");
+    assert!(
+        !user.totp_enabled(),
+        "TOTP should not be enforced before the enrolment is confirmed"
+    );
+
+    let uri = user.totp_provisioning_uri().unwrap();
+    assert!(uri.starts_with("otpauth://totp/"));
+    let code = totp_rs::TOTP::from_url(&uri)
+        .unwrap()
+        .generate_current()
+        .unwrap();
+    assert!(
+        user.verify_totp(db, &code).await.unwrap(),
+        "Current TOTP code should be valid"
+    );
+    assert!(
+        !user.verify_totp(db, &code).await.unwrap(),
+        "A TOTP code should only be accepted once"
+    );
+    assert!(!user.verify_totp(db, "000000").await.unwrap());
+
+    let (user, codes) = user
+        .into_active_model()
+        .enable_totp(db)
+        .await
+        .expect("Failed to enable TOTP");
+    assert!(user.totp_enabled());
+    assert_eq!(codes.len(), recovery_codes::RECOVERY_CODE_COUNT);
+
+    assert!(
+        user.verify_second_factor(db, &codes[0].to_uppercase())
+            .await
+            .unwrap(),
+        "Recovery code should be accepted"
+    );
+    assert!(
+        !user.verify_second_factor(db, &codes[0]).await.unwrap(),
+        "Recovery code should only be accepted once"
+    );
+    assert_eq!(
+        recovery_codes::Model::count_unused(db, user.id)
+            .await
+            .unwrap(),
+        (recovery_codes::RECOVERY_CODE_COUNT - 1) as u64
+    );
+
+    let user = user.into_active_model().disable_totp(db).await.unwrap();
+    assert!(!user.totp_enabled());
+    assert_eq!(
+        recovery_codes::Model::count_unused(db, user.id)
+            .await
+            .unwrap(),
+        0
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn can_only_take_webauthn_challenge_once() {
+    let boot = boot_test::<App>().await.unwrap();
+    seed::<App>(&boot.app_context).await.unwrap();
+    let db = &boot.app_context.db;
+
+    let user = Model::find_by_pid(db, "11111111-1111-1111-1111-111111111111")
+        .await
+        .unwrap();
+    let challenge = webauthn_challenges::Model::create_for_user(
+        db,
+        user.id,
+        webauthn_challenges::KIND_AUTHENTICATION,
+        &"state",
+    )
+    .await
+    .unwrap();
+
+    let taken = webauthn_challenges::Model::take::<String>(
+        db,
+        &challenge.token,
+        webauthn_challenges::KIND_REGISTRATION,
+    )
+    .await;
+    assert!(taken.is_err(), "Challenge kind must match");
+
+    let (user_id, state) = webauthn_challenges::Model::take::<String>(
+        db,
+        &challenge.token,
+        webauthn_challenges::KIND_AUTHENTICATION,
+    )
+    .await
+    .unwrap();
+    assert_eq!(user_id, user.id);
+    assert_eq!(state, "state");
+
+    let taken = webauthn_challenges::Model::take::<String>(
+        db,
+        &challenge.token,
+        webauthn_challenges::KIND_AUTHENTICATION,
+    )
+    .await;
+    assert!(taken.is_err(), "Challenge must be single use");
+
+    assert!(user.second_factor_methods(db).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+#[serial]
+async fn can_approve_and_reject_pending_accounts() {
+    let boot = boot_test::<App>()
+        .await
+        .expect("Failed to boot test application");
+    seed::<App>(&boot.app_context)
+        .await
+        .expect("Failed to seed database");
+    let db = &boot.app_context.db;
+
+    let mut pending = Vec::new();
+    for name in ["approved", "rejected"] {
+        let user = Model::create_with_password(
+            db,
+            &RegisterParams {
+                email: format!("{name}@example.com"),
+                password: "correct horse battery".to_string(),
+                name: name.to_string(),
+                password_confirmation: "correct horse battery".to_string(),
+            },
+            &PasswordPolicy::default(),
+        )
+        .await
+        .unwrap();
+        let mut user = user.into_active_model();
+        user.status = ActiveValue::Set(users::STATUS_PENDING_APPROVAL.to_string());
+        pending.push(user.update(db).await.unwrap());
+    }
+    assert_eq!(Model::count_pending_approval(db).await.unwrap(), 2);
+    let rejected = pending.pop().unwrap();
+    let approved = pending.pop().unwrap();
+
+    let approved = approved.approve(db).await.unwrap();
+    assert!(approved.is_active());
+    assert!(
+        approved.clone().approve(db).await.is_err(),
+        "Only pending accounts can be approved"
+    );
+    assert!(
+        approved.reject(db).await.is_err(),
+        "Only pending accounts can be rejected"
+    );
+
+    rejected.reject(db).await.unwrap();
+    assert!(
+        Model::find_by_email(db, "rejected@example.com")
+            .await
+            .is_err(),
+        "Rejected account should be deleted"
+    );
+    assert_eq!(Model::count_pending_approval(db).await.unwrap(), 0);
+}
+
+#[tokio::test]
+#[serial]
+async fn can_suspend_reactivate_and_delete_accounts() {
+    let boot = boot_test::<App>()
+        .await
+        .expect("Failed to boot test application");
+    seed::<App>(&boot.app_context)
+        .await
+        .expect("Failed to seed database");
+    let db = &boot.app_context.db;
+
+    let owner = Model::find_by_email(db, "user1@example.com").await.unwrap();
+    let member = Model::create_with_password(
+        db,
+        &RegisterParams {
+            email: "member@example.com".to_string(),
+            password: "correct horse battery".to_string(),
+            name: "member".to_string(),
+            password_confirmation: "correct horse battery".to_string(),
+        },
+        &PasswordPolicy::default(),
+    )
+    .await
+    .unwrap();
+
+    let suspended = owner.clone().suspend(db).await.unwrap();
+    assert_eq!(suspended.status, users::STATUS_SUSPENDED);
+    assert!(
+        Model::find_by_claims_key(db, &owner.pid.to_string())
+            .await
+            .is_err(),
+        "A suspended account cannot use its sessions"
+    );
+    assert!(suspended.clone().suspend(db).await.is_err());
+    let owner = suspended.reactivate(db).await.unwrap();
+    assert!(owner.is_active());
+    assert!(owner.clone().reactivate(db).await.is_err());
+
+    let team = teams::Model::create_team(
+        db,
+        owner.id,
+        &CreateTeamParams {
+            name: "Sole Owner Team".to_string(),
+            description: None,
+        },
+    )
+    .await
+    .unwrap();
+    let (invitation, _) = team_memberships::Model::create_invitation(
+        db,
+        team.id,
+        &member.name,
+        &InvitationParams {
+            invited_by_id: owner.id,
+            role: DEFAULT_INVITATION_ROLE.to_string(),
+            message: None,
+        },
+        &TokenLifetimes::default(),
+    )
+    .await
+    .unwrap();
+    invitation.accept_invitation(db).await.unwrap();
+
+    let sole_owned = owner.find_sole_owned_teams(db).await.unwrap();
+    assert_eq!(sole_owned.len(), 1);
+    assert!(
+        owner
+            .clone()
+            .delete_account(db, &HashMap::new())
+            .await
+            .is_err(),
+        "Deleting the only Owner of a team requires a new Owner"
+    );
+    assert!(
+        owner
+            .clone()
+            .delete_account(db, &HashMap::from([(team.id, owner.id)]))
+            .await
+            .is_err(),
+        "The deleted user cannot be the new Owner"
+    );
+
+    owner
+        .clone()
+        .delete_account(db, &HashMap::from([(team.id, member.id)]))
+        .await
+        .unwrap();
+    assert!(Model::find_by_email(db, "user1@example.com").await.is_err());
+    assert_eq!(
+        team.member_role(db, member.id)
+            .await
+            .unwrap()
+            .map(|role| role.name)
+            .as_deref(),
+        Some("Owner")
+    );
+    assert_eq!(team.get_members(db).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+#[serial]
+async fn can_expire_and_purge_sessions() {
+    let boot = boot_test::<App>()
+        .await
+        .expect("Failed to boot test application");
+    seed::<App>(&boot.app_context).await.unwrap();
+    let db = &boot.app_context.db;
+
+    let user = Model::find_by_email(db, "user1@example.com").await.unwrap();
+    let device = sessions::SessionDevice::default();
+    let current = sessions::Model::create_for_user(db, user.id, 3600, &device)
+        .await
+        .unwrap();
+    let expired = sessions::Model::create_for_user(db, user.id, 3600, &device)
+        .await
+        .unwrap();
+    let mut expired = expired.into_active_model();
+    expired.expires_at = ActiveValue::set(Some((Local::now() - Duration::minutes(1)).into()));
+    let expired = expired.update(db).await.unwrap();
+
+    let active = sessions::Model::find_active_for_user(db, user.id)
+        .await
+        .unwrap();
+    assert_eq!(
+        active.iter().map(|session| session.id).collect::<Vec<_>>(),
+        vec![current.id],
+        "An expired session is no longer signed in"
+    );
+
+    assert_eq!(sessions::Model::purge_expired(db).await.unwrap(), 1);
+    assert!(
+        sessions::Model::find_by_jti(db, &expired.jti)
+            .await
+            .is_err()
+    );
+    assert!(sessions::Model::find_by_jti(db, &current.jti).await.is_ok());
+}
+
+#[test]
+fn can_read_client_ip_behind_trusted_proxies() {
+    let mut headers = axum::http::HeaderMap::new();
+    headers.insert(
+        "x-forwarded-for",
+        "198.51.100.1, 203.0.113.7, 10.0.0.2".parse().unwrap(),
+    );
+    let proxy: std::net::IpAddr = "10.0.0.1".parse().unwrap();
+    let trusted: Vec<ipnet::IpNet> = vec!["10.0.0.0/8".parse().unwrap()];
+
+    let device = sessions::SessionDevice::from_request(&headers, Some(proxy), &[]);
+    assert_eq!(
+        device.ip.as_deref(),
+        Some("10.0.0.1"),
+        "Forwarded headers from an untrusted peer are ignored"
+    );
+
+    let device = sessions::SessionDevice::from_request(&headers, Some(proxy), &trusted);
+    assert_eq!(
+        device.ip.as_deref(),
+        Some("203.0.113.7"),
+        "The client is the last hop that is not a trusted proxy"
+    );
+}
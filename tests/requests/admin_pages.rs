@@ -0,0 +1,440 @@
+use hosting_farm::{
+    app::App,
+    models::{
+        email_failures, impersonation_events,
+        password_policy::PasswordPolicy,
+        roles, sessions, team_memberships,
+        teams::{self, CreateTeamParams},
+        users::{self, RegisterParams},
+    },
+};
+use loco_rs::testing::prelude::*;
+use sea_orm::{ColumnTrait, EntityTrait, PaginatorTrait, QueryFilter};
+use serial_test::serial;
+
+use super::prepare_data;
+
+/// Logs a user in and makes them an application administrator
+async fn init_admin_login(
+    request: &loco_rs::TestServer,
+    ctx: &loco_rs::app::AppContext,
+) -> prepare_data::LoggedInUser {
+    let login_data = prepare_data::init_user_login(request, ctx).await;
+    teams::Model::create_team(
+        &ctx.db,
+        login_data.user.id,
+        &CreateTeamParams {
+            name: users::Model::get_admin_team_name(ctx),
+            description: None,
+        },
+    )
+    .await
+    .unwrap();
+    login_data
+}
+
+#[tokio::test]
+#[serial]
+async fn can_impersonate_a_user_and_stop() {
+    request::<App, _, _>(|request, ctx| async move {
+        let login_data = init_admin_login(&request, &ctx).await;
+        let target = users::Model::create_with_password(
+            &ctx.db,
+            &RegisterParams {
+                email: "target@example.com".to_string(),
+                password: "correct horse battery".to_string(),
+                name: "target".to_string(),
+                password_confirmation: "correct horse battery".to_string(),
+            },
+            &PasswordPolicy::default(),
+        )
+        .await
+        .unwrap();
+
+        let (auth_key, auth_value) = prepare_data::auth_header(&login_data.token);
+        let response = request
+            .post(&format!("/admin/users/{}/impersonate", target.pid))
+            .add_header(auth_key, auth_value)
+            .await;
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(response.header("HX-Redirect"), "/home");
+        let cookie = response.header("Set-Cookie");
+        let token = cookie
+            .to_str()
+            .unwrap()
+            .strip_prefix("auth_token=")
+            .and_then(|value| value.split(';').next())
+            .unwrap()
+            .to_string();
+
+        let (auth_key, auth_value) = prepare_data::auth_header(&token);
+        let response = request
+            .get("/admin/impersonation/banner")
+            .add_header(auth_key.clone(), auth_value.clone())
+            .await;
+        assert!(response.text().contains("target@example.com"));
+        assert!(response.text().contains("Stop impersonating"));
+
+        let response = request
+            .post("/users/profile/password")
+            .add_header(auth_key.clone(), auth_value.clone())
+            .form(&serde_json::json!({
+                "current_password": "correct horse battery",
+                "password": "another horse battery",
+                "password_confirmation": "another horse battery",
+            }))
+            .await;
+        assert!(
+            response
+                .text()
+                .contains("cannot be changed while impersonating")
+        );
+
+        let response = request
+            .post("/admin/impersonation/stop")
+            .add_header(auth_key, auth_value)
+            .await;
+        assert_eq!(response.header("HX-Redirect"), "/admin/users");
+
+        let events = impersonation_events::Entity::find()
+            .count(&ctx.db)
+            .await
+            .unwrap();
+        assert_eq!(events, 2, "Start and stop should be recorded");
+        assert!(
+            sessions::Model::find_active_for_user(&ctx.db, target.id)
+                .await
+                .unwrap()
+                .is_empty(),
+            "The impersonation session should be revoked"
+        );
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial]
+async fn can_see_dashboard_panels() {
+    request::<App, _, _>(|request, ctx| async move {
+        let login_data = init_admin_login(&request, &ctx).await;
+        email_failures::Model::record(&ctx.db, "lost@example.com", "Welcome", "connection refused")
+            .await
+            .unwrap();
+
+        let (auth_key, auth_value) = prepare_data::auth_header(&login_data.token);
+        let response = request
+            .get("/admin")
+            .add_header(auth_key.clone(), auth_value.clone())
+            .await;
+        assert_eq!(response.status_code(), 200);
+        assert!(response.text().contains("/admin/dashboard/users"));
+
+        for (panel, expected) in [
+            ("users", "Verified PGP key"),
+            ("teams", "Largest teams"),
+            ("ssh_keys", "No SSH keys yet."),
+            ("registrations", "test@loco.com"),
+            ("email_failures", "lost@example.com"),
+        ] {
+            let response = request
+                .get(&format!("/admin/dashboard/{panel}"))
+                .add_header(auth_key.clone(), auth_value.clone())
+                .await;
+            assert_eq!(response.status_code(), 200, "Panel {panel} should load");
+            assert!(
+                response.text().contains(expected),
+                "Panel {panel} should contain {expected}"
+            );
+        }
+
+        let response = request
+            .get("/admin/dashboard/unknown")
+            .add_header(auth_key, auth_value)
+            .await;
+        assert_eq!(response.status_code(), 404);
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial]
+async fn can_recover_and_manage_teams() {
+    request::<App, _, _>(|request, ctx| async move {
+        let login_data = init_admin_login(&request, &ctx).await;
+        let member = users::Model::create_with_password(
+            &ctx.db,
+            &RegisterParams {
+                email: "member@example.com".to_string(),
+                password: "correct horse battery".to_string(),
+                name: "member".to_string(),
+                password_confirmation: "correct horse battery".to_string(),
+            },
+            &PasswordPolicy::default(),
+        )
+        .await
+        .unwrap();
+        // A team whose only owner left, leaving it without any member
+        let team = teams::Model::create_team(
+            &ctx.db,
+            member.id,
+            &CreateTeamParams {
+                name: "Orphans".to_string(),
+                description: None,
+            },
+        )
+        .await
+        .unwrap();
+        team_memberships::Entity::delete_many()
+            .filter(team_memberships::team_memberships::Column::TeamId.eq(team.id))
+            .exec(&ctx.db)
+            .await
+            .unwrap();
+
+        let (auth_key, auth_value) = prepare_data::auth_header(&login_data.token);
+        let response = request
+            .get("/admin/teams")
+            .add_header(auth_key.clone(), auth_value.clone())
+            .await;
+        assert_eq!(response.status_code(), 200);
+        let response = request
+            .get("/admin/teams/fragment")
+            .add_header(auth_key.clone(), auth_value.clone())
+            .await;
+        assert!(response.text().contains("Orphans"));
+        assert!(response.text().contains("Orphaned"));
+
+        let response = request
+            .post(&format!("/admin/teams/{}/members", team.pid))
+            .add_header(auth_key.clone(), auth_value.clone())
+            .form(&serde_json::json!({ "user_name": "member", "role": "Developer" }))
+            .await;
+        assert_eq!(response.status_code(), 200);
+        assert!(response.text().contains("member added as Developer."));
+
+        let response = request
+            .post(&format!(
+                "/admin/teams/{}/members/{}/role",
+                team.pid, member.pid
+            ))
+            .add_header(auth_key.clone(), auth_value.clone())
+            .form(&serde_json::json!({ "role": "Owner" }))
+            .await;
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(
+            team.member_role(&ctx.db, member.id)
+                .await
+                .unwrap()
+                .map(|role| role.name)
+                .as_deref(),
+            Some("Owner")
+        );
+        let response = request
+            .get("/admin/teams/fragment")
+            .add_header(auth_key.clone(), auth_value.clone())
+            .await;
+        assert!(!response.text().contains("Orphaned"));
+
+        let response = request
+            .post(&format!("/admin/teams/{}", team.pid))
+            .add_header(auth_key.clone(), auth_value.clone())
+            .form(&serde_json::json!({ "name": "Recovered", "description": "" }))
+            .await;
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(
+            teams::Model::find_by_pid(&ctx.db, &team.pid.to_string())
+                .await
+                .unwrap()
+                .name,
+            "Recovered"
+        );
+
+        let response = request
+            .delete(&format!("/admin/teams/{}/members/{}", team.pid, member.pid))
+            .add_header(auth_key.clone(), auth_value.clone())
+            .await;
+        assert_eq!(response.status_code(), 200);
+        assert!(
+            response
+                .text()
+                .contains("The last owner cannot leave the team")
+        );
+        assert_eq!(
+            team.member_role(&ctx.db, member.id)
+                .await
+                .unwrap()
+                .map(|role| role.name)
+                .as_deref(),
+            Some("Owner")
+        );
+
+        let response = request
+            .delete(&format!("/admin/teams/{}", team.pid))
+            .add_header(auth_key.clone(), auth_value.clone())
+            .await;
+        assert_eq!(response.status_code(), 200);
+        assert!(
+            teams::Model::find_by_pid(&ctx.db, &team.pid.to_string())
+                .await
+                .is_err()
+        );
+
+        // The administrators team cannot be deleted
+        let admin_team = teams::teams::Entity::find()
+            .filter(teams::teams::Column::Name.eq(users::Model::get_admin_team_name(&ctx)))
+            .one(&ctx.db)
+            .await
+            .unwrap()
+            .unwrap();
+        let response = request
+            .delete(&format!("/admin/teams/{}", admin_team.pid))
+            .add_header(auth_key, auth_value)
+            .await;
+        assert!(response.text().contains("cannot be deleted"));
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial]
+async fn can_import_and_export_users() {
+    request::<App, _, _>(|request, ctx| async move {
+        let login_data = init_admin_login(&request, &ctx).await;
+        let (auth_key, auth_value) = prepare_data::auth_header(&login_data.token);
+        let data = "name,email,team,role\nnewcomer,newcomer@example.com,Customer,Owner\n";
+
+        let response = request
+            .post("/admin/users/import")
+            .add_header(auth_key.clone(), auth_value.clone())
+            .form(&serde_json::json!({ "format": "csv", "data": data, "dry_run": "true" }))
+            .await;
+        assert_eq!(response.status_code(), 200);
+        assert!(
+            response
+                .text()
+                .contains("Creates the user, creates the team")
+        );
+        assert!(
+            users::Model::find_by_email(&ctx.db, "newcomer@example.com")
+                .await
+                .is_err()
+        );
+
+        let response = request
+            .post("/admin/users/import")
+            .add_header(auth_key.clone(), auth_value.clone())
+            .form(&serde_json::json!({ "format": "csv", "data": data, "dry_run": "false" }))
+            .await;
+        assert_eq!(response.status_code(), 200);
+        assert!(
+            response
+                .text()
+                .contains("Imported 1 users and created 1 teams.")
+        );
+        let newcomer = users::Model::find_by_email(&ctx.db, "newcomer@example.com")
+            .await
+            .unwrap();
+        assert!(newcomer.reset_token.is_some());
+
+        // A second import of the same user is refused
+        let response = request
+            .post("/admin/users/import")
+            .add_header(auth_key.clone(), auth_value.clone())
+            .form(&serde_json::json!({ "format": "csv", "data": data, "dry_run": "false" }))
+            .await;
+        assert!(response.text().contains("Email already registered."));
+
+        let response = request
+            .get("/admin/users/export?format=json")
+            .add_header(auth_key, auth_value)
+            .await;
+        assert_eq!(response.status_code(), 200);
+        assert!(
+            response
+                .header("Content-Disposition")
+                .to_str()
+                .unwrap()
+                .ends_with(".json\"")
+        );
+        let records: Vec<serde_json::Value> = response.json();
+        assert!(records.iter().any(|record| {
+            record["email"] == "newcomer@example.com" && record["team"] == "Customer"
+        }));
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial]
+async fn can_manage_roles() {
+    request::<App, _, _>(|request, ctx| async move {
+        let login_data = init_admin_login(&request, &ctx).await;
+        let (auth_key, auth_value) = prepare_data::auth_header(&login_data.token);
+
+        let response = request
+            .get("/admin/roles")
+            .add_header(auth_key.clone(), auth_value.clone())
+            .await;
+        assert_eq!(response.status_code(), 200);
+        assert!(response.text().contains("Administrator"));
+        assert!(response.text().contains("service_accounts.manage"));
+
+        let response = request
+            .post("/admin/roles")
+            .add_header(auth_key.clone(), auth_value.clone())
+            .form(&[
+                ("name", "Recruiter"),
+                ("description", "Invites people"),
+                ("permissions", "team.view"),
+                ("permissions", "team.invite"),
+            ])
+            .await;
+        assert_eq!(response.status_code(), 200);
+        assert!(response.text().contains("Role Recruiter created."));
+        let role = roles::Model::find_by_name(&ctx.db, "Recruiter")
+            .await
+            .unwrap();
+        assert_eq!(role.permissions(&ctx.db).await.unwrap().len(), 2);
+
+        let response = request
+            .post("/admin/roles")
+            .add_header(auth_key.clone(), auth_value.clone())
+            .form(&[("name", "Recruiter")])
+            .await;
+        assert!(
+            response
+                .text()
+                .contains("A role with this name already exists")
+        );
+
+        let response = request
+            .post(&format!("/admin/roles/{}", role.pid))
+            .add_header(auth_key.clone(), auth_value.clone())
+            .form(&[("permissions", "team.view")])
+            .await;
+        assert_eq!(response.status_code(), 200);
+        assert!(response.text().contains("Role Recruiter updated."));
+        assert_eq!(role.permissions(&ctx.db).await.unwrap().len(), 1);
+
+        let owner = roles::Model::find_by_name(&ctx.db, roles::OWNER_ROLE)
+            .await
+            .unwrap();
+        let response = request
+            .delete(&format!("/admin/roles/{}", owner.pid))
+            .add_header(auth_key.clone(), auth_value.clone())
+            .await;
+        assert!(response.text().contains("Built-in roles cannot be deleted"));
+
+        let response = request
+            .delete(&format!("/admin/roles/{}", role.pid))
+            .add_header(auth_key, auth_value)
+            .await;
+        assert_eq!(response.status_code(), 200);
+        assert!(response.text().contains("Role Recruiter deleted."));
+        assert!(
+            roles::Model::find_by_name(&ctx.db, "Recruiter")
+                .await
+                .is_err()
+        );
+    })
+    .await;
+}
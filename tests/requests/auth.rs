@@ -1,7 +1,12 @@
-use hosting_farm::{app::App, models::users};
+use hosting_farm::{
+    app::App,
+    models::{login_attempts, sessions, tokens::TokenLifetimes, users, webauthn_credentials},
+    views::auth::{LoginResponse, MfaChallengeResponse},
+};
 use insta::{assert_debug_snapshot, with_settings};
 use loco_rs::testing::prelude::*;
 use rstest::rstest;
+use sea_orm::IntoActiveModel;
 use serial_test::serial;
 
 use super::prepare_data;
@@ -39,8 +44,11 @@ async fn can_register() {
         );
         let saved_user = users::Model::find_by_email(&ctx.db, email).await;
 
+        // Tokens are stored as random SHA-256 hashes
+        let mut filters = cleanup_user_model();
+        filters.push((r"[0-9a-f]{64}", "TOKEN_HASH"));
         with_settings!({
-            filters => cleanup_user_model()
+            filters => filters
         }, {
             assert_debug_snapshot!(saved_user);
         });
@@ -87,9 +95,15 @@ async fn can_login_with_verify(#[case] test_name: &str, #[case] password: &str)
         );
 
         let user = users::Model::find_by_email(&ctx.db, email).await.unwrap();
-        let email_verification_token = user
-            .email_verification_token
-            .expect("Email verification token should be generated");
+        assert!(
+            user.email_verification_token.is_some(),
+            "Email verification token should be generated"
+        );
+        let (_, email_verification_token) = user
+            .into_active_model()
+            .generate_email_verification_token(&ctx.db, &TokenLifetimes::default())
+            .await
+            .unwrap();
         request
             .get(&format!("/api/auth/verify/{email_verification_token}"))
             .await;
@@ -205,9 +219,14 @@ async fn can_reset_password() {
             "Expected reset_sent_at to be set, but it was None. User: {user:?}"
         );
 
+        // Only a hash of the emailed token is stored, issue a new one to reset the password
+        let (user, reset_token) = user
+            .initiate_password_reset(&ctx.db, &TokenLifetimes::default())
+            .await
+            .unwrap();
         let new_password = "new-password";
         let reset_payload = serde_json::json!({
-            "token": user.reset_token,
+            "token": reset_token,
             "password": new_password,
         });
 
@@ -372,3 +391,375 @@ async fn can_reject_invalid_magic_link_token() {
     })
     .await;
 }
+
+#[tokio::test]
+#[serial]
+async fn can_logout_and_revoke_token() {
+    configure_insta!();
+
+    request::<App, _, _>(|request, ctx| async move {
+        let user = prepare_data::init_user_login(&request, &ctx).await;
+
+        let (auth_key, auth_value) = prepare_data::auth_header(&user.token);
+        let logout_response = request
+            .post("/api/auth/logout")
+            .add_header(auth_key.clone(), auth_value.clone())
+            .await;
+        assert_eq!(
+            logout_response.status_code(),
+            200,
+            "Logout request should succeed"
+        );
+
+        let response = request
+            .get("/api/auth/current")
+            .add_header(auth_key, auth_value)
+            .await;
+        assert_eq!(
+            response.status_code(),
+            401,
+            "Token should be rejected after logout"
+        );
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial]
+async fn can_revoke_tokens_on_password_reset() {
+    configure_insta!();
+
+    request::<App, _, _>(|request, ctx| async move {
+        let login_data = prepare_data::init_user_login(&request, &ctx).await;
+
+        let (_, reset_token) = login_data
+            .user
+            .initiate_password_reset(&ctx.db, &TokenLifetimes::default())
+            .await
+            .unwrap();
+        let reset_response = request
+            .post("/api/auth/reset")
+            .json(&serde_json::json!({
+                "token": reset_token,
+                "password": "new-password",
+            }))
+            .await;
+        assert_eq!(
+            reset_response.status_code(),
+            200,
+            "Reset password request should succeed"
+        );
+
+        let (auth_key, auth_value) = prepare_data::auth_header(&login_data.token);
+        let response = request
+            .get("/api/auth/current")
+            .add_header(auth_key, auth_value)
+            .await;
+        assert_eq!(
+            response.status_code(),
+            401,
+            "Token issued before the password reset should be rejected"
+        );
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial]
+async fn can_record_login_device() {
+    configure_insta!();
+
+    request::<App, _, _>(|request, ctx| async move {
+        let login_data = prepare_data::init_user_login(&request, &ctx).await;
+
+        let response = request
+            .post("/api/auth/login")
+            .add_header(
+                axum::http::HeaderName::from_static("user-agent"),
+                axum::http::HeaderValue::from_static("test-browser/1.0"),
+            )
+            .add_header(
+                axum::http::HeaderName::from_static("x-forwarded-for"),
+                axum::http::HeaderValue::from_static("203.0.113.7, 10.0.0.1"),
+            )
+            .json(&serde_json::json!({
+                "email": login_data.user.email,
+                "password": "12341234"
+            }))
+            .await;
+        assert_eq!(response.status_code(), 200, "Login request should succeed");
+
+        let active = sessions::Model::find_active_for_user(&ctx.db, login_data.user.id)
+            .await
+            .unwrap();
+        assert_eq!(active.len(), 2, "Each login should open its own session");
+        assert!(active.iter().any(|session| {
+            session.user_agent.as_deref() == Some("test-browser/1.0") && session.ip.is_some()
+        }));
+        assert!(
+            active
+                .iter()
+                .all(|session| session.ip.as_deref() != Some("203.0.113.7")),
+            "Forwarded headers are ignored when no proxy is trusted"
+        );
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial]
+async fn can_login_with_second_factor() {
+    configure_insta!();
+
+    request::<App, _, _>(|request, ctx| async move {
+        let login_data = prepare_data::init_user_login(&request, &ctx).await;
+        let user = login_data
+            .user
+            .into_active_model()
+            .begin_totp_enrolment(&ctx.db)
+            .await
+            .unwrap();
+        let (user, _codes) = user.into_active_model().enable_totp(&ctx.db).await.unwrap();
+
+        let response = request
+            .post("/api/auth/login")
+            .json(&serde_json::json!({
+                "email": user.email,
+                "password": "12341234"
+            }))
+            .await;
+        assert_eq!(response.status_code(), 200);
+        let challenge: MfaChallengeResponse = serde_json::from_str(&response.text()).unwrap();
+        assert!(challenge.mfa_required);
+
+        let (auth_key, auth_value) = prepare_data::auth_header(&challenge.mfa_token);
+        let response = request
+            .get("/api/auth/current")
+            .add_header(auth_key, auth_value)
+            .await;
+        assert_eq!(
+            response.status_code(),
+            401,
+            "The two-factor challenge must not be usable as a session token"
+        );
+
+        let response = request
+            .post("/api/auth/login/2fa")
+            .json(&serde_json::json!({
+                "mfa_token": challenge.mfa_token,
+                "code": "not-a-code"
+            }))
+            .await;
+        assert_eq!(
+            response.status_code(),
+            401,
+            "Invalid code should be rejected"
+        );
+
+        let code = totp_rs::TOTP::from_url(user.totp_provisioning_uri().unwrap())
+            .unwrap()
+            .generate_current()
+            .unwrap();
+        let response = request
+            .post("/api/auth/login/2fa")
+            .json(&serde_json::json!({
+                "mfa_token": challenge.mfa_token,
+                "code": code
+            }))
+            .await;
+        assert_eq!(response.status_code(), 200);
+        let login: LoginResponse = serde_json::from_str(&response.text()).unwrap();
+
+        let (auth_key, auth_value) = prepare_data::auth_header(&login.token);
+        let response = request
+            .get("/api/auth/current")
+            .add_header(auth_key, auth_value)
+            .await;
+        assert_eq!(response.status_code(), 200);
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial]
+async fn can_start_passkey_registration_and_reject_unknown_challenge() {
+    configure_insta!();
+
+    request::<App, _, _>(|request, ctx| async move {
+        let login_data = prepare_data::init_user_login(&request, &ctx).await;
+
+        let response = request.post("/api/auth/webauthn/register/start").await;
+        assert_eq!(
+            response.status_code(),
+            401,
+            "Registration requires a session"
+        );
+
+        let (auth_key, auth_value) = prepare_data::auth_header(&login_data.token);
+        let response = request
+            .post("/api/auth/webauthn/register/start")
+            .add_header(auth_key.clone(), auth_value.clone())
+            .await;
+        assert_eq!(response.status_code(), 200);
+        let start: serde_json::Value = serde_json::from_str(&response.text()).unwrap();
+        assert!(start["challenge_id"].as_str().is_some());
+        assert_eq!(start["options"]["publicKey"]["rp"]["id"], "localhost");
+        assert!(
+            start["options"]["publicKey"]["challenge"]
+                .as_str()
+                .is_some()
+        );
+
+        let response = request
+            .post("/api/auth/webauthn/register/finish")
+            .add_header(auth_key, auth_value)
+            .json(&serde_json::json!({
+                "challenge_id": "not-a-challenge",
+                "name": "My key",
+                "credential": {
+                    "id": "AAAA",
+                    "rawId": "AAAA",
+                    "type": "public-key",
+                    "response": {
+                        "attestationObject": "AAAA",
+                        "clientDataJSON": "AAAA"
+                    },
+                    "extensions": {}
+                }
+            }))
+            .await;
+        assert_eq!(response.status_code(), 400);
+        assert!(
+            webauthn_credentials::Model::find_for_user(&ctx.db, login_data.user.id)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        let response = request
+            .post("/api/auth/webauthn/login/start")
+            .json(&serde_json::json!({ "email": login_data.user.email }))
+            .await;
+        assert_eq!(
+            response.status_code(),
+            401,
+            "Passwordless login needs a registered passkey"
+        );
+        let unknown = request
+            .post("/api/auth/webauthn/login/start")
+            .json(&serde_json::json!({ "email": "nobody@example.com" }))
+            .await;
+        assert_eq!(
+            (unknown.status_code(), unknown.text()),
+            (response.status_code(), response.text()),
+            "Unknown emails cannot be told apart from accounts without a passkey"
+        );
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial]
+async fn can_throttle_and_lock_after_failed_logins() {
+    configure_insta!();
+
+    request::<App, _, _>(|request, ctx| async move {
+        let login_data = prepare_data::init_user_login(&request, &ctx).await;
+        let wrong_login = serde_json::json!({
+            "email": login_data.user.email,
+            "password": "wrong-password"
+        });
+
+        for _ in 0..=login_attempts::ACCOUNT_FREE_ATTEMPTS {
+            let response = request.post("/api/auth/login").json(&wrong_login).await;
+            assert_eq!(response.status_code(), 401);
+        }
+
+        let response = request
+            .post("/api/auth/login")
+            .json(&serde_json::json!({
+                "email": login_data.user.email,
+                "password": "12341234"
+            }))
+            .await;
+        assert_eq!(
+            response.status_code(),
+            429,
+            "Attempts should be delayed after repeated failures, even with the right password"
+        );
+        assert!(response.headers().get("retry-after").is_some());
+
+        let user = users::Model::find_by_email(&ctx.db, &login_data.user.email)
+            .await
+            .unwrap();
+        assert_eq!(
+            user.failed_login_count,
+            login_attempts::ACCOUNT_FREE_ATTEMPTS + 1
+        );
+
+        let mut user = user;
+        let mut locked = false;
+        while !locked {
+            (user, locked) = user
+                .into_active_model()
+                .record_failed_login(&ctx.db)
+                .await
+                .unwrap();
+        }
+        assert!(user.is_locked());
+        assert_eq!(
+            user.failed_login_count,
+            login_attempts::ACCOUNT_LOCKOUT_THRESHOLD
+        );
+        assert!(user.login_retry_after().unwrap() > 60);
+
+        let user = user
+            .into_active_model()
+            .clear_failed_logins(&ctx.db)
+            .await
+            .unwrap();
+        assert!(!user.is_locked());
+        assert!(user.login_retry_after().is_none());
+    })
+    .await;
+}
+
+#[tokio::test]
+#[serial]
+async fn can_reject_weak_password_and_rate_strength() {
+    request::<App, _, _>(|request, ctx| async move {
+        let response = request
+            .post("/api/auth/register")
+            .json(&serde_json::json!({
+                "name": "weak",
+                "email": "weak@loco.com",
+                "password": "1234",
+                "password_confirmation": "1234"
+            }))
+            .await;
+        assert_eq!(response.status_code(), 400);
+        assert!(response.text().contains("at least 8 characters"));
+        assert!(
+            users::Model::find_by_email(&ctx.db, "weak@loco.com")
+                .await
+                .is_err(),
+            "No user should be created with a rejected password"
+        );
+
+        let response = request
+            .post("/auth/password-strength")
+            .form(&serde_json::json!({ "password": "1234" }))
+            .await;
+        assert_eq!(response.status_code(), 200);
+        let html = response.text();
+        assert!(html.contains("Very weak"));
+        assert!(html.contains("at least 8 characters"));
+
+        let response = request
+            .post("/auth/password-strength")
+            .form(&serde_json::json!({ "password": "Quiet-Lantern-73-Orbit" }))
+            .await;
+        assert!(response.text().contains("Very strong"));
+    })
+    .await;
+}
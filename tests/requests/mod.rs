@@ -1,2 +1,6 @@
+mod admin_pages;
 mod auth;
+mod organizations_pages;
 mod prepare_data;
+mod teams_pages;
+mod users_pages;
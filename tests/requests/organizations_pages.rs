@@ -0,0 +1,107 @@
+use hosting_farm::{
+    app::App,
+    models::{
+        organizations::{self, OrganizationParams},
+        teams::{self, CreateTeamParams},
+    },
+};
+use loco_rs::testing::prelude::*;
+use serial_test::serial;
+
+use super::prepare_data;
+
+#[tokio::test]
+#[serial]
+async fn can_switch_organization() {
+    request::<App, _, _>(|request, ctx| async move {
+        let login_data = prepare_data::init_user_login(&request, &ctx).await;
+        let (auth_key, auth_value) = prepare_data::auth_header(&login_data.token);
+
+        let response = request
+            .post("/organizations/new")
+            .add_header(auth_key.clone(), auth_value.clone())
+            .form(&serde_json::json!({ "name": "Engineering", "description": "" }))
+            .await;
+        assert_eq!(response.status_code(), 303);
+        let organization = organizations::Model::list_for_user(&ctx.db, login_data.user.id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(organization, _)| organization)
+            .next()
+            .unwrap();
+        assert!(
+            organizations::Model::create(
+                &ctx.db,
+                login_data.user.id,
+                &OrganizationParams {
+                    name: "Engineering".to_string(),
+                    description: None,
+                },
+            )
+            .await
+            .is_err()
+        );
+
+        let response = request
+            .post("/teams/new")
+            .add_header(auth_key.clone(), auth_value.clone())
+            .form(&serde_json::json!({
+                "name": "Platform",
+                "description": "",
+                "organization": organization.pid.to_string(),
+            }))
+            .await;
+        assert_eq!(response.status_code(), 303);
+        teams::Model::create_team(
+            &ctx.db,
+            login_data.user.id,
+            &CreateTeamParams {
+                name: "Personal".to_string(),
+                description: None,
+            },
+        )
+        .await
+        .unwrap();
+
+        let response = request
+            .get(&format!("/organizations/{}", organization.pid))
+            .add_header(auth_key.clone(), auth_value.clone())
+            .await;
+        assert_eq!(response.status_code(), 200);
+        assert!(response.text().contains("Platform"));
+        assert!(!response.text().contains("Personal"));
+
+        let response = request
+            .get("/organizations/switcher")
+            .add_header(auth_key.clone(), auth_value.clone())
+            .await;
+        assert!(response.text().contains("Engineering"));
+
+        let response = request
+            .post("/organizations/switch")
+            .add_header(auth_key.clone(), auth_value.clone())
+            .form(&serde_json::json!({ "organization": organization.pid.to_string() }))
+            .await;
+        assert_eq!(response.header("HX-Redirect"), "/teams");
+        let cookie = response.header("Set-Cookie");
+        let cookie = cookie.to_str().unwrap();
+        assert!(cookie.contains(&organization.pid.to_string()));
+
+        let response = request
+            .get("/teams")
+            .add_header(auth_key.clone(), auth_value.clone())
+            .add_header(
+                "cookie",
+                format!("current_organization={}", organization.pid),
+            )
+            .await;
+        assert!(response.text().contains("Platform"));
+        assert!(!response.text().contains("Personal"));
+
+        let response = request.get("/teams").add_header(auth_key, auth_value).await;
+        assert!(response.text().contains("Platform"));
+        assert!(response.text().contains("Personal"));
+    })
+    .await;
+}
@@ -1,9 +1,13 @@
 use axum::http::{HeaderName, HeaderValue};
-use hosting_farm::{models::users, views::auth::LoginResponse};
+use hosting_farm::{
+    models::{tokens::TokenLifetimes, users},
+    views::auth::LoginResponse,
+};
 use loco_rs::{TestServer, app::AppContext};
+use sea_orm::IntoActiveModel;
 
 const USER_EMAIL: &str = "test@loco.com";
-const USER_PASSWORD: &str = "1234";
+const USER_PASSWORD: &str = "12341234";
 
 pub struct LoggedInUser {
     pub user: users::Model,
@@ -27,8 +31,14 @@ pub async fn init_user_login(request: &TestServer, ctx: &AppContext) -> LoggedIn
         .await
         .unwrap();
 
+    // Only a hash of the emailed token is stored, issue a new one to verify the user
+    let (_, verification_token) = user
+        .into_active_model()
+        .generate_email_verification_token(&ctx.db, &TokenLifetimes::default())
+        .await
+        .unwrap();
     let verify_payload = serde_json::json!({
-        "token": user.email_verification_token,
+        "token": verification_token,
     });
 
     request.post("/api/auth/verify").json(&verify_payload).await;
@@ -0,0 +1,112 @@
+use hosting_farm::{
+    app::App,
+    models::{
+        password_policy::PasswordPolicy,
+        team_join_links::{self, CreateJoinLinkParams},
+        team_memberships,
+        teams::{self, CreateTeamParams},
+        users::{self, RegisterParams},
+    },
+    views::auth::LoginResponse,
+};
+use loco_rs::testing::prelude::*;
+use sea_orm::IntoActiveModel;
+use serial_test::serial;
+
+use super::prepare_data;
+
+#[tokio::test]
+#[serial]
+async fn unverified_users_cannot_use_domain_restricted_join_links() {
+    request::<App, _, _>(|request, ctx| async move {
+        let owner = users::Model::create_with_password(
+            &ctx.db,
+            &RegisterParams {
+                email: "owner@example.org".to_string(),
+                password: "correct horse battery".to_string(),
+                name: "owner".to_string(),
+                password_confirmation: "correct horse battery".to_string(),
+            },
+            &PasswordPolicy::default(),
+        )
+        .await
+        .unwrap();
+        let team = teams::Model::create_team(
+            &ctx.db,
+            owner.id,
+            &CreateTeamParams {
+                name: "Workshop".to_string(),
+                description: None,
+            },
+        )
+        .await
+        .unwrap();
+        let (_, token) = team_join_links::Model::create(
+            &ctx.db,
+            &team,
+            &owner,
+            &CreateJoinLinkParams {
+                role: "Developer".to_string(),
+                max_uses: None,
+                expires_in_days: None,
+                allowed_domain: Some("example.com".to_string()),
+            },
+        )
+        .await
+        .unwrap();
+
+        // An address at the allowed domain that was never verified
+        request
+            .post("/api/auth/register")
+            .json(&serde_json::json!({
+                "name": "newcomer",
+                "email": "newcomer@example.com",
+                "password": "correct horse battery",
+                "password_confirmation": "correct horse battery",
+            }))
+            .await;
+        let response = request
+            .post("/api/auth/login")
+            .json(&serde_json::json!({
+                "email": "newcomer@example.com",
+                "password": "correct horse battery",
+            }))
+            .await;
+        let login_response: LoginResponse = serde_json::from_str(&response.text()).unwrap();
+        let (auth_key, auth_value) = prepare_data::auth_header(&login_response.token);
+        let newcomer = users::Model::find_by_email(&ctx.db, "newcomer@example.com")
+            .await
+            .unwrap();
+        assert!(newcomer.email_verified_at.is_none());
+
+        let response = request
+            .post(&format!("/teams/join/{token}"))
+            .add_header(auth_key.clone(), auth_value.clone())
+            .await;
+        assert!(response.text().contains("Verify your email address"));
+        assert!(
+            team_memberships::Model::find_by_team_and_user(&ctx.db, team.id, newcomer.id)
+                .await
+                .is_err()
+        );
+
+        // Once the address is verified, the link works
+        newcomer
+            .clone()
+            .into_active_model()
+            .verified(&ctx.db)
+            .await
+            .unwrap();
+        request
+            .post(&format!("/teams/join/{token}"))
+            .add_header(auth_key, auth_value)
+            .await;
+        let membership =
+            team_memberships::Model::find_by_team_and_user(&ctx.db, team.id, newcomer.id)
+                .await
+                .unwrap();
+        assert!(!membership.pending);
+        assert_eq!(membership.role, "Developer");
+    })
+    .await;
+}
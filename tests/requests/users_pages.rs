@@ -0,0 +1,47 @@
+use hosting_farm::{app::App, models::sessions};
+use loco_rs::testing::prelude::*;
+use serial_test::serial;
+
+use super::prepare_data;
+
+#[tokio::test]
+#[serial]
+async fn can_list_and_revoke_other_sessions() {
+    request::<App, _, _>(|request, ctx| async move {
+        let login_data = prepare_data::init_user_login(&request, &ctx).await;
+        let other_login = request
+            .post("/api/auth/login")
+            .json(&serde_json::json!({
+                "email": login_data.user.email,
+                "password": "12341234"
+            }))
+            .await;
+        assert_eq!(
+            other_login.status_code(),
+            200,
+            "Login request should succeed"
+        );
+
+        let (auth_key, auth_value) = prepare_data::auth_header(&login_data.token);
+        let response = request
+            .get("/users/profile/sessions_fragment")
+            .add_header(auth_key.clone(), auth_value.clone())
+            .await;
+        assert_eq!(response.status_code(), 200);
+        let html = response.text();
+        assert!(html.contains("This device"));
+        assert!(html.contains("Sign out everywhere else"));
+
+        let response = request
+            .post("/users/profile/sessions/revoke-others")
+            .add_header(auth_key, auth_value)
+            .await;
+        assert_eq!(response.status_code(), 200);
+
+        let active = sessions::Model::find_active_for_user(&ctx.db, login_data.user.id)
+            .await
+            .unwrap();
+        assert_eq!(active.len(), 1, "Only the current session should remain");
+    })
+    .await;
+}